@@ -0,0 +1,35 @@
+//! Times World::step at 100k agents. Compare the serial and parallel versions
+//! with `cargo bench --bench step` and `cargo bench --bench step --features rayon`.
+
+use agent_sim::{builder::WorldBuilder, geometry::Vec2D, StructureType};
+use std::time::Instant;
+
+const AGENTS: usize = 100_000;
+const STEPS: usize = 48;
+
+fn main() {
+    let mut world = WorldBuilder::new()
+        .size(Vec2D::new(1000.0, 1000.0))
+        .random_agents(AGENTS)
+        .step_size(3600)
+        .structures(StructureType::Home, 25_000)
+        .structures(StructureType::Work, 1_000)
+        .structures(StructureType::School, 100)
+        .seed(0)
+        .index_cases(1_000)
+        .build()
+        .unwrap();
+
+    let now = Instant::now();
+    for _ in 0..STEPS {
+        world.step();
+    }
+    let elapsed = now.elapsed();
+
+    println!(
+        "{} agents, {} steps: {:.1} ms/step",
+        AGENTS,
+        STEPS,
+        elapsed.as_secs_f64() * 1000.0 / STEPS as f64
+    );
+}
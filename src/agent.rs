@@ -1,54 +1,274 @@
+use rand::distributions::{Distribution, WeightedIndex};
 use rand::Rng;
 
-use crate::{disease::Disease, Vec2D};
-use crate::{BLUE, GREEN, ORANGE, RED, RESET, YELLOW};
-use std::collections::HashMap;
+use crate::disease::{BasicDisease, Disease};
+use crate::geometry::Rect;
+use crate::{StructureType, Vec2D};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
+use std::io::{self, Write};
 
-/// Represents the status of each agent.
-#[derive(Debug)]
+/// SECONDS_PER_YEAR is the length of a year used for ages, ignoring leap years.
+pub const SECONDS_PER_YEAR: i64 = 365 * 86400;
+
+/// DEFAULT_SPEED_RANGE is the range of speeds, in units per second, given to
+/// generated agents unless configured otherwise.
+pub const DEFAULT_SPEED_RANGE: (f64, f64) = (5.0 / 3600.0, 15.0 / 3600.0);
+
+/// WALKING_UNITS_PER_HOUR is the speed of a walking agent, the slow end of
+/// DEFAULT_SPEED_RANGE. It is walking pace if a unit is a kilometer.
+pub const WALKING_UNITS_PER_HOUR: f64 = 5.0;
+
+/// MovementError describes why a movement profile could not be created.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MovementError {
+    /// The speed must be finite and not negative.
+    InvalidSpeed(f64),
+}
+
+impl fmt::Display for MovementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovementError::InvalidSpeed(speed) => write!(f, "invalid speed {}", speed),
+        }
+    }
+}
+
+impl std::error::Error for MovementError {}
+
+/// MovementProfile is how fast an agent travels toward its destinations.
+/// Profiles are created from speeds in whichever units are convenient, and
+/// only valid speeds are accepted, so that agents never cross the world in a
+/// single step by accident.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MovementProfile {
+    /// speed is in units per second.
+    speed: f64,
+}
+
+impl MovementProfile {
+    /// Creates a profile that travels at the given speed in units per second.
+    pub fn from_units_per_second(speed: f64) -> Result<Self, MovementError> {
+        if !speed.is_finite() || speed < 0.0 {
+            return Err(MovementError::InvalidSpeed(speed));
+        }
+        Ok(Self { speed })
+    }
+
+    /// Creates a profile that travels at the given speed in units per hour.
+    pub fn from_units_per_hour(speed: f64) -> Result<Self, MovementError> {
+        Self::from_units_per_second(speed / 3600.0).map_err(|_| MovementError::InvalidSpeed(speed))
+    }
+
+    /// Creates a profile that covers the given distance over a whole day of
+    /// travel.
+    pub fn commuting(units_per_day: f64) -> Result<Self, MovementError> {
+        Self::from_units_per_second(units_per_day / 86400.0)
+            .map_err(|_| MovementError::InvalidSpeed(units_per_day))
+    }
+
+    /// Creates a profile that travels at WALKING_UNITS_PER_HOUR.
+    pub fn walking() -> Self {
+        Self {
+            speed: WALKING_UNITS_PER_HOUR / 3600.0,
+        }
+    }
+
+    /// Creates a profile with a speed drawn uniformly from the range, in units
+    /// per second. A range that is empty gives its lower end.
+    pub fn uniform<R: Rng>(range: (f64, f64), rng: &mut R) -> Result<Self, MovementError> {
+        let min = Self::from_units_per_second(range.0)?;
+        let max = Self::from_units_per_second(range.1)?;
+        if min.speed < max.speed {
+            Ok(Self {
+                speed: rng.gen_range(min.speed..max.speed),
+            })
+        } else {
+            Ok(min)
+        }
+    }
+
+    /// Returns the speed in units per second.
+    pub fn speed_per_sec(&self) -> f64 {
+        self.speed
+    }
+}
+
+/// StatusChange is a transition in status made by an agent during its step.
+/// Exposure isn't included, since it is caused by other agents.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StatusChange {
+    BecameInfectious,
+    Recovered,
+    /// Immunity waned and the agent can be infected again.
+    BecameSusceptible,
+    Died,
+}
+
+/// DeathCause is what an agent died of.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeathCause {
+    /// The infection the agent had, according to the infection fatality
+    /// ratio of the disease.
+    Disease,
+    /// Background mortality by age, regardless of infection.
+    Other,
+}
+
+/// Represents the status of each agent. The statuses that end on their own
+/// hold the absolute time the agent entered them, in seconds, so that how
+/// long it has been in one is the current time minus since.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Status {
     Susceptible,
-    /// Exposed contains an integer representing the length of time since the
-    /// agent was exposed. Only after a certain length of time being exposed
-    /// will the agent become infectious. Time is measured in seconds.
-    Exposed(i64),
-    /// Infectious contains an integer that tells how long the agent has been
-    /// infectious for. This is used to determine when they should transition
-    /// into being recovered. Time is measured in seconds.
-    Infectious(i64),
-    Recovered,
+    /// Exposed agents become infectious once they have been exposed for
+    /// longer than their incubation period.
+    Exposed {
+        since: i64,
+    },
+    /// Infectious agents recover once they have been infectious for longer
+    /// than their infectious period.
+    Infectious {
+        since: i64,
+    },
+    /// Hospitalized agents are severe cases that are still infectious. since
+    /// is carried over from when the agent became infectious, so that it
+    /// recovers on the same schedule.
+    Hospitalized {
+        since: i64,
+    },
+    /// Recovered agents become susceptible again once they have been
+    /// recovered for longer than the immunity period of the disease.
+    Recovered {
+        since: i64,
+    },
     Dead,
 }
 
 impl Status {
+    /// Returns the letter of the compartment the status belongs to, one of
+    /// S, E, I, H, R, or D.
+    pub fn as_char(&self) -> char {
+        match self {
+            Status::Susceptible => 'S',
+            Status::Exposed { .. } => 'E',
+            Status::Infectious { .. } => 'I',
+            Status::Hospitalized { .. } => 'H',
+            Status::Recovered { .. } => 'R',
+            Status::Dead => 'D',
+        }
+    }
+
+    /// Whether the agent can infect others, which includes hospitalized
+    /// agents.
     pub fn is_infectious(&self) -> bool {
-        matches!(self, Status::Infectious(_))
+        matches!(
+            self,
+            Status::Infectious { .. } | Status::Hospitalized { .. }
+        )
+    }
+
+    pub fn is_hospitalized(&self) -> bool {
+        matches!(self, Status::Hospitalized { .. })
+    }
+
+    /// Whether the agent is currently infected, whether or not it can infect
+    /// others yet.
+    pub fn is_infected(&self) -> bool {
+        self.is_exposed() || self.is_infectious()
     }
 
     pub fn is_susceptible(&self) -> bool {
         matches!(self, Status::Susceptible)
     }
 
+    pub fn is_exposed(&self) -> bool {
+        matches!(self, Status::Exposed { .. })
+    }
+
     pub fn is_dead(&self) -> bool {
         matches!(self, Status::Dead)
     }
+
+    pub fn is_recovered(&self) -> bool {
+        matches!(self, Status::Recovered { .. })
+    }
+
+    /// Returns the time the agent entered the status, for the statuses that
+    /// end on their own.
+    pub fn since(&self) -> Option<i64> {
+        match self {
+            Status::Exposed { since }
+            | Status::Infectious { since }
+            | Status::Hospitalized { since }
+            | Status::Recovered { since } => Some(*since),
+            Status::Susceptible | Status::Dead => None,
+        }
+    }
+
+    /// Returns how long the agent has been in the status as of now, for the
+    /// statuses that end on their own.
+    pub fn elapsed(&self, now: i64) -> Option<i64> {
+        self.since().map(|since| now - since)
+    }
 }
 
 /// Task represents the current action that the agent is taking, allowing one to
 /// determine where the agent is headed
 // TODO(tslnc04): decide whether the task should include a none option or if it should just be
 // wrapped in an Option<> when that would be necessary
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Task {
     Work,
     Home,
     School,
+    Hospital,
+    Travel,
     None,
 }
 
+/// GroupId is one of the groups agents can be tagged with, such as healthcare
+/// workers or a neighborhood, so that policies can target them and stats can
+/// be broken down by them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GroupId(u8);
+
+impl GroupId {
+    /// MAX_GROUPS is the number of distinct groups, with ids from 0 up to but
+    /// not including it.
+    pub const MAX_GROUPS: u8 = 32;
+
+    /// Returns the group with the given id, or None if it isn't below
+    /// MAX_GROUPS.
+    pub fn new(id: u8) -> Option<Self> {
+        (id < Self::MAX_GROUPS).then_some(Self(id))
+    }
+
+    pub fn id(&self) -> u8 {
+        self.0
+    }
+
+    fn bit(&self) -> u32 {
+        1 << self.0
+    }
+}
+
+impl fmt::Display for GroupId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 /// Each agent is a distinct entity that gets simulated. It currently only uses
 /// the position and the status to determine infection and recovery.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Agent {
     pub pos: Vec2D<f64>,
     pub status: Status,
@@ -56,55 +276,402 @@ pub struct Agent {
     pub home: Vec2D<f64>,
     pub work: Vec2D<f64>,
     pub school: Vec2D<f64>,
-    /// speed is the distance the agent can move per second, regardless of the
-    /// size of the simulation step.
-    pub speed: f64,
+    /// hospital is the position of the hospital the agent is admitted to, or
+    /// NaN if it isn't in one.
+    pub hospital: Vec2D<f64>,
+    /// travel_dest is where the agent stays while on a trip to another
+    /// region, or NaN if it isn't on one.
+    pub travel_dest: Vec2D<f64>,
+    /// movement is how fast the agent travels, regardless of the size of the
+    /// simulation step.
+    pub movement: MovementProfile,
     /// age is the time the agent has been alive for, in seconds. This is
     /// relative to the life of the agent, not the simulation.
     pub age: i64,
-    pub disease: Option<Box<dyn Disease>>,
+    /// disease is the particular disease the agent carries, if any.
+    pub disease: Option<BasicDisease>,
+    /// incubation_period is how long after exposure the agent becomes
+    /// infectious, in seconds. Drawn when the agent is exposed.
+    pub incubation_period: i64,
+    /// infectious_period is how long after becoming infectious the agent
+    /// recovers, in seconds. Drawn when the agent is exposed.
+    pub infectious_period: i64,
+    /// vaccinated is whether the agent has been vaccinated. The protection of
+    /// the vaccine is part of its immunity.
+    pub vaccinated: bool,
+    /// immunity is the partial immunity of the agent from past infections and
+    /// vaccination, from 0 for none to 1 for full protection. It reduces the
+    /// probability of the agent being exposed and the mortality of infections
+    /// in proportion, and decays with the half-life of the disease.
+    pub immunity: f64,
+    /// detected is set once the agent's infection has been detected, and is
+    /// cleared when the agent stops being infectious.
+    pub detected: bool,
+    /// isolated agents stay home and don't infect others until they recover.
+    pub isolated: bool,
+    /// quarantine_end is the absolute time at which an agent quarantined
+    /// through contact tracing is released, unless it has been detected as
+    /// infectious in the meantime.
+    pub quarantine_end: Option<i64>,
+    /// locked_down agents stay home for as long as a lockdown is active.
+    pub locked_down: bool,
+    /// asymptomatic is set when the agent becomes infectious without showing
+    /// symptoms. Only meaningful while the agent is infectious.
+    pub asymptomatic: bool,
+    /// household is the id of the household the agent belongs to, if any.
+    pub household: Option<usize>,
+    /// home_id, work_id, and school_id are the ids of the structures the
+    /// agent has been assigned, if any.
+    pub home_id: Option<usize>,
+    pub work_id: Option<usize>,
+    pub school_id: Option<usize>,
+    /// hospital_id is the id of the hospital the agent is admitted to, if
+    /// any.
+    pub hospital_id: Option<usize>,
+    /// hospitalization_prob is the probability that the agent is hospitalized
+    /// when it becomes infectious with symptoms.
+    pub hospitalization_prob: f64,
+    /// mortality_multiplier scales the infection fatality ratio of the disease
+    /// while the agent is infectious. Set by the world while the agent is
+    /// hospitalized, and 1 otherwise.
+    pub mortality_multiplier: f64,
+    /// home_region is the id of the region the agent lives in, if any.
+    pub home_region: Option<usize>,
+    /// trip_end is the absolute time at which the agent returns home from a
+    /// trip to another region, if it is on one.
+    pub trip_end: Option<i64>,
+    /// arrived_at is the absolute time at which the agent arrived at the
+    /// destination of its current task, if it has.
+    pub arrived_at: Option<i64>,
+    /// commute_time is the total time the agent has spent moving, in seconds.
+    pub commute_time: f64,
+    /// relative_susceptibility and relative_infectiousness scale the
+    /// probability of the agent being infected and of it infecting others.
+    /// Both are 1 for an average agent.
+    pub relative_susceptibility: f64,
+    pub relative_infectiousness: f64,
+    /// contact_multiplier scales how many contacts the agent makes, and so the
+    /// probability of it infecting others. It is 1 for an average agent, and
+    /// is drawn from a gamma distribution when the world has contact
+    /// dispersion, which makes some agents superspreaders.
+    pub contact_multiplier: f64,
+    /// contact_count is the number of living agents that have been within
+    /// the contact radius of the agent at the start of a step, summed over
+    /// the steps. It is only counted while the world records contacts.
+    pub contact_count: u64,
+    /// mask_compliance is how consistently the agent wears a mask, from 0 for
+    /// never to 1 for always.
+    pub mask_compliance: f64,
+    /// strain is the id of the strain of the agent's most recent infection,
+    /// if it has ever been infected.
+    pub strain: Option<usize>,
+    /// past_strains are the strains the agent has recovered from and is
+    /// immune to. Cleared when its immunity wanes.
+    pub past_strains: HashSet<usize>,
+    /// tags has the bit of every group the agent belongs to, see has_tag.
+    pub tags: u32,
+    /// death_cause is what the agent died of, if it is dead.
+    pub death_cause: Option<DeathCause>,
 }
 
 impl Agent {
-    pub fn new(pos: Vec2D<f64>, speed: f64) -> Self {
+    pub fn new(pos: Vec2D<f64>, movement: MovementProfile) -> Self {
         Agent {
-            pos: pos,
+            pos,
             status: Status::Susceptible,
             task: Task::Home,
             home: Vec2D::new_nan(),
             work: Vec2D::new_nan(),
             school: Vec2D::new_nan(),
-            speed: speed,
+            hospital: Vec2D::new_nan(),
+            travel_dest: Vec2D::new_nan(),
+            movement,
             age: 0,
             disease: None,
+            incubation_period: 0,
+            infectious_period: 0,
+            vaccinated: false,
+            immunity: 0.0,
+            detected: false,
+            isolated: false,
+            quarantine_end: None,
+            locked_down: false,
+            asymptomatic: false,
+            household: None,
+            home_id: None,
+            work_id: None,
+            school_id: None,
+            hospital_id: None,
+            hospitalization_prob: 0.0,
+            mortality_multiplier: 1.0,
+            home_region: None,
+            trip_end: None,
+            arrived_at: None,
+            commute_time: 0.0,
+            relative_susceptibility: 1.0,
+            relative_infectiousness: 1.0,
+            contact_multiplier: 1.0,
+            contact_count: 0,
+            mask_compliance: 0.0,
+            strain: None,
+            past_strains: HashSet::new(),
+            tags: 0,
+            death_cause: None,
+        }
+    }
+
+    /// Creates an agent that heads to the given home, workplace, and school.
+    /// Pass a NaN position, such as Vec2D::new_nan(), for any the agent
+    /// doesn't have.
+    pub fn new_with_locations(
+        pos: Vec2D<f64>,
+        movement: MovementProfile,
+        home: Vec2D<f64>,
+        work: Vec2D<f64>,
+        school: Vec2D<f64>,
+    ) -> Self {
+        Agent {
+            home,
+            work,
+            school,
+            ..Self::new(pos, movement)
+        }
+    }
+
+    /// Creates an agent that has already been alive for the given number of
+    /// seconds.
+    pub fn new_with_age(pos: Vec2D<f64>, movement: MovementProfile, age: i64) -> Self {
+        Agent {
+            age: age.max(0),
+            ..Self::new(pos, movement)
         }
     }
 
-    pub fn step<R: Rng>(&mut self, step_size: i64, rng: &mut R) {
+    /// Whether the agent belongs to the group.
+    pub fn has_tag(&self, group: GroupId) -> bool {
+        self.tags & group.bit() != 0
+    }
+
+    /// Adds the agent to the group.
+    pub fn add_tag(&mut self, group: GroupId) {
+        self.tags |= group.bit();
+    }
+
+    /// Takes the agent out of the group.
+    pub fn remove_tag(&mut self, group: GroupId) {
+        self.tags &= !group.bit();
+    }
+
+    /// Returns the letter the agent is drawn with, which is the letter of its
+    /// status except that vaccinated susceptible agents are V and
+    /// asymptomatic infectious agents are A.
+    pub fn status_letter(&self) -> char {
         match self.status {
-            Status::Exposed(t) => {
-                // Simulates the incubation period for the agent
-                if t > 21 * 86400 {
-                    self.status = Status::Infectious(0);
-                } else {
-                    self.status = Status::Exposed(t + step_size);
+            Status::Susceptible if self.vaccinated => 'V',
+            Status::Infectious { .. } if self.asymptomatic => 'A',
+            status => status.as_char(),
+        }
+    }
+
+    /// Returns the three columns the agent is drawn with at the given time.
+    /// Exposed, infectious, and hospitalized agents are drawn as their letter
+    /// followed by the whole days they have been in the status, so less than
+    /// a day is 0 and anything over 99 days is a + in place of the digits.
+    /// A status that starts after the given time has a ? for its days. Other
+    /// statuses are drawn as their letter between two spaces.
+    pub fn render_cell(&self, now: i64) -> [char; 3] {
+        let letter = self.status_letter();
+        let since = match self.status {
+            Status::Exposed { since }
+            | Status::Infectious { since }
+            | Status::Hospitalized { since } => since,
+            _ => return [' ', letter, ' '],
+        };
+
+        let days = (now - since).div_euclid(86400);
+        let digit = |n: i64| char::from_digit(n as u32, 10).expect("n is a single digit");
+        match days {
+            days if days < 0 => [letter, '?', ' '],
+            0..=9 => [letter, digit(days), ' '],
+            10..=99 => [letter, digit(days / 10), digit(days % 10)],
+            _ => [letter, '+', ' '],
+        }
+    }
+
+    /// Whether the agent can be infected with the given strain. Susceptible
+    /// agents can be infected with any strain, and recovered agents with any
+    /// strain they haven't had.
+    pub fn is_susceptible_to(&self, strain: usize) -> bool {
+        match self.status {
+            Status::Susceptible => true,
+            Status::Recovered { .. } => !self.past_strains.contains(&strain),
+            _ => false,
+        }
+    }
+
+    /// Exposes the agent to the disease at the given absolute time, drawing
+    /// the lengths of its incubation and infectious periods.
+    pub fn expose<R: Rng, D: Disease>(&mut self, now: i64, disease: &D, rng: &mut R) {
+        let incubation_period = disease.sample_incubation_period(rng);
+        let infectious_period = disease.sample_infectious_period(rng);
+        self.expose_for(now, incubation_period, infectious_period);
+    }
+
+    /// Exposes the agent at the given absolute time with incubation and
+    /// infectious periods that were already drawn.
+    pub fn expose_for(&mut self, now: i64, incubation_period: i64, infectious_period: i64) {
+        self.status = Status::Exposed { since: now };
+        self.incubation_period = incubation_period;
+        self.infectious_period = infectious_period;
+    }
+
+    /// Returns the task the agent should leave home for, based on which
+    /// structures it has been assigned. Agents without a workplace or school
+    /// stay home.
+    pub fn daytime_task(&self) -> Task {
+        if !self.work.is_nan() {
+            Task::Work
+        } else if !self.school.is_nan() {
+            Task::School
+        } else {
+            Task::Home
+        }
+    }
+
+    /// Advances the disease and age of the agent by a step starting at the
+    /// absolute time now, and possibly kills it, see roll_death. Statuses the
+    /// agent changes to start at the end of the step. Returns the change in
+    /// status, if there was one.
+    pub fn step<R: Rng, D: Disease>(
+        &mut self,
+        now: i64,
+        step_size: i64,
+        disease: &D,
+        background_mortality: bool,
+        rng: &mut R,
+    ) -> Option<StatusChange> {
+        let change = self.progress(now, step_size, disease, rng);
+        if let Some(cause) = self.roll_death(now, step_size, disease, background_mortality, rng) {
+            return self.kill(cause).or(change);
+        }
+        change
+    }
+
+    /// Advances the disease and age of the agent like step, without rolling
+    /// for its death.
+    pub fn progress<R: Rng, D: Disease>(
+        &mut self,
+        now: i64,
+        step_size: i64,
+        disease: &D,
+        rng: &mut R,
+    ) -> Option<StatusChange> {
+        self.progress_with(now, step_size, disease, |prob| rng.gen_bool(prob))
+    }
+
+    /// Advances the agent like progress, calling roll_asymptomatic with the
+    /// probability that the agent shows no symptoms when it becomes
+    /// infectious, which is the only draw progress makes.
+    pub fn progress_with<D: Disease>(
+        &mut self,
+        now: i64,
+        step_size: i64,
+        disease: &D,
+        roll_asymptomatic: impl FnOnce(f64) -> bool,
+    ) -> Option<StatusChange> {
+        let end = now + step_size;
+        let mut change = None;
+        if let Some(half_life) = disease.immunity_half_life().filter(|h| *h > 0) {
+            self.immunity *= 0.5f64.powf(step_size.max(0) as f64 / half_life as f64);
+        }
+        match self.status {
+            // Simulates the incubation period for the agent
+            Status::Exposed { since } if now - since > self.incubation_period => {
+                self.status = Status::Infectious { since: end };
+                self.asymptomatic = roll_asymptomatic(disease.asymptomatic_prob().clamp(0.0, 1.0));
+                change = Some(StatusChange::BecameInfectious);
+            }
+            // Simulates the infectious period for the agent
+            Status::Infectious { since } if now - since > self.infectious_period => {
+                self.status = Status::Recovered { since: end };
+                self.boost_immunity(disease.recovery_immunity());
+                if let Some(strain) = self.strain {
+                    self.past_strains.insert(strain);
                 }
+                change = Some(StatusChange::Recovered);
             }
-            Status::Infectious(t) => {
-                // Simulates the infectious period for the agent
-                if t > 28 * 86400 {
-                    self.status = Status::Recovered;
-                } else {
-                    self.status = Status::Infectious(t + step_size);
+            // hospitalized agents recover on the same schedule, and are no
+            // longer at greater risk once they do
+            Status::Hospitalized { since } if now - since > self.infectious_period => {
+                self.status = Status::Recovered { since: end };
+                self.mortality_multiplier = 1.0;
+                self.boost_immunity(disease.recovery_immunity());
+                if let Some(strain) = self.strain {
+                    self.past_strains.insert(strain);
                 }
+                change = Some(StatusChange::Recovered);
+            }
+            // Simulates waning immunity, if the disease allows for it
+            Status::Recovered { since }
+                if disease
+                    .immunity_period()
+                    .is_some_and(|immunity| now - since > immunity) =>
+            {
+                self.status = Status::Susceptible;
+                self.past_strains.clear();
+                change = Some(StatusChange::BecameSusceptible);
             }
             _ => (),
         }
 
         self.age += step_size;
 
-        if rng.gen_bool(self.death_probability(step_size)) {
-            self.status = Status::Dead;
+        change
+    }
+
+    /// Kills the agent of the given cause, returning the change in status if
+    /// it wasn't already dead.
+    pub fn kill(&mut self, cause: DeathCause) -> Option<StatusChange> {
+        if self.status.is_dead() {
+            return None;
+        }
+        self.status = Status::Dead;
+        self.death_cause = Some(cause);
+        Some(StatusChange::Died)
+    }
+
+    /// Raises the immunity of the agent to the given level, clamped to 0..=1,
+    /// if it isn't already higher.
+    pub fn boost_immunity(&mut self, level: f64) {
+        self.immunity = self.immunity.max(level.clamp(0.0, 1.0));
+    }
+
+    /// Rolls for whether the agent dies during a step starting at the absolute
+    /// time now, and of what. A single draw decides both causes, with the
+    /// disease taking precedence when both would have fired. Background
+    /// mortality is left out unless background is set.
+    pub fn roll_death<R: Rng, D: Disease>(
+        &self,
+        now: i64,
+        step_size: i64,
+        disease: &D,
+        background: bool,
+        rng: &mut R,
+    ) -> Option<DeathCause> {
+        let from_disease = self.disease_death_probability(now, step_size, disease);
+        let other = if background {
+            self.background_death_probability(step_size)
+        } else {
+            0.0
+        };
+
+        let roll = rng.gen::<f64>();
+        if roll < from_disease {
+            Some(DeathCause::Disease)
+        } else if roll < from_disease + (1.0 - from_disease) * other {
+            Some(DeathCause::Other)
+        } else {
+            None
         }
     }
 
@@ -114,98 +681,732 @@ impl Agent {
     /// components are just made to roughly approximate the actual function for
     /// annual probability of mortality.
     ///
-    /// The annual probability of mortality is converted to probability over a
-    /// step through dividing by the number of seconds in a year and multiplying
-    /// by number of seconds in a step.
-    ///
-    /// A flat increase of 0.1% per year is added for infectious agents.
+    /// The annual probability of mortality is converted to the probability
+    /// over a step by compounding, so that surviving every step of a year is as
+    /// likely as surviving the year. This keeps the probability within 0..=1
+    /// for any step size, which matters since gen_bool panics otherwise.
     ///
     /// https://www.ssa.gov/oact/STATS/table4c6.html
-    // TODO(tslnc04): ensure that this function only returns values 0..1,
-    // otherwise the program panics
-    // like with something better than a clamp hopefully
-    pub fn death_probability(&self, step_size: i64) -> f64 {
-        ((match self.age / (365 * 86400) {
-            0..=20 => 0.001,
-            21..=50 => 0.0001 * (self.age as f64 - 20.0) + 0.001,
-            51..=80 => 0.0001 * (self.age as f64 - 50.0) + 0.005,
-            81..=100 => 0.01 * (self.age as f64 - 80.0) + 0.05,
-            101..=119 => 0.03 * (self.age as f64 - 100.0) + 0.2,
+    pub fn background_death_probability(&self, step_size: i64) -> f64 {
+        let year = SECONDS_PER_YEAR as f64;
+        let age_years = self.age as f64 / year;
+
+        let annual = match self.age / SECONDS_PER_YEAR {
+            i64::MIN..=20 => 0.001,
+            21..=50 => 0.0001 * (age_years - 20.0) + 0.001,
+            51..=80 => 0.0001 * (age_years - 50.0) + 0.005,
+            81..=100 => 0.01 * (age_years - 80.0) + 0.05,
+            101..=119 => 0.03 * (age_years - 100.0) + 0.2,
             _ => 0.9,
-        } + if self.status.is_infectious() {
-            0.001
+        };
+
+        compound(annual, step_size as f64 / year)
+    }
+
+    /// Calculate the probability of dying of the disease during a step
+    /// starting at the absolute time now, which is 0 unless the agent is
+    /// infectious. The infection fatality ratio of the disease at the age of
+    /// the agent, scaled by its mortality multiplier and reduced in proportion
+    /// to its immunity, is spread evenly over the seconds of its infectious
+    /// period, so that the probability of surviving all of them is one minus
+    /// the ratio however the step size changes along the way.
+    pub fn disease_death_probability<D: Disease>(
+        &self,
+        now: i64,
+        step_size: i64,
+        disease: &D,
+    ) -> f64 {
+        let since = match self.status {
+            Status::Infectious { since } | Status::Hospitalized { since } => since,
+            _ => return 0.0,
+        };
+
+        let ifr = disease.infection_fatality_ratio(self.age as f64 / SECONDS_PER_YEAR as f64)
+            * self.mortality_multiplier.max(0.0)
+            * (1.0 - self.immunity.clamp(0.0, 1.0));
+        let period = self.infectious_period.max(0);
+        // the step the agent becomes infectious in ends at since, so only
+        // the steps after it count, and the last one only up to the end of
+        // the period. Without a period, the whole ratio falls on the first
+        if period == 0 {
+            return if now == since {
+                compound(ifr, 1.0)
+            } else {
+                0.0
+            };
+        }
+        let overlap = (now + step_size.max(0)).min(since + period) - now.max(since);
+
+        compound(ifr, overlap.max(0) as f64 / period as f64)
+    }
+}
+
+/// Converts the probability of an event over a period to the probability over
+/// the given fraction of it, so that avoiding the event over every fraction
+/// is as likely as avoiding it over the period. Both are clamped to 0..=1.
+fn compound(prob: f64, fraction: f64) -> f64 {
+    (1.0 - (1.0 - prob.clamp(0.0, 1.0)).powf(fraction.max(0.0))).clamp(0.0, 1.0)
+}
+
+/// AgePyramid is a piecewise uniform distribution of ages in years. Each band
+/// is chosen with probability proportional to its weight, then an age is drawn
+/// uniformly from within it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgePyramid {
+    /// bands are the start and end ages, in years, and the weight of each band.
+    bands: Vec<(f64, f64, f64)>,
+    weights: WeightedIndex<f64>,
+}
+
+impl AgePyramid {
+    /// Creates a pyramid from bands of (start, end, weight). Returns None if
+    /// there are no bands, a band is empty or has a negative start, or the
+    /// weights are invalid.
+    pub fn new(bands: Vec<(f64, f64, f64)>) -> Option<Self> {
+        if bands
+            .iter()
+            .any(|&(start, end, _)| !(start >= 0.0 && end > start && end.is_finite()))
+        {
+            return None;
+        }
+        let weights = WeightedIndex::new(bands.iter().map(|band| band.2)).ok()?;
+        Some(Self { bands, weights })
+    }
+
+    /// Creates a pyramid with ages uniformly between 0 and max_age years.
+    pub fn uniform(max_age: f64) -> Self {
+        Self::new(vec![(0.0, max_age.max(f64::EPSILON), 1.0)])
+            .expect("a single positive band is always valid")
+    }
+
+    /// Creates a pyramid that roughly approximates the age distribution of the
+    /// US population, in bands of ten years with a mean of about 40 years.
+    pub fn realistic() -> Self {
+        Self::new(vec![
+            (0.0, 10.0, 12.0),
+            (10.0, 20.0, 13.0),
+            (20.0, 30.0, 13.5),
+            (30.0, 40.0, 13.5),
+            (40.0, 50.0, 12.5),
+            (50.0, 60.0, 12.5),
+            (60.0, 70.0, 11.5),
+            (70.0, 80.0, 7.0),
+            (80.0, 90.0, 3.5),
+            (90.0, 100.0, 1.0),
+        ])
+        .expect("the realistic bands are valid")
+    }
+
+    /// Returns the mean age of the pyramid, in years.
+    pub fn mean(&self) -> f64 {
+        let total: f64 = self.bands.iter().map(|band| band.2).sum();
+        self.bands
+            .iter()
+            .map(|&(start, end, weight)| (start + end) / 2.0 * weight)
+            .sum::<f64>()
+            / total
+    }
+}
+
+impl Distribution<f64> for AgePyramid {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        let (start, end, _) = self.bands[self.weights.sample(rng)];
+        rng.gen_range(start..end)
+    }
+}
+
+/// Generates agents at uniformly random positions within the bounds, with ages
+/// in years drawn from the distribution and speeds from DEFAULT_SPEED_RANGE.
+pub fn generate_population<R: Rng>(
+    count: usize,
+    age_distribution: &impl Distribution<f64>,
+    world_bounds: Rect<f64>,
+    rng: &mut R,
+) -> Vec<Agent> {
+    let coord = |rng: &mut R, min: f64, max: f64| {
+        if min < max {
+            rng.gen_range(min..max)
         } else {
-            0.0
-        }) / (365.0 * 86400.0)
-            * step_size as f64)
-            .clamp(0.0, 1.0)
+            min
+        }
+    };
+
+    (0..count)
+        .map(|_| {
+            let pos = Vec2D::new(
+                coord(rng, world_bounds.bl.x, world_bounds.tr.x),
+                coord(rng, world_bounds.bl.y, world_bounds.tr.y),
+            );
+            let movement = MovementProfile::uniform(DEFAULT_SPEED_RANGE, rng)
+                .expect("the default speeds are valid");
+            let age_years: f64 = age_distribution.sample(rng);
+            Agent::new_with_age(pos, movement, (age_years * SECONDS_PER_YEAR as f64) as i64)
+        })
+        .collect()
+}
+
+/// Gives each agent the movement profile returned for its age in years, such
+/// as walking for children and the elderly and commuting for working adults.
+pub fn assign_movement_by_age<'a>(
+    agents: impl IntoIterator<Item = &'a mut Agent>,
+    mut profile: impl FnMut(f64) -> MovementProfile,
+) {
+    for agent in agents {
+        agent.movement = profile(agent.age as f64 / SECONDS_PER_YEAR as f64);
     }
 }
 
 impl fmt::Display for Agent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.status {
-            Status::Susceptible => write!(f, "{} S {}", GREEN, RESET),
-            Status::Exposed(t) => write!(f, "{}E{} {}", ORANGE, t / 86400, RESET),
-            Status::Infectious(t) => write!(f, "{}I{} {}", RED, t / 86400, RESET),
-            Status::Recovered => write!(f, "{} R {}", YELLOW, RESET),
-            Status::Dead => write!(f, "{} D {}", BLUE, RESET),
-        }
+        crate::render::write_agent_cell(f, self, None, crate::RenderOptions::default())
     }
 }
 
 // build the graph during the simulation and use that to replace the src field
 // of the agent struct
-// TODO(tslnc04): turn this into a nonsimple digraph; eliminate the strict
-// parent-child hierarchy and allow things like double edges
-#[derive(Debug)]
+/// ContactGraph records the infections of agents as nodes, with a weighted
+/// edge from each infectious agent to every infection it was in contact with.
+/// Repeated contact between the same pair adds to the weight of their edge
+/// rather than adding another, and exactly one edge into each node with a
+/// parent is attributed as the transmission. The attributed edges form the
+/// transmission trees the analyses below walk.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ContactGraph {
     // this doesn't support deletion of nodes?
     // probably not too necessary though
     nodes: Vec<ContactNode>,
     /// agent_table provides a lookup between agent ids (keys) and nodes indices (values)
     agent_table: HashMap<usize, usize>,
+    /// unlinked counts the nodes whose requested parent was not in the graph
+    unlinked: usize,
 }
 
+/// ContactGraphError describes why a node could not be added to the graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContactGraphError {
+    /// The agent with the given id already has a node. Use add_reinfection to
+    /// record another infection of the same agent.
+    DuplicateNode(usize),
+}
+
+impl fmt::Display for ContactGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContactGraphError::DuplicateNode(agent_id) => {
+                write!(f, "agent {} already has a node in the graph", agent_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ContactGraphError {}
+
 impl ContactGraph {
     pub fn new() -> Self {
         Self {
             nodes: Vec::new(),
             agent_table: HashMap::new(),
+            unlinked: 0,
+        }
+    }
+
+    /// Adds a node for the first infection of the given agent by the parent
+    /// agent at the given absolute time, with an attributed edge of weight 0
+    /// from the parent that add_exposure adds to. Refuses to add a second node
+    /// for an agent already in the graph. Returns whether the node was linked
+    /// to the requested parent; a parent that is not in the graph leaves the
+    /// node as a root and is counted in unlinked_count. Nodes without a
+    /// requested parent are always considered linked.
+    pub fn add_node(
+        &mut self,
+        agent_id: usize,
+        parent: Option<usize>,
+        time: i64,
+        strain: usize,
+    ) -> Result<bool, ContactGraphError> {
+        if self.agent_table.contains_key(&agent_id) {
+            return Err(ContactGraphError::DuplicateNode(agent_id));
         }
+        Ok(self.push_node(agent_id, parent, time, strain))
+    }
+
+    /// Adds a node for another infection of an agent, such as after its
+    /// immunity waned. Lookups by agent id refer to its most recent infection
+    /// afterwards. Agents not yet in the graph are added as with add_node.
+    /// Returns whether the node was linked to the requested parent.
+    pub fn add_reinfection(
+        &mut self,
+        agent_id: usize,
+        parent: Option<usize>,
+        time: i64,
+        strain: usize,
+    ) -> bool {
+        self.push_node(agent_id, parent, time, strain)
+    }
+
+    /// Records where the most recent infection of the agent happened. Does
+    /// nothing if the agent isn't in the graph.
+    pub fn set_setting(&mut self, agent_id: usize, setting: Setting) {
+        if let Some(index) = self.agent_table.get(&agent_id) {
+            self.nodes[*index].setting = Some(setting);
+        }
+    }
+
+    /// Returns where the most recent infection of the agent happened, if it
+    /// was recorded.
+    pub fn setting_of(&self, agent_id: usize) -> Option<Setting> {
+        self.agent_table
+            .get(&agent_id)
+            .and_then(|index| self.nodes[*index].setting)
+    }
+
+    /// Returns the setting of every infection where it was recorded, in the
+    /// order the infections were recorded. Index cases have no setting.
+    pub fn settings(&self) -> impl Iterator<Item = Setting> + '_ {
+        self.nodes.iter().filter_map(|node| node.setting)
+    }
+
+    /// Returns the strain of the most recent infection of the agent.
+    pub fn strain_of(&self, agent_id: usize) -> Option<usize> {
+        self.agent_table
+            .get(&agent_id)
+            .map(|index| self.nodes[*index].strain)
     }
 
-    pub fn add_node(&mut self, agent_id: usize, parent: Option<usize>) {
+    /// Returns whether the agent has been infected at least once.
+    pub fn contains(&self, agent_id: usize) -> bool {
+        self.agent_table.contains_key(&agent_id)
+    }
+
+    /// Returns the number of nodes whose requested parent was not in the graph
+    /// when they were added, leaving their transmission chain severed.
+    pub fn unlinked_count(&self) -> usize {
+        self.unlinked
+    }
+
+    fn push_node(
+        &mut self,
+        agent_id: usize,
+        parent: Option<usize>,
+        time: i64,
+        strain: usize,
+    ) -> bool {
         let graph_parent = match parent {
-            Some(parent_agent) => match self.agent_table.get(&parent_agent) {
-                Some(parent_index) => Some(*parent_index),
-                None => None,
-            },
+            Some(parent_agent) => self.agent_table.get(&parent_agent).copied(),
             None => None,
         };
+        let linked = parent.is_none() || graph_parent.is_some();
+        if !linked {
+            self.unlinked += 1;
+        }
+
         let new_node = ContactNode {
             index: self.nodes.len(),
             parent: graph_parent,
-            children: Vec::new(),
+            edges: Vec::new(),
+            child_agents: Vec::new(),
             agent_id,
+            time,
+            strain,
+            setting: None,
         };
 
-        if graph_parent.is_some() {
-            if let Some(parent_node) = self.nodes.get_mut(graph_parent.unwrap()) {
-                parent_node.children.push(new_node.index);
-            }
+        if let Some(parent_node) = graph_parent.and_then(|parent| self.nodes.get_mut(parent)) {
+            parent_node.edges.push(Edge {
+                to: new_node.index,
+                weight: 0.0,
+                attributed: true,
+            });
+            parent_node.child_agents.push(agent_id);
         }
 
         self.agent_table.insert(agent_id, self.nodes.len());
         self.nodes.push(new_node);
+        linked
+    }
+
+    /// Adds the weight of a contact between an infectious agent and an agent
+    /// that is already infected to the edge between their most recent
+    /// infections, adding an unattributed edge if there isn't one yet. Returns
+    /// false without recording anything if either agent was never infected or
+    /// they are the same agent.
+    pub fn add_exposure(&mut self, from: usize, to: usize, weight: f64) -> bool {
+        let (from_index, to_index) = match (self.agent_table.get(&from), self.agent_table.get(&to))
+        {
+            (Some(from_index), Some(to_index)) if from_index != to_index => {
+                (*from_index, *to_index)
+            }
+            _ => return false,
+        };
+
+        let edges = &mut self.nodes[from_index].edges;
+        match edges.iter_mut().find(|edge| edge.to == to_index) {
+            Some(edge) => edge.weight += weight,
+            None => edges.push(Edge {
+                to: to_index,
+                weight,
+                attributed: false,
+            }),
+        }
+        true
+    }
+
+    /// Returns the weight of the edge between the most recent infections of
+    /// the two agents, if there is one.
+    pub fn exposure_weight(&self, from: usize, to: usize) -> Option<f64> {
+        let from_index = self.agent_table.get(&from)?;
+        let to_index = self.agent_table.get(&to)?;
+        self.nodes[*from_index]
+            .edges
+            .iter()
+            .find(|edge| edge.to == *to_index)
+            .map(|edge| edge.weight)
+    }
+
+    /// Returns every agent with an edge into the most recent infection of the
+    /// given agent, along with the weight of the edge and whether it is the
+    /// attributed transmission. The attributed edge comes first, followed by
+    /// the rest in the order the infectors were infected.
+    pub fn potential_infectors(&self, agent_id: usize) -> Vec<(usize, f64, bool)> {
+        let index = match self.agent_table.get(&agent_id) {
+            Some(index) => *index,
+            None => return Vec::new(),
+        };
+
+        let mut infectors = self
+            .nodes
+            .iter()
+            .flat_map(|node| {
+                node.edges
+                    .iter()
+                    .filter(|edge| edge.to == index)
+                    .map(|edge| (node.agent_id, edge.weight, edge.attributed))
+            })
+            .collect::<Vec<_>>();
+        infectors.sort_by_key(|(_, _, attributed)| !attributed);
+        infectors
+    }
+
+    /// Returns the number of edges in the graph, attributed or not.
+    pub fn edge_count(&self) -> usize {
+        self.nodes.iter().map(|node| node.edges.len()).sum()
+    }
+
+    /// Returns the ids of the agents directly infected by the given agent
+    /// during its most recent infection, or None if it was never infected.
+    pub fn children_of(&self, agent_id: usize) -> Option<&[usize]> {
+        let index = self.agent_table.get(&agent_id)?;
+        Some(&self.nodes[*index].child_agents)
+    }
+
+    /// Returns the id of the agent that infected the given agent, if known.
+    pub fn parent_of(&self, agent_id: usize) -> Option<usize> {
+        let index = self.agent_table.get(&agent_id)?;
+        let parent = self.nodes[*index].parent?;
+        Some(self.nodes[parent].agent_id)
+    }
+
+    /// Returns the number of transmission links between the most recent
+    /// infection of the agent and the root of its tree, or None if the agent
+    /// was never infected. Index cases have a depth of 0.
+    pub fn chain_depth(&self, agent_id: usize) -> Option<usize> {
+        let mut index = *self.agent_table.get(&agent_id)?;
+        let mut depth = 0;
+        while let Some(parent) = self.nodes[index].parent {
+            index = parent;
+            depth += 1;
+        }
+        Some(depth)
+    }
+
+    /// Returns the length of the longest transmission chain in the graph.
+    pub fn max_chain_depth(&self) -> usize {
+        // parents are always added before their children, so a single pass in
+        // node order sees every parent's depth first
+        let mut depths = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter() {
+            let depth = match node.parent {
+                Some(parent) => depths[parent] + 1,
+                None => 0,
+            };
+            depths.push(depth);
+        }
+        depths.into_iter().max().unwrap_or(0)
+    }
+
+    /// Returns the ids of the agents at the roots of the transmission trees, in
+    /// the order they were infected, without duplicates.
+    pub fn roots(&self) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        self.nodes
+            .iter()
+            .filter(|node| node.parent.is_none() && seen.insert(node.agent_id))
+            .map(|node| node.agent_id)
+            .collect()
     }
 
+    /// Returns the number of infections in the tree below the most recent
+    /// infection of the agent, including the agent itself. Agents that were
+    /// never infected have a subtree size of 0.
+    pub fn subtree_size(&self, agent_id: usize) -> usize {
+        let index = match self.agent_table.get(&agent_id) {
+            Some(index) => *index,
+            None => return 0,
+        };
+
+        let mut size = 0;
+        let mut stack = vec![index];
+        while let Some(curr) = stack.pop() {
+            size += 1;
+            stack.extend(self.nodes[curr].children());
+        }
+        size
+    }
+
+    /// Finds every agent within the given number of transmission links of an
+    /// agent, walking breadth-first from the agent to those it infected and,
+    /// if requested, to the agent that infected it. The starting agent is not
+    /// included, and agents are returned in the order they were reached.
+    pub fn trace(&self, agent_id: usize, depth: usize, include_parent: bool) -> Vec<usize> {
+        let mut traced = Vec::new();
+        let mut visited = HashSet::from([agent_id]);
+        let mut frontier = vec![agent_id];
+
+        for _ in 0..depth {
+            let mut next_frontier = Vec::new();
+            for curr in frontier {
+                let mut neighbors = self.children_of(curr).unwrap_or_default().to_vec();
+                if include_parent {
+                    neighbors.extend(self.parent_of(curr));
+                }
+
+                for neighbor in neighbors {
+                    if visited.insert(neighbor) {
+                        traced.push(neighbor);
+                        next_frontier.push(neighbor);
+                    }
+                }
+            }
+            frontier = next_frontier;
+        }
+
+        traced
+    }
+
+    /// Returns the mean number of edges into and out of each node. Repeated
+    /// contact between the same pair is a single weighted edge, so it counts
+    /// once.
     pub fn get_average_degree(&self) -> f64 {
-        let mut total_degree = 0;
+        2.0 * self.edge_count() as f64 / self.nodes.len() as f64
+    }
+
+    /// Returns the mean total weight of the edges into and out of each node.
+    pub fn get_average_weighted_degree(&self) -> f64 {
+        let total_weight = self
+            .nodes
+            .iter()
+            .flat_map(|node| node.edges.iter())
+            .map(|edge| edge.weight)
+            .sum::<f64>();
+        2.0 * total_weight / self.nodes.len() as f64
+    }
+
+    /// Estimates the effective reproduction number over time by averaging the
+    /// number of secondary infections caused by the infections in each window
+    /// of the given length. Returns the start time of each window along with
+    /// its estimate, skipping windows without any infections. Infections near
+    /// the end of a run have not had the chance to infect others yet, so the
+    /// last windows underestimate.
+    pub fn reproduction_number_over_time(&self, window_secs: i64) -> Vec<(i64, f64)> {
+        if window_secs <= 0 {
+            return Vec::new();
+        }
+
+        let mut windows = BTreeMap::<i64, (usize, usize)>::new();
+        for node in self.nodes.iter() {
+            let start = node.time.div_euclid(window_secs) * window_secs;
+            let (infections, secondary) = windows.entry(start).or_insert((0, 0));
+            *infections += 1;
+            *secondary += node.child_agents.len();
+        }
+
+        windows
+            .into_iter()
+            .map(|(start, (infections, secondary))| (start, secondary as f64 / infections as f64))
+            .collect()
+    }
+
+    /// Returns the time from the infection of the parent to the infection of
+    /// the child, in seconds, for every infection with a known parent, in the
+    /// order the infections were recorded.
+    pub fn generation_intervals(&self) -> Vec<i64> {
+        self.nodes
+            .iter()
+            .filter_map(|node| {
+                node.parent
+                    .map(|parent| node.time - self.nodes[parent].time)
+            })
+            .collect()
+    }
+
+    /// Returns the absolute time of every infection, in the order they were
+    /// recorded. Agents infected more than once appear once per infection.
+    pub fn infection_times(&self) -> Vec<i64> {
+        self.nodes.iter().map(|node| node.time).collect()
+    }
+
+    /// Returns the ids of the distinct agents that were ever infected, in no
+    /// particular order.
+    pub fn agent_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.agent_table.keys().copied()
+    }
+
+    /// Returns the number of distinct agents that were ever infected.
+    pub fn infected_agent_count(&self) -> usize {
+        self.agent_table.len()
+    }
+
+    /// Returns a histogram of the number of secondary infections per infection,
+    /// where the value at index i is the number of infections that caused
+    /// exactly i others.
+    pub fn secondary_case_distribution(&self) -> Vec<usize> {
+        let mut histogram = Vec::new();
+        for node in self.nodes.iter() {
+            let count = node.child_agents.len();
+            if histogram.len() <= count {
+                histogram.resize(count + 1, 0);
+            }
+            histogram[count] += 1;
+        }
+        histogram
+    }
+
+    /// Returns the exported id of each node, in node order. Nodes are named by
+    /// their agent id so they can be joined with per-agent data, with the
+    /// episode appended for agents that were infected more than once, e.g. the
+    /// second infection of agent 4 is "4.1".
+    fn export_ids(&self) -> Vec<String> {
+        let mut episodes = HashMap::<usize, usize>::new();
+        self.nodes
+            .iter()
+            .map(|node| {
+                let episode = episodes.entry(node.agent_id).or_insert(0);
+                let id = if *episode == 0 {
+                    node.agent_id.to_string()
+                } else {
+                    format!("{}.{}", node.agent_id, episode)
+                };
+                *episode += 1;
+                id
+            })
+            .collect()
+    }
+
+    /// Writes the graph as GraphML, with a directed edge from each infectious
+    /// agent to the infections it was in contact with. Each node carries its
+    /// agent id and the time and strain of the infection, and each edge its
+    /// weight and whether it is the attributed transmission.
+    pub fn to_graphml<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let ids = self.export_ids();
+
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            w,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        writeln!(
+            w,
+            r#"  <key id="agent_id" for="node" attr.name="agent_id" attr.type="long"/>"#
+        )?;
+        writeln!(
+            w,
+            r#"  <key id="time" for="node" attr.name="time" attr.type="long"/>"#
+        )?;
+        writeln!(
+            w,
+            r#"  <key id="strain" for="node" attr.name="strain" attr.type="long"/>"#
+        )?;
+        writeln!(
+            w,
+            r#"  <key id="weight" for="edge" attr.name="weight" attr.type="double"/>"#
+        )?;
+        writeln!(
+            w,
+            r#"  <key id="attributed" for="edge" attr.name="attributed" attr.type="boolean"/>"#
+        )?;
+        writeln!(w, r#"  <graph id="ContactGraph" edgedefault="directed">"#)?;
+
+        for node in self.nodes.iter() {
+            writeln!(w, r#"    <node id="{}">"#, ids[node.index])?;
+            writeln!(w, r#"      <data key="agent_id">{}</data>"#, node.agent_id)?;
+            writeln!(w, r#"      <data key="time">{}</data>"#, node.time)?;
+            writeln!(w, r#"      <data key="strain">{}</data>"#, node.strain)?;
+            writeln!(w, "    </node>")?;
+        }
+        for node in self.nodes.iter() {
+            for edge in node.edges.iter() {
+                writeln!(
+                    w,
+                    r#"    <edge source="{}" target="{}">"#,
+                    ids[node.index], ids[edge.to]
+                )?;
+                writeln!(w, r#"      <data key="weight">{}</data>"#, edge.weight)?;
+                writeln!(
+                    w,
+                    r#"      <data key="attributed">{}</data>"#,
+                    edge.attributed
+                )?;
+                writeln!(w, "    </edge>")?;
+            }
+        }
+
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</graphml>")?;
+        Ok(())
+    }
+
+    /// Writes the graph as a JSON object with a list of nodes, each with its
+    /// id, agent id, and the time and strain of the infection, and a list of
+    /// edges with their weights and whether they are attributed.
+    pub fn to_json<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let ids = self.export_ids();
+
+        write!(w, r#"{{"nodes":["#)?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if i > 0 {
+                write!(w, ",")?;
+            }
+            write!(
+                w,
+                r#"{{"id":"{}","agent_id":{},"time":{},"strain":{}}}"#,
+                ids[node.index], node.agent_id, node.time, node.strain
+            )?;
+        }
+
+        write!(w, r#"],"edges":["#)?;
+        let mut first = true;
         for node in self.nodes.iter() {
-            total_degree += node.get_degree();
+            for edge in node.edges.iter() {
+                if !first {
+                    write!(w, ",")?;
+                }
+                first = false;
+                write!(
+                    w,
+                    r#"{{"source":"{}","target":"{}","weight":{},"attributed":{}}}"#,
+                    ids[node.index], ids[edge.to], edge.weight, edge.attributed
+                )?;
+            }
         }
-        total_degree as f64 / self.nodes.len() as f64
+        writeln!(w, "]}}")?;
+        Ok(())
+    }
+}
+
+impl Default for ContactGraph {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -220,20 +1421,81 @@ impl fmt::Display for ContactGraph {
     }
 }
 
+/// SettingTag is the kind of place a transmission happened in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SettingTag {
+    Home,
+    Work,
+    School,
+    Hospital,
+    /// Away from any structure.
+    Community,
+}
+
+impl SettingTag {
+    pub const ALL: [SettingTag; 5] = [
+        SettingTag::Home,
+        SettingTag::Work,
+        SettingTag::School,
+        SettingTag::Hospital,
+        SettingTag::Community,
+    ];
+}
+
+/// Setting is where a transmission happened: at the structure nearest the
+/// infector, or in the community if none was close enough.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Setting {
+    Structure { id: usize, typ: StructureType },
+    Community,
+}
+
+impl Setting {
+    pub fn tag(&self) -> SettingTag {
+        match self {
+            Setting::Structure { typ, .. } => match typ {
+                StructureType::Home => SettingTag::Home,
+                StructureType::Work => SettingTag::Work,
+                StructureType::School => SettingTag::School,
+                StructureType::Hospital => SettingTag::Hospital,
+            },
+            Setting::Community => SettingTag::Community,
+        }
+    }
+}
+
 /// Each ContactNode stores the place of an agent in the contact-tracing graph.
-/// The parent is the source of the infection and the children are all the agents
-/// infected by this node's agent.
-#[derive(Debug)]
+/// The parent is the source of the infection and the attributed edges lead to
+/// all the agents infected by this node's agent.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct ContactNode {
     index: usize,
     parent: Option<usize>,
-    children: Vec<usize>,
+    /// edges lead to the nodes this node's agent was in contact with while
+    /// infectious, at most one per node
+    edges: Vec<Edge>,
+    /// child_agents holds the agent ids of the targets of the attributed
+    /// edges, in the same order
+    child_agents: Vec<usize>,
     agent_id: usize,
+    /// time is the absolute time of the infection in seconds
+    time: i64,
+    /// strain is the id of the strain the agent was infected with
+    strain: usize,
+    /// setting is where the agent was infected, if it is known
+    setting: Option<Setting>,
 }
 
 impl ContactNode {
-    pub fn get_degree(&self) -> usize {
-        self.children.len() + if self.parent.is_some() { 1 } else { 0 }
+    /// Returns the indices of the nodes this node's agent infected.
+    fn children(&self) -> impl Iterator<Item = usize> + '_ {
+        self.edges
+            .iter()
+            .filter(|edge| edge.attributed)
+            .map(|edge| edge.to)
     }
 }
 
@@ -244,9 +1506,366 @@ impl fmt::Display for ContactNode {
             "ContactNode{}[label=\"Agent {}\"];",
             self.index, self.agent_id
         )?;
-        for child in self.children.iter() {
-            write!(f, "ContactNode{} -> ContactNode{};", self.index, child)?;
+        for edge in self.edges.iter() {
+            write!(
+                f,
+                "ContactNode{} -> ContactNode{}[label=\"{}\",style={}];",
+                self.index,
+                edge.to,
+                edge.weight,
+                if edge.attributed { "solid" } else { "dashed" }
+            )?;
         }
         Ok(())
     }
 }
+
+/// Edge is a contact from an infectious agent to another infection.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Edge {
+    /// to is the index of the node of the infection that was contacted
+    to: usize,
+    /// weight is the cumulative exposure over every contact
+    weight: f64,
+    /// attributed is whether the contact is the one the infection is
+    /// attributed to
+    attributed: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the graph of the chain 0 -> 1 -> 2 -> 3, with 1 also infecting
+    /// 4 and 0 also infecting 5.
+    fn chain_graph() -> ContactGraph {
+        let mut graph = ContactGraph::new();
+        for (agent_id, parent, time) in [
+            (0, None, 0),
+            (1, Some(0), 10),
+            (2, Some(1), 20),
+            (3, Some(2), 30),
+            (4, Some(1), 40),
+            (5, Some(0), 50),
+        ] {
+            assert_eq!(graph.add_node(agent_id, parent, time, 0), Ok(true));
+        }
+        graph
+    }
+
+    #[test]
+    fn tracing_finds_exactly_the_agents_at_each_depth() {
+        let graph = chain_graph();
+
+        assert_eq!(graph.trace(1, 0, false), Vec::<usize>::new());
+        assert_eq!(graph.trace(1, 1, false), vec![2, 4]);
+        assert_eq!(graph.trace(1, 2, false), vec![2, 4, 3]);
+        assert_eq!(graph.trace(1, 5, false), vec![2, 4, 3]);
+        assert_eq!(graph.trace(1, 1, true), vec![2, 4, 0]);
+        assert_eq!(graph.trace(1, 2, true), vec![2, 4, 0, 3, 5]);
+        assert_eq!(graph.trace(3, 3, true), vec![2, 1, 4, 0]);
+        assert_eq!(graph.trace(9, 3, true), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn reproduction_numbers_average_the_children_in_each_window() {
+        let graph = chain_graph();
+
+        assert_eq!(
+            graph.reproduction_number_over_time(20),
+            vec![(0, 2.0), (20, 0.5), (40, 0.0)]
+        );
+        assert_eq!(
+            graph.reproduction_number_over_time(100),
+            vec![(0, 5.0 / 6.0)]
+        );
+        assert_eq!(graph.reproduction_number_over_time(0), Vec::new());
+        assert_eq!(graph.secondary_case_distribution(), vec![3, 1, 2]);
+        assert_eq!(
+            ContactGraph::new().secondary_case_distribution(),
+            Vec::<usize>::new()
+        );
+    }
+
+    /// Returns the graph of the chain 7 -> 3 -> 9.
+    fn three_chain() -> ContactGraph {
+        let mut graph = ContactGraph::new();
+        graph.add_node(7, None, 0, 0).unwrap();
+        graph.add_node(3, Some(7), 100, 0).unwrap();
+        graph.add_node(9, Some(3), 250, 0).unwrap();
+        graph
+    }
+
+    #[test]
+    fn graphml_names_nodes_by_agent_id() {
+        let mut graphml = Vec::new();
+        three_chain().to_graphml(&mut graphml).unwrap();
+        let graphml = String::from_utf8(graphml).unwrap();
+
+        for fragment in [
+            r#"<graph id="ContactGraph" edgedefault="directed">"#,
+            r#"<node id="7">"#,
+            r#"<node id="3">"#,
+            r#"<node id="9">"#,
+            r#"<data key="time">250</data>"#,
+            r#"<edge source="7" target="3">"#,
+            r#"<edge source="3" target="9">"#,
+        ] {
+            assert!(graphml.contains(fragment), "missing {}", fragment);
+        }
+        assert_eq!(graphml.matches("<node ").count(), 3);
+        assert_eq!(graphml.matches("<edge ").count(), 2);
+        assert!(graphml.trim_end().ends_with("</graphml>"));
+    }
+
+    #[test]
+    fn json_parses_back_into_the_chain() {
+        let mut json = Vec::new();
+        three_chain().to_json(&mut json).unwrap();
+        let json = serde_json::from_slice::<serde_json::Value>(&json).unwrap();
+
+        let nodes = json["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[1]["id"], "3");
+        assert_eq!(nodes[1]["agent_id"], 3);
+        assert_eq!(nodes[1]["time"], 100);
+        let edges = json["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|edge| {
+                assert_eq!(edge["attributed"], true);
+                (
+                    edge["source"].as_str().unwrap(),
+                    edge["target"].as_str().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(edges, vec![("7", "3"), ("3", "9")]);
+    }
+
+    #[test]
+    fn queries_translate_between_nodes_and_agents() {
+        let graph = chain_graph();
+
+        assert_eq!(graph.children_of(1), Some(&[2, 4][..]));
+        assert_eq!(graph.children_of(3), Some(&[][..]));
+        assert_eq!(graph.children_of(8), None);
+        assert_eq!(graph.parent_of(2), Some(1));
+        assert_eq!(graph.parent_of(0), None);
+        assert_eq!(graph.parent_of(8), None);
+        assert_eq!(graph.chain_depth(0), Some(0));
+        assert_eq!(graph.chain_depth(3), Some(3));
+        assert_eq!(graph.chain_depth(5), Some(1));
+        assert_eq!(graph.chain_depth(8), None);
+        assert_eq!(graph.max_chain_depth(), 3);
+        assert_eq!(graph.roots(), vec![0]);
+        assert_eq!(graph.subtree_size(0), 6);
+        assert_eq!(graph.subtree_size(1), 4);
+        assert_eq!(graph.subtree_size(3), 1);
+        assert_eq!(graph.subtree_size(8), 0);
+        assert_eq!(ContactGraph::default().max_chain_depth(), 0);
+    }
+
+    fn aged(years: i64) -> Agent {
+        Agent::new_with_age(
+            Vec2D::new_zero(),
+            MovementProfile::walking(),
+            years * SECONDS_PER_YEAR,
+        )
+    }
+
+    #[test]
+    fn annual_background_death_probabilities_follow_the_table() {
+        for (years, annual) in [
+            (10, 0.001),
+            (30, 0.002),
+            (60, 0.006),
+            (90, 0.15),
+            (105, 0.35),
+        ] {
+            let prob = aged(years).background_death_probability(SECONDS_PER_YEAR);
+            assert!((prob - annual).abs() < 1e-9, "{} at {}", prob, years);
+        }
+    }
+
+    #[test]
+    fn background_death_probabilities_stay_probabilities_for_long_steps() {
+        for years in (0..=130).step_by(5) {
+            let agent = aged(years);
+            for step_size in [1, 3600, 86400, 30 * 86400, SECONDS_PER_YEAR] {
+                let prob = agent.background_death_probability(step_size);
+                assert!((0.0..=1.0).contains(&prob), "{} at {}", prob, years);
+            }
+        }
+    }
+
+    #[test]
+    fn generated_populations_average_the_configured_age() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let bounds = Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(50.0, 20.0));
+        let mut rng = StdRng::seed_from_u64(47);
+        for pyramid in [
+            AgePyramid::realistic(),
+            AgePyramid::new(vec![(20.0, 40.0, 1.0)]).unwrap(),
+        ] {
+            let agents = generate_population(100_000, &pyramid, bounds, &mut rng);
+            let mean = agents
+                .iter()
+                .map(|agent| agent.age as f64 / SECONDS_PER_YEAR as f64)
+                .sum::<f64>()
+                / agents.len() as f64;
+
+            assert!(
+                (mean - pyramid.mean()).abs() < 0.5,
+                "{} vs {}",
+                mean,
+                pyramid.mean()
+            );
+            assert!(agents.iter().all(|agent| bounds.contains(agent.pos)));
+        }
+    }
+
+    #[test]
+    fn repeated_exposures_add_up_on_parallel_edges() {
+        let mut graph = ContactGraph::new();
+        for (agent_id, parent) in [(0, None), (1, None), (2, Some(0))] {
+            assert_eq!(graph.add_node(agent_id, parent, 0, 0), Ok(true));
+        }
+
+        assert!(graph.add_exposure(0, 2, 0.5));
+        assert!(graph.add_exposure(0, 2, 0.5));
+        assert!(graph.add_exposure(1, 2, 0.25));
+        assert!(!graph.add_exposure(2, 2, 1.0));
+        assert!(!graph.add_exposure(3, 2, 1.0));
+
+        assert_eq!(
+            graph.potential_infectors(2),
+            vec![(0, 1.0, true), (1, 0.25, false)]
+        );
+        assert_eq!(graph.exposure_weight(1, 0), None);
+        assert_eq!(graph.edge_count(), 2);
+        assert_eq!(graph.get_average_degree(), 4.0 / 3.0);
+        assert_eq!(graph.get_average_weighted_degree(), 2.5 / 3.0);
+        // only the attributed edge is a transmission
+        assert_eq!(graph.children_of(1), Some(&[][..]));
+
+        let dot = graph.to_string();
+        assert!(dot.contains("ContactNode0 -> ContactNode2[label=\"1\",style=solid];"));
+        assert!(dot.contains("ContactNode1 -> ContactNode2[label=\"0.25\",style=dashed];"));
+    }
+
+    #[test]
+    fn invalid_speeds_are_rejected() {
+        for speed in [f64::NAN, f64::INFINITY, -1.0] {
+            for profile in [
+                MovementProfile::from_units_per_second(speed),
+                MovementProfile::from_units_per_hour(speed),
+                MovementProfile::commuting(speed),
+            ] {
+                let rejected = match profile {
+                    Err(MovementError::InvalidSpeed(rejected)) => rejected,
+                    Ok(_) => panic!("speed {} was accepted", speed),
+                };
+                assert_eq!(rejected.to_bits(), speed.to_bits());
+            }
+        }
+
+        assert_eq!(
+            MovementProfile::from_units_per_hour(7.2).map(|profile| profile.speed_per_sec()),
+            Ok(0.002)
+        );
+        assert_eq!(
+            MovementProfile::from_units_per_second(0.0).map(|profile| profile.speed_per_sec()),
+            Ok(0.0)
+        );
+    }
+
+    #[test]
+    fn immunity_halves_every_half_life() {
+        use crate::disease::{DurationDistribution, IfrTable};
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let disease = BasicDisease {
+            immunity_half_life_secs: Some(10 * 86400),
+            ..BasicDisease::new(
+                0.1,
+                DurationDistribution::Fixed(86400),
+                DurationDistribution::Fixed(86400),
+                IfrTable::flat(0.0),
+            )
+        };
+        let mut rng = StdRng::seed_from_u64(90);
+        let mut agent = aged(30);
+        agent.boost_immunity(0.8);
+
+        let mut now = 0;
+        let mut step = |agent: &mut Agent, steps: usize, step_size: i64| {
+            for _ in 0..steps {
+                agent.progress(now, step_size, &disease, &mut rng);
+                now += step_size;
+            }
+        };
+
+        step(&mut agent, 10, 86400);
+        assert!((agent.immunity - 0.4).abs() < 1e-12);
+        // the same time in smaller steps decays by the same amount
+        step(&mut agent, 240, 3600);
+        assert!((agent.immunity - 0.2).abs() < 1e-12);
+        step(&mut agent, 5, 86400);
+        assert!((agent.immunity - 0.2 * 0.5f64.sqrt()).abs() < 1e-12);
+
+        // boosts only ever raise immunity, and cap it at 1
+        agent.boost_immunity(0.1);
+        assert!((agent.immunity - 0.2 * 0.5f64.sqrt()).abs() < 1e-12);
+        agent.boost_immunity(1.5);
+        assert_eq!(agent.immunity, 1.0);
+    }
+
+    #[test]
+    fn every_status_renders_three_columns() {
+        const DAY: i64 = 86400;
+        let now = 500 * DAY;
+        let cell = |status: Status, asymptomatic: bool, vaccinated: bool| {
+            let mut agent = Agent::new(Vec2D::new(0.0, 0.0), MovementProfile::walking());
+            agent.status = status;
+            agent.asymptomatic = asymptomatic;
+            agent.vaccinated = vaccinated;
+            agent.render_cell(now).iter().collect::<String>()
+        };
+
+        for (since, days) in [
+            (now, "0 "),
+            (now - DAY + 1, "0 "),
+            (now - DAY, "1 "),
+            (now - 9 * DAY, "9 "),
+            (now - 10 * DAY, "10"),
+            (now - 99 * DAY, "99"),
+            (now - 100 * DAY, "+ "),
+            (0, "+ "),
+            (i64::MIN / 2, "+ "),
+            (now + 1, "? "),
+            (i64::MAX / 2, "? "),
+        ] {
+            for (status, letter) in [
+                (Status::Exposed { since }, 'E'),
+                (Status::Infectious { since }, 'I'),
+                (Status::Hospitalized { since }, 'H'),
+            ] {
+                assert_eq!(cell(status, false, false), format!("{}{}", letter, days));
+            }
+            assert_eq!(
+                cell(Status::Infectious { since }, true, false),
+                format!("A{}", days)
+            );
+            assert_eq!(cell(Status::Recovered { since }, false, false), " R ");
+        }
+        assert_eq!(cell(Status::Susceptible, false, false), " S ");
+        assert_eq!(cell(Status::Susceptible, false, true), " V ");
+        assert_eq!(cell(Status::Dead, false, false), " D ");
+    }
+}
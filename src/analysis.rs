@@ -0,0 +1,482 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::agent::{ContactGraph, Setting, SettingTag};
+use crate::{StructureType, World};
+
+/// SECONDS_PER_DAY is the length of the days infections are binned into.
+const SECONDS_PER_DAY: i64 = 86400;
+
+/// GenerationIntervals is the distribution of the time between the infection
+/// of an agent and the infection of the agent that infected it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GenerationIntervals {
+    /// intervals holds every generation interval in seconds, in the order the
+    /// infections were recorded.
+    pub intervals: Vec<i64>,
+    /// mean is the mean interval in seconds, or NaN if there are none.
+    pub mean: f64,
+    /// bin_secs is the width of each bin of the histogram, in seconds.
+    pub bin_secs: i64,
+    /// histogram holds the number of intervals in each bin, where the bin at
+    /// index i covers intervals from i * bin_secs up to (i + 1) * bin_secs.
+    pub histogram: Vec<usize>,
+}
+
+impl GenerationIntervals {
+    /// Collects the generation intervals of every infection in the graph with
+    /// a known infector. Panics if bin_secs isn't positive.
+    pub fn from_graph(graph: &ContactGraph, bin_secs: i64) -> Self {
+        assert!(bin_secs > 0, "invalid bin width {}", bin_secs);
+
+        let intervals = graph.generation_intervals();
+        let mean = intervals.iter().sum::<i64>() as f64 / intervals.len() as f64;
+
+        let mut histogram = Vec::new();
+        for interval in intervals.iter() {
+            let bin = (*interval).max(0) / bin_secs;
+            if histogram.len() <= bin as usize {
+                histogram.resize(bin as usize + 1, 0);
+            }
+            histogram[bin as usize] += 1;
+        }
+
+        Self {
+            intervals,
+            mean,
+            bin_secs,
+            histogram,
+        }
+    }
+
+    /// Writes the histogram as CSV with a header row followed by one row per
+    /// bin, labelled with the start of the bin in seconds.
+    pub fn write_csv<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "bin_start_secs,count")?;
+        for (i, count) in self.histogram.iter().enumerate() {
+            writeln!(w, "{},{}", i as i64 * self.bin_secs, count)?;
+        }
+
+        w.flush()
+    }
+}
+
+/// DailyIncidence is the number of new infections on each day of a run.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DailyIncidence {
+    /// infections holds the number of infections on each day, where the day
+    /// at index i is the one starting i days after the run began. It ends on
+    /// the day of the last infection.
+    pub infections: Vec<usize>,
+}
+
+impl DailyIncidence {
+    /// Bins every infection in the graph, including index cases and
+    /// reinfections, by the day it happened on.
+    pub fn from_graph(graph: &ContactGraph) -> Self {
+        let mut infections = Vec::new();
+        for time in graph.infection_times() {
+            let day = time.max(0) / SECONDS_PER_DAY;
+            if infections.len() <= day as usize {
+                infections.resize(day as usize + 1, 0);
+            }
+            infections[day as usize] += 1;
+        }
+
+        Self { infections }
+    }
+
+    /// Writes the incidence as CSV with a header row followed by one row per
+    /// day.
+    pub fn write_csv<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "day,infections")?;
+        for (day, infections) in self.infections.iter().enumerate() {
+            writeln!(w, "{},{}", day, infections)?;
+        }
+
+        w.flush()
+    }
+}
+
+/// DoublingTime is the exponential growth of the daily incidence over a window
+/// of days, found by fitting a line to the logarithm of the incidence.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DoublingTime {
+    /// start_day and end_day bound the window fitted, with end_day excluded.
+    pub start_day: usize,
+    pub end_day: usize,
+    /// growth_rate is the exponential growth rate of the incidence per day.
+    pub growth_rate: f64,
+    /// doubling_days is the number of days the incidence takes to double. It
+    /// is negative if the incidence is shrinking, and infinite if it is flat.
+    pub doubling_days: f64,
+}
+
+impl DoublingTime {
+    /// Fits the growth of the incidence over window_days days starting from
+    /// start_day, which should be during the growth phase of the epidemic.
+    /// Days without infections are left out of the fit since their logarithm
+    /// is undefined. Returns None if fewer than two days of the window had
+    /// infections.
+    pub fn fit(incidence: &DailyIncidence, start_day: usize, window_days: usize) -> Option<Self> {
+        let end_day = start_day
+            .saturating_add(window_days)
+            .min(incidence.infections.len());
+        let points = (start_day..end_day)
+            .filter(|day| incidence.infections[*day] > 0)
+            .map(|day| (day as f64, (incidence.infections[day] as f64).ln()))
+            .collect::<Vec<_>>();
+        if points.len() < 2 {
+            return None;
+        }
+
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let covariance = points
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum::<f64>();
+        let variance = points
+            .iter()
+            .map(|(x, _)| (x - mean_x).powi(2))
+            .sum::<f64>();
+        let growth_rate = covariance / variance;
+
+        Some(Self {
+            start_day,
+            end_day,
+            growth_rate,
+            doubling_days: std::f64::consts::LN_2 / growth_rate,
+        })
+    }
+
+    /// Writes the fit as CSV with a header row followed by a single row.
+    pub fn write_csv<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "start_day,end_day,growth_rate,doubling_days")?;
+        writeln!(
+            w,
+            "{},{},{},{}",
+            self.start_day, self.end_day, self.growth_rate, self.doubling_days
+        )?;
+
+        w.flush()
+    }
+}
+
+/// FinalSize is how much of the population was infected over a run.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FinalSize {
+    /// infected is the number of distinct agents that were ever infected.
+    pub infected: usize,
+    pub population: usize,
+    /// attack_rate is the fraction of the population that was ever infected,
+    /// or 0 for an empty population.
+    pub attack_rate: f64,
+}
+
+impl FinalSize {
+    /// Counts the agents in the graph out of the given population, such as
+    /// the total of the last stats of a run. Reinfections count once.
+    pub fn from_graph(graph: &ContactGraph, population: usize) -> Self {
+        let infected = graph.infected_agent_count();
+        let attack_rate = if population == 0 {
+            0.0
+        } else {
+            infected as f64 / population as f64
+        };
+
+        Self {
+            infected,
+            population,
+            attack_rate,
+        }
+    }
+
+    /// Writes the final size as CSV with a header row followed by a single
+    /// row.
+    pub fn write_csv<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "infected,population,attack_rate")?;
+        writeln!(
+            w,
+            "{},{},{}",
+            self.infected, self.population, self.attack_rate
+        )?;
+
+        w.flush()
+    }
+}
+
+/// Counts the transmissions in the contact graph of the world by the kind of
+/// place they happened in. Every tag is included, even without any
+/// transmissions. Index cases and infections without a recorded setting
+/// aren't counted.
+pub fn infections_by_setting<R: Rng>(world: &World<R>) -> HashMap<SettingTag, usize> {
+    let mut counts = SettingTag::ALL
+        .iter()
+        .map(|tag| (*tag, 0))
+        .collect::<HashMap<_, _>>();
+    for setting in world.contacts.settings() {
+        *counts.entry(setting.tag()).or_insert(0) += 1;
+    }
+
+    counts
+}
+
+/// StructureTransmissions is the number of transmissions attributed to a
+/// single structure.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StructureTransmissions {
+    pub id: usize,
+    pub typ: StructureType,
+    pub transmissions: usize,
+}
+
+/// Returns every structure that transmissions were attributed to, from the
+/// most transmissions to the fewest, with ties in order of id.
+pub fn transmissions_by_structure<R: Rng>(world: &World<R>) -> Vec<StructureTransmissions> {
+    let mut counts = HashMap::<(usize, StructureType), usize>::new();
+    for setting in world.contacts.settings() {
+        if let Setting::Structure { id, typ } = setting {
+            *counts.entry((id, typ)).or_insert(0) += 1;
+        }
+    }
+
+    let mut leaderboard = counts
+        .into_iter()
+        .map(|((id, typ), transmissions)| StructureTransmissions {
+            id,
+            typ,
+            transmissions,
+        })
+        .collect::<Vec<_>>();
+    leaderboard.sort_by_key(|entry| (std::cmp::Reverse(entry.transmissions), entry.id));
+    leaderboard
+}
+
+/// Returns the distance from home to the workplace, or the school for agents
+/// without a workplace, of every living agent that has both, in order of
+/// agent id.
+pub fn commute_distances<R: Rng>(world: &World<R>) -> Vec<f64> {
+    world
+        .agents
+        .iter()
+        .filter(|agent| !agent.status.is_dead() && !agent.home.is_nan())
+        .filter_map(|agent| {
+            let dest = if agent.work.is_nan() {
+                agent.school
+            } else {
+                agent.work
+            };
+            (!dest.is_nan()).then(|| agent.home.dist(dest))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{Agent, MovementProfile, Status};
+    use crate::builder::WorldBuilder;
+    use crate::disease::{BasicDisease, DurationDistribution, IfrTable, InfectionKernel};
+    use crate::geometry::Vec2D;
+    use crate::{BoundaryMode, Structure};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Returns a world of the agents that infects every agent in contact with
+    /// an infectious one, with a single school at the given position and a
+    /// home a few units to its left.
+    fn attribution_world(agents: Vec<Agent>, school: Vec2D<f64>) -> (World<StdRng>, usize) {
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(100.0, 100.0),
+            agents,
+            StdRng::seed_from_u64(93),
+        )
+        .unwrap();
+        world.disease = BasicDisease::new(
+            1.0,
+            DurationDistribution::Fixed(3600),
+            DurationDistribution::Fixed(86400),
+            IfrTable::flat(0.0),
+        );
+        world.contact_radius = 0.6;
+        world.infection_kernel = InfectionKernel::Step;
+        world.background_mortality = false;
+        world.step_size = 3600;
+        let school_id = world.add_structure(Structure::new(StructureType::School, school, 100));
+        world.add_structure(Structure::new(
+            StructureType::Home,
+            school - Vec2D::new(3.0, 0.0),
+            10,
+        ));
+        (world, school_id)
+    }
+
+    /// Returns an agent that is infectious for the rest of the test.
+    fn infector(pos: Vec2D<f64>) -> Agent {
+        let mut agent = Agent::new(pos, MovementProfile::walking());
+        agent.status = Status::Infectious { since: 0 };
+        agent.infectious_period = i64::MAX / 2;
+        agent
+    }
+
+    /// Returns a graph of an index case at time 0 that infects agent 1 ten
+    /// hours later and agent 2 a day later, with agent 1 infecting agent 3 a
+    /// day after its own infection, and agent 4 infected by an agent outside
+    /// the graph.
+    fn known_graph() -> ContactGraph {
+        let mut graph = ContactGraph::new();
+        for (agent_id, parent, time) in [
+            (0, None, 0),
+            (1, Some(0), 36000),
+            (2, Some(0), 86400),
+            (3, Some(1), 122400),
+            (4, Some(9), 200000),
+        ] {
+            graph.add_node(agent_id, parent, time, 0).unwrap();
+        }
+        graph
+    }
+
+    #[test]
+    fn generation_intervals_are_binned_exactly() {
+        let intervals = GenerationIntervals::from_graph(&known_graph(), 43200);
+
+        assert_eq!(intervals.intervals, vec![36000, 86400, 86400]);
+        assert_eq!(intervals.mean, 69600.0);
+        assert_eq!(intervals.histogram, vec![1, 0, 2]);
+
+        let mut csv = Vec::new();
+        intervals.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "bin_start_secs,count\n0,1\n43200,0\n86400,2\n"
+        );
+    }
+
+    #[test]
+    fn infections_are_counted_by_day() {
+        let graph = known_graph();
+
+        let incidence = DailyIncidence::from_graph(&graph);
+        assert_eq!(incidence.infections, vec![2, 2, 1]);
+
+        let final_size = FinalSize::from_graph(&graph, 10);
+        assert_eq!(final_size.infected, 5);
+        assert_eq!(final_size.attack_rate, 0.5);
+        assert_eq!(FinalSize::from_graph(&graph, 0).attack_rate, 0.0);
+    }
+
+    #[test]
+    fn doubling_times_fit_exponential_growth() {
+        let incidence = DailyIncidence {
+            infections: vec![5, 1, 2, 0, 8, 16, 3],
+        };
+
+        // the empty day is left out, and the rest of the window doubles daily
+        let fit = DoublingTime::fit(&incidence, 1, 5).unwrap();
+        assert_eq!((fit.start_day, fit.end_day), (1, 6));
+        assert!((fit.growth_rate - std::f64::consts::LN_2).abs() < 1e-12);
+        assert!((fit.doubling_days - 1.0).abs() < 1e-12);
+
+        assert_eq!(DoublingTime::fit(&incidence, 3, 2), None);
+        assert_eq!(DoublingTime::fit(&incidence, 10, 5), None);
+    }
+
+    #[test]
+    fn infections_inside_a_school_are_attributed_to_it() {
+        // a chain of agents standing in the school, without anywhere to go
+        let mut agents = vec![infector(Vec2D::new(49.1, 50.0))];
+        agents.extend((1..9).map(|i| {
+            Agent::new(
+                Vec2D::new(49.1 + i as f64 * 0.225, 50.0),
+                MovementProfile::walking(),
+            )
+        }));
+        let (mut world, school_id) = attribution_world(agents, Vec2D::new(50.0, 50.0));
+        world.contacts.add_node(0, None, 0, 0).unwrap();
+
+        for _ in 0..48 {
+            world.step();
+        }
+
+        let transmissions = world.contacts.settings().count();
+        assert_eq!(transmissions, 8);
+        let by_setting = infections_by_setting(&world);
+        assert_eq!(by_setting.len(), SettingTag::ALL.len());
+        assert_eq!(by_setting[&SettingTag::School], transmissions);
+        assert_eq!(
+            transmissions_by_structure(&world),
+            vec![StructureTransmissions {
+                id: school_id,
+                typ: StructureType::School,
+                transmissions,
+            }]
+        );
+    }
+
+    #[test]
+    fn settings_are_found_across_the_joined_edges() {
+        // the home is the nearest structure in a straight line, but the
+        // school is just across the edge
+        let agents = vec![
+            infector(Vec2D::new(99.7, 50.0)),
+            Agent::new(Vec2D::new(0.2, 50.0), MovementProfile::walking()),
+        ];
+        let (mut world, school_id) = attribution_world(agents, Vec2D::new(0.5, 50.0));
+        world.add_structure(Structure::new(
+            StructureType::Home,
+            Vec2D::new(97.5, 50.0),
+            10,
+        ));
+        world.boundary_mode = BoundaryMode::Wrap;
+        world.contacts.add_node(0, None, 0, 0).unwrap();
+
+        world.step();
+
+        assert_eq!(
+            world.contacts.setting_of(1),
+            Some(Setting::Structure {
+                id: school_id,
+                typ: StructureType::School,
+            })
+        );
+    }
+
+    #[test]
+    fn commutes_shorten_as_beta_grows() {
+        let mean_commute = |beta: f64| {
+            let mut world: World<StdRng> = WorldBuilder::new()
+                .size(Vec2D::new(100.0, 100.0))
+                .random_agents(500)
+                .structures(StructureType::Home, 150)
+                .structures(StructureType::Work, 20)
+                .structures(StructureType::School, 8)
+                .seed(99)
+                .build()
+                .unwrap();
+            world.assign_structures_gravity(beta).unwrap();
+            let distances = commute_distances(&world);
+            // retirees have neither a workplace nor a school
+            assert!(distances.len() > 250);
+            distances.iter().sum::<f64>() / distances.len() as f64
+        };
+
+        let means = [0.0, 0.05, 0.5].map(mean_commute);
+        assert!(
+            means[0] > means[1] && means[1] > means[2],
+            "mean commutes of {:?}",
+            means
+        );
+        // uniformly random commutes across a 100 by 100 square average
+        // about 52
+        assert!((means[0] - 52.1).abs() < 5.0, "{}", means[0]);
+    }
+}
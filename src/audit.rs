@@ -0,0 +1,268 @@
+use rand::Rng;
+
+use crate::geometry::Vec2D;
+use crate::World;
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Cross-checks the state of the world, returning a description of every
+    /// violated invariant. Checks the internal consistency of the quadtree,
+    /// that the counts of active infections and deaths match a recount of the
+    /// agents, that every agent in the contact graph was once in the world,
+    /// whether it still is, was archived after dying, or was removed with its
+    /// history left behind, that the home, workplace, and school
+    /// of every agent are either NaN or within the world, and that a stats
+    /// entry has been recorded for every step.
+    pub fn audit(&self) -> Result<(), Vec<String>> {
+        let mut violations = Vec::new();
+        self.check_cheap_invariants(&mut violations);
+
+        if let Err(err) = self.agents.check_invariants() {
+            violations.push(format!("quadtree: {}", err));
+        }
+
+        let stats = self.current_stats();
+        let counters = [
+            ("exposed", self.currently_exposed, stats.exposed),
+            ("infectious", self.currently_infectious, stats.infectious),
+            ("dead", self.deaths, stats.dead),
+        ];
+        for (name, counted, recounted) in counters {
+            if counted != recounted {
+                violations.push(format!(
+                    "{} count is {} but a recount gives {}",
+                    name, counted, recounted
+                ));
+            }
+        }
+
+        let mut graph_ids = self.contacts.agent_ids().collect::<Vec<_>>();
+        graph_ids.sort_unstable();
+        // ids are handed out in order and never reused, so every agent that
+        // was ever in the world has an id below the next one
+        let next_agent_id = self.agents.next_agent_id();
+        for agent_id in graph_ids {
+            if agent_id >= next_agent_id {
+                violations.push(format!(
+                    "agent {} is in the contact graph but was never in the world",
+                    agent_id
+                ));
+            }
+        }
+
+        let within = |pos: Vec2D<f64>| {
+            pos.x >= 0.0 && pos.x <= self.size.x && pos.y >= 0.0 && pos.y <= self.size.y
+        };
+        for agent_id in self.agents.get_agent_ids() {
+            let agent = self.agents.get_agent(agent_id).unwrap();
+            let locations = [
+                ("home", agent.home),
+                ("work", agent.work),
+                ("school", agent.school),
+            ];
+            for (name, pos) in locations {
+                if !pos.is_nan() && !within(pos) {
+                    violations.push(format!(
+                        "agent {} has its {} at ({}, {}) outside the world",
+                        agent_id, name, pos.x, pos.y
+                    ));
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Runs the checks of audit that take constant time, which are cheap
+    /// enough to run after every step of a debug build.
+    #[cfg(any(debug_assertions, feature = "debug-checks"))]
+    pub(crate) fn audit_cheap(&self) -> Result<(), Vec<String>> {
+        let mut violations = Vec::new();
+        self.check_cheap_invariants(&mut violations);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    fn check_cheap_invariants(&self, violations: &mut Vec<String>) {
+        if self.history.recorded() as i64 != self.curr_step {
+            violations.push(format!(
+                "history recorded {} entries after {} steps",
+                self.history.recorded(),
+                self.curr_step
+            ));
+        }
+        if self.strain_history.recorded() as i64 != self.curr_step {
+            violations.push(format!(
+                "strain history recorded {} entries after {} steps",
+                self.strain_history.recorded(),
+                self.curr_step
+            ));
+        }
+
+        let population = self.agents.len() + self.deceased.len();
+        let active = self.currently_exposed + self.currently_infectious + self.deaths;
+        if active > population {
+            violations.push(format!(
+                "{} agents are counted as exposed, infectious, or dead out of {}",
+                active, population
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::agent::{Agent, MovementProfile};
+    use crate::geometry::Vec2D;
+    use crate::World;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Returns a world of 20 agents that has run for a few steps, with two
+    /// index cases.
+    fn stepped_world() -> World<StdRng> {
+        let agents = (0..20)
+            .map(|i| {
+                let pos = Vec2D::new((i % 5) as f64 + 0.5, (i / 5) as f64 + 0.5);
+                Agent::new(pos, MovementProfile::walking())
+            })
+            .collect();
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(10.0, 10.0),
+            agents,
+            StdRng::seed_from_u64(88),
+        )
+        .unwrap();
+        world.infect_random_agents(2);
+        for _ in 0..3 {
+            world.step();
+        }
+        world
+    }
+
+    #[test]
+    fn consistent_worlds_pass() {
+        assert_eq!(stepped_world().audit(), Ok(()));
+    }
+
+    #[test]
+    fn miscounted_infections_are_reported() {
+        let mut world = stepped_world();
+        let infectious = world.current_stats().infectious;
+        world.currently_infectious = infectious + 1;
+
+        assert_eq!(
+            world.audit(),
+            Err(vec![format!(
+                "infectious count is {} but a recount gives {}",
+                infectious + 1,
+                infectious
+            )])
+        );
+    }
+
+    #[cfg(feature = "debug-checks")]
+    #[test]
+    #[should_panic(expected = "dead count is 0 but a recount gives 1")]
+    fn steps_report_counts_that_drifted() {
+        let mut world = stepped_world();
+        let agent_id = world
+            .agents
+            .get_agent_ids()
+            .into_iter()
+            .find(|agent_id| {
+                world
+                    .agents
+                    .get_agent(*agent_id)
+                    .unwrap()
+                    .status
+                    .is_susceptible()
+            })
+            .unwrap();
+        // killing an agent directly isn't counted
+        world.agents.get_agent_mut(agent_id).unwrap().status = crate::agent::Status::Dead;
+
+        world.step();
+    }
+
+    #[cfg(any(debug_assertions, feature = "debug-checks"))]
+    #[test]
+    fn impossible_counts_are_reported_by_the_cheap_checks() {
+        let mut world = stepped_world();
+        world.currently_exposed = 100;
+        world.deaths = 0;
+
+        assert_eq!(
+            world.audit_cheap(),
+            Err(vec![format!(
+                "{} agents are counted as exposed, infectious, or dead out of 20",
+                100 + world.currently_infectious
+            )])
+        );
+    }
+
+    #[test]
+    fn missing_history_is_reported() {
+        let mut world = stepped_world();
+        world.curr_step += 1;
+
+        assert_eq!(
+            world.audit(),
+            Err(vec![
+                "history recorded 3 entries after 4 steps".to_string(),
+                "strain history recorded 3 entries after 4 steps".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn strangers_in_the_contact_graph_are_reported() {
+        let mut world = stepped_world();
+        world.contacts.add_node(25, None, 0, 0).unwrap();
+
+        assert_eq!(
+            world.audit(),
+            Err(vec![
+                "agent 25 is in the contact graph but was never in the world".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn locations_outside_the_world_are_reported() {
+        let mut world = stepped_world();
+        world.agents.get_agent_mut(3).unwrap().school = Vec2D::new(12.0, 4.5);
+
+        assert_eq!(
+            world.audit(),
+            Err(vec![
+                "agent 3 has its school at (12, 4.5) outside the world".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn agents_moved_behind_the_back_of_the_quadtree_are_reported() {
+        let mut world = stepped_world();
+        let pos = world.agents.get_agent(7).unwrap().pos;
+        let leaf_id = world.agents.get_node_for_pos(pos).unwrap();
+        world.agents.get_agent_mut(7).unwrap().pos = Vec2D::new(10.5, 5.0);
+
+        assert_eq!(
+            world.audit(),
+            Err(vec![format!(
+                "quadtree: item 7 is outside the bounds of its leaf {}",
+                leaf_id
+            )])
+        );
+    }
+}
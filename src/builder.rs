@@ -0,0 +1,448 @@
+use rand::distributions::Distribution;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::agent::{
+    AgePyramid, Agent, MovementError, MovementProfile, DEFAULT_SPEED_RANGE, SECONDS_PER_YEAR,
+};
+use crate::disease::{BasicDisease, InfectionKernel, TransmissionModel};
+use crate::geometry::Vec2D;
+use crate::quadtree::QuadtreeConfig;
+use crate::{Error, Schedule, StructureType, World};
+
+/// BuildError describes why a WorldBuilder could not build a world.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// The size of the world was never set, but something depends on it.
+    MissingSize,
+    /// The size of the world must be positive and finite in both dimensions.
+    InvalidSize(Vec2D<f64>),
+    /// The step size must be positive.
+    InvalidStepSize(i64),
+    /// The contact dispersion must be positive and finite.
+    InvalidContactDispersion(f64),
+    /// The speeds of generated agents must be finite and not negative.
+    InvalidSpeed(MovementError),
+    /// Index cases were requested, but there are no agents to infect.
+    IndexCasesWithoutAgents,
+    /// More index cases were requested than there are agents.
+    TooManyIndexCases { requested: usize, available: usize },
+    /// The world rejected the agents or structures.
+    World(Error),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::MissingSize => write!(f, "the size of the world was not set"),
+            BuildError::InvalidSize(size) => {
+                write!(f, "invalid world size {}x{}", size.x, size.y)
+            }
+            BuildError::InvalidStepSize(step_size) => {
+                write!(f, "invalid step size {}", step_size)
+            }
+            BuildError::InvalidContactDispersion(k) => {
+                write!(f, "invalid contact dispersion {}", k)
+            }
+            BuildError::InvalidSpeed(err) => write!(f, "{}", err),
+            BuildError::IndexCasesWithoutAgents => {
+                write!(f, "index cases were requested for a world without agents")
+            }
+            BuildError::TooManyIndexCases {
+                requested,
+                available,
+            } => write!(
+                f,
+                "{} index cases were requested but there are only {} agents",
+                requested, available
+            ),
+            BuildError::World(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// How the agents of the world are created.
+#[derive(Debug, Clone)]
+enum AgentSource {
+    Given(Vec<Agent>),
+    /// The given number of agents placed uniformly at random.
+    Random(usize),
+    /// An agent on each integer grid point with the given probability.
+    Grid(f64),
+}
+
+/// WorldBuilder configures a world, then places structures, assigns them, and
+/// seeds the index cases in one validated step.
+#[derive(Debug, Clone)]
+pub struct WorldBuilder {
+    size: Option<Vec2D<f64>>,
+    agents: AgentSource,
+    speed_range: (f64, f64),
+    ages: AgePyramid,
+    step_size: i64,
+    structures: HashMap<StructureType, (usize, i64)>,
+    seed: Option<u64>,
+    index_cases: usize,
+    quadtree_config: QuadtreeConfig,
+    contact_radius: Option<f64>,
+    infection_kernel: Option<InfectionKernel>,
+    transmission_model: Option<TransmissionModel>,
+    disease: Option<BasicDisease>,
+    schedule: Option<Schedule>,
+    contact_dispersion: Option<f64>,
+}
+
+impl WorldBuilder {
+    pub fn new() -> Self {
+        Self {
+            size: None,
+            agents: AgentSource::Given(Vec::new()),
+            speed_range: DEFAULT_SPEED_RANGE,
+            ages: AgePyramid::uniform(90.0),
+            step_size: 1,
+            structures: HashMap::new(),
+            seed: None,
+            index_cases: 0,
+            quadtree_config: QuadtreeConfig::default(),
+            contact_radius: None,
+            infection_kernel: None,
+            transmission_model: None,
+            disease: None,
+            schedule: None,
+            contact_dispersion: None,
+        }
+    }
+
+    pub fn size(mut self, size: Vec2D<f64>) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Uses the given agents, replacing any agents requested before.
+    pub fn agents(mut self, agents: Vec<Agent>) -> Self {
+        self.agents = AgentSource::Given(agents);
+        self
+    }
+
+    /// Creates the given number of agents at uniformly random positions.
+    /// Replaces any agents requested before.
+    pub fn random_agents(mut self, count: usize) -> Self {
+        self.agents = AgentSource::Random(count);
+        self
+    }
+
+    /// Creates an agent at each integer grid point of the world with the given
+    /// probability. Replaces any agents requested before.
+    pub fn grid_agents(mut self, density: f64) -> Self {
+        self.agents = AgentSource::Grid(density.clamp(0.0, 1.0));
+        self
+    }
+
+    /// Sets the range of speeds, in units per second, that generated agents
+    /// are given. Speeds that aren't finite or are negative are rejected when
+    /// the world is built.
+    pub fn agent_speed(mut self, min: f64, max: f64) -> Self {
+        self.speed_range = if min <= max { (min, max) } else { (max, min) };
+        self
+    }
+
+    /// Sets the distribution that the ages of generated agents are drawn from.
+    /// Defaults to uniformly random ages up to 90 years.
+    pub fn age_distribution(mut self, ages: AgePyramid) -> Self {
+        self.ages = ages;
+        self
+    }
+
+    pub fn step_size(mut self, step_size: i64) -> Self {
+        self.step_size = step_size;
+        self
+    }
+
+    /// Places the given number of structures of a type, without any limit on
+    /// their capacity.
+    pub fn structures(self, typ: StructureType, count: usize) -> Self {
+        self.structures_with_capacity(typ, count, 0)
+    }
+
+    pub fn structures_with_capacity(
+        mut self,
+        typ: StructureType,
+        count: usize,
+        capacity: i64,
+    ) -> Self {
+        self.structures.insert(typ, (count, capacity));
+        self
+    }
+
+    /// Seeds the RNG of the world so that it is reproducible. Without a seed,
+    /// the RNG is seeded from the operating system.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn index_cases(mut self, index_cases: usize) -> Self {
+        self.index_cases = index_cases;
+        self
+    }
+
+    pub fn quadtree_config(mut self, config: QuadtreeConfig) -> Self {
+        self.quadtree_config = config;
+        self
+    }
+
+    pub fn contact_radius(mut self, contact_radius: f64) -> Self {
+        self.contact_radius = Some(contact_radius);
+        self
+    }
+
+    pub fn infection_kernel(mut self, infection_kernel: InfectionKernel) -> Self {
+        self.infection_kernel = Some(infection_kernel);
+        self
+    }
+
+    pub fn transmission_model(mut self, transmission_model: TransmissionModel) -> Self {
+        self.transmission_model = Some(transmission_model);
+        self
+    }
+
+    pub fn disease(mut self, disease: BasicDisease) -> Self {
+        self.disease = Some(disease);
+        self
+    }
+
+    pub fn schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    /// Draws the contact multiplier of every agent from a gamma distribution
+    /// with a mean of 1 and the dispersion parameter k, so that a few agents
+    /// cause most infections. See World::contact_dispersion.
+    pub fn contact_dispersion(mut self, k: f64) -> Self {
+        self.contact_dispersion = Some(k);
+        self
+    }
+
+    /// Validates the configuration and builds the world, placing and assigning
+    /// structures and then infecting the index cases.
+    pub fn build(self) -> Result<World<StdRng>, BuildError> {
+        let size = self.size.ok_or(BuildError::MissingSize)?;
+        if !(size.x > 0.0 && size.y > 0.0 && size.x.is_finite() && size.y.is_finite()) {
+            return Err(BuildError::InvalidSize(size));
+        }
+        if self.step_size <= 0 {
+            return Err(BuildError::InvalidStepSize(self.step_size));
+        }
+        if let Some(k) = self.contact_dispersion {
+            if !(k > 0.0 && k.is_finite()) {
+                return Err(BuildError::InvalidContactDispersion(k));
+            }
+        }
+        for speed in [self.speed_range.0, self.speed_range.1] {
+            MovementProfile::from_units_per_second(speed).map_err(BuildError::InvalidSpeed)?;
+        }
+
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        let agents = match self.agents {
+            AgentSource::Given(agents) => agents,
+            AgentSource::Random(count) => (0..count)
+                .map(|_| {
+                    let pos = Vec2D::new(rng.gen_range(0.0..size.x), rng.gen_range(0.0..size.y));
+                    Self::random_agent(pos, self.speed_range, &self.ages, &mut rng)
+                })
+                .collect(),
+            AgentSource::Grid(density) => {
+                let mut agents = Vec::new();
+                for i in 0..size.x.ceil() as i64 {
+                    for j in 0..size.y.ceil() as i64 {
+                        if rng.gen_bool(density) {
+                            let pos = Vec2D::new(i as f64, j as f64);
+                            agents.push(Self::random_agent(
+                                pos,
+                                self.speed_range,
+                                &self.ages,
+                                &mut rng,
+                            ));
+                        }
+                    }
+                }
+                agents
+            }
+        };
+
+        if self.index_cases > 0 && agents.is_empty() {
+            return Err(BuildError::IndexCasesWithoutAgents);
+        }
+        if self.index_cases > agents.len() {
+            return Err(BuildError::TooManyIndexCases {
+                requested: self.index_cases,
+                available: agents.len(),
+            });
+        }
+
+        let mut world = World::new_with_quadtree_config(size, agents, self.quadtree_config, rng)
+            .map_err(BuildError::World)?;
+        world.step_size = self.step_size;
+        if let Some(contact_radius) = self.contact_radius {
+            world.contact_radius = contact_radius;
+        }
+        if let Some(infection_kernel) = self.infection_kernel {
+            world.infection_kernel = infection_kernel;
+        }
+        if let Some(transmission_model) = self.transmission_model {
+            world.transmission_model = transmission_model;
+        }
+        if let Some(disease) = self.disease {
+            world.disease = disease;
+        }
+        if let Some(schedule) = self.schedule {
+            world.schedule = schedule;
+        }
+        if self.contact_dispersion.is_some() {
+            world.contact_dispersion = self.contact_dispersion;
+            world.draw_contact_multipliers();
+        }
+
+        if !self.structures.is_empty() {
+            world
+                .place_structures(self.structures)
+                .map_err(BuildError::World)?;
+            // worlds without homes are allowed, such as a single classroom
+            match world.assign_structures() {
+                Ok(()) | Err(Error::NoStructuresOfType(_)) => (),
+                Err(err) => return Err(BuildError::World(err)),
+            }
+        }
+
+        world.infect_random_agents(self.index_cases);
+
+        Ok(world)
+    }
+
+    fn random_agent(
+        pos: Vec2D<f64>,
+        speed_range: (f64, f64),
+        ages: &AgePyramid,
+        rng: &mut StdRng,
+    ) -> Agent {
+        let movement =
+            MovementProfile::uniform(speed_range, rng).expect("the speed range was validated");
+        let age_years = ages.sample(rng);
+        Agent::new_with_age(pos, movement, (age_years * SECONDS_PER_YEAR as f64) as i64)
+    }
+}
+
+impl Default for WorldBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sized() -> WorldBuilder {
+        WorldBuilder::new().size(Vec2D::new(10.0, 10.0))
+    }
+
+    #[test]
+    fn a_world_needs_a_size() {
+        assert_eq!(
+            WorldBuilder::new().random_agents(5).build().unwrap_err(),
+            BuildError::MissingSize
+        );
+    }
+
+    #[test]
+    fn sizes_must_be_positive_and_finite() {
+        for size in [
+            Vec2D::new(0.0, 10.0),
+            Vec2D::new(10.0, -1.0),
+            Vec2D::new(f64::INFINITY, 10.0),
+        ] {
+            assert_eq!(
+                WorldBuilder::new().size(size).build().unwrap_err(),
+                BuildError::InvalidSize(size)
+            );
+        }
+        let err = WorldBuilder::new()
+            .size(Vec2D::new(f64::NAN, 10.0))
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, BuildError::InvalidSize(size) if size.x.is_nan()));
+    }
+
+    #[test]
+    fn step_sizes_must_be_positive() {
+        for step_size in [0, -60] {
+            assert_eq!(
+                sized().step_size(step_size).build().unwrap_err(),
+                BuildError::InvalidStepSize(step_size)
+            );
+        }
+    }
+
+    #[test]
+    fn contact_dispersion_must_be_positive_and_finite() {
+        for k in [0.0, -0.5, f64::INFINITY] {
+            assert_eq!(
+                sized().contact_dispersion(k).build().unwrap_err(),
+                BuildError::InvalidContactDispersion(k)
+            );
+        }
+    }
+
+    #[test]
+    fn speeds_must_be_finite_and_not_negative() {
+        assert_eq!(
+            sized().agent_speed(-1.0, 1.0).build().unwrap_err(),
+            BuildError::InvalidSpeed(MovementError::InvalidSpeed(-1.0))
+        );
+        assert_eq!(
+            sized().agent_speed(0.5, f64::INFINITY).build().unwrap_err(),
+            BuildError::InvalidSpeed(MovementError::InvalidSpeed(f64::INFINITY))
+        );
+    }
+
+    #[test]
+    fn index_cases_need_agents() {
+        assert_eq!(
+            sized().index_cases(1).build().unwrap_err(),
+            BuildError::IndexCasesWithoutAgents
+        );
+    }
+
+    #[test]
+    fn index_cases_cannot_outnumber_the_agents() {
+        assert_eq!(
+            sized().random_agents(3).index_cases(4).build().unwrap_err(),
+            BuildError::TooManyIndexCases {
+                requested: 4,
+                available: 3,
+            }
+        );
+        assert!(sized().random_agents(3).index_cases(3).build().is_ok());
+    }
+
+    #[test]
+    fn errors_from_the_world_are_passed_on() {
+        let outside = Agent::new(Vec2D::new(12.0, 5.0), MovementProfile::walking());
+        assert_eq!(
+            sized().agents(vec![outside]).build().unwrap_err(),
+            BuildError::World(Error::AgentOutOfBounds {
+                id: 0,
+                pos: Vec2D::new(12.0, 5.0),
+            })
+        );
+    }
+}
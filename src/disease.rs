@@ -1,6 +1,408 @@
+use rand::distributions::Distribution;
+use rand::Rng;
+
 pub trait Disease {
-    fn will_infect(&self) -> bool;
-    fn mutate(&self) -> Self
+    /// Rolls for whether a single contact results in transmission.
+    fn will_infect<R: Rng>(&self, rng: &mut R) -> bool
     where
         Self: Sized;
+    /// Creates a new variant of the disease, which may differ from the
+    /// original in any of its parameters.
+    fn mutate<R: Rng>(&self, rng: &mut R) -> Self
+    where
+        Self: Sized;
+    /// Draws the length of time in seconds that an agent is exposed before
+    /// becoming infectious.
+    fn sample_incubation_period<R: Rng>(&self, rng: &mut R) -> i64
+    where
+        Self: Sized;
+    /// Draws the length of time in seconds that an agent is infectious before
+    /// recovering.
+    fn sample_infectious_period<R: Rng>(&self, rng: &mut R) -> i64
+    where
+        Self: Sized;
+    /// Probability that an infection of an agent of the given age in years
+    /// ends in its death, spread over its infectious period.
+    fn infection_fatality_ratio(&self, age_years: f64) -> f64;
+    /// Length of time in seconds that recovered agents are immune for, or
+    /// `None` if immunity never wanes.
+    fn immunity_period(&self) -> Option<i64>;
+    /// Partial immunity, from 0 to 1, that agents are given on recovery, which
+    /// keeps protecting them after their immunity period has ended.
+    fn recovery_immunity(&self) -> f64;
+    /// Half-life in seconds of the partial immunity of agents, or `None` if
+    /// it never decays.
+    fn immunity_half_life(&self) -> Option<i64>;
+    /// Probability that an agent shows no symptoms while infectious.
+    fn asymptomatic_prob(&self) -> f64;
+    /// Transmissibility of asymptomatic agents relative to symptomatic ones.
+    fn asymptomatic_transmissibility(&self) -> f64;
+}
+
+/// BasicDisease is a disease with fixed parameters that do not change over the
+/// course of the simulation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BasicDisease {
+    /// transmission_prob is the probability that a contact results in
+    /// exposure, before any distance weighting is applied.
+    pub transmission_prob: f64,
+    /// incubation is the distribution of the length of the exposed period.
+    pub incubation: DurationDistribution,
+    /// infectious is the distribution of the length of the infectious period.
+    pub infectious: DurationDistribution,
+    /// ifr is the infection fatality ratio of agents by age.
+    pub ifr: IfrTable,
+    /// immunity_secs is how long recovered agents stay immune, or `None` if
+    /// they are immune forever.
+    pub immunity_secs: Option<i64>,
+    /// recovery_immunity is the partial immunity recovered agents are given,
+    /// which reduces their chance of being infected again and the mortality
+    /// of the infection if they are. 0 by default.
+    pub recovery_immunity: f64,
+    /// immunity_half_life_secs is how long partial immunity, from recovery or
+    /// vaccination, takes to halve, or `None` if it never decays.
+    pub immunity_half_life_secs: Option<i64>,
+    /// asymptomatic_prob is the probability that an agent leaving incubation
+    /// never shows symptoms.
+    pub asymptomatic_prob: f64,
+    /// asymptomatic_transmissibility scales the probability of transmission
+    /// from asymptomatic agents.
+    pub asymptomatic_transmissibility: f64,
+}
+
+impl BasicDisease {
+    pub fn new(
+        transmission_prob: f64,
+        incubation: DurationDistribution,
+        infectious: DurationDistribution,
+        ifr: IfrTable,
+    ) -> Self {
+        Self {
+            transmission_prob,
+            incubation,
+            infectious,
+            ifr,
+            immunity_secs: None,
+            recovery_immunity: 0.0,
+            immunity_half_life_secs: None,
+            asymptomatic_prob: 0.0,
+            asymptomatic_transmissibility: 0.5,
+        }
+    }
+}
+
+impl Default for BasicDisease {
+    /// The default disease always transmits, is incubated for 21 days, is
+    /// infectious for 28 days, kills 0.1% of the agents it infects at any
+    /// age, confers permanent immunity, and is always symptomatic.
+    fn default() -> Self {
+        Self::new(
+            1.0,
+            DurationDistribution::Fixed(21 * 86400),
+            DurationDistribution::Fixed(28 * 86400),
+            IfrTable::flat(0.001),
+        )
+    }
+}
+
+impl Disease for BasicDisease {
+    fn will_infect<R: Rng>(&self, rng: &mut R) -> bool {
+        rng.gen_bool(self.transmission_prob.clamp(0.0, 1.0))
+    }
+
+    /// Variants randomly become up to a quarter more or less transmissible
+    /// and deadly, independently of each other.
+    fn mutate<R: Rng>(&self, rng: &mut R) -> Self {
+        Self {
+            transmission_prob: (self.transmission_prob * rng.gen_range(0.8..1.25)).clamp(0.0, 1.0),
+            ifr: self.ifr.scaled(rng.gen_range(0.8..1.25)),
+            ..*self
+        }
+    }
+
+    fn sample_incubation_period<R: Rng>(&self, rng: &mut R) -> i64 {
+        self.incubation.sample(rng)
+    }
+
+    fn sample_infectious_period<R: Rng>(&self, rng: &mut R) -> i64 {
+        self.infectious.sample(rng)
+    }
+
+    fn infection_fatality_ratio(&self, age_years: f64) -> f64 {
+        self.ifr.at(age_years)
+    }
+
+    fn immunity_period(&self) -> Option<i64> {
+        self.immunity_secs
+    }
+
+    fn recovery_immunity(&self) -> f64 {
+        self.recovery_immunity
+    }
+
+    fn immunity_half_life(&self) -> Option<i64> {
+        self.immunity_half_life_secs
+    }
+
+    fn asymptomatic_prob(&self) -> f64 {
+        self.asymptomatic_prob
+    }
+
+    fn asymptomatic_transmissibility(&self) -> f64 {
+        self.asymptomatic_transmissibility
+    }
+}
+
+/// IfrTable is the infection fatality ratio by age, the probability that an
+/// infection ends in death, in bands of a decade.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IfrTable {
+    /// by_decade holds the ratio for ages 0 to 9, 10 to 19, and so on, with
+    /// the last band covering everyone 80 and over.
+    pub by_decade: [f64; 9],
+}
+
+impl IfrTable {
+    /// Creates a table with the same ratio at every age.
+    pub fn flat(ifr: f64) -> Self {
+        Self {
+            by_decade: [ifr; 9],
+        }
+    }
+
+    /// Creates a table that roughly follows estimates for COVID-19 before
+    /// vaccination, rising from about 0.002% in children to about 15% in
+    /// those 80 and over.
+    pub fn realistic() -> Self {
+        Self {
+            by_decade: [
+                0.00002, 0.00005, 0.0002, 0.0005, 0.0015, 0.005, 0.015, 0.05, 0.15,
+            ],
+        }
+    }
+
+    /// Returns the ratio at the given age in years, clamped to 0..=1.
+    pub fn at(&self, age_years: f64) -> f64 {
+        let band = (age_years.max(0.0) / 10.0) as usize;
+        self.by_decade[band.min(self.by_decade.len() - 1)].clamp(0.0, 1.0)
+    }
+
+    /// Returns the table with every ratio multiplied by factor, clamped to
+    /// 0..=1.
+    pub fn scaled(&self, factor: f64) -> Self {
+        Self {
+            by_decade: self.by_decade.map(|ifr| (ifr * factor).clamp(0.0, 1.0)),
+        }
+    }
+}
+
+/// DurationDistribution describes how the length of a stage of infection is
+/// drawn for each agent. All durations are in seconds.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DurationDistribution {
+    /// Every agent gets exactly the same duration.
+    Fixed(i64),
+    /// Durations are drawn uniformly from the inclusive range [min, max].
+    Uniform(i64, i64),
+    /// Durations are drawn from a log-normal distribution with the given mean
+    /// and standard deviation of the duration itself, not of its logarithm.
+    LogNormal { mean: f64, std_dev: f64 },
+}
+
+impl DurationDistribution {
+    /// Draws a single duration from the distribution. Never negative.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> i64 {
+        match *self {
+            DurationDistribution::Fixed(t) => t.max(0),
+            DurationDistribution::Uniform(min, max) => {
+                if min >= max {
+                    min.max(0)
+                } else {
+                    rng.gen_range(min..=max).max(0)
+                }
+            }
+            DurationDistribution::LogNormal { mean, std_dev } => {
+                if mean <= 0.0 {
+                    return 0;
+                }
+
+                // convert the mean and standard deviation of the duration into
+                // the parameters of the underlying normal distribution
+                let sigma_sq = (1.0 + (std_dev / mean).powi(2)).ln();
+                let mu = mean.ln() - sigma_sq / 2.0;
+
+                (mu + sigma_sq.sqrt() * standard_normal(rng)).exp().round() as i64
+            }
+        }
+    }
+
+    /// Returns the expected duration of the distribution.
+    pub fn mean(&self) -> f64 {
+        match *self {
+            DurationDistribution::Fixed(t) => t as f64,
+            DurationDistribution::Uniform(min, max) => (min + max) as f64 / 2.0,
+            DurationDistribution::LogNormal { mean, .. } => mean,
+        }
+    }
+}
+
+/// Draws a sample from the standard normal distribution using the Box-Muller
+/// transform.
+fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Gamma is the gamma distribution with the given shape and scale, which has
+/// a mean of shape * scale.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Gamma {
+    shape: f64,
+    scale: f64,
+}
+
+impl Gamma {
+    /// Returns None unless the shape and scale are positive and finite.
+    pub fn new(shape: f64, scale: f64) -> Option<Self> {
+        let valid = |x: f64| x > 0.0 && x.is_finite();
+        (valid(shape) && valid(scale)).then_some(Self { shape, scale })
+    }
+
+    /// Creates the gamma distribution with a mean of 1 and the given shape,
+    /// also known as the dispersion parameter k. The smaller k is, the more
+    /// the samples are spread out, with a variance of 1 / k.
+    pub fn with_unit_mean(k: f64) -> Option<Self> {
+        Self::new(k, 1.0 / k)
+    }
+}
+
+impl Distribution<f64> for Gamma {
+    /// Samples with the method of Marsaglia and Tsang, boosting the shape by
+    /// one for shapes below one.
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        if self.shape < 1.0 {
+            let boosted = Gamma {
+                shape: self.shape + 1.0,
+                scale: self.scale,
+            };
+            let u: f64 = 1.0 - rng.gen::<f64>();
+            return boosted.sample(rng) * u.powf(1.0 / self.shape);
+        }
+
+        let d = self.shape - 1.0 / 3.0;
+        let c = 1.0 / (9.0 * d).sqrt();
+        loop {
+            let z = standard_normal(rng);
+            let v = (1.0 + c * z).powi(3);
+            if v <= 0.0 {
+                continue;
+            }
+            let u: f64 = 1.0 - rng.gen::<f64>();
+            if u.ln() < 0.5 * z * z + d - d * v + d * v.ln() {
+                return d * v * self.scale;
+            }
+        }
+    }
+}
+
+/// TransmissionModel is how infectious agents within the contact radius of a
+/// susceptible agent combine into the chance of it being exposed during a
+/// step.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransmissionModel {
+    /// Each nearby infectious agent independently exposes the agent with a
+    /// probability per step given by the kernel and the transmission
+    /// probability of its strain, whatever the length of the step.
+    #[default]
+    PerContactBernoulli,
+    /// The hazards of the nearby infectious agents are summed and the agent is
+    /// exposed with probability 1 - exp(-hazard * step length), so results
+    /// converge as the step size shrinks. Each infectious agent contributes
+    /// hourly_rate times the kernel, the transmission probability of its
+    /// strain, and the infectiousness and susceptibility of the pair, and is
+    /// credited with the exposure in proportion to its contribution.
+    ForceOfInfection { hourly_rate: f64 },
+}
+
+/// InfectionKernel describes how the probability of infection decays with the
+/// distance between an infectious agent and a susceptible one.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InfectionKernel {
+    /// Every agent within the contact radius is infected.
+    Step,
+    /// Probability falls off linearly from 1 at a distance of zero to 0 at the
+    /// contact radius.
+    Linear,
+    /// Probability falls off exponentially with the given length scale, cut
+    /// off at the contact radius.
+    Exponential(f64),
+}
+
+impl InfectionKernel {
+    /// Returns the probability of infection for two agents a given distance
+    /// apart. Always in the range [0, 1], and 0 outside of the radius.
+    pub fn weight(&self, dist: f64, radius: f64) -> f64 {
+        if dist > radius {
+            return 0.0;
+        }
+
+        match self {
+            InfectionKernel::Step => 1.0,
+            InfectionKernel::Linear => (1.0 - dist / radius).clamp(0.0, 1.0),
+            InfectionKernel::Exponential(scale) => (-dist / scale).exp().clamp(0.0, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{Agent, MovementProfile};
+    use crate::geometry::Vec2D;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn drawn_durations_match_the_configured_means() {
+        let disease = BasicDisease::new(
+            1.0,
+            DurationDistribution::Uniform(86400, 9 * 86400),
+            DurationDistribution::LogNormal {
+                mean: 7.0 * 86400.0,
+                std_dev: 3.0 * 86400.0,
+            },
+            IfrTable::flat(0.0),
+        );
+        let mut rng = StdRng::seed_from_u64(5);
+        let count = 20_000;
+
+        let (mut incubation, mut infectious) = (0.0, 0.0);
+        for _ in 0..count {
+            let mut agent = Agent::new(Vec2D::new_zero(), MovementProfile::walking());
+            agent.expose(0, &disease, &mut rng);
+            incubation += agent.incubation_period as f64;
+            infectious += agent.infectious_period as f64;
+        }
+        incubation /= count as f64;
+        infectious /= count as f64;
+
+        let within = |mean: f64, expected: f64| (mean / expected - 1.0).abs() < 0.02;
+        assert!(
+            within(incubation, disease.incubation.mean()),
+            "{}",
+            incubation
+        );
+        assert!(
+            within(infectious, disease.infectious.mean()),
+            "{}",
+            infectious
+        );
+    }
 }
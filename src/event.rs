@@ -0,0 +1,84 @@
+use std::io::{self, Write};
+
+use crate::agent::StatusChange;
+use crate::retention::CsvRow;
+
+/// WorldEvent is a change in the status of an agent, recorded by the world as
+/// it happens. Times are absolute times in seconds.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WorldEvent {
+    /// The agent was exposed by another agent, or seeded as an index case if
+    /// there is none.
+    Exposed {
+        agent: usize,
+        by: Option<usize>,
+        time: i64,
+    },
+    BecameInfectious {
+        agent: usize,
+        time: i64,
+    },
+    /// The agent became a severe case. It may not have found a free bed.
+    Hospitalized {
+        agent: usize,
+        time: i64,
+    },
+    Recovered {
+        agent: usize,
+        time: i64,
+    },
+    /// The immunity of the agent waned and it can be infected again.
+    BecameSusceptible {
+        agent: usize,
+        time: i64,
+    },
+    Died {
+        agent: usize,
+        time: i64,
+    },
+}
+
+impl WorldEvent {
+    /// Creates the event for a change in status made by an agent during its
+    /// step.
+    pub fn from_status_change(agent: usize, change: StatusChange, time: i64) -> Self {
+        match change {
+            StatusChange::BecameInfectious => WorldEvent::BecameInfectious { agent, time },
+            StatusChange::Recovered => WorldEvent::Recovered { agent, time },
+            StatusChange::BecameSusceptible => WorldEvent::BecameSusceptible { agent, time },
+            StatusChange::Died => WorldEvent::Died { agent, time },
+        }
+    }
+
+    /// Returns the id of the agent the event is about.
+    pub fn agent(&self) -> usize {
+        match *self {
+            WorldEvent::Exposed { agent, .. }
+            | WorldEvent::BecameInfectious { agent, .. }
+            | WorldEvent::Hospitalized { agent, .. }
+            | WorldEvent::Recovered { agent, .. }
+            | WorldEvent::BecameSusceptible { agent, .. }
+            | WorldEvent::Died { agent, .. } => agent,
+        }
+    }
+}
+
+impl CsvRow for WorldEvent {
+    const HEADER: &'static str = "time,agent,event,by";
+
+    /// Writes the event with its kind in lowercase, and the agent that caused
+    /// an exposure in the last column, which is empty otherwise.
+    fn write_row(&self, w: &mut dyn Write) -> io::Result<()> {
+        let (time, event, by) = match *self {
+            WorldEvent::Exposed { by, time, .. } => (time, "exposed", by),
+            WorldEvent::BecameInfectious { time, .. } => (time, "infectious", None),
+            WorldEvent::Hospitalized { time, .. } => (time, "hospitalized", None),
+            WorldEvent::Recovered { time, .. } => (time, "recovered", None),
+            WorldEvent::BecameSusceptible { time, .. } => (time, "susceptible", None),
+            WorldEvent::Died { time, .. } => (time, "died", None),
+        };
+        let by = by.map_or(String::new(), |by| by.to_string());
+        writeln!(w, "{},{},{},{}", time, self.agent(), event, by)
+    }
+}
@@ -0,0 +1,225 @@
+use std::io::{self, Write};
+
+use crate::builder::{BuildError, WorldBuilder};
+use crate::run::{RunSummary, StopCondition};
+
+/// Builds a world from the config for each seed and runs it until the stop
+/// condition is met, returning the summaries in the same order as the seeds.
+/// Any seed already set on the config is replaced. With the rayon feature the
+/// replicates run in parallel, which doesn't change their results.
+pub fn run_replicates(
+    config: WorldBuilder,
+    seeds: &[u64],
+    stop: StopCondition,
+) -> Result<Vec<RunSummary>, BuildError> {
+    let run = |seed: &u64| {
+        let mut world = config.clone().seed(*seed).build()?;
+        Ok(world.run(stop.clone()))
+    };
+
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        seeds.par_iter().map(run).collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        seeds.iter().map(run).collect()
+    }
+}
+
+/// The fraction of agents that were infected over the run. Agents that were
+/// reinfected count once per infection.
+pub fn attack_rate(run: &RunSummary) -> f64 {
+    if run.total_agents == 0 {
+        return 0.0;
+    }
+    run.total_infections as f64 / run.total_agents as f64
+}
+
+/// The largest fraction of agents infectious at once during the run.
+pub fn peak_prevalence(run: &RunSummary) -> f64 {
+    if run.total_agents == 0 {
+        return 0.0;
+    }
+    run.peak_infectious as f64 / run.total_agents as f64
+}
+
+/// SampleStats summarizes a single measure across replicates.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SampleStats {
+    pub mean: f64,
+    pub median: f64,
+    /// p05 and p95 are the 5th and 95th percentiles.
+    pub p05: f64,
+    pub p95: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl SampleStats {
+    /// Summarizes the values, ignoring any that are NaN. Returns None if no
+    /// values are left.
+    pub fn from_values(values: impl IntoIterator<Item = f64>) -> Option<Self> {
+        let mut values = values
+            .into_iter()
+            .filter(|value| !value.is_nan())
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+
+        Some(Self {
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            median: quantile(&values, 0.5),
+            p05: quantile(&values, 0.05),
+            p95: quantile(&values, 0.95),
+            min: values[0],
+            max: values[values.len() - 1],
+        })
+    }
+}
+
+/// Returns the q-th quantile of the sorted values, interpolating linearly
+/// between the closest ranks. The slice must not be empty.
+pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let rank = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+/// ExperimentSummary aggregates the outcomes of a set of replicates.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExperimentSummary {
+    pub replicates: usize,
+    pub attack_rate: SampleStats,
+    pub peak_prevalence: SampleStats,
+    /// peak_time is the absolute time of the peak, in seconds.
+    pub peak_time: SampleStats,
+    pub deaths: SampleStats,
+}
+
+impl ExperimentSummary {
+    /// Aggregates the runs. Returns None if there are no runs.
+    pub fn from_runs(runs: &[RunSummary]) -> Option<Self> {
+        Some(Self {
+            replicates: runs.len(),
+            attack_rate: SampleStats::from_values(runs.iter().map(attack_rate))?,
+            peak_prevalence: SampleStats::from_values(runs.iter().map(peak_prevalence))?,
+            peak_time: SampleStats::from_values(runs.iter().map(|run| run.peak_time as f64))?,
+            deaths: SampleStats::from_values(runs.iter().map(|run| run.total_deaths as f64))?,
+        })
+    }
+}
+
+/// Writes one CSV row per replicate, labelled with its seed, followed by a
+/// row labelled "mean" with the mean of each column. The runs must be in the
+/// same order as the seeds.
+pub fn write_csv<W: Write>(seeds: &[u64], runs: &[RunSummary], mut w: W) -> io::Result<()> {
+    writeln!(
+        w,
+        "seed,steps,final_time,attack_rate,peak_prevalence,peak_time,deaths,wall_time_ms"
+    )?;
+
+    for (seed, run) in seeds.iter().zip(runs) {
+        writeln!(
+            w,
+            "{},{},{},{},{},{},{},{}",
+            seed,
+            run.steps,
+            run.final_time,
+            attack_rate(run),
+            peak_prevalence(run),
+            run.peak_time,
+            run.total_deaths,
+            run.wall_time.as_millis(),
+        )?;
+    }
+
+    if !runs.is_empty() {
+        let mean =
+            |value: fn(&RunSummary) -> f64| runs.iter().map(value).sum::<f64>() / runs.len() as f64;
+        writeln!(
+            w,
+            "mean,{},{},{},{},{},{},{}",
+            mean(|run| run.steps as f64),
+            mean(|run| run.final_time as f64),
+            mean(attack_rate),
+            mean(peak_prevalence),
+            mean(|run| run.peak_time as f64),
+            mean(|run| run.total_deaths as f64),
+            mean(|run| run.wall_time.as_millis() as f64),
+        )?;
+    }
+
+    w.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disease::{BasicDisease, DurationDistribution, IfrTable};
+    use crate::geometry::Vec2D;
+    use crate::StructureType;
+
+    fn config() -> WorldBuilder {
+        WorldBuilder::new()
+            .size(Vec2D::new(30.0, 30.0))
+            .random_agents(300)
+            .structures(StructureType::Home, 60)
+            .structures(StructureType::Work, 5)
+            .step_size(3600)
+            .contact_radius(2.0)
+            .disease(BasicDisease::new(
+                0.05,
+                DurationDistribution::Fixed(6 * 3600),
+                DurationDistribution::Fixed(2 * 86400),
+                IfrTable::flat(0.05),
+            ))
+            .index_cases(3)
+    }
+
+    /// Returns everything about the run but the wall time.
+    fn outcome(run: &RunSummary) -> (u64, i64, usize, i64, i64, usize) {
+        (
+            run.steps,
+            run.final_time,
+            run.peak_infectious,
+            run.peak_time,
+            run.total_infections,
+            run.total_deaths,
+        )
+    }
+
+    #[test]
+    fn replicates_differ_by_seed_and_repeat_exactly() {
+        let seeds = [1, 2];
+        let run = || {
+            run_replicates(config(), &seeds, StopCondition::Steps(72))
+                .unwrap()
+                .iter()
+                .map(outcome)
+                .collect::<Vec<_>>()
+        };
+
+        let first = run();
+        assert_eq!(first.len(), 2);
+        assert_ne!(first[0], first[1]);
+        assert_eq!(first, run());
+
+        let runs = run_replicates(config(), &seeds, StopCondition::Steps(72)).unwrap();
+        let mut csv = Vec::new();
+        write_csv(&seeds, &runs, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows = csv
+            .lines()
+            .map(|row| row.split(',').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(rows, ["seed", "1", "2", "mean"]);
+        assert_eq!(ExperimentSummary::from_runs(&runs).unwrap().replicates, 2);
+    }
+}
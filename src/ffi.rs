@@ -0,0 +1,243 @@
+//! A minimal C API for driving a world from other languages, such as Python
+//! through ctypes. Build it as a shared library with
+//! `cargo rustc --release --features ffi --crate-type cdylib`.
+//!
+//! Worlds are created with world_new and must be released with world_free.
+//! Every function accepts a null handle and catches panics rather than
+//! unwinding into the caller, reporting either as a failure.
+
+use rand::rngs::StdRng;
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::builder::WorldBuilder;
+use crate::geometry::Vec2D;
+use crate::World;
+
+/// WorldHandle is an opaque pointer to a world owned by the caller.
+pub struct WorldHandle {
+    world: World<StdRng>,
+}
+
+/// COUNTS_LEN is the number of counts written by world_get_counts.
+pub const COUNTS_LEN: usize = 5;
+
+/// Creates a world of the given size with agents placed uniformly at random,
+/// seeded so that runs are reproducible. Returns null if the world can't be
+/// built, such as for a size that isn't positive.
+#[no_mangle]
+pub extern "C" fn world_new(width: f64, height: f64, n_agents: u64, seed: u64) -> *mut WorldHandle {
+    let built = panic::catch_unwind(|| {
+        WorldBuilder::new()
+            .size(Vec2D::new(width, height))
+            .random_agents(n_agents as usize)
+            .seed(seed)
+            .build()
+    });
+
+    match built {
+        Ok(Ok(world)) => Box::into_raw(Box::new(WorldHandle { world })),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Infects up to count random susceptible agents as index cases. Returns the
+/// number infected, or 0 if the handle is null.
+///
+/// # Safety
+///
+/// The handle must be null or come from world_new and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn world_infect_random(handle: *mut WorldHandle, count: u64) -> u64 {
+    let handle = match handle.as_mut() {
+        Some(handle) => handle,
+        None => return 0,
+    };
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        handle.world.infect_random_agents(count as usize).len() as u64
+    }))
+    .unwrap_or(0)
+}
+
+/// Advances the world by n steps. Returns 0 on success, -1 if the handle is
+/// null, and -2 if the simulation panicked, after which the world should be
+/// freed rather than used.
+///
+/// # Safety
+///
+/// The handle must be null or come from world_new and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn world_step(handle: *mut WorldHandle, n: u64) -> c_int {
+    let handle = match handle.as_mut() {
+        Some(handle) => handle,
+        None => return -1,
+    };
+    let stepped = panic::catch_unwind(AssertUnwindSafe(|| {
+        for _ in 0..n {
+            handle.world.step();
+        }
+    }));
+
+    match stepped {
+        Ok(()) => 0,
+        Err(_) => -2,
+    }
+}
+
+/// Writes the number of susceptible, exposed, infectious, recovered, and dead
+/// agents, in that order, to out, stopping after len counts. Returns the
+/// number of counts written, which is 0 if the handle or out is null.
+///
+/// # Safety
+///
+/// The handle must be null or come from world_new and not have been freed,
+/// and out must be null or valid for writing len values.
+#[no_mangle]
+pub unsafe extern "C" fn world_get_counts(
+    handle: *const WorldHandle,
+    out: *mut u64,
+    len: usize,
+) -> usize {
+    let handle = match handle.as_ref() {
+        Some(handle) if !out.is_null() => handle,
+        _ => return 0,
+    };
+    let stats = match panic::catch_unwind(AssertUnwindSafe(|| handle.world.current_stats())) {
+        Ok(stats) => stats,
+        Err(_) => return 0,
+    };
+
+    let counts = [
+        stats.susceptible,
+        stats.exposed,
+        stats.infectious,
+        stats.recovered,
+        stats.dead,
+    ];
+    let written = len.min(COUNTS_LEN);
+    for (i, count) in counts.iter().take(written).enumerate() {
+        *out.add(i) = *count as u64;
+    }
+    written
+}
+
+/// Writes the positions of the agents as x, y pairs to out_xy in ascending
+/// order of agent id, stopping once cap values are written. Returns the total
+/// number of agents, so that a caller can size the buffer to twice that, or
+/// 0 if the handle is null. Nothing is written if out_xy is null.
+///
+/// # Safety
+///
+/// The handle must be null or come from world_new and not have been freed,
+/// and out_xy must be null or valid for writing cap values.
+#[no_mangle]
+pub unsafe extern "C" fn world_get_positions(
+    handle: *const WorldHandle,
+    out_xy: *mut f64,
+    cap: usize,
+) -> usize {
+    let handle = match handle.as_ref() {
+        Some(handle) => handle,
+        None => return 0,
+    };
+    let positions = match panic::catch_unwind(AssertUnwindSafe(|| {
+        let agents = &handle.world.agents;
+        agents
+            .get_agent_ids()
+            .into_iter()
+            .map(|id| agents.get_agent(id).unwrap().pos)
+            .collect::<Vec<_>>()
+    })) {
+        Ok(positions) => positions,
+        Err(_) => return 0,
+    };
+
+    if !out_xy.is_null() {
+        for (i, pos) in positions.iter().take(cap / 2).enumerate() {
+            *out_xy.add(2 * i) = pos.x;
+            *out_xy.add(2 * i + 1) = pos.y;
+        }
+    }
+    positions.len()
+}
+
+/// Frees the world. Null handles are ignored.
+///
+/// # Safety
+///
+/// The handle must be null or come from world_new and not have been freed
+/// already.
+#[no_mangle]
+pub unsafe extern "C" fn world_free(handle: *mut WorldHandle) {
+    if !handle.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(handle))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worlds_are_driven_through_the_c_api() {
+        unsafe {
+            let handle = world_new(20.0, 20.0, 100, 1);
+            assert!(!handle.is_null());
+            assert_eq!(world_infect_random(handle, 5), 5);
+
+            let mut counts = [u64::MAX; COUNTS_LEN];
+            assert_eq!(world_get_counts(handle, counts.as_mut_ptr(), COUNTS_LEN), 5);
+            assert_eq!(counts, [95, 5, 0, 0, 0]);
+
+            assert_eq!(world_step(handle, 24), 0);
+            assert_eq!(world_get_counts(handle, counts.as_mut_ptr(), COUNTS_LEN), 5);
+            assert_eq!(counts.iter().sum::<u64>(), 100);
+
+            // short buffers are only written up to their length
+            let mut short = [u64::MAX; COUNTS_LEN];
+            assert_eq!(world_get_counts(handle, short.as_mut_ptr(), 2), 2);
+            assert_eq!(short[..2], counts[..2]);
+            assert_eq!(short[2..], [u64::MAX; 3]);
+
+            assert_eq!(world_get_positions(handle, ptr::null_mut(), 0), 100);
+            let mut positions = vec![f64::NAN; 200];
+            assert_eq!(
+                world_get_positions(handle, positions.as_mut_ptr(), 200),
+                100
+            );
+            assert!(positions.iter().all(|coord| (0.0..=20.0).contains(coord)));
+            let mut partial = [f64::NAN; 5];
+            assert_eq!(world_get_positions(handle, partial.as_mut_ptr(), 5), 100);
+            assert_eq!(partial[..4], positions[..4]);
+            assert!(partial[4].is_nan());
+
+            world_free(handle);
+        }
+    }
+
+    #[test]
+    fn null_handles_and_invalid_worlds_are_failures() {
+        assert!(world_new(-1.0, 20.0, 10, 1).is_null());
+
+        unsafe {
+            let mut counts = [0; COUNTS_LEN];
+            let mut positions = [0.0; 2];
+            assert_eq!(world_infect_random(ptr::null_mut(), 1), 0);
+            assert_eq!(world_step(ptr::null_mut(), 1), -1);
+            assert_eq!(
+                world_get_counts(ptr::null(), counts.as_mut_ptr(), COUNTS_LEN),
+                0
+            );
+            assert_eq!(
+                world_get_positions(ptr::null(), positions.as_mut_ptr(), 2),
+                0
+            );
+            world_free(ptr::null_mut());
+
+            let handle = world_new(10.0, 10.0, 10, 2);
+            assert_eq!(world_get_counts(handle, ptr::null_mut(), COUNTS_LEN), 0);
+            world_free(handle);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use rand::Rng;
+use std::io::{self, Write};
+
+use crate::agent::SECONDS_PER_YEAR;
+use crate::geometry::Vec2D;
+use crate::World;
+
+/// METERS_PER_DEGREE is the length of a degree of latitude, and of longitude
+/// at the equator, on a spherical earth.
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// GeoTransform places a world on the map by putting its origin at a
+/// longitude and latitude, with x pointing east and y pointing north. The
+/// mapping is equirectangular around the origin, which is accurate enough for
+/// worlds up to a few tens of kilometers across.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GeoTransform {
+    /// origin_lon and origin_lat are the coordinates of the origin of the
+    /// world, in degrees.
+    pub origin_lon: f64,
+    pub origin_lat: f64,
+    /// meters_per_unit is the length of a unit of the world on the ground.
+    pub meters_per_unit: f64,
+}
+
+impl GeoTransform {
+    /// Returns the longitude and latitude of the position, in degrees.
+    pub fn apply(&self, pos: Vec2D<f64>) -> (f64, f64) {
+        let east = pos.x * self.meters_per_unit;
+        let north = pos.y * self.meters_per_unit;
+        (
+            self.origin_lon + east / (METERS_PER_DEGREE * self.origin_lat.to_radians().cos()),
+            self.origin_lat + north / METERS_PER_DEGREE,
+        )
+    }
+}
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Returns the living agents and the structures as a GeoJSON
+    /// FeatureCollection, see write_geojson.
+    pub fn to_geojson(&self, transform: Option<GeoTransform>) -> String {
+        let mut buf = Vec::new();
+        self.write_geojson(transform, &mut buf)
+            .expect("writing to a Vec doesn't fail");
+        String::from_utf8(buf).expect("GeoJSON is written as UTF-8")
+    }
+
+    /// Writes the living agents and the structures as a GeoJSON
+    /// FeatureCollection of points. Agents have the properties kind "agent",
+    /// id, status as its letter, age_years, and task, and structures have
+    /// kind "structure", id, type, capacity, and occupancy. Coordinates are
+    /// the positions in the world unless a transform places them on the map.
+    pub fn write_geojson<W: Write>(
+        &self,
+        transform: Option<GeoTransform>,
+        mut w: W,
+    ) -> io::Result<()> {
+        let coordinates = |pos: Vec2D<f64>| match transform {
+            Some(transform) => transform.apply(pos),
+            None => (pos.x, pos.y),
+        };
+
+        write!(w, r#"{{"type":"FeatureCollection","features":["#)?;
+        let mut first = true;
+        let mut write_feature = |w: &mut W, pos: Vec2D<f64>, properties: String| {
+            if !first {
+                write!(w, ",")?;
+            }
+            first = false;
+            let (x, y) = coordinates(pos);
+            write!(
+                w,
+                r#"{{"type":"Feature","geometry":{{"type":"Point","coordinates":[{},{}]}},"properties":{{{}}}}}"#,
+                x, y, properties
+            )
+        };
+
+        for agent_id in self.agents.get_agent_ids() {
+            let agent = self.agents.get_agent(agent_id).unwrap();
+            if agent.status.is_dead() {
+                continue;
+            }
+            let properties = format!(
+                r#""kind":"agent","id":{},"status":"{}","age_years":{},"task":"{:?}""#,
+                agent_id,
+                agent.status.as_char(),
+                agent.age as f64 / SECONDS_PER_YEAR as f64,
+                agent.task
+            );
+            write_feature(&mut w, agent.pos, properties)?;
+        }
+
+        for structure in self.structures.iter() {
+            let properties = format!(
+                r#""kind":"structure","id":{},"type":"{:?}","capacity":{},"occupancy":{}"#,
+                structure.id, structure.typ, structure.capacity, structure.occupancy
+            );
+            write_feature(&mut w, structure.pos, properties)?;
+        }
+
+        writeln!(w, "]}}")?;
+        w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Status;
+    use crate::builder::WorldBuilder;
+    use crate::StructureType;
+    use serde_json::Value;
+
+    #[test]
+    fn every_living_agent_and_structure_is_a_feature() {
+        let mut world = WorldBuilder::new()
+            .size(Vec2D::new(30.0, 30.0))
+            .random_agents(50)
+            .structures(StructureType::Home, 20)
+            .structures(StructureType::Work, 4)
+            .seed(1)
+            .build()
+            .unwrap();
+        world.agents.get_agent_mut(7).unwrap().status = Status::Dead;
+
+        let geojson: Value = serde_json::from_str(&world.to_geojson(None)).unwrap();
+        let features = geojson["features"].as_array().unwrap();
+        let count = |kind: &str| {
+            features
+                .iter()
+                .filter(|feature| feature["properties"]["kind"] == kind)
+                .count()
+        };
+
+        assert_eq!(geojson["type"], "FeatureCollection");
+        assert_eq!(features.len(), 49 + 24);
+        assert_eq!(count("agent"), 49);
+        assert_eq!(count("structure"), 24);
+        let first = &features[0];
+        let agent = world.agents.get_agent(0).unwrap();
+        assert_eq!(first["properties"]["id"], 0);
+        assert_eq!(first["geometry"]["coordinates"][0], agent.pos.x);
+        assert_eq!(first["geometry"]["coordinates"][1], agent.pos.y);
+    }
+
+    #[test]
+    fn transforms_place_the_origin_and_scale_to_meters() {
+        let transform = GeoTransform {
+            origin_lon: 10.0,
+            origin_lat: 0.0,
+            meters_per_unit: 2.0,
+        };
+
+        assert_eq!(transform.apply(Vec2D::new(0.0, 0.0)), (10.0, 0.0));
+        let (lon, lat) = transform.apply(Vec2D::new(METERS_PER_DEGREE / 2.0, METERS_PER_DEGREE));
+        assert!((lon - 11.0).abs() < 1e-9);
+        assert!((lat - 2.0).abs() < 1e-9);
+    }
+}
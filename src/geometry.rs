@@ -1,24 +1,21 @@
 use num;
 use std::ops::{Add, AddAssign, Div, Mul, Sub};
 
+/// Vec2D is a 2D vector. Most operations work for both integer and float
+/// components, while those involving lengths or NaN need floats. NaN
+/// components are serialized as missing, since formats like JSON can't
+/// represent NaN, and are read back as NaN.
 #[derive(Debug, Copy, Clone, PartialEq)]
-pub struct Vec2D<T: num::Float> {
+pub struct Vec2D<T> {
     pub x: T,
     pub y: T,
 }
 
-impl<T: num::Float> Vec2D<T> {
+impl<T: num::Num + Copy + PartialOrd> Vec2D<T> {
     pub fn new(x: T, y: T) -> Self {
         Self { x, y }
     }
 
-    pub fn new_nan() -> Self {
-        Self {
-            x: T::nan(),
-            y: T::nan(),
-        }
-    }
-
     pub fn new_zero() -> Self {
         Self {
             x: T::zero(),
@@ -45,6 +42,49 @@ impl<T: num::Float> Vec2D<T> {
         }
     }
 
+    pub fn dot(&self, other: &Self) -> T {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn is_in_bounds(&self, pos: Self, dim: Self) -> bool {
+        self.x >= pos.x && self.x <= pos.x + dim.x && self.y >= pos.y && self.y <= pos.y + dim.y
+    }
+
+    /// Finds which quadrant of the rectangular bounds the vector is in.
+    /// Quadrants are numbered by the following system:
+    /// +---+---+
+    /// | 0 | 1 |
+    /// +---+---+
+    /// | 2 | 3 |
+    /// +---+---+
+    /// (0,0) => 2
+    /// (0,1) => 0
+    /// (1,0) => 3
+    /// (1,1) => 1
+    pub fn get_bounds_quadrant(&self, pos: Self, dim: Self) -> usize {
+        let two = T::one() + T::one();
+        let x = if self.x < pos.x + dim.x / two { 0 } else { 1 };
+        let y = if self.y < pos.y + dim.y / two { 0 } else { 1 };
+        2 - 2 * y + x
+    }
+
+    /// intersects checks if there is any overlap between the two bounding
+    /// boxes, even if one is completely inside the other.
+    pub fn intersects(pos_1: Self, dim_1: Self, pos_2: Self, dim_2: Self) -> bool {
+        let x_overlap = (pos_1.x + dim_1.x > pos_2.x) && (pos_1.x < pos_2.x + dim_2.x);
+        let y_overlap = (pos_1.y + dim_1.y > pos_2.y) && (pos_1.y < pos_2.y + dim_2.y);
+        x_overlap && y_overlap
+    }
+}
+
+impl<T: num::Float> Vec2D<T> {
+    pub fn new_nan() -> Self {
+        Self {
+            x: T::nan(),
+            y: T::nan(),
+        }
+    }
+
     /// Returns whether any of the components are NaN
     pub fn is_nan(&self) -> bool {
         self.x.is_nan() || self.y.is_nan()
@@ -62,10 +102,6 @@ impl<T: num::Float> Vec2D<T> {
         self.div(self.mag())
     }
 
-    pub fn dot(&self, other: &Self) -> T {
-        self.x * other.x + self.y * other.y
-    }
-
     pub fn clamp_mag(&self, max: T) -> Self {
         if self.mag() > max {
             self.normalize() * max
@@ -74,46 +110,104 @@ impl<T: num::Float> Vec2D<T> {
         }
     }
 
-    pub fn is_in_bounds(&self, pos: Self, dim: Self) -> bool {
-        self.x >= pos.x && self.x <= pos.x + dim.x && self.y >= pos.y && self.y <= pos.y + dim.y
+    /// Returns the shortest displacement from this point to the other in a
+    /// world of the given size whose opposite edges are joined, so that
+    /// moving off one edge continues from the other.
+    pub fn toroidal_offset(&self, other: Self, world_size: Self) -> Self {
+        let wrap = |delta: T, size: T| {
+            let half = size / T::from(2.0).unwrap();
+            if delta > half {
+                delta - size
+            } else if delta < -half {
+                delta + size
+            } else {
+                delta
+            }
+        };
+        Self {
+            x: wrap(other.x - self.x, world_size.x),
+            y: wrap(other.y - self.y, world_size.y),
+        }
     }
 
-    /// Finds which quadrant of the rectangular bounds the vector is in.
-    /// Quadrants are numbered by the following system:
-    /// +---+---+
-    /// | 0 | 1 |
-    /// +---+---+
-    /// | 2 | 3 |
-    /// +---+---+
-    /// (0,0) => 2
-    /// (0,1) => 0
-    /// (1,0) => 3
-    /// (1,1) => 1
-    pub fn get_bounds_quadrant(&self, pos: Self, dim: Self) -> usize {
-        let x = if self.x < pos.x + dim.x / T::from(2.0).unwrap() {
-            0
-        } else {
-            1
-        };
-        let y = if self.y < pos.y + dim.y / T::from(2.0).unwrap() {
-            0
-        } else {
-            1
+    /// Returns the distance between the points in a world of the given size
+    /// whose opposite edges are joined.
+    pub fn toroidal_dist(&self, other: Self, world_size: Self) -> T {
+        self.toroidal_offset(other, world_size).mag()
+    }
+
+    /// Wraps the point into a world of the given size whose opposite edges
+    /// are joined.
+    pub fn wrap(&self, world_size: Self) -> Self {
+        let wrap = |value: T, size: T| {
+            let wrapped = value % size;
+            if wrapped < T::zero() {
+                wrapped + size
+            } else {
+                wrapped
+            }
         };
-        2 - 2 * y + x
+        Self {
+            x: wrap(self.x, world_size.x),
+            y: wrap(self.y, world_size.y),
+        }
     }
+}
 
-    /// intersects checks if there is any overlap between the two bounding
-    /// boxes, even if one is completely inside the other.
-    pub fn intersects(pos_1: Self, dim_1: Self, pos_2: Self, dim_2: Self) -> bool {
-        let x_overlap = (pos_1.x + dim_1.x > pos_2.x) && (pos_1.x < pos_2.x + dim_2.x);
-        let y_overlap = (pos_1.y + dim_1.y > pos_2.y) && (pos_1.y < pos_2.y + dim_2.y);
-        x_overlap && y_overlap
+impl Vec2D<f64> {
+    /// Rounds both components down to the nearest integer, saturating at the
+    /// limits of i64. NaN components become 0.
+    pub fn floor_to_i64(&self) -> Vec2D<i64> {
+        Vec2D::new(self.x.floor() as i64, self.y.floor() as i64)
+    }
+}
+
+impl Vec2D<i64> {
+    pub fn to_f64(&self) -> Vec2D<f64> {
+        Vec2D::new(self.x as f64, self.y as f64)
+    }
+}
+
+/// Components is how Vec2D is serialized, with NaN components left out.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "Vec2D")]
+struct Components<T> {
+    x: Option<T>,
+    y: Option<T>,
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize + num::NumCast + Copy> serde::Serialize for Vec2D<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let component =
+            |value: T| Some(value).filter(|value| !value.to_f64().is_some_and(f64::is_nan));
+        Components {
+            x: component(self.x),
+            y: component(self.y),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de> + num::NumCast> serde::Deserialize<'de> for Vec2D<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let components = Components::<T>::deserialize(deserializer)?;
+        let component = |value: Option<T>| {
+            value
+                .or_else(|| T::from(f64::NAN))
+                .ok_or_else(|| serde::de::Error::custom("integer vectors can't be NaN"))
+        };
+        Ok(Self {
+            x: component(components.x)?,
+            y: component(components.y)?,
+        })
     }
 }
 
 // Vector addition
-impl<T: Add<Output = T> + num::Float> Add for Vec2D<T> {
+impl<T: num::Num + Copy> Add for Vec2D<T> {
     type Output = Self;
 
     fn add(self, other: Self) -> Self::Output {
@@ -125,14 +219,14 @@ impl<T: Add<Output = T> + num::Float> Add for Vec2D<T> {
 }
 
 // Vector addition and assignment
-impl<T: Add<Output = T> + num::Float> AddAssign for Vec2D<T> {
+impl<T: num::Num + Copy> AddAssign for Vec2D<T> {
     fn add_assign(&mut self, other: Self) {
         *self = *self + other;
     }
 }
 
 // Vector subtraction
-impl<T: Sub<Output = T> + num::Float> Sub for Vec2D<T> {
+impl<T: num::Num + Copy> Sub for Vec2D<T> {
     type Output = Self;
 
     fn sub(self, other: Self) -> Self::Output {
@@ -144,7 +238,7 @@ impl<T: Sub<Output = T> + num::Float> Sub for Vec2D<T> {
 }
 
 // Vector minus scalar subtraction
-impl<T: Sub<Output = T> + num::Float> Sub<T> for Vec2D<T> {
+impl<T: num::Num + Copy> Sub<T> for Vec2D<T> {
     type Output = Self;
 
     fn sub(self, other: T) -> Self::Output {
@@ -156,7 +250,7 @@ impl<T: Sub<Output = T> + num::Float> Sub<T> for Vec2D<T> {
 }
 
 // Computes the Hadamard product of two vectors
-impl<T: Mul<Output = T> + num::Float> Mul for Vec2D<T> {
+impl<T: num::Num + Copy> Mul for Vec2D<T> {
     type Output = Self;
 
     fn mul(self, other: Self) -> Self::Output {
@@ -168,7 +262,7 @@ impl<T: Mul<Output = T> + num::Float> Mul for Vec2D<T> {
 }
 
 // Vector by scalar multiplication
-impl<T: Mul<Output = T> + num::Float> Mul<T> for Vec2D<T> {
+impl<T: num::Num + Copy> Mul<T> for Vec2D<T> {
     type Output = Self;
 
     fn mul(self, other: T) -> Self::Output {
@@ -180,7 +274,7 @@ impl<T: Mul<Output = T> + num::Float> Mul<T> for Vec2D<T> {
 }
 
 // Hadamard division of two vectors
-impl<T: Div<Output = T> + num::Float> Div for Vec2D<T> {
+impl<T: num::Num + Copy> Div for Vec2D<T> {
     type Output = Self;
 
     fn div(self, other: Self) -> Self::Output {
@@ -192,7 +286,7 @@ impl<T: Div<Output = T> + num::Float> Div for Vec2D<T> {
 }
 
 // Vector by scalar division
-impl<T: Div<Output = T> + num::Float> Div<T> for Vec2D<T> {
+impl<T: num::Num + Copy> Div<T> for Vec2D<T> {
     type Output = Self;
 
     fn div(self, other: T) -> Self::Output {
@@ -209,6 +303,7 @@ impl<T: Div<Output = T> + num::Float> Div<T> for Vec2D<T> {
 /// has the smallest x and y values and the second corner has the largest x and
 /// y values.
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rect<T: num::Float> {
     pub bl: Vec2D<T>,
     pub tr: Vec2D<T>,
@@ -256,6 +351,83 @@ impl<T: num::Float> Rect<T> {
             || other.bl.y > self.tr.y)
     }
 
+    /// Checks if there is overlap between this rectangle and a circle. Points
+    /// exactly on the edge of the circle count as overlapping.
+    pub fn intersects_circle(&self, center: Vec2D<T>, radius: T) -> bool {
+        self.distance_to_point(center) <= radius
+    }
+
+    /// Returns the point in the rectangle closest to the given point, which is
+    /// the point itself if it is inside.
+    pub fn clamp_point(&self, point: Vec2D<T>) -> Vec2D<T> {
+        Vec2D::new(
+            point.x.max(self.bl.x).min(self.tr.x),
+            point.y.max(self.bl.y).min(self.tr.y),
+        )
+    }
+
+    /// Returns the distance from the point to the closest point in the
+    /// rectangle, which is zero if the point is inside or on an edge.
+    pub fn distance_to_point(&self, point: Vec2D<T>) -> T {
+        self.clamp_point(point).dist(point)
+    }
+
+    pub fn area(&self) -> T {
+        self.get_width() * self.get_height()
+    }
+
+    /// Grows the rectangle outward by the amount on every side.
+    pub fn expand(&self, amount: T) -> Self {
+        let amount = Vec2D::new(amount, amount);
+        Self {
+            bl: self.bl - amount,
+            tr: self.tr + amount,
+        }
+    }
+
+    /// Shrinks the rectangle inward by the amount on every side. Sides that
+    /// would pass each other instead meet in the middle, leaving a rectangle
+    /// with no width or height.
+    pub fn shrink(&self, amount: T) -> Self {
+        let center = self.center();
+        let shrink_axis = |min: T, max: T, center: T| {
+            if max - min > amount + amount {
+                (min + amount, max - amount)
+            } else {
+                (center, center)
+            }
+        };
+        let (min_x, max_x) = shrink_axis(self.bl.x, self.tr.x, center.x);
+        let (min_y, max_y) = shrink_axis(self.bl.y, self.tr.y, center.y);
+
+        Self {
+            bl: Vec2D::new(min_x, min_y),
+            tr: Vec2D::new(max_x, max_y),
+        }
+    }
+
+    /// Returns the overlap of the two rectangles, if they overlap at all.
+    /// Rectangles that only share an edge overlap in a rectangle with no
+    /// width or height.
+    pub fn intersection(&self, other: Self) -> Option<Self> {
+        if !self.intersects(other) {
+            return None;
+        }
+
+        Some(Self {
+            bl: Vec2D::new(self.bl.x.max(other.bl.x), self.bl.y.max(other.bl.y)),
+            tr: Vec2D::new(self.tr.x.min(other.tr.x), self.tr.y.min(other.tr.y)),
+        })
+    }
+
+    /// Returns the smallest rectangle containing both rectangles.
+    pub fn union(&self, other: Self) -> Self {
+        Self {
+            bl: Vec2D::new(self.bl.x.min(other.bl.x), self.bl.y.min(other.bl.y)),
+            tr: Vec2D::new(self.tr.x.max(other.tr.x), self.tr.y.max(other.tr.y)),
+        }
+    }
+
     /// Finds which quadrant of the rectangle a point is in. This will not fail
     /// even if the point is not in the rectangle, instead pretending the
     /// quadrants extend outward from the rectangle to infinity.
@@ -304,3 +476,148 @@ impl<T: num::Float> Rect<T> {
         ]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn circles_touching_a_rect_intersect_it() {
+        let rect = Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(1.0, 1.0));
+
+        assert!(rect.intersects_circle(Vec2D::new(0.5, 0.5), 0.1));
+        assert!(rect.intersects_circle(Vec2D::new(2.0, 0.5), 1.0));
+        assert!(!rect.intersects_circle(Vec2D::new(2.0, 2.0), 1.0));
+        assert!(rect.intersects_circle(Vec2D::new(2.0, 2.0), 2f64.sqrt()));
+    }
+
+    #[test]
+    fn integer_vectors_support_arithmetic() {
+        let a = Vec2D::new(3i64, -4);
+        let b = Vec2D::new(-1i64, 2);
+
+        assert_eq!(a + b, Vec2D::new(2, -2));
+        assert_eq!(a - b, Vec2D::new(4, -6));
+        assert_eq!(a * b, Vec2D::new(-3, -8));
+        assert_eq!(a * 2, Vec2D::new(6, -8));
+        assert_eq!(a / 2, Vec2D::new(1, -2));
+        assert_eq!(a.dot(&b), -11);
+        assert_eq!(
+            Vec2D::<i64>::new_zero() + Vec2D::new_one(),
+            Vec2D::new(1, 1)
+        );
+        assert!(Vec2D::new(1i64, 1).is_in_bounds(Vec2D::new_zero(), Vec2D::new(2, 2)));
+    }
+
+    #[test]
+    fn vectors_convert_between_floats_and_integers() {
+        assert_eq!(Vec2D::new(2.7, -2.2).floor_to_i64(), Vec2D::new(2, -3));
+        assert_eq!(Vec2D::new(-0.5, 0.0).floor_to_i64(), Vec2D::new(-1, 0));
+        assert_eq!(Vec2D::new_nan().floor_to_i64(), Vec2D::new(0, 0));
+
+        for v in [
+            Vec2D::new(-7i64, 12),
+            Vec2D::new(0, -1),
+            Vec2D::new(1 << 40, -(1 << 40)),
+        ] {
+            assert_eq!(v.to_f64().floor_to_i64(), v);
+        }
+        assert_eq!(Vec2D::new(-3i64, 5).to_f64(), Vec2D::new(-3.0, 5.0));
+    }
+
+    fn unit_square() -> Rect<f64> {
+        Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(1.0, 1.0))
+    }
+
+    #[test]
+    fn rects_have_an_area() {
+        assert_eq!(unit_square().area(), 1.0);
+        assert_eq!(
+            Rect::new(Vec2D::new(4.0, 1.0), Vec2D::new(1.0, 3.0)).area(),
+            6.0
+        );
+        assert_eq!(
+            Rect::new(Vec2D::new(1.0, 1.0), Vec2D::new(1.0, 5.0)).area(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn rects_expand_and_shrink_on_every_side() {
+        let expanded = unit_square().expand(0.5);
+        assert_eq!(
+            expanded,
+            Rect::new(Vec2D::new(-0.5, -0.5), Vec2D::new(1.5, 1.5))
+        );
+        assert_eq!(expanded.shrink(0.5), unit_square());
+
+        let wide = Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(4.0, 1.0));
+        // the height can't shrink by 1 on both sides, so it collapses
+        assert_eq!(
+            wide.shrink(1.0),
+            Rect::new(Vec2D::new(1.0, 0.5), Vec2D::new(3.0, 0.5))
+        );
+        assert_eq!(
+            unit_square().shrink(10.0),
+            Rect::new(Vec2D::new(0.5, 0.5), Vec2D::new(0.5, 0.5))
+        );
+    }
+
+    #[test]
+    fn points_clamp_to_the_nearest_point_in_a_rect() {
+        let rect = unit_square();
+
+        assert_eq!(
+            rect.clamp_point(Vec2D::new(0.5, 0.25)),
+            Vec2D::new(0.5, 0.25)
+        );
+        assert_eq!(rect.clamp_point(Vec2D::new(1.0, 0.0)), Vec2D::new(1.0, 0.0));
+        assert_eq!(rect.clamp_point(Vec2D::new(3.0, 0.5)), Vec2D::new(1.0, 0.5));
+        assert_eq!(
+            rect.clamp_point(Vec2D::new(-2.0, -2.0)),
+            Vec2D::new(0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn distances_to_points_are_zero_inside_and_on_edges() {
+        let rect = unit_square();
+
+        assert_eq!(rect.distance_to_point(Vec2D::new(0.5, 0.5)), 0.0);
+        assert_eq!(rect.distance_to_point(Vec2D::new(0.0, 0.5)), 0.0);
+        assert_eq!(rect.distance_to_point(Vec2D::new(1.0, 1.0)), 0.0);
+        assert_eq!(rect.distance_to_point(Vec2D::new(0.5, 3.0)), 2.0);
+        assert_eq!(rect.distance_to_point(Vec2D::new(4.0, 5.0)), 5.0);
+    }
+
+    #[test]
+    fn rects_intersect_where_they_overlap() {
+        let rect = unit_square();
+        let overlapping = Rect::new(Vec2D::new(0.5, -1.0), Vec2D::new(2.0, 0.5));
+        let touching = Rect::new(Vec2D::new(1.0, 0.0), Vec2D::new(2.0, 1.0));
+        let apart = Rect::new(Vec2D::new(1.5, 1.5), Vec2D::new(2.0, 2.0));
+
+        assert_eq!(
+            rect.intersection(overlapping),
+            Some(Rect::new(Vec2D::new(0.5, 0.0), Vec2D::new(1.0, 0.5)))
+        );
+        assert_eq!(
+            rect.intersection(touching).map(|edge| edge.area()),
+            Some(0.0)
+        );
+        assert_eq!(rect.intersection(apart), None);
+        assert_eq!(apart.intersection(rect), None);
+    }
+
+    #[test]
+    fn the_union_of_rects_contains_both() {
+        let rect = unit_square();
+        let apart = Rect::new(Vec2D::new(1.5, 1.5), Vec2D::new(2.0, 3.0));
+
+        assert_eq!(
+            rect.union(apart),
+            Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(2.0, 3.0))
+        );
+        assert_eq!(rect.union(rect), rect);
+    }
+}
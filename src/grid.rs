@@ -0,0 +1,176 @@
+use rand::Rng;
+use std::fmt::Display;
+use std::io::{self, Write};
+
+use crate::agent::Agent;
+use crate::geometry::Vec2D;
+use crate::World;
+
+/// Grid is a rectangular grid of square cells covering a world, starting from
+/// the origin. Cells are stored row by row, with x as columns and y as rows.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    /// cell_size is the length of the sides of each cell, in world units.
+    cell_size: f64,
+    cells: Vec<T>,
+}
+
+impl<T: Copy + Default> Grid<T> {
+    /// Creates a grid of cells of the given size covering the given size, with
+    /// every cell set to the default value. The last row and column are
+    /// partially outside the covered area if the cell size doesn't divide it.
+    /// Panics if the cell size isn't positive and finite.
+    pub fn new(size: Vec2D<f64>, cell_size: f64) -> Self {
+        assert!(
+            cell_size > 0.0 && cell_size.is_finite(),
+            "invalid cell size {}",
+            cell_size
+        );
+
+        let width = ((size.x / cell_size).ceil() as usize).max(1);
+        let height = ((size.y / cell_size).ceil() as usize).max(1);
+        Self {
+            width,
+            height,
+            cell_size,
+            cells: vec![T::default(); width * height],
+        }
+    }
+}
+
+impl<T> Grid<T> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn cell_size(&self) -> f64 {
+        self.cell_size
+    }
+
+    /// Returns the value of the cell in the given column and row.
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        if x < self.width && y < self.height {
+            self.cells.get(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the cells row by row.
+    pub fn cells(&self) -> &[T] {
+        &self.cells
+    }
+
+    /// Returns the cell containing the position. Positions past the last row
+    /// or column, such as those exactly on the far edges of the world, belong
+    /// to the last row or column. Returns None for NaN or negative positions.
+    pub fn cell_at_mut(&mut self, pos: Vec2D<f64>) -> Option<&mut T> {
+        if !(pos.x >= 0.0 && pos.y >= 0.0) {
+            return None;
+        }
+        let x = ((pos.x / self.cell_size) as usize).min(self.width - 1);
+        let y = ((pos.y / self.cell_size) as usize).min(self.height - 1);
+        self.cells.get_mut(y * self.width + x)
+    }
+}
+
+impl<T: Copy + Ord> Grid<T> {
+    /// Returns the largest value of any cell.
+    pub fn max(&self) -> Option<T> {
+        self.cells.iter().copied().max()
+    }
+}
+
+impl<T: Display> Grid<T> {
+    /// Writes the grid as CSV without a header, one line per row starting from
+    /// the row at y = 0.
+    pub fn write_csv<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for row in self.cells.chunks(self.width) {
+            for (i, cell) in row.iter().enumerate() {
+                if i > 0 {
+                    write!(w, ",")?;
+                }
+                write!(w, "{}", cell)?;
+            }
+            writeln!(w)?;
+        }
+
+        w.flush()
+    }
+}
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Counts the living agents in each cell of a grid with the given cell
+    /// size. Panics if the cell size isn't positive and finite.
+    pub fn density_grid(&self, cell_size: f64) -> Grid<u32> {
+        self.count_agents_in_grid(cell_size, |agent| !agent.status.is_dead())
+    }
+
+    /// Counts the infectious agents in each cell of a grid with the given cell
+    /// size. Panics if the cell size isn't positive and finite.
+    pub fn infectious_density_grid(&self, cell_size: f64) -> Grid<u32> {
+        self.count_agents_in_grid(cell_size, |agent| agent.status.is_infectious())
+    }
+
+    fn count_agents_in_grid(&self, cell_size: f64, counted: impl Fn(&Agent) -> bool) -> Grid<u32> {
+        let mut grid = Grid::new(self.size, cell_size);
+        for agent in self.agents.iter().filter(|agent| counted(agent)) {
+            if let Some(cell) = grid.cell_at_mut(agent.pos) {
+                *cell += 1;
+            }
+        }
+        grid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::MovementProfile;
+
+    fn world_with_agents_at(pos: Vec2D<f64>, count: usize) -> World<rand::rngs::ThreadRng> {
+        let agents = (0..count)
+            .map(|_| Agent::new(pos, MovementProfile::walking()))
+            .collect();
+        World::new_with_agents(Vec2D::new(10.0, 10.0), agents).unwrap()
+    }
+
+    #[test]
+    fn agents_in_one_corner_fill_only_that_cell() {
+        for (pos, cell) in [
+            (Vec2D::new(0.0, 0.0), (0, 0)),
+            (Vec2D::new(10.0, 10.0), (3, 3)),
+        ] {
+            let grid = world_with_agents_at(pos, 7).density_grid(2.5);
+
+            assert_eq!((grid.width(), grid.height()), (4, 4));
+            for y in 0..4 {
+                for x in 0..4 {
+                    let expected = if (x, y) == cell { 7 } else { 0 };
+                    assert_eq!(grid.get(x, y), Some(&expected), "cell ({}, {})", x, y);
+                }
+            }
+            assert_eq!(grid.max(), Some(7));
+        }
+    }
+
+    #[test]
+    fn grids_are_written_a_row_per_line() {
+        let grid = world_with_agents_at(Vec2D::new(10.0, 0.0), 2).density_grid(5.0);
+
+        let mut csv = Vec::new();
+        grid.write_csv(&mut csv).unwrap();
+
+        assert_eq!(String::from_utf8(csv).unwrap(), "0,2\n0,0\n");
+    }
+}
@@ -0,0 +1,235 @@
+use rand::Rng;
+use std::io::{self, Write};
+
+use crate::agent::{Agent, GroupId};
+use crate::retention::CsvRow;
+use crate::stats::WorldStats;
+use crate::World;
+
+/// GroupStats counts the agents of a group in each compartment at the end of
+/// a step, living or dead.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GroupStats {
+    pub step: i64,
+    pub abs_time: i64,
+    pub group: GroupId,
+    pub susceptible: usize,
+    pub exposed: usize,
+    pub infectious: usize,
+    pub recovered: usize,
+    pub dead: usize,
+    pub vaccinated: usize,
+}
+
+impl GroupStats {
+    fn from_stats(group: GroupId, stats: &WorldStats) -> Self {
+        Self {
+            step: stats.step,
+            abs_time: stats.abs_time,
+            group,
+            susceptible: stats.susceptible,
+            exposed: stats.exposed,
+            infectious: stats.infectious,
+            recovered: stats.recovered,
+            dead: stats.dead,
+            vaccinated: stats.vaccinated,
+        }
+    }
+}
+
+impl CsvRow for GroupStats {
+    const HEADER: &'static str =
+        "step,abs_time,group,susceptible,exposed,infectious,recovered,dead,vaccinated";
+
+    fn write_row(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            w,
+            "{},{},{},{},{},{},{},{},{}",
+            self.step,
+            self.abs_time,
+            self.group,
+            self.susceptible,
+            self.exposed,
+            self.infectious,
+            self.recovered,
+            self.dead,
+            self.vaccinated
+        )
+    }
+}
+
+/// Writes the group stats as CSV with a header row followed by one row per
+/// group per step, so that the counts of each group are broken out by the
+/// group column.
+pub fn write_csv<W: Write>(stats: &[GroupStats], mut w: W) -> io::Result<()> {
+    writeln!(w, "{}", GroupStats::HEADER)?;
+    for row in stats {
+        row.write_row(&mut w)?;
+    }
+
+    w.flush()
+}
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Adds every agent that matches the filter to the group, including dead
+    /// agents that are still in the world, and returns how many matched.
+    pub fn tag_agents(&mut self, filter: impl Fn(&Agent) -> bool, group: GroupId) -> usize {
+        let mut tagged = 0;
+        for agent in self.agents.iter_mut() {
+            if filter(agent) {
+                agent.add_tag(group);
+                tagged += 1;
+            }
+        }
+
+        tagged
+    }
+
+    /// Vaccinates the given fraction of the living, unvaccinated agents in
+    /// the group, chosen uniformly at random, and returns their ids.
+    pub fn vaccinate_fraction_of(&mut self, group: GroupId, fraction: f64) -> Vec<usize> {
+        self.vaccinate_fraction_matching(fraction, |agent| agent.has_tag(group))
+    }
+
+    /// Quarantines every living agent in the group for the given number of
+    /// seconds, so that they stay home and don't infect others, like contacts
+    /// found through tracing. Returns the ids of the agents quarantined.
+    pub fn quarantine_group(&mut self, group: GroupId, secs: i64) -> Vec<usize> {
+        let end = self.time.abs_time() + secs;
+        let mut quarantined = Vec::new();
+        for agent_id in self.agents.get_agent_ids() {
+            let agent = match self.agents.get_agent_mut(agent_id) {
+                Some(agent) if agent.has_tag(group) && !agent.status.is_dead() => agent,
+                _ => continue,
+            };
+            agent.isolated = true;
+            agent.quarantine_end = Some(end);
+            quarantined.push(agent_id);
+        }
+
+        quarantined
+    }
+
+    /// Counts the agents in the group in each compartment, like
+    /// current_stats.
+    pub fn current_stats_for(&self, group: GroupId) -> WorldStats {
+        WorldStats::from_agents(
+            self.curr_step,
+            self.time.abs_time(),
+            self.agents
+                .iter()
+                .chain(self.deceased.iter().map(|(_, agent)| agent))
+                .filter(|agent| agent.has_tag(group)),
+        )
+    }
+
+    /// Sets the groups whose stats are recorded into the group history at the
+    /// end of every step, replacing any set before.
+    pub fn set_stratified_groups(&mut self, mut groups: Vec<GroupId>) {
+        groups.sort_unstable();
+        groups.dedup();
+        self.stratified_groups = groups;
+    }
+
+    /// Returns the stats of the stratified groups recorded at the end of each
+    /// step that were kept, oldest first and in order of group within a step.
+    pub fn group_history(&self) -> &[GroupStats] {
+        self.group_history.entries()
+    }
+
+    /// Writes the group history that was kept as CSV. See `groups::write_csv`
+    /// for the columns.
+    pub fn write_group_stats_csv<W: Write>(&self, w: W) -> io::Result<()> {
+        write_csv(self.group_history(), w)
+    }
+
+    /// Records the stats of each of the stratified groups.
+    pub(crate) fn record_group_stats(&mut self) {
+        for group in self.stratified_groups.clone() {
+            let stats = self.current_stats_for(group);
+            self.group_history
+                .push(GroupStats::from_stats(group, &stats));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Status;
+    use crate::builder::WorldBuilder;
+    use crate::disease::{BasicDisease, DurationDistribution, IfrTable};
+    use crate::{StructureType, Vec2D};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn vaccinating_a_tagged_half_protects_it() {
+        let mut world: World<StdRng> = WorldBuilder::new()
+            .size(Vec2D::new(60.0, 60.0))
+            .random_agents(400)
+            .structures(StructureType::Home, 130)
+            .structures(StructureType::Work, 6)
+            .step_size(3600)
+            .contact_radius(2.0)
+            .disease(BasicDisease::new(
+                0.05,
+                DurationDistribution::Fixed(12 * 3600),
+                DurationDistribution::Fixed(3 * 86400),
+                IfrTable::flat(0.0),
+            ))
+            .index_cases(8)
+            .seed(98)
+            .build()
+            .unwrap();
+        world.background_mortality = false;
+        world.vaccine_efficacy = 1.0;
+        let left = GroupId::new(3).unwrap();
+        let tagged = world.tag_agents(|agent| agent.pos.x < 30.0, left);
+        assert!(tagged > 150 && tagged < 250);
+
+        let vaccinated = world.vaccinate_fraction_of(left, 1.0);
+        assert!(vaccinated.iter().all(|agent_id| {
+            let agent = world.agents.get_agent(*agent_id).unwrap();
+            agent.has_tag(left) && agent.vaccinated
+        }));
+        world.set_stratified_groups(vec![left]);
+        for _ in 0..14 * 24 {
+            world.step();
+        }
+
+        // the index cases are the only infections the vaccine can't prevent
+        let attack_rate = |in_group: bool| {
+            let members = world
+                .agents
+                .iter()
+                .filter(|agent| agent.has_tag(left) == in_group)
+                .collect::<Vec<_>>();
+            let infected = members
+                .iter()
+                .filter(|agent| agent.status != Status::Susceptible)
+                .count();
+            infected as f64 / members.len() as f64
+        };
+        assert!(
+            attack_rate(true) < attack_rate(false) / 2.0,
+            "attack rate of {} among the tagged and {} among the rest",
+            attack_rate(true),
+            attack_rate(false)
+        );
+
+        let stats = world.current_stats_for(left);
+        assert_eq!(stats.total(), tagged);
+        assert_eq!(
+            world.group_history().last(),
+            Some(&GroupStats::from_stats(left, &stats))
+        );
+
+        let restored = World::from_snapshot(world.snapshot(), StdRng::seed_from_u64(0));
+        assert_eq!(restored.current_stats_for(left), stats);
+    }
+}
@@ -0,0 +1,123 @@
+/// IsolationPolicy controls how infectious agents are detected and sent home
+/// to isolate until they recover.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IsolationPolicy {
+    /// detection_prob is the probability per step that an infectious agent is
+    /// detected, once the delay has passed.
+    pub detection_prob: f64,
+    /// delay is how long in seconds an agent must be infectious before it can
+    /// be detected.
+    pub delay: i64,
+    /// compliance is the probability that a detected agent actually isolates.
+    pub compliance: f64,
+}
+
+impl IsolationPolicy {
+    pub fn new(detection_prob: f64, delay: i64, compliance: f64) -> Self {
+        Self {
+            detection_prob,
+            delay,
+            compliance,
+        }
+    }
+}
+
+/// TracingPolicy controls how the contacts of detected agents are found through
+/// the contact graph and quarantined.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TracingPolicy {
+    /// depth is the number of transmission links followed from a detected
+    /// agent.
+    pub depth: usize,
+    /// trace_parent determines whether the agent that infected a detected
+    /// agent is traced as well as the agents it infected.
+    pub trace_parent: bool,
+    /// quarantine_prob is the probability that a traced agent quarantines.
+    pub quarantine_prob: f64,
+    /// quarantine_secs is how long a traced agent quarantines for.
+    pub quarantine_secs: i64,
+}
+
+impl TracingPolicy {
+    pub fn new(
+        depth: usize,
+        trace_parent: bool,
+        quarantine_prob: f64,
+        quarantine_secs: i64,
+    ) -> Self {
+        Self {
+            depth,
+            trace_parent,
+            quarantine_prob,
+            quarantine_secs,
+        }
+    }
+}
+
+/// LockdownPolicy activates a lockdown once the prevalence of infectious agents
+/// among the living rises to a threshold, and lifts it once prevalence falls to
+/// another threshold.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LockdownPolicy {
+    /// activate_prevalence is the fraction of living agents that must be
+    /// infectious for the lockdown to begin.
+    pub activate_prevalence: f64,
+    /// deactivate_prevalence is the fraction of living agents that are
+    /// infectious at or below which the lockdown is lifted.
+    pub deactivate_prevalence: f64,
+}
+
+impl LockdownPolicy {
+    pub fn new(activate_prevalence: f64, deactivate_prevalence: f64) -> Self {
+        Self {
+            activate_prevalence,
+            deactivate_prevalence,
+        }
+    }
+}
+
+/// TestingPolicy controls how agents seek diagnostic tests and what happens
+/// when their results come back. Agents that test positive are detected,
+/// isolate, and have their contacts traced according to the tracing policy.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TestingPolicy {
+    /// seek_prob is the probability per step that a symptomatic agent gets
+    /// tested, unless it is already detected or waiting on a result.
+    pub seek_prob: f64,
+    /// background_prob is the probability per step that any other living
+    /// agent gets tested, such as for symptoms of something else.
+    pub background_prob: f64,
+    /// sensitivity is the probability that an infected agent tests positive.
+    pub sensitivity: f64,
+    /// specificity is the probability that an agent that isn't infected tests
+    /// negative.
+    pub specificity: f64,
+    /// delay is how long in seconds a result takes to come back.
+    pub delay: i64,
+    /// compliance is the probability that an agent isolates after testing
+    /// positive.
+    pub compliance: f64,
+    /// isolation_secs is how long an agent that tested positive isolates for
+    /// at least. Infectious agents keep isolating until they recover.
+    pub isolation_secs: i64,
+}
+
+impl TestingPolicy {
+    /// Creates a policy under which only symptomatic agents get tested, and
+    /// every agent that tests positive isolates for ten days.
+    pub fn new(seek_prob: f64, sensitivity: f64, specificity: f64, delay: i64) -> Self {
+        Self {
+            seek_prob,
+            background_prob: 0.0,
+            sensitivity,
+            specificity,
+            delay,
+            compliance: 1.0,
+            isolation_secs: 10 * 86400,
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::str::FromStr;
+
+use crate::agent::{Agent, MovementProfile, DEFAULT_SPEED_RANGE, SECONDS_PER_YEAR};
+use crate::geometry::Vec2D;
+use crate::{Structure, StructureType};
+
+/// DEFAULT_SPEED is the speed, in units per second, of loaded agents without
+/// one, the middle of DEFAULT_SPEED_RANGE.
+pub const DEFAULT_SPEED: f64 = (DEFAULT_SPEED_RANGE.0 + DEFAULT_SPEED_RANGE.1) / 2.0;
+
+/// IoError describes why a file could not be loaded. Line numbers start from
+/// 1 at the header.
+#[derive(Debug)]
+pub enum IoError {
+    /// Reading failed.
+    Io(io::Error),
+    /// The file has no header row.
+    MissingHeader,
+    /// A required column isn't in the header.
+    MissingColumn(String),
+    /// A row has a different number of fields than the header.
+    FieldCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// A field could not be parsed, or a required field is empty.
+    InvalidValue {
+        line: usize,
+        column: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IoError::Io(err) => write!(f, "failed to read: {}", err),
+            IoError::MissingHeader => write!(f, "missing header row"),
+            IoError::MissingColumn(column) => write!(f, "missing column {}", column),
+            IoError::FieldCount {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected {} fields but found {}",
+                line, expected, found
+            ),
+            IoError::InvalidValue {
+                line,
+                column,
+                value,
+            } => write!(
+                f,
+                "line {}, column {}: invalid value {:?}",
+                line, column, value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IoError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for IoError {
+    fn from(err: io::Error) -> Self {
+        IoError::Io(err)
+    }
+}
+
+/// Loads agents from CSV with a header row naming the columns, which can be
+/// in any order. Every agent is susceptible.
+///
+/// - x, y: the position of the agent, required
+/// - age: the age in years, 0 if missing
+/// - speed: the speed in units per second, DEFAULT_SPEED if missing, which
+///   must be finite and not negative
+/// - home_x, home_y, work_x, work_y, school_x, school_y: the locations the
+///   agent heads to, NaN if missing
+///
+/// Empty fields count as missing, and other columns are ignored. Fields are
+/// split on commas without any quoting.
+pub fn load_agents_csv<R: Read>(r: R) -> Result<Vec<Agent>, IoError> {
+    parse_csv(r, &["x", "y"], |row| {
+        let location = |x, y| -> Result<Vec2D<f64>, IoError> {
+            Ok(Vec2D::new(
+                row.get(x)?.unwrap_or(f64::NAN),
+                row.get(y)?.unwrap_or(f64::NAN),
+            ))
+        };
+
+        let age_years = row.get::<f64>("age")?.unwrap_or(0.0);
+        let speed = row.get("speed")?.unwrap_or(DEFAULT_SPEED);
+        let movement =
+            MovementProfile::from_units_per_second(speed).map_err(|_| row.invalid("speed"))?;
+        let mut agent = Agent::new_with_locations(
+            Vec2D::new(row.require("x")?, row.require("y")?),
+            movement,
+            location("home_x", "home_y")?,
+            location("work_x", "work_y")?,
+            location("school_x", "school_y")?,
+        );
+        agent.age = (age_years * SECONDS_PER_YEAR as f64).round().max(0.0) as i64;
+        Ok(agent)
+    })
+}
+
+/// Loads structures from CSV with a header row naming the columns, which can
+/// be in any order. Structures are given ids when they are added to a world.
+///
+/// - type: home, work, school, or hospital, in any case, required
+/// - x, y: the position of the structure, required
+/// - capacity: the maximum number of agents assigned, unlimited if missing
+///
+/// Empty fields count as missing, and other columns are ignored. Fields are
+/// split on commas without any quoting.
+pub fn load_structures_csv<R: Read>(r: R) -> Result<Vec<Structure>, IoError> {
+    parse_csv(r, &["type", "x", "y"], |row| {
+        let typ = match row.field("type").map(str::to_lowercase).as_deref() {
+            Some("home") => StructureType::Home,
+            Some("work") => StructureType::Work,
+            Some("school") => StructureType::School,
+            Some("hospital") => StructureType::Hospital,
+            _ => return Err(row.invalid("type")),
+        };
+        let pos = Vec2D::new(row.require("x")?, row.require("y")?);
+        Ok(Structure::new(typ, pos, row.get("capacity")?.unwrap_or(0)))
+    })
+}
+
+/// Writes the agents as CSV in the format read by load_agents_csv, with a
+/// header row followed by one row per agent. Locations the agent doesn't
+/// have are left empty.
+pub fn write_agents_csv<'a, W: Write>(
+    agents: impl IntoIterator<Item = &'a Agent>,
+    mut w: W,
+) -> io::Result<()> {
+    writeln!(
+        w,
+        "x,y,age,speed,home_x,home_y,work_x,work_y,school_x,school_y"
+    )?;
+
+    let coord = |value: f64| {
+        if value.is_nan() {
+            String::new()
+        } else {
+            value.to_string()
+        }
+    };
+    for agent in agents {
+        writeln!(
+            w,
+            "{},{},{},{},{},{},{},{},{},{}",
+            agent.pos.x,
+            agent.pos.y,
+            agent.age as f64 / SECONDS_PER_YEAR as f64,
+            agent.movement.speed_per_sec(),
+            coord(agent.home.x),
+            coord(agent.home.y),
+            coord(agent.work.x),
+            coord(agent.work.y),
+            coord(agent.school.x),
+            coord(agent.school.y),
+        )?;
+    }
+
+    w.flush()
+}
+
+/// Row is one line of a CSV file along with the header naming its fields.
+pub(crate) struct Row<'a> {
+    line: usize,
+    header: &'a [String],
+    fields: Vec<&'a str>,
+}
+
+impl Row<'_> {
+    /// Returns the field in the named column, or None if the column is
+    /// missing or the field is empty.
+    pub(crate) fn field(&self, column: &str) -> Option<&str> {
+        self.header
+            .iter()
+            .position(|name| name == column)
+            .map(|i| self.fields[i])
+            .filter(|field| !field.is_empty())
+    }
+
+    /// Parses the field in the named column, returning None if it is missing.
+    pub(crate) fn get<T: FromStr>(&self, column: &str) -> Result<Option<T>, IoError> {
+        match self.field(column) {
+            Some(field) => field.parse().map(Some).map_err(|_| self.invalid(column)),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses the field in the named column, which must not be empty.
+    pub(crate) fn require<T: FromStr>(&self, column: &str) -> Result<T, IoError> {
+        self.get(column)?.ok_or_else(|| self.invalid(column))
+    }
+
+    pub(crate) fn invalid(&self, column: &str) -> IoError {
+        IoError::InvalidValue {
+            line: self.line,
+            column: column.to_string(),
+            value: self.field(column).unwrap_or("").to_string(),
+        }
+    }
+}
+
+/// Parses CSV with a header row that must name every required column, calling
+/// parse_row on each line after it. Blank lines are skipped.
+pub(crate) fn parse_csv<R: Read, T>(
+    r: R,
+    required: &[&str],
+    parse_row: impl Fn(&Row) -> Result<T, IoError>,
+) -> Result<Vec<T>, IoError> {
+    let mut lines = BufReader::new(r).lines();
+    let header = match lines.next() {
+        Some(line) => line?
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .collect::<Vec<_>>(),
+        None => return Err(IoError::MissingHeader),
+    };
+    if let Some(column) = required
+        .iter()
+        .find(|column| !header.contains(&column.to_string()))
+    {
+        return Err(IoError::MissingColumn(column.to_string()));
+    }
+
+    let mut parsed = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let row = Row {
+            line: i + 2,
+            header: &header,
+            fields: line.split(',').map(str::trim).collect(),
+        };
+        if row.fields.len() != header.len() {
+            return Err(IoError::FieldCount {
+                line: row.line,
+                expected: header.len(),
+                found: row.fields.len(),
+            });
+        }
+        parsed.push(parse_row(&row)?);
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nan() -> Vec2D<f64> {
+        Vec2D::new(f64::NAN, f64::NAN)
+    }
+
+    /// Compares two locations, counting missing locations as equal.
+    fn same_location(a: Vec2D<f64>, b: Vec2D<f64>) -> bool {
+        (a.x.is_nan() && b.x.is_nan() && a.y.is_nan() && b.y.is_nan()) || a == b
+    }
+
+    #[test]
+    fn agents_survive_a_round_trip() {
+        let mut adult = Agent::new_with_locations(
+            Vec2D::new(1.5, 2.25),
+            MovementProfile::from_units_per_second(1.25).unwrap(),
+            Vec2D::new(1.0, 2.0),
+            Vec2D::new(8.5, 9.0),
+            nan(),
+        );
+        adult.age = 40 * SECONDS_PER_YEAR;
+        let mut child = Agent::new_with_locations(
+            Vec2D::new(0.0, 10.0),
+            MovementProfile::from_units_per_second(0.75).unwrap(),
+            Vec2D::new(1.0, 2.0),
+            nan(),
+            Vec2D::new(4.0, 4.5),
+        );
+        child.age = 7 * SECONDS_PER_YEAR;
+        let agents = vec![adult, child];
+
+        let mut csv = Vec::new();
+        write_agents_csv(&agents, &mut csv).unwrap();
+        let loaded = load_agents_csv(csv.as_slice()).unwrap();
+
+        assert_eq!(loaded.len(), agents.len());
+        for (loaded, agent) in loaded.iter().zip(&agents) {
+            assert_eq!(loaded.pos, agent.pos);
+            assert_eq!(loaded.age, agent.age);
+            assert_eq!(
+                loaded.movement.speed_per_sec(),
+                agent.movement.speed_per_sec()
+            );
+            assert!(same_location(loaded.home, agent.home));
+            assert!(same_location(loaded.work, agent.work));
+            assert!(same_location(loaded.school, agent.school));
+        }
+    }
+
+    #[test]
+    fn missing_optional_columns_take_their_defaults() {
+        let agents = load_agents_csv("y,x,note\n2,1,ignored\n\n4,3,\n".as_bytes()).unwrap();
+
+        assert_eq!(agents.len(), 2);
+        assert_eq!(agents[1].pos, Vec2D::new(3.0, 4.0));
+        assert_eq!(agents[1].age, 0);
+        assert_eq!(agents[1].movement.speed_per_sec(), DEFAULT_SPEED);
+        assert!(agents[1].school.x.is_nan());
+
+        let structures =
+            load_structures_csv("type,x,y,capacity\nHome,1,2,4\nschool,3,4,\n".as_bytes()).unwrap();
+        assert_eq!(
+            structures,
+            vec![
+                Structure::new(StructureType::Home, Vec2D::new(1.0, 2.0), 4),
+                Structure::new(StructureType::School, Vec2D::new(3.0, 4.0), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_files_report_where_they_went_wrong() {
+        let agent_error = |csv: &str| load_agents_csv(csv.as_bytes()).unwrap_err().to_string();
+
+        assert_eq!(agent_error(""), "missing header row");
+        assert_eq!(agent_error("x,age\n1,2\n"), "missing column y");
+        assert_eq!(
+            agent_error("x,y\n1,2\n3\n"),
+            "line 3: expected 2 fields but found 1"
+        );
+        assert_eq!(
+            agent_error("x,y,age\n1,2,3\n4,five,6\n"),
+            "line 3, column y: invalid value \"five\""
+        );
+        assert_eq!(
+            agent_error("x,y\n1,\n"),
+            "line 2, column y: invalid value \"\""
+        );
+        assert_eq!(
+            agent_error("x,y,speed\n1,2,-1\n"),
+            "line 2, column speed: invalid value \"-1\""
+        );
+
+        assert_eq!(
+            load_structures_csv("type,x,y\nhome,1,2\ncastle,3,4\n".as_bytes())
+                .unwrap_err()
+                .to_string(),
+            "line 3, column type: invalid value \"castle\""
+        );
+    }
+}
@@ -1,23 +1,65 @@
-use rand::distributions::{Distribution, Uniform};
+use rand::distributions::{Distribution, Uniform, WeightedIndex};
+use rand::seq::SliceRandom;
 use rand::Rng;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::time::Instant;
 
 pub mod agent;
+pub mod analysis;
+mod audit;
+pub mod builder;
 pub mod disease;
+pub mod event;
+pub mod experiment;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod geojson;
 pub mod geometry;
+pub mod grid;
+pub mod groups;
+pub mod intervention;
+pub mod io;
+pub mod pipeline;
+pub mod prelude;
 pub mod quadtree;
+pub mod region;
+mod render;
+pub mod replay;
+pub mod retention;
+pub mod run;
+pub mod scenarios;
+pub mod snapshot;
+pub mod stats;
+pub mod testing;
+pub mod trajectory;
 
-use crate::agent::{Agent, ContactGraph, Status, Task};
-use crate::geometry::{Rect, Vec2D};
-use crate::quadtree::Quadtree;
+use crate::agent::{
+    Agent, ContactGraph, GroupId, MovementProfile, Setting, Status, Task, DEFAULT_SPEED_RANGE,
+    SECONDS_PER_YEAR,
+};
+use crate::disease::{BasicDisease, Disease, Gamma, InfectionKernel, TransmissionModel};
+use crate::event::WorldEvent;
+pub use crate::geometry::{Rect, Vec2D};
+use crate::groups::GroupStats;
+use crate::intervention::{IsolationPolicy, LockdownPolicy, TestingPolicy, TracingPolicy};
+use crate::pipeline::{AdaptiveConfig, Phase, StepTally, StepTimings};
+use crate::quadtree::{AgentTree, MoveError, QuadtreeConfig};
+use crate::region::Region;
+use crate::replay::{decode_death, encode_death, DecisionKind, DecisionMode};
+use crate::retention::Log;
+use crate::snapshot::WorldSnapshot;
+use crate::stats::WorldStats;
+use crate::testing::PendingTest;
+use crate::trajectory::{PositionRecording, TrajectoryPoint};
 
 /// Representation of time within the simulation. `abs_time` is a variation on
 /// epoch time, which is the number of seconds since the simulation began.
 /// `day_time` is similar, but reset every day. `day_of_week` is an integer
 /// representing which day of the week it is, starting with Sunday as 0.
-struct Time {
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Time {
     day_of_week: i64,
     abs_time: i64,
     day_time: i64,
@@ -32,25 +74,208 @@ impl Time {
         }
     }
 
+    /// Moves time forward by the given number of seconds, rolling over as
+    /// many days as necessary.
     pub fn advance(&mut self, seconds: i64) {
         self.abs_time += seconds;
         self.day_time += seconds;
 
-        if self.day_time >= 86400 {
-            self.day_time -= 86400;
-            self.day_of_week += 1;
-            if self.day_of_week >= 7 {
-                self.day_of_week = 0;
-            }
+        let days = self.day_time.div_euclid(86400);
+        self.day_time = self.day_time.rem_euclid(86400);
+        self.day_of_week = (self.day_of_week + days).rem_euclid(7);
+    }
+
+    /// Number of seconds since the simulation began
+    pub fn abs_time(&self) -> i64 {
+        self.abs_time
+    }
+
+    /// Number of seconds since midnight
+    pub fn day_time(&self) -> i64 {
+        self.day_time
+    }
+
+    /// Day of the week, with Sunday as 0 and Saturday as 6
+    pub fn day_of_week(&self) -> i64 {
+        self.day_of_week
+    }
+
+    /// Number of whole days since the simulation began
+    pub fn day(&self) -> i64 {
+        self.abs_time.div_euclid(86400)
+    }
+
+    /// Hour of the day, from 0 to 23
+    pub fn hour_of_day(&self) -> i64 {
+        self.day_time / 3600
+    }
+
+    pub fn is_weekend(&self) -> bool {
+        self.day_of_week == 0 || self.day_of_week == 6
+    }
+
+    pub fn is_weekday(&self) -> bool {
+        !self.is_weekend()
+    }
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Schedule gives the hours of the day, in seconds since midnight, during which
+/// agents are at work or at school on weekdays. Outside of those hours and on
+/// weekends agents head home.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Schedule {
+    pub work_start: i64,
+    pub work_end: i64,
+    pub school_start: i64,
+    pub school_end: i64,
+}
+
+impl Schedule {
+    pub fn is_work_hours(&self, day_time: i64) -> bool {
+        day_time >= self.work_start && day_time < self.work_end
+    }
+
+    pub fn is_school_hours(&self, day_time: i64) -> bool {
+        day_time >= self.school_start && day_time < self.school_end
+    }
+}
+
+impl Default for Schedule {
+    /// Work runs from 8:00 to 17:00 and school from 8:00 to 15:00.
+    fn default() -> Self {
+        Self {
+            work_start: 8 * 3600,
+            work_end: 17 * 3600,
+            school_start: 8 * 3600,
+            school_end: 15 * 3600,
+        }
+    }
+}
+
+/// DwellTimes gives the minimum time, in seconds, that agents stay at each
+/// destination after arriving before their task may change. Isolating and
+/// locked down agents head home regardless.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DwellTimes {
+    pub home: i64,
+    pub work: i64,
+    pub school: i64,
+}
+
+impl DwellTimes {
+    pub fn for_task(&self, task: Task) -> i64 {
+        match task {
+            Task::Home => self.home,
+            Task::Work => self.work,
+            Task::School => self.school,
+            Task::Hospital | Task::Travel | Task::None => 0,
+        }
+    }
+}
+
+/// SettleRadii gives the radius of the disc around each kind of destination
+/// that agents settle into, so that they don't all stand on the same point.
+/// Each agent has its own spot in the disc, which is the same every time it
+/// heads there. A radius of 0, the default, sends every agent to the exact
+/// position of the structure.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SettleRadii {
+    pub home: f64,
+    pub work: f64,
+    pub school: f64,
+    pub hospital: f64,
+}
+
+impl SettleRadii {
+    pub fn for_task(&self, task: Task) -> f64 {
+        match task {
+            Task::Home | Task::None => self.home,
+            Task::Work => self.work,
+            Task::School => self.school,
+            Task::Hospital => self.hospital,
+            Task::Travel => 0.0,
+        }
+    }
+
+    /// Returns the offset of the agent's spot from the center of a disc of
+    /// the given radius. The spot is spread uniformly over the disc by
+    /// hashing the agent id.
+    fn offset(agent_id: usize, radius: f64) -> Vec2D<f64> {
+        // splitmix64, which is enough to spread consecutive ids
+        let mut hash = (agent_id as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        hash ^= hash >> 31;
+
+        let u = (hash >> 32) as f64 / (1u64 << 32) as f64;
+        let v = (hash & 0xFFFF_FFFF) as f64 / (1u64 << 32) as f64;
+        let r = radius * u.sqrt();
+        let theta = 2.0 * std::f64::consts::PI * v;
+        Vec2D::new(r * theta.cos(), r * theta.sin())
+    }
+}
+
+/// BoundaryMode is what happens at the edges of the world.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BoundaryMode {
+    /// Agents stop at the edges, and nothing is in contact across them.
+    #[default]
+    Clamp,
+    /// Opposite edges are joined, so agents leaving one edge re-enter from the
+    /// other, and agents near opposite edges can be in contact.
+    Wrap,
+}
+
+/// RenderOptions controls how a world is drawn as text.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RenderOptions {
+    /// use_color is whether to color the statuses with ANSI escape codes,
+    /// which should be off when the output isn't a terminal. Without color,
+    /// structures are drawn in brackets to tell them apart from agents.
+    pub use_color: bool,
+    /// show_legend is whether to explain the glyphs below the grid.
+    pub show_legend: bool,
+    /// cell_width is the number of characters each grid square takes up.
+    /// Agents always take up at least 3.
+    pub cell_width: usize,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            use_color: true,
+            show_legend: false,
+            cell_width: 3,
         }
     }
 }
 
-#[derive(Eq, Hash, PartialEq, Debug, Copy, Clone)]
+#[derive(Eq, Hash, PartialEq, Ord, PartialOrd, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StructureType {
     Home,
     Work,
     School,
+    Hospital,
+}
+
+impl StructureType {
+    /// Returns the bit standing for the type in a mask of closed structure
+    /// types.
+    pub fn closure_bit(&self) -> u8 {
+        1 << *self as u8
+    }
 }
 
 impl fmt::Display for StructureType {
@@ -59,356 +284,4980 @@ impl fmt::Display for StructureType {
             StructureType::Home => write!(f, "H"),
             StructureType::Work => write!(f, "W"),
             StructureType::School => write!(f, "S"),
+            StructureType::Hospital => write!(f, "+"),
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Structure {
+    /// id is unique among all of the structures in a world, and is assigned
+    /// when the structure is placed.
+    pub id: usize,
     pub typ: StructureType,
     pub pos: Vec2D<f64>,
+    /// capacity is the maximum number of agents that can be assigned to the
+    /// structure. A capacity of 0 or less means the structure is unlimited.
     pub capacity: i64,
+    /// occupancy is the number of agents currently assigned to the structure.
+    pub occupancy: i64,
 }
 
 impl Structure {
     pub fn new(typ: StructureType, pos: Vec2D<f64>, capacity: i64) -> Self {
-        Self { typ, pos, capacity }
-    }
-
-    pub fn new_without_capacity(typ: StructureType, pos: Vec2D<f64>) -> Self {
         Self {
+            id: 0,
             typ,
             pos,
-            capacity: 0,
+            capacity,
+            occupancy: 0,
+        }
+    }
+
+    pub fn new_without_capacity(typ: StructureType, pos: Vec2D<f64>) -> Self {
+        Self::new(typ, pos, 0)
+    }
+
+    /// Whether another agent can be assigned without exceeding capacity
+    pub fn has_room(&self) -> bool {
+        self.capacity <= 0 || self.occupancy < self.capacity
+    }
+}
+
+/// Error describes why the world rejected an operation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Error {
+    /// The agent with the given id, or that would have been given the id, is
+    /// outside of the world or at a NaN position.
+    AgentOutOfBounds { id: usize, pos: Vec2D<f64> },
+    /// There is no living agent with the given id.
+    UnknownAgent(usize),
+    /// There are no structures of the given type.
+    NoStructuresOfType(StructureType),
+    /// The named parameter has a value that can't be used.
+    InvalidParameter(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::AgentOutOfBounds { id, pos } => {
+                write!(f, "agent {} at ({}, {}) is out of bounds", id, pos.x, pos.y)
+            }
+            Error::UnknownAgent(id) => write!(f, "unknown agent {}", id),
+            Error::NoStructuresOfType(typ) => {
+                write!(f, "there are no structures of type {:?}", typ)
+            }
+            Error::InvalidParameter(name) => write!(f, "invalid {}", name),
         }
     }
 }
 
+impl std::error::Error for Error {}
+
 /// World is the wrapper for all simulation, with this struct being responsible
 /// for managing all of the agents and anything else that can happen within the
 /// simulation.
 pub struct World<R: Rng> {
-    pub agents: Quadtree,
+    pub agents: AgentTree,
     /// curr_step measures simulation steps independent of time.
     curr_step: i64,
     /// step_size is the number of seconds between each simulation step.
     pub step_size: i64,
+    /// adaptive_stepping adjusts the step size after every step to the
+    /// activity of the epidemic if set.
+    pub adaptive_stepping: Option<AdaptiveConfig>,
+    /// last_step_size is the size of the step that brought the world to the
+    /// current time, or 0 before the first step.
+    last_step_size: i64,
     size: Vec2D<f64>,
-    infected: i64,
+    /// cumulative_infections counts every exposure since the simulation
+    /// began, so agents that are reinfected are counted once per infection.
+    cumulative_infections: i64,
+    /// currently_exposed, currently_infectious, and deaths count the agents
+    /// in those states now, including removed dead agents. They are kept up
+    /// to date as agents are exposed, progress, are added, and are removed,
+    /// so statuses changed directly through agents aren't counted, which the
+    /// audit reports.
+    currently_exposed: usize,
+    currently_infectious: usize,
+    deaths: usize,
     rng: Box<R>,
     pub contacts: ContactGraph,
+    /// contact_radius is the maximum distance at which an infectious agent can
+    /// infect another agent.
+    pub contact_radius: f64,
+    /// setting_radius is how close the infector must be to a structure for a
+    /// transmission to be attributed to it in the contact graph. Transmissions
+    /// further from every structure happen in the community.
+    pub setting_radius: f64,
+    /// infection_kernel determines how the probability of infection falls off
+    /// with distance within the contact radius.
+    pub infection_kernel: InfectionKernel,
+    /// transmission_model determines how the infectious agents near an agent
+    /// combine into its chance of being exposed.
+    pub transmission_model: TransmissionModel,
+    /// disease is consulted for transmission and for the progression of every
+    /// agent infected with the original strain, which has id 0.
+    pub disease: BasicDisease,
+    /// variants are the strains that appeared after the original one, where
+    /// the strain with id i is at index i - 1.
+    variants: Vec<BasicDisease>,
+    /// mutation_prob is the probability that a transmission creates a new
+    /// strain by mutating the strain of the infector.
+    pub mutation_prob: f64,
+    /// strain_history holds the number of infectious agents of each strain,
+    /// by strain id, recorded at the end of every step. It is kept like the
+    /// history.
+    strain_history: Log<Vec<usize>>,
+    /// stratified_groups are the groups whose stats are recorded into the
+    /// group history at the end of every step.
+    stratified_groups: Vec<GroupId>,
+    /// group_history holds the stats of each of the stratified groups
+    /// recorded at the end of every step. It is kept like the history.
+    group_history: Log<GroupStats>,
     time: Time,
-    structures: HashMap<StructureType, Vec<Structure>>,
+    /// structures holds every structure, where the structure with id i is at
+    /// index i.
+    structures: Vec<Structure>,
+    /// structure_ids holds the ids of the structures of each type, in the
+    /// order they were added.
+    structure_ids: HashMap<StructureType, Vec<usize>>,
+    /// last_step_duration is how long the last step took in milliseconds.
+    #[deprecated(note = "use timings, which is in microseconds")]
     pub last_step_duration: u128,
+    /// timings is how long the last step and its phases took. It isn't part
+    /// of snapshots.
+    timings: StepTimings,
+    /// pipeline is the phases run by each step, in order.
+    pipeline: Vec<Phase>,
+    /// phase_durations is how long each phase of the last step took, in
+    /// microseconds. It isn't part of snapshots.
+    phase_durations: HashMap<Phase, u128>,
+    /// history holds the stats recorded at the end of every step, as many as
+    /// its retention keeps.
+    history: Log<WorldStats>,
+    /// last_stats are the stats recorded at the end of the last step, which
+    /// are kept whatever the history keeps.
+    last_stats: Option<WorldStats>,
+    /// prune_dead controls whether dead agents are removed from the quadtree
+    /// during each step, which keeps spatial queries from visiting them.
+    pub prune_dead: bool,
+    /// deceased archives agents that have been removed from the quadtree
+    /// after dying, alongside their ids.
+    deceased: Vec<(usize, Agent)>,
+    /// school_age_cutoff is the age in seconds below which agents are assigned
+    /// a school instead of a workplace.
+    pub school_age_cutoff: i64,
+    /// retirement_age is the age in seconds at which agents are no longer
+    /// assigned a workplace.
+    pub retirement_age: i64,
+    /// schedule determines when agents head to work or school.
+    pub schedule: Schedule,
+    /// dwell_times keeps agents at a destination for a while after they
+    /// arrive, even if the schedule would send them elsewhere.
+    pub dwell_times: DwellTimes,
+    /// settle_radii spreads agents over a disc around their destinations
+    /// rather than stacking them on a single point.
+    pub settle_radii: SettleRadii,
+    /// speed_jitter is the largest fraction by which agents randomly travel
+    /// slower than their speed during a step, from 0 for always traveling at
+    /// full speed to 1 for anywhere down to standing still.
+    pub speed_jitter: f64,
+    /// boundary_mode determines whether the edges of the world are walls or
+    /// are joined to the opposite edge.
+    pub boundary_mode: BoundaryMode,
+    /// contact_dispersion is the dispersion parameter k of the gamma
+    /// distribution, with a mean of 1, that the contact multipliers of agents
+    /// are drawn from. Empirical epidemics have a k of about 0.1 to 0.5, and
+    /// the smaller k is, the more transmission is dominated by superspreaders.
+    /// With None, every agent makes the same number of contacts.
+    pub contact_dispersion: Option<f64>,
+    /// record_contacts is whether to count the pairs of living agents within
+    /// the contact radius of each other every step, whether or not either is
+    /// infectious. The counts are in the stats and the contact counts of the
+    /// agents. It is off by default since it searches around every agent.
+    pub record_contacts: bool,
+    /// vaccine_efficacy is the immunity given to agents when they are
+    /// vaccinated, from 0 for no protection to 1 for full immunity. Agents
+    /// keep the higher of it and the immunity they already have.
+    pub vaccine_efficacy: f64,
+    /// mask_source_control and mask_wearer_protection scale the probability
+    /// of transmission from and to an agent that always wears a mask. Agents
+    /// that wear masks less consistently are protected proportionally less.
+    pub mask_source_control: f64,
+    pub mask_wearer_protection: f64,
+    /// isolation_policy determines how infectious agents are detected and
+    /// isolated. With no policy, nobody isolates.
+    pub isolation_policy: Option<IsolationPolicy>,
+    /// tracing_policy determines whether the contacts of detected agents are
+    /// traced and quarantined. Relies on the isolation policy for detection.
+    pub tracing_policy: Option<TracingPolicy>,
+    /// testing_policy determines how agents get tested. Positive results
+    /// detect agents like the isolation policy does, but only after a delay
+    /// and with imperfect accuracy.
+    pub testing_policy: Option<TestingPolicy>,
+    /// pending_tests are the tests waiting on their results, in the order
+    /// they were administered.
+    pending_tests: Vec<PendingTest>,
+    /// tests_administered counts every test since the simulation began.
+    tests_administered: usize,
+    /// lockdown_active is whether a lockdown is currently in place.
+    lockdown_active: bool,
+    /// lockdown_compliance is the fraction of agents sent home during a
+    /// lockdown. The rest are essential and keep their schedules.
+    pub lockdown_compliance: f64,
+    /// lockdown_trigger starts and lifts lockdowns automatically based on
+    /// prevalence.
+    pub lockdown_trigger: Option<LockdownPolicy>,
+    /// closures has the closure bit of every structure type that is closed.
+    closures: u8,
+    /// self_isolation_prob is the probability that an agent isolates on its
+    /// own as soon as it becomes infectious with symptoms.
+    pub self_isolation_prob: f64,
+    /// hospital_mortality_multiplier scales the infection fatality ratio of
+    /// the disease for hospitalized agents with a bed, and
+    /// overload_mortality_multiplier for those left at home because every
+    /// hospital is full.
+    pub hospital_mortality_multiplier: f64,
+    pub overload_mortality_multiplier: f64,
+    /// background_mortality is whether agents also die of causes other than
+    /// the disease, at a rate that rises with age. Set by default.
+    pub background_mortality: bool,
+    /// households maps household ids to the ids of their members.
+    households: HashMap<usize, Vec<usize>>,
+    /// household_transmission_prob is the nightly probability that an
+    /// infectious agent exposes each of its susceptible housemates.
+    pub household_transmission_prob: f64,
+    /// structure_members maps structure ids to the ids of the agents assigned
+    /// to them.
+    structure_members: HashMap<usize, Vec<usize>>,
+    /// cohort_transmission_rate is the hourly rate at which an infectious
+    /// agent exposes each susceptible member of its workplace or school while
+    /// both are attending, regardless of where they are within it.
+    pub cohort_transmission_rate: f64,
+    /// regions are the named parts of the world, indexed by id.
+    regions: Vec<Region>,
+    /// travel_matrix gives the daily probability that an agent living in the
+    /// first region leaves on a trip to the second, keyed by region names.
+    pub travel_matrix: HashMap<(String, String), f64>,
+    /// trip_duration is how long in seconds a trip to another region lasts.
+    pub trip_duration: i64,
+    /// birth_rate is the annual number of births per living agent. Newborns
+    /// are placed at a random home, which they are assigned.
+    pub birth_rate: f64,
+    /// scheduled holds the actions waiting to run at the start of a step.
+    /// They aren't part of snapshots.
+    scheduled: Vec<ScheduledAction<R>>,
+    /// next_action_seq orders actions due at the same time by when they were
+    /// scheduled.
+    next_action_seq: u64,
+    /// position_recording controls whether and which agent positions are
+    /// recorded into trajectories. Neither is part of snapshots.
+    position_recording: Option<PositionRecording>,
+    trajectories: Log<TrajectoryPoint>,
+    /// events are the status changes since the start of the last step
+    events: Log<WorldEvent>,
+    /// decisions is whether stochastic decisions are being recorded or
+    /// replayed. It isn't part of snapshots.
+    decisions: DecisionMode,
+    /// render_options controls how Display draws the world. They aren't part
+    /// of snapshots.
+    render_options: RenderOptions,
 }
 
 impl World<rand::prelude::ThreadRng> {
     pub fn new(size: Vec2D<f64>) -> Self {
-        World {
-            agents: Quadtree::new(Rect::new(Vec2D::new_zero(), size)),
+        Self::new_with_rng(size, rand::thread_rng())
+    }
+
+    pub fn new_with_agents(size: Vec2D<f64>, agents: Vec<Agent>) -> Result<Self, Error> {
+        Self::new_with_agents_and_rng(size, agents, rand::thread_rng())
+    }
+}
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Creates an empty world that draws all of its randomness from the given
+    /// RNG. Passing a seeded RNG makes the simulation reproducible.
+    pub fn new_with_rng(size: Vec2D<f64>, rng: R) -> Self {
+        Self::new_with_agents_and_rng(size, Vec::new(), rng).expect("there are no agents to reject")
+    }
+
+    /// Creates a world with the given agents, which are given ids in order
+    /// starting from 0. Fails if any agent is outside of the world.
+    pub fn new_with_agents_and_rng(
+        size: Vec2D<f64>,
+        agents: Vec<Agent>,
+        rng: R,
+    ) -> Result<Self, Error> {
+        Self::new_with_quadtree_config(size, agents, QuadtreeConfig::default(), rng)
+    }
+
+    /// Creates a world whose quadtree splits leaves according to the given
+    /// config, which is useful for tuning very dense or very sparse worlds.
+    /// Fails if any agent is outside of the world.
+    pub fn new_with_quadtree_config(
+        size: Vec2D<f64>,
+        agents: Vec<Agent>,
+        config: QuadtreeConfig,
+        rng: R,
+    ) -> Result<Self, Error> {
+        let bounds = Rect::new(Vec2D::new_zero(), size);
+        if let Some((id, agent)) = agents
+            .iter()
+            .enumerate()
+            .find(|(_, agent)| !bounds.contains(agent.pos))
+        {
+            return Err(Error::AgentOutOfBounds { id, pos: agent.pos });
+        }
+
+        let mut world = World {
+            agents: AgentTree::new_with_agents_and_config(bounds, agents, config),
             curr_step: 0,
             step_size: 1,
+            adaptive_stepping: None,
+            last_step_size: 0,
             size,
-            infected: 0,
-            rng: Box::new(rand::thread_rng()),
+            cumulative_infections: 0,
+            currently_exposed: 0,
+            currently_infectious: 0,
+            deaths: 0,
+            rng: Box::new(rng),
             contacts: ContactGraph::new(),
+            contact_radius: 1.0,
+            setting_radius: 1.0,
+            infection_kernel: InfectionKernel::Linear,
+            transmission_model: TransmissionModel::default(),
+            disease: BasicDisease::default(),
+            variants: Vec::new(),
+            mutation_prob: 0.0,
+            strain_history: Log::new(),
+            stratified_groups: Vec::new(),
+            group_history: Log::new(),
             time: Time::new(),
-            structures: HashMap::new(),
+            structures: Vec::new(),
+            structure_ids: HashMap::new(),
+            #[allow(deprecated)]
             last_step_duration: 0,
+            timings: StepTimings::default(),
+            pipeline: Phase::DEFAULT_PIPELINE.to_vec(),
+            phase_durations: HashMap::new(),
+            history: Log::new(),
+            last_stats: None,
+            prune_dead: false,
+            deceased: Vec::new(),
+            school_age_cutoff: 18 * 365 * 86400,
+            retirement_age: 65 * 365 * 86400,
+            schedule: Schedule::default(),
+            dwell_times: DwellTimes::default(),
+            settle_radii: SettleRadii::default(),
+            speed_jitter: 0.0,
+            boundary_mode: BoundaryMode::Clamp,
+            contact_dispersion: None,
+            record_contacts: false,
+            vaccine_efficacy: 0.9,
+            mask_source_control: 0.5,
+            mask_wearer_protection: 0.8,
+            isolation_policy: None,
+            tracing_policy: None,
+            testing_policy: None,
+            pending_tests: Vec::new(),
+            tests_administered: 0,
+            lockdown_active: false,
+            lockdown_compliance: 0.8,
+            lockdown_trigger: None,
+            closures: 0,
+            self_isolation_prob: 0.0,
+            hospital_mortality_multiplier: 2.0,
+            overload_mortality_multiplier: 5.0,
+            background_mortality: true,
+            households: HashMap::new(),
+            household_transmission_prob: 0.0,
+            structure_members: HashMap::new(),
+            cohort_transmission_rate: 0.0,
+            regions: Vec::new(),
+            travel_matrix: HashMap::new(),
+            trip_duration: 2 * 86400,
+            birth_rate: 0.0,
+            scheduled: Vec::new(),
+            next_action_seq: 0,
+            position_recording: None,
+            trajectories: Log::new(),
+            events: Log::new(),
+            decisions: DecisionMode::Off,
+            render_options: RenderOptions::default(),
+        };
+        let stats = world.current_stats();
+        world.recount(&stats);
+        Ok(world)
+    }
+
+    /// Captures the full state of the world, other than the RNG, so that it
+    /// can be saved and restored later.
+    pub fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot {
+            size: self.size,
+            agents: self
+                .agents
+                .get_agent_ids()
+                .into_iter()
+                .map(|id| (id, self.agents.get_agent(id).unwrap().clone()))
+                .collect(),
+            deceased: self.deceased.clone(),
+            next_agent_id: self.agents.next_agent_id(),
+            structures: self.structures.clone(),
+            contacts: self.contacts.clone(),
+            time: self.time,
+            curr_step: self.curr_step,
+            step_size: self.step_size,
+            adaptive_stepping: self.adaptive_stepping,
+            last_step_size: self.last_step_size,
+            cumulative_infections: self.cumulative_infections,
+            history: self.history().to_vec(),
+            quadtree_config: self.agents.config(),
+            contact_radius: self.contact_radius,
+            setting_radius: self.setting_radius,
+            infection_kernel: self.infection_kernel,
+            transmission_model: self.transmission_model,
+            disease: self.disease,
+            variants: self.variants.clone(),
+            mutation_prob: self.mutation_prob,
+            strain_history: self.strain_history().to_vec(),
+            stratified_groups: self.stratified_groups.clone(),
+            group_history: self.group_history().to_vec(),
+            schedule: self.schedule,
+            dwell_times: self.dwell_times,
+            settle_radii: self.settle_radii,
+            speed_jitter: self.speed_jitter,
+            boundary_mode: self.boundary_mode,
+            school_age_cutoff: self.school_age_cutoff,
+            retirement_age: self.retirement_age,
+            prune_dead: self.prune_dead,
+            contact_dispersion: self.contact_dispersion,
+            record_contacts: self.record_contacts,
+            vaccine_efficacy: self.vaccine_efficacy,
+            mask_source_control: self.mask_source_control,
+            mask_wearer_protection: self.mask_wearer_protection,
+            isolation_policy: self.isolation_policy,
+            tracing_policy: self.tracing_policy,
+            testing_policy: self.testing_policy,
+            pending_tests: self.pending_tests.clone(),
+            tests_administered: self.tests_administered,
+            lockdown_active: self.lockdown_active,
+            lockdown_compliance: self.lockdown_compliance,
+            lockdown_trigger: self.lockdown_trigger,
+            closures: self.closures,
+            self_isolation_prob: self.self_isolation_prob,
+            hospital_mortality_multiplier: self.hospital_mortality_multiplier,
+            overload_mortality_multiplier: self.overload_mortality_multiplier,
+            background_mortality: self.background_mortality,
+            households: self.households.clone(),
+            household_transmission_prob: self.household_transmission_prob,
+            structure_members: self.structure_members.clone(),
+            cohort_transmission_rate: self.cohort_transmission_rate,
+            regions: self.regions.clone(),
+            travel_matrix: {
+                let mut travel_matrix = self
+                    .travel_matrix
+                    .iter()
+                    .map(|((from, to), prob)| (from.clone(), to.clone(), *prob))
+                    .collect::<Vec<_>>();
+                travel_matrix.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+                travel_matrix
+            },
+            trip_duration: self.trip_duration,
+            birth_rate: self.birth_rate,
+            pipeline: self.pipeline.clone(),
         }
     }
 
-    pub fn new_with_agents(size: Vec2D<f64>, agents: Vec<Agent>) -> Self {
-        World {
-            agents: Quadtree::new_with_agents(Rect::new(Vec2D::new_zero(), size), agents),
-            curr_step: 0,
-            step_size: 1,
-            size,
-            infected: 0,
-            rng: Box::new(rand::thread_rng()),
-            contacts: ContactGraph::new(),
-            time: Time::new(),
-            structures: HashMap::new(),
-            last_step_duration: 0,
+    /// Recreates a world from a snapshot, rebuilding the quadtree from the
+    /// agents. Agent ids are kept exactly, so the contact graph still refers
+    /// to the right agents. The RNG is not part of the snapshot, so it must be
+    /// provided.
+    pub fn from_snapshot(snapshot: WorldSnapshot, rng: R) -> Self {
+        let mut world = Self::new_with_quadtree_config(
+            snapshot.size,
+            Vec::new(),
+            snapshot.quadtree_config,
+            rng,
+        )
+        .expect("there are no agents to reject");
+        world.agents.rebuild_with_ids(snapshot.agents);
+        world.agents.reserve_agent_ids(snapshot.next_agent_id);
+
+        let mut structures = snapshot.structures;
+        structures.sort_by_key(|structure| structure.id);
+        for structure in structures {
+            world.add_structure(structure);
         }
+
+        world.deceased = snapshot.deceased;
+        world.contacts = snapshot.contacts;
+        world.time = snapshot.time;
+        world.curr_step = snapshot.curr_step;
+        world.step_size = snapshot.step_size;
+        world.adaptive_stepping = snapshot.adaptive_stepping;
+        world.last_step_size = snapshot.last_step_size;
+        world.cumulative_infections = snapshot.cumulative_infections;
+        world.last_stats = snapshot.history.last().copied();
+        world.history = Log::from_entries(snapshot.history);
+        world.contact_radius = snapshot.contact_radius;
+        world.setting_radius = snapshot.setting_radius;
+        world.infection_kernel = snapshot.infection_kernel;
+        world.transmission_model = snapshot.transmission_model;
+        world.disease = snapshot.disease;
+        world.variants = snapshot.variants;
+        world.mutation_prob = snapshot.mutation_prob;
+        world.strain_history = Log::from_entries(snapshot.strain_history);
+        world.stratified_groups = snapshot.stratified_groups;
+        world.group_history = Log::from_entries(snapshot.group_history);
+        world.schedule = snapshot.schedule;
+        world.dwell_times = snapshot.dwell_times;
+        world.settle_radii = snapshot.settle_radii;
+        world.speed_jitter = snapshot.speed_jitter;
+        world.boundary_mode = snapshot.boundary_mode;
+        world.school_age_cutoff = snapshot.school_age_cutoff;
+        world.retirement_age = snapshot.retirement_age;
+        world.prune_dead = snapshot.prune_dead;
+        world.contact_dispersion = snapshot.contact_dispersion;
+        world.record_contacts = snapshot.record_contacts;
+        world.vaccine_efficacy = snapshot.vaccine_efficacy;
+        world.mask_source_control = snapshot.mask_source_control;
+        world.mask_wearer_protection = snapshot.mask_wearer_protection;
+        world.isolation_policy = snapshot.isolation_policy;
+        world.tracing_policy = snapshot.tracing_policy;
+        world.testing_policy = snapshot.testing_policy;
+        world.pending_tests = snapshot.pending_tests;
+        world.tests_administered = snapshot.tests_administered;
+        world.lockdown_active = snapshot.lockdown_active;
+        world.lockdown_compliance = snapshot.lockdown_compliance;
+        world.lockdown_trigger = snapshot.lockdown_trigger;
+        world.closures = snapshot.closures;
+        world.self_isolation_prob = snapshot.self_isolation_prob;
+        world.hospital_mortality_multiplier = snapshot.hospital_mortality_multiplier;
+        world.overload_mortality_multiplier = snapshot.overload_mortality_multiplier;
+        world.background_mortality = snapshot.background_mortality;
+        world.households = snapshot.households;
+        world.household_transmission_prob = snapshot.household_transmission_prob;
+        world.structure_members = snapshot.structure_members;
+        world.cohort_transmission_rate = snapshot.cohort_transmission_rate;
+        world.regions = snapshot.regions;
+        world.travel_matrix = snapshot
+            .travel_matrix
+            .into_iter()
+            .map(|(from, to, prob)| ((from, to), prob))
+            .collect();
+        world.trip_duration = snapshot.trip_duration;
+        world.birth_rate = snapshot.birth_rate;
+        world.pipeline = snapshot.pipeline;
+        let stats = world.current_stats();
+        world.recount(&stats);
+
+        world
     }
-}
 
-impl<R> World<R>
-where
-    R: Rng,
-{
-    /// Randomly infect an agent as the index case. Does not check if other
-    /// agents are already infected
-    /// Is not actually random since the quadtree makes it hard.
-    // TODO(tslnc04): Make this actually random, and add it to the contact graph
+    /// Randomly infect a susceptible agent as the index case. Kept for
+    /// compatibility, see infect_random_agents.
     pub fn infect_index_case(&mut self) {
-        if self.agents.len() == 0 {
-            return;
-        }
+        self.infect_random_agents(1);
+    }
 
-        if let Some(agent) = self.agents.get_agent_mut(0) {
-            agent.status = Status::Exposed(0);
-            self.infected += 1;
-        }
+    /// Exposes the given number of distinct susceptible agents, chosen
+    /// uniformly at random, as index cases and returns their ids. Each becomes
+    /// a root of the contact graph. Fewer agents are infected if there are not
+    /// enough susceptible agents.
+    pub fn infect_random_agents(&mut self, count: usize) -> Vec<usize> {
+        self.infect_random_agents_with_strain(count, 0)
     }
 
-    pub fn step(&mut self) {
-        let now = Instant::now();
-        for agent_id in self.agents.get_agent_ids() {
-            let agent = self.agents.get_agent(agent_id).unwrap();
-            if !agent.status.is_infectious() {
-                continue;
-            }
+    /// Exposes random index cases like infect_random_agents, but with the
+    /// given strain. Agents that recovered from other strains may be chosen.
+    /// Nobody is infected if the strain doesn't exist.
+    pub fn infect_random_agents_with_strain(&mut self, count: usize, strain: usize) -> Vec<usize> {
+        if self.strain(strain).is_none() {
+            return Vec::new();
+        }
 
-            // setup a 2x2 bounding box centered around the agent
-            let bounds = Rect::new_centered(agent.pos, Vec2D::new_one() * 2.0);
+        let candidates = self
+            .agents
+            .get_agent_ids()
+            .into_iter()
+            .filter(|id| {
+                self.agents
+                    .get_agent(*id)
+                    .unwrap()
+                    .is_susceptible_to(strain)
+            })
+            .collect::<Vec<_>>();
 
-            for other_agent_id in self.agents.find_agents_in_bounds(bounds) {
-                if let Some(other_agent) = self.agents.get_agent_mut(other_agent_id) {
-                    if other_agent.status.is_susceptible() {
-                        other_agent.status = Status::Exposed(0);
-                        self.contacts.add_node(other_agent_id, Some(agent_id));
-                        self.infected += 1;
-                    }
-                }
-            }
+        let count = count.min(candidates.len());
+        let mut chosen = rand::seq::index::sample(&mut self.rng, candidates.len(), count)
+            .into_iter()
+            .map(|i| candidates[i])
+            .collect::<Vec<_>>();
+        chosen.sort_unstable();
+
+        for agent_id in &chosen {
+            self.expose_agent(*agent_id, None, strain, None);
         }
+        chosen
+    }
 
-        for agent in self.agents.iter_mut() {
-            agent.step(self.step_size, &mut self.rng);
+    /// Returns the disease of the strain with the given id.
+    pub fn strain(&self, strain: usize) -> Option<&BasicDisease> {
+        match strain {
+            0 => Some(&self.disease),
+            _ => self.variants.get(strain - 1),
         }
+    }
 
-        self.move_agents();
-        self.agents.clean_tree();
+    /// Number of strains, including the original one.
+    pub fn strain_count(&self) -> usize {
+        self.variants.len() + 1
+    }
 
-        self.curr_step += 1;
+    /// Adds a strain with the given disease and returns its id.
+    pub fn add_strain(&mut self, disease: BasicDisease) -> usize {
+        self.variants.push(disease);
+        self.variants.len()
+    }
 
-        self.time.advance(self.step_size);
-        self.last_step_duration = now.elapsed().as_millis();
-        // TODO(tslnc04): i'm pretty sure this is backwards. if the goal is to
-        // keep the ratio between simulation time and real time constant, the
-        // step size should increase when the simulation is running slowly
-        // but like also this is kinda unnecessary for now, ig that's why it's a todo
-        // if step_duration >= 100 {
-        //     self.step_size /= 2;
-        // } else if step_duration < 10 {
-        //     self.step_size *= 2;
-        // }
+    /// Returns the disease of the strain of the agent's most recent infection,
+    /// falling back to the original strain.
+    fn disease_of(&self, agent: &Agent) -> &BasicDisease {
+        agent
+            .strain
+            .and_then(|strain| self.strain(strain))
+            .unwrap_or(&self.disease)
     }
 
-    fn move_agents(&mut self) {
-        let distro = Uniform::from(0.0..1.0);
-        for agent_id in self.agents.get_agent_ids() {
-            // TODO(tslnc04): get rid of the unwrap
-            let agent = self.agents.get_agent_mut(agent_id).unwrap();
-            if agent.status.is_dead() {
-                continue;
+    /// Counts the infectious agents of each strain, by strain id.
+    pub fn infectious_by_strain(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.strain_count()];
+        for agent in self.agents.iter() {
+            if agent.status.is_infectious() {
+                if let Some(count) = agent.strain.and_then(|strain| counts.get_mut(strain)) {
+                    *count += 1;
+                }
             }
+        }
+        counts
+    }
 
-            let dest = match agent.task {
-                Task::Home => agent.home,
-                Task::Work => agent.work,
-                Task::None => agent.home,
-                Task::School => agent.school,
-            };
+    /// Returns the number of infectious agents of each strain recorded at the
+    /// end of each step, oldest first. Strains that appeared later are
+    /// missing from earlier entries.
+    pub fn strain_history(&self) -> &[Vec<usize>] {
+        self.strain_history.entries()
+    }
 
-            let dir = dest - agent.pos;
+    /// Vaccinates the given fraction of the living, unvaccinated agents,
+    /// chosen uniformly at random, and returns their ids.
+    pub fn vaccinate_fraction(&mut self, fraction: f64) -> Vec<usize> {
+        self.vaccinate_fraction_matching(fraction, |_| true)
+    }
 
-            if dir.mag() < 1e-6 {
-                agent.task = match agent.task {
-                    Task::Home => Task::Work,
-                    Task::Work => Task::Home,
-                    Task::None => Task::None,
-                    Task::School => Task::Home,
-                };
-                continue;
-            }
+    /// Vaccinates the given fraction of the living, unvaccinated agents that
+    /// match the filter, chosen uniformly at random, and returns their ids.
+    fn vaccinate_fraction_matching(
+        &mut self,
+        fraction: f64,
+        filter: impl Fn(&Agent) -> bool,
+    ) -> Vec<usize> {
+        let candidates = self
+            .agents
+            .get_agent_ids()
+            .into_iter()
+            .filter(|id| {
+                let agent = self.agents.get_agent(*id).unwrap();
+                !agent.vaccinated && !agent.status.is_dead() && filter(agent)
+            })
+            .collect::<Vec<_>>();
 
-            let movement = (dir.normalize()
-                * distro.sample(&mut self.rng)
-                * agent.speed
-                * self.step_size as f64)
-                .clamp_mag(dir.mag());
+        let count = ((candidates.len() as f64 * fraction.clamp(0.0, 1.0)).round() as usize)
+            .min(candidates.len());
+        let mut chosen = rand::seq::index::sample(&mut self.rng, candidates.len(), count)
+            .into_iter()
+            .map(|i| candidates[i])
+            .collect::<Vec<_>>();
+        chosen.sort_unstable();
 
-            let new_pos = agent.pos + movement;
-            self.agents.move_agent(agent_id, new_pos);
+        self.vaccinate_agents(&chosen);
+        chosen
+    }
+
+    /// Has the given fraction of the living agents, chosen uniformly at random
+    /// at start_time, always wear masks until end_time, if any. The chosen
+    /// agents stop wearing masks at the end, whether or not they did before.
+    /// Relies on scheduled actions, so it must be set before the start.
+    pub fn set_mask_policy(&mut self, fraction: f64, start_time: i64, end_time: Option<i64>)
+    where
+        R: 'static,
+    {
+        let chosen = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+
+        let started = chosen.clone();
+        self.schedule_at(
+            start_time,
+            Box::new(move |world: &mut World<R>| {
+                let candidates = world
+                    .agents
+                    .get_agent_ids()
+                    .into_iter()
+                    .filter(|id| !world.agents.get_agent(*id).unwrap().status.is_dead())
+                    .collect::<Vec<_>>();
+                let count = ((candidates.len() as f64 * fraction.clamp(0.0, 1.0)).round() as usize)
+                    .min(candidates.len());
+                let mut ids = rand::seq::index::sample(&mut world.rng, candidates.len(), count)
+                    .into_iter()
+                    .map(|i| candidates[i])
+                    .collect::<Vec<_>>();
+                ids.sort_unstable();
+
+                for id in ids.iter() {
+                    world.agents.get_agent_mut(*id).unwrap().mask_compliance = 1.0;
+                }
+                *started.borrow_mut() = ids;
+            }),
+        );
+
+        if let Some(end_time) = end_time {
+            self.schedule_at(
+                end_time,
+                Box::new(move |world: &mut World<R>| {
+                    for id in chosen.borrow().iter() {
+                        if let Some(agent) = world.agents.get_agent_mut(*id) {
+                            agent.mask_compliance = 0.0;
+                        }
+                    }
+                }),
+            );
         }
     }
 
-    /// Apply a random movement to each of the agents with a magnitude in the
-    /// range of [0, max_mag). World boundaries are handled by clipping
-    /// position, not by wrapping.
-    #[allow(dead_code)]
-    fn move_agents_random(&mut self, max_mag: f64) {
-        let distro = Uniform::from(-1.0..1.0);
-        for agent in self.agents.iter_mut() {
-            if agent.status.is_dead() {
-                continue;
+    /// Vaccinates each of the agents with the given ids. Unknown ids are
+    /// ignored.
+    pub fn vaccinate_agents(&mut self, ids: &[usize]) {
+        for id in ids {
+            if let Some(agent) = self.agents.get_agent_mut(*id) {
+                agent.vaccinated = true;
+                agent.boost_immunity(self.vaccine_efficacy);
             }
-
-            // generate a movement vector with components in the range of [-1, 1)
-            let movement = Vec2D::new(distro.sample(&mut self.rng), distro.sample(&mut self.rng));
-            // scale the movement based on maximum magnitude and update position
-            agent.pos += movement.normalize() * max_mag;
-            // clamp position to world size
-            agent.pos.x = agent.pos.x.clamp(0.0, self.size.x);
-            agent.pos.y = agent.pos.y.clamp(0.0, self.size.y);
         }
     }
 
-    pub fn place_structures(
+    /// Exposes the agent, recording the infector and the setting in the
+    /// contact graph.
+    fn expose_agent(
         &mut self,
-        counts: HashMap<StructureType, usize>,
-    ) -> Result<(), String> {
-        let x_distro = Uniform::from(0.0..self.size.x);
-        let y_distro = Uniform::from(0.0..self.size.y);
-
-        for (structure, count) in counts.iter() {
-            if !self.structures.contains_key(structure) {
-                self.structures.insert(*structure, Vec::new());
+        agent_id: usize,
+        infector: Option<usize>,
+        strain: usize,
+        setting: Option<Setting>,
+    ) {
+        let disease = match self.strain(strain) {
+            Some(disease) => *disease,
+            None => return,
+        };
+        if let Some(agent) = self.agents.get_agent_mut(agent_id) {
+            let previous = agent.status;
+            let incubation_period = self.decisions.decide(
+                self.curr_step,
+                agent_id,
+                DecisionKind::IncubationPeriod,
+                &mut self.rng,
+                |rng| disease.sample_incubation_period(rng) as f64,
+            );
+            let infectious_period = self.decisions.decide(
+                self.curr_step,
+                agent_id,
+                DecisionKind::InfectiousPeriod,
+                &mut self.rng,
+                |rng| disease.sample_infectious_period(rng) as f64,
+            );
+            agent.expose_for(
+                self.time.abs_time(),
+                incubation_period as i64,
+                infectious_period as i64,
+            );
+            agent.strain = Some(strain);
+            let status = agent.status;
+            self.count_status_change(previous, status);
+            let time = self.time.abs_time();
+            if self.contacts.contains(agent_id) {
+                // reinfected after its immunity waned or with a new strain
+                self.contacts
+                    .add_reinfection(agent_id, infector, time, strain);
+            } else {
+                self.contacts
+                    .add_node(agent_id, infector, time, strain)
+                    .expect("agent was checked to not be in the graph");
             }
-
-            if let Some(structure_vec) = self.structures.get_mut(structure) {
-                for _ in 0..*count {
-                    structure_vec.push(Structure::new_without_capacity(
-                        *structure,
-                        Vec2D::new(
-                            x_distro.sample(&mut self.rng),
-                            y_distro.sample(&mut self.rng),
-                        ),
-                    ));
-                }
+            if let Some(setting) = setting {
+                self.contacts.set_setting(agent_id, setting);
             }
+            self.cumulative_infections += 1;
+            self.events.push(WorldEvent::Exposed {
+                agent: agent_id,
+                by: infector,
+                time,
+            });
         }
-
-        Ok(())
     }
 
-    // TODO(tslnc04): randomly assign structures to agents, take into account
-    // age and changing behavior since schools shouldn't go to older agents and
-    // workplaces not to young agents
-    pub fn assign_structures(&mut self) {
-        if let Some(home_structures) = self.structures.get(&StructureType::Home) {
-            let homes_distro = Uniform::from(0..home_structures.len());
-            for agent in self.agents.iter_mut() {
-                agent.home = home_structures[homes_distro.sample(&mut self.rng)].pos;
+    /// Adds an agent with the given status to the counts of active infections
+    /// and deaths, or takes it away if it is leaving that status.
+    fn count_status(&mut self, status: Status, entering: bool) {
+        let count = match status {
+            Status::Exposed { .. } => &mut self.currently_exposed,
+            Status::Infectious { .. } | Status::Hospitalized { .. } => {
+                &mut self.currently_infectious
             }
+            Status::Dead => &mut self.deaths,
+            Status::Susceptible | Status::Recovered { .. } => return,
+        };
+        if entering {
+            *count += 1;
+        } else {
+            *count = count.saturating_sub(1);
         }
+    }
 
-        if let Some(work_structures) = self.structures.get(&StructureType::Work) {
-            let work_distro = Uniform::from(0..work_structures.len());
-            for agent in self.agents.iter_mut() {
-                agent.work = work_structures[work_distro.sample(&mut self.rng)].pos;
-            }
+    /// Moves an agent that changed status from one count of active infections
+    /// and deaths to another.
+    fn count_status_change(&mut self, previous: Status, status: Status) {
+        if previous != status {
+            self.count_status(previous, false);
+            self.count_status(status, true);
         }
+    }
 
-        if let Some(school_structures) = self.structures.get(&StructureType::School) {
-            let schools_distro = Uniform::from(0..school_structures.len());
-            for agent in self.agents.iter_mut() {
-                agent.school = school_structures[schools_distro.sample(&mut self.rng)].pos;
-            }
-        }
+    /// Sets the counts of active infections and deaths from a full count of
+    /// the agents.
+    fn recount(&mut self, stats: &WorldStats) {
+        self.currently_exposed = stats.exposed;
+        self.currently_infectious = stats.infectious;
+        self.deaths = stats.dead;
     }
 
-    // TODO(tslnc04): determine whether this function is worth keeping
-    #[allow(dead_code)]
-    fn new_structure_map() -> HashMap<StructureType, Vec<Structure>> {
-        HashMap::from([
-            (StructureType::Home, Vec::new()),
-            (StructureType::Work, Vec::new()),
-            (StructureType::School, Vec::new()),
-        ])
+    /// Returns the number of exposures since the simulation began, counting
+    /// agents that were reinfected once per infection.
+    pub fn cumulative_infections(&self) -> i64 {
+        self.cumulative_infections
     }
-}
 
-/// Debug output for World is simply a listing of the agents and their statuses
-impl<R> fmt::Debug for World<R>
-where
-    R: Rng,
-{
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(
-            f,
-            "----- Time {:2}; Infected {} -----",
-            self.curr_step, self.infected
-        )?;
-        for agent in self.agents.iter() {
-            write!(f, "{}", agent)?
+    /// Returns the number of agents that are exposed but not yet infectious.
+    pub fn currently_exposed(&self) -> usize {
+        self.currently_exposed
+    }
+
+    /// Returns the number of agents that are infectious, including those in
+    /// hospital.
+    pub fn currently_infectious(&self) -> usize {
+        self.currently_infectious
+    }
+
+    /// Returns the number of agents that have died, including those removed
+    /// after dying.
+    pub fn deaths(&self) -> usize {
+        self.deaths
+    }
+
+    /// Relative probability of the agent being exposed on contact, accounting
+    /// for immunity, masks, and the agent's own susceptibility.
+    fn susceptibility(&self, agent: &Agent) -> f64 {
+        (1.0 - agent.immunity.clamp(0.0, 1.0))
+            * Self::mask_factor(agent, self.mask_wearer_protection)
+            * agent.relative_susceptibility.max(0.0)
+    }
+
+    /// Scales the factor of a mask by how consistently the agent wears one.
+    fn mask_factor(agent: &Agent, factor: f64) -> f64 {
+        let compliance = agent.mask_compliance.clamp(0.0, 1.0);
+        1.0 - compliance * (1.0 - factor.max(0.0))
+    }
+
+    /// Relative probability of the agent transmitting on contact, accounting
+    /// for symptoms, masks, and the agent's own infectiousness and contacts.
+    fn infectiousness(&self, agent: &Agent) -> f64 {
+        let symptoms = if agent.asymptomatic {
+            self.disease_of(agent).asymptomatic_transmissibility()
+        } else {
+            1.0
+        };
+        symptoms
+            * Self::mask_factor(agent, self.mask_source_control)
+            * agent.relative_infectiousness.max(0.0)
+            * agent.contact_multiplier.max(0.0)
+    }
+
+    /// Draws the contact multiplier of every agent from the gamma distribution
+    /// given by the contact dispersion, in id order, or resets them all to 1
+    /// if there is none. Agents born or imported later draw their own.
+    pub fn draw_contact_multipliers(&mut self) {
+        let gamma = self.contact_dispersion.and_then(Gamma::with_unit_mean);
+        for agent_id in self.agents.get_agent_ids() {
+            let agent = self.agents.get_agent_mut(agent_id).unwrap();
+            agent.contact_multiplier = match gamma {
+                Some(gamma) => gamma.sample(&mut self.rng),
+                None => 1.0,
+            };
         }
+    }
 
-        Ok(())
+    /// Sets the relative susceptibility of every agent from its age in years.
+    pub fn scale_susceptibility_by_age(&mut self, susceptibility: impl Fn(f64) -> f64) {
+        for agent in self.agents.iter_mut() {
+            agent.relative_susceptibility =
+                susceptibility(agent.age as f64 / SECONDS_PER_YEAR as f64);
+        }
     }
-}
 
-/// Display output for World is a visualization of the agents on a grid
-impl<R> fmt::Display for World<R>
-where
-    R: Rng,
-{
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut dead: i64 = 0;
-        for agent in self.agents.iter() {
-            if agent.status.is_dead() {
-                dead += 1;
-            }
+    /// Sets the probability of every agent being hospitalized once it becomes
+    /// infectious with symptoms from its age in years. Agents born later are
+    /// never hospitalized.
+    pub fn set_hospitalization_prob_by_age(&mut self, hospitalization_prob: impl Fn(f64) -> f64) {
+        for agent in self.agents.iter_mut() {
+            agent.hospitalization_prob =
+                hospitalization_prob(agent.age as f64 / SECONDS_PER_YEAR as f64);
         }
-        // since spatial storing of agents hasn't been implemented yet, each
-        // grid square is an O(1) operation that only takes the first agent at a
-        // given grid square. this could be problematic
-        writeln!(
-            f,
-            "----- Time {:2} {}; Infected {}/{}; Dead {}; Step Duration: {} -----",
-            self.curr_step,
-            self.step_size,
-            self.infected,
-            self.agents.len(),
-            dead,
-            self.last_step_duration,
-        )?;
+    }
 
-        for i in 0..self.size.x.ceil() as i64 {
-            for j in 0..self.size.y.ceil() as i64 {
-                let mut structure_found = false;
-                for (structure_type, structures) in self.structures.iter() {
-                    for structure in structures.iter() {
-                        if structure.pos.x.floor() as i64 == i
-                            && structure.pos.y.floor() as i64 == j
-                        {
-                            write!(f, " {} ", structure_type)?;
-                            structure_found = true;
-                            break;
-                        }
+    /// Returns the distance between two points, across the edges of the world
+    /// if they are joined.
+    fn distance(&self, a: Vec2D<f64>, b: Vec2D<f64>) -> f64 {
+        match self.boundary_mode {
+            BoundaryMode::Clamp => a.dist(b),
+            BoundaryMode::Wrap => a.toroidal_dist(b, self.size),
+        }
+    }
+
+    /// Every infectious agent that isn't isolating may expose each susceptible
+    /// agent within the contact radius, weighted by the infection kernel and
+    /// combined according to the transmission model. Successful contacts are
+    /// pushed as (infector, target) exposure events. Contacts with agents that
+    /// are already infected are added to the contact graph as the hours of
+    /// exposure between the two. The neighbors of every living agent are
+    /// reused if they were already found this step.
+    fn propose_spatial_exposures(
+        &mut self,
+        neighbors: Option<&[(usize, Vec<usize>)]>,
+        events: &mut Vec<(usize, usize)>,
+    ) {
+        let hourly_rate = match self.transmission_model {
+            TransmissionModel::PerContactBernoulli => None,
+            TransmissionModel::ForceOfInfection { hourly_rate } => Some(hourly_rate.max(0.0)),
+        };
+        // the hazard each infectious agent puts on each susceptible agent,
+        // with the force of infection model
+        let mut hazards = BTreeMap::<usize, Vec<(usize, f64)>>::new();
+
+        for (agent_id, candidates) in self.find_transmission_candidates(neighbors) {
+            let agent = self.agents.get_agent(agent_id).unwrap();
+            let pos = agent.pos;
+            let strain = agent.strain.unwrap_or(0);
+            let disease = *self.disease_of(agent);
+            let infectiousness = self.infectiousness(agent);
+
+            for other_agent_id in candidates {
+                let other_agent = match self.agents.get_agent(other_agent_id) {
+                    Some(other_agent) if other_agent.is_susceptible_to(strain) => other_agent,
+                    Some(other_agent) if other_agent.status.is_infected() => {
+                        self.contacts
+                            .add_exposure(agent_id, other_agent_id, self.exposure_hours());
+                        continue;
                     }
-                }
+                    _ => continue,
+                };
 
-                if structure_found {
+                let dist = self.distance(pos, other_agent.pos);
+                let weight = self.infection_kernel.weight(dist, self.contact_radius)
+                    * infectiousness
+                    * self.susceptibility(other_agent);
+                if let Some(hourly_rate) = hourly_rate {
+                    let hazard = hourly_rate * weight * disease.transmission_prob.clamp(0.0, 1.0);
+                    if hazard > 0.0 {
+                        hazards
+                            .entry(other_agent_id)
+                            .or_default()
+                            .push((agent_id, hazard));
+                    }
                     continue;
                 }
 
-                let mut agent_found = false;
-                for agent in self.agents.iter() {
-                    if agent.pos.x.round() as i64 == i && agent.pos.y.round() as i64 == j {
-                        write!(f, "{}", agent)?;
-                        agent_found = true;
-                        break;
-                    }
+                let infected = if self.adaptive_stepping.is_some() {
+                    // both rolls are combined so the chance of transmission
+                    // as a whole is converted to the step size
+                    let prob =
+                        self.scale_to_step((weight * disease.transmission_prob).clamp(0.0, 1.0));
+                    self.decisions.decide_bool(
+                        self.curr_step,
+                        other_agent_id,
+                        DecisionKind::Infection,
+                        &mut self.rng,
+                        |rng| rng.gen_bool(prob.clamp(0.0, 1.0)),
+                    )
+                } else if weight > 1.0 {
+                    // a weight above one, such as from a more infectious
+                    // agent, scales the chance of transmission as a whole,
+                    // which a single contact roll would cap at one
+                    let prob = (weight * disease.transmission_prob).clamp(0.0, 1.0);
+                    self.decisions.decide_bool(
+                        self.curr_step,
+                        other_agent_id,
+                        DecisionKind::Infection,
+                        &mut self.rng,
+                        |rng| rng.gen_bool(prob),
+                    )
+                } else {
+                    let prob = weight.clamp(0.0, 1.0);
+                    self.decisions.decide_bool(
+                        self.curr_step,
+                        other_agent_id,
+                        DecisionKind::Infection,
+                        &mut self.rng,
+                        |rng| rng.gen_bool(prob) && disease.will_infect(rng),
+                    )
+                };
+                if infected {
+                    events.push((agent_id, other_agent_id));
                 }
+            }
+        }
 
-                if !agent_found {
-                    write!(f, "   ")?;
-                }
+        for (target, contributions) in hazards {
+            let total = contributions.iter().map(|(_, hazard)| hazard).sum::<f64>();
+            let prob = 1.0 - (-total * self.step_size as f64 / 3600.0).exp();
+            if !self.decisions.decide_bool(
+                self.curr_step,
+                target,
+                DecisionKind::Infection,
+                &mut self.rng,
+                |rng| rng.gen_bool(prob.clamp(0.0, 1.0)),
+            ) {
+                continue;
             }
 
-            writeln!(f)?;
+            let infector = if contributions.len() == 1 {
+                contributions[0].0
+            } else {
+                let weights = WeightedIndex::new(contributions.iter().map(|(_, hazard)| *hazard))
+                    .expect("every hazard is positive");
+                self.decisions.decide_index(
+                    self.curr_step,
+                    target,
+                    DecisionKind::InfectorPick,
+                    &mut self.rng,
+                    |rng| contributions[weights.sample(rng)].0,
+                )
+            };
+            events.push((infector, target));
+        }
+    }
+
+    /// Finds the agents within the contact radius of each infectious agent that
+    /// isn't isolating and that are either susceptible to its strain or
+    /// already infected, in id order of the infectious agents. The neighbors
+    /// are looked up in the given lists, which must be in id order, rather
+    /// than searched for if they are given.
+    fn find_transmission_candidates(
+        &self,
+        neighbors: Option<&[(usize, Vec<usize>)]>,
+    ) -> Vec<(usize, Vec<usize>)> {
+        let infectors = self
+            .agents
+            .get_agent_ids()
+            .into_iter()
+            .filter(|id| {
+                let agent = self.agents.get_agent(*id).unwrap();
+                agent.status.is_infectious() && !agent.isolated
+            })
+            .collect::<Vec<_>>();
+
+        let nearby = match neighbors {
+            Some(neighbors) => infectors
+                .iter()
+                .map(|agent_id| {
+                    let i = neighbors
+                        .binary_search_by_key(agent_id, |(id, _)| *id)
+                        .expect("every living agent has a list of neighbors");
+                    neighbors[i].clone()
+                })
+                .collect(),
+            None => self.find_neighbors(&infectors),
+        };
+
+        nearby
+            .into_iter()
+            .map(|(agent_id, nearby)| {
+                let strain = self.agents.get_agent(agent_id).unwrap().strain.unwrap_or(0);
+                let candidates = nearby
+                    .into_iter()
+                    .filter(|id| {
+                        *id != agent_id
+                            && self.agents.get_agent(*id).is_some_and(|agent| {
+                                agent.is_susceptible_to(strain) || agent.status.is_infected()
+                            })
+                    })
+                    .collect::<Vec<_>>();
+                (agent_id, candidates)
+            })
+            .collect()
+    }
+
+    /// Finds the neighbors of every living agent and adds the number of them to
+    /// the contact count of each agent. Returns the number of distinct pairs
+    /// of neighbors along with the neighbors of each agent in id order, so
+    /// the search can be reused for transmission.
+    fn count_contacts(&mut self) -> (usize, Vec<(usize, Vec<usize>)>) {
+        let living = self
+            .agents
+            .get_agent_ids()
+            .into_iter()
+            .filter(|id| !self.agents.get_agent(*id).unwrap().status.is_dead())
+            .collect::<Vec<_>>();
+        let neighbors = self.find_neighbors(&living);
+
+        let mut pairs = 0;
+        for (agent_id, nearby) in neighbors.iter() {
+            let contacts = nearby
+                .iter()
+                .filter(|id| **id != *agent_id && living.binary_search(id).is_ok())
+                .collect::<Vec<_>>();
+            pairs += contacts.iter().filter(|id| ***id > *agent_id).count();
+            self.agents.get_agent_mut(*agent_id).unwrap().contact_count += contacts.len() as u64;
+        }
+
+        (pairs, neighbors)
+    }
+
+    /// Returns the contact count of every living agent in id order. The
+    /// counts only grow while record_contacts is on.
+    pub fn agent_contact_counts(&self) -> Vec<(usize, u64)> {
+        self.agents
+            .get_agent_ids()
+            .into_iter()
+            .filter_map(|id| {
+                let agent = self.agents.get_agent(id)?;
+                (!agent.status.is_dead()).then_some((id, agent.contact_count))
+            })
+            .collect()
+    }
+
+    /// Finds the agents within the contact radius of each of the given agents,
+    /// including the agent itself, in the same order as the agents. The
+    /// search only reads the world, so it runs in parallel with the rayon
+    /// feature, while anything drawn from the results is still drawn serially.
+    fn find_neighbors(&self, agent_ids: &[usize]) -> Vec<(usize, Vec<usize>)> {
+        let agents = &self.agents;
+        let contact_radius = self.contact_radius;
+        let boundary_mode = self.boundary_mode;
+        let size = self.size;
+
+        let find_nearby = |agent_id: &usize| {
+            let agent = agents.get_agent(*agent_id).unwrap();
+            let nearby = match boundary_mode {
+                BoundaryMode::Clamp => agents.find_agents_in_radius(agent.pos, contact_radius),
+                BoundaryMode::Wrap => {
+                    let mut nearby = wrapped_query_bounds(agent.pos, contact_radius, size)
+                        .into_iter()
+                        .flat_map(|bounds| agents.find_agents_in_bounds(bounds))
+                        .filter(|id| {
+                            agents.get_agent(*id).is_some_and(|other| {
+                                agent.pos.toroidal_dist(other.pos, size) <= contact_radius
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    nearby.sort_unstable();
+                    nearby.dedup();
+                    nearby
+                }
+            };
+            (*agent_id, nearby)
+        };
+
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            agent_ids.par_iter().map(find_nearby).collect()
+        }
+        #[cfg(not(feature = "rayon"))]
+        {
+            agent_ids.iter().map(find_nearby).collect()
+        }
+    }
+
+    /// Advances the disease of every agent by one step. With the rayon feature
+    /// the agents are stepped in parallel, each with its own RNG seeded from
+    /// the world RNG and its id, so seeded runs are still reproducible but
+    /// differ from runs without the feature.
+    fn step_agents(&mut self) {
+        #[cfg(feature = "rayon")]
+        {
+            use crate::agent::StatusChange;
+            use rand::rngs::SmallRng;
+            use rand::SeedableRng;
+            use rayon::iter::ParallelIterator;
+
+            let step_seed = self.rng.gen::<u64>();
+            let now = self.time.abs_time();
+            let step_size = self.step_size;
+            let background_mortality = self.background_mortality;
+            let strains = (0..self.strain_count())
+                .map(|strain| *self.strain(strain).unwrap())
+                .collect::<Vec<_>>();
+            let mut outcomes = self
+                .agents
+                .par_agents_mut()
+                .map(|(agent_id, agent)| {
+                    let seed = step_seed ^ (agent_id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+                    let mut rng = SmallRng::seed_from_u64(seed);
+                    let disease = agent
+                        .strain
+                        .and_then(|strain| strains.get(strain))
+                        .unwrap_or(&strains[0]);
+                    let previous = agent.status;
+                    let change = agent.progress(now, step_size, disease, &mut rng);
+                    let cause =
+                        agent.roll_death(now, step_size, disease, background_mortality, &mut rng);
+                    (agent_id, previous, change, encode_death(cause))
+                })
+                .collect::<Vec<_>>();
+            outcomes.sort_unstable_by_key(|(agent_id, _, _, _)| *agent_id);
+
+            // the rolls made in parallel are logged, or swapped for the
+            // replayed ones, in id order afterwards. The step seed needn't be
+            // logged, since nothing drawn with it is kept otherwise
+            for (agent_id, previous, mut change, cause) in outcomes {
+                if change == Some(StatusChange::BecameInfectious) {
+                    let agent = self.agents.get_agent_mut(agent_id).unwrap();
+                    let asymptomatic = agent.asymptomatic;
+                    agent.asymptomatic = self.decisions.decide_bool(
+                        self.curr_step,
+                        agent_id,
+                        DecisionKind::Asymptomatic,
+                        &mut self.rng,
+                        |_| asymptomatic,
+                    );
+                }
+                let cause = self.decisions.decide_index(
+                    self.curr_step,
+                    agent_id,
+                    DecisionKind::Death,
+                    &mut self.rng,
+                    |_| cause,
+                );
+                let agent = match self.agents.get_agent_mut(agent_id) {
+                    Some(agent) => agent,
+                    None => continue,
+                };
+                if let Some(cause) = decode_death(cause) {
+                    change = agent.kill(cause).or(change);
+                }
+                let status = agent.status;
+                self.count_status_change(previous, status);
+                if let Some(change) = change {
+                    self.events
+                        .push(WorldEvent::from_status_change(agent_id, change, now));
+                }
+            }
+        }
+
+        // agents are visited in id order so that a seeded RNG is consumed
+        // identically between runs
+        #[cfg(not(feature = "rayon"))]
+        for agent_id in self.agents.get_agent_ids() {
+            let disease = match self.agents.get_agent(agent_id) {
+                Some(agent) => *self.disease_of(agent),
+                None => continue,
+            };
+            if let Some(agent) = self.agents.get_agent_mut(agent_id) {
+                let previous = agent.status;
+                let mut change =
+                    agent.progress_with(self.time.abs_time(), self.step_size, &disease, |prob| {
+                        self.decisions.decide_bool(
+                            self.curr_step,
+                            agent_id,
+                            DecisionKind::Asymptomatic,
+                            &mut self.rng,
+                            |rng| rng.gen_bool(prob),
+                        )
+                    });
+                let cause = self.decisions.decide_index(
+                    self.curr_step,
+                    agent_id,
+                    DecisionKind::Death,
+                    &mut self.rng,
+                    |rng| {
+                        encode_death(agent.roll_death(
+                            self.time.abs_time(),
+                            self.step_size,
+                            &disease,
+                            self.background_mortality,
+                            rng,
+                        ))
+                    },
+                );
+                if let Some(cause) = decode_death(cause) {
+                    change = agent.kill(cause).or(change);
+                }
+                let status = agent.status;
+                self.count_status_change(previous, status);
+                if let Some(change) = change {
+                    self.events.push(WorldEvent::from_status_change(
+                        agent_id,
+                        change,
+                        self.time.abs_time(),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Every infectious agent attending its workplace or school may expose
+    /// each susceptible member of the same structure that is also attending.
+    /// The probability of exposure grows with the length of the step.
+    fn propose_cohort_exposures(&mut self, events: &mut Vec<(usize, usize)>) {
+        if self.cohort_transmission_rate <= 0.0 {
+            return;
+        }
+
+        let base_prob =
+            1.0 - (-self.cohort_transmission_rate * self.step_size as f64 / 3600.0).exp();
+
+        let structure_ids = self
+            .structures
+            .iter()
+            .filter(|structure| {
+                matches!(structure.typ, StructureType::Work | StructureType::School)
+                    && !self.is_structure_closed(structure.typ)
+            })
+            .map(|structure| structure.id)
+            .collect::<Vec<_>>();
+
+        for structure_id in structure_ids {
+            // only agents actually at the structure mix with each other
+            let attending = self
+                .structure_members(structure_id)
+                .iter()
+                .copied()
+                .filter(|id| {
+                    self.agents.get_agent(*id).is_some_and(|agent| {
+                        !agent.isolated
+                            && match agent.task {
+                                Task::Work => agent.work_id == Some(structure_id),
+                                Task::School => agent.school_id == Some(structure_id),
+                                _ => false,
+                            }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            for agent_id in attending.iter() {
+                let agent = self.agents.get_agent(*agent_id).unwrap();
+                if !agent.status.is_infectious() {
+                    continue;
+                }
+                let strain = agent.strain.unwrap_or(0);
+                let infectiousness = self.infectiousness(agent);
+
+                for other_agent_id in attending.iter() {
+                    let other_agent = self.agents.get_agent(*other_agent_id).unwrap();
+                    if !other_agent.is_susceptible_to(strain) {
+                        continue;
+                    }
+
+                    let prob = (base_prob * infectiousness * self.susceptibility(other_agent))
+                        .clamp(0.0, 1.0);
+                    if self.decisions.decide_bool(
+                        self.curr_step,
+                        *other_agent_id,
+                        DecisionKind::Infection,
+                        &mut self.rng,
+                        |rng| rng.gen_bool(prob),
+                    ) {
+                        events.push((*agent_id, *other_agent_id));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Once a night, every infectious agent may expose each of its susceptible
+    /// housemates, regardless of where they are. Isolating agents still
+    /// infect their housemates.
+    fn propose_household_exposures(&mut self, events: &mut Vec<(usize, usize)>) {
+        if self.household_transmission_prob <= 0.0 {
+            return;
+        }
+
+        let mut household_ids = self.households.keys().copied().collect::<Vec<_>>();
+        household_ids.sort_unstable();
+
+        for household_id in household_ids {
+            let members = self.households[&household_id].clone();
+            for agent_id in members.iter() {
+                let agent = match self.agents.get_agent(*agent_id) {
+                    Some(agent) if agent.status.is_infectious() => agent,
+                    _ => continue,
+                };
+                let strain = agent.strain.unwrap_or(0);
+                let infectiousness = self.infectiousness(agent);
+
+                for other_agent_id in members.iter() {
+                    let other_agent = match self.agents.get_agent(*other_agent_id) {
+                        Some(other_agent) if other_agent.is_susceptible_to(strain) => other_agent,
+                        _ => continue,
+                    };
+
+                    let prob = (self.household_transmission_prob
+                        * infectiousness
+                        * self.susceptibility(other_agent))
+                    .clamp(0.0, 1.0);
+                    if self.decisions.decide_bool(
+                        self.curr_step,
+                        *other_agent_id,
+                        DecisionKind::Infection,
+                        &mut self.rng,
+                        |rng| rng.gen_bool(prob),
+                    ) {
+                        events.push((*agent_id, *other_agent_id));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the setting of a transmission by the infector where it is now.
+    fn setting_of(&self, infector: usize, nearest_index: &NearestIndex) -> Setting {
+        let pos = match self.agents.get_agent(infector) {
+            Some(agent) => agent.pos,
+            None => return Setting::Community,
+        };
+        // the index only knows about straight line distances, so structures
+        // across the joined edges of a wrapped world are found by a scan
+        let nearest = match self.boundary_mode {
+            BoundaryMode::Clamp => nearest_index.nearest(&self.structures, pos, |_| true),
+            BoundaryMode::Wrap => (0..self.structures.len()).min_by(|a, b| {
+                self.distance(pos, self.structures[*a].pos)
+                    .total_cmp(&self.distance(pos, self.structures[*b].pos))
+            }),
+        };
+        match nearest {
+            Some(id) if self.distance(pos, self.structures[id].pos) <= self.setting_radius => {
+                Setting::Structure {
+                    id,
+                    typ: self.structures[id].typ,
+                }
+            }
+            _ => Setting::Community,
+        }
+    }
+
+    /// Applies the proposed exposure events once every route of transmission
+    /// has been considered, so the order agents are visited in doesn't decide
+    /// who infects whom. A target proposed by several infectors is attributed
+    /// to one of them chosen uniformly at random. The target is infected with
+    /// the strain of its infector, or with a new variant of it with
+    /// probability mutation_prob.
+    fn apply_exposures(&mut self, events: Vec<(usize, usize)>) {
+        let mut infectors_by_target = BTreeMap::<usize, Vec<usize>>::new();
+        for (infector, target) in events {
+            infectors_by_target
+                .entry(target)
+                .or_default()
+                .push(infector);
+        }
+
+        let all_structures = (0..self.structures.len()).collect::<Vec<_>>();
+        let nearest_index = NearestIndex::new(&self.structures, &all_structures);
+
+        for (target, mut infectors) in infectors_by_target {
+            infectors.sort_unstable();
+            infectors.dedup();
+            let infector = if infectors.len() == 1 {
+                infectors[0]
+            } else {
+                self.decisions.decide_index(
+                    self.curr_step,
+                    target,
+                    DecisionKind::InfectorPick,
+                    &mut self.rng,
+                    |rng| *infectors.choose(rng).unwrap(),
+                )
+            };
+            let mut strain = self
+                .agents
+                .get_agent(infector)
+                .and_then(|agent| agent.strain)
+                .unwrap_or(0);
+            let mutation_prob = self.mutation_prob;
+            if mutation_prob > 0.0
+                && self.decisions.decide_bool(
+                    self.curr_step,
+                    target,
+                    DecisionKind::Mutation,
+                    &mut self.rng,
+                    |rng| rng.gen_bool(mutation_prob.min(1.0)),
+                )
+            {
+                let parent = *self.strain(strain).unwrap_or(&self.disease);
+                let mut rng = self.decisions.decide_seed(
+                    self.curr_step,
+                    target,
+                    DecisionKind::Variant,
+                    &mut self.rng,
+                );
+                let variant = parent.mutate(&mut rng);
+                strain = self.add_strain(variant);
+            }
+            let setting = self.setting_of(infector, &nearest_index);
+            self.expose_agent(target, Some(infector), strain, Some(setting));
+            for infector in infectors {
+                self.contacts
+                    .add_exposure(infector, target, self.exposure_hours());
+            }
+        }
+    }
+
+    /// Returns the exposure between two agents in contact for a step, in
+    /// hours, which is what edges in the contact graph are weighted by.
+    fn exposure_hours(&self) -> f64 {
+        self.step_size as f64 / 3600.0
+    }
+
+    /// Groups the living agents into households, with sizes drawn from the
+    /// given distribution, where the first entry is the relative weight of a
+    /// household of one, the second of a household of two, and so on. Each
+    /// household is assigned a single home with room for it if possible, and
+    /// every member shares that home. Replaces any existing households.
+    pub fn assign_households(&mut self, size_distribution: &[f64]) -> Result<(), String> {
+        let sizes = WeightedIndex::new(size_distribution)
+            .map_err(|err| format!("invalid household size distribution: {}", err))?;
+
+        let mut agent_ids = self
+            .agents
+            .get_agent_ids()
+            .into_iter()
+            .filter(|id| !self.agents.get_agent(*id).unwrap().status.is_dead())
+            .collect::<Vec<_>>();
+        agent_ids.shuffle(&mut self.rng);
+
+        self.households.clear();
+        let home_ids = self
+            .structure_ids
+            .get(&StructureType::Home)
+            .cloned()
+            .unwrap_or_default();
+        for home_id in home_ids.iter() {
+            self.structures[*home_id].occupancy = 0;
+            self.structure_members.insert(*home_id, Vec::new());
+        }
+
+        let mut remaining = agent_ids.as_slice();
+        let mut household_id = 0;
+        while !remaining.is_empty() {
+            let size = (sizes.sample(&mut self.rng) + 1).min(remaining.len());
+            let (members, rest) = remaining.split_at(size);
+            remaining = rest;
+
+            let (home, home_id) = if home_ids.is_empty() {
+                (Vec2D::new_nan(), None)
+            } else {
+                let home_id = self.decisions.decide_index(
+                    self.curr_step,
+                    members[0],
+                    DecisionKind::StructurePick,
+                    &mut self.rng,
+                    |rng| Self::choose_structure(&self.structures, &home_ids, rng),
+                );
+                let home = &mut self.structures[home_id];
+                home.occupancy += size as i64;
+                (home.pos, Some(home_id))
+            };
+
+            for agent_id in members {
+                let agent = self.agents.get_agent_mut(*agent_id).unwrap();
+                agent.home = home;
+                agent.home_id = home_id;
+                agent.household = Some(household_id);
+            }
+            if let Some(home_id) = home_id {
+                let home_members = self.structure_members.entry(home_id).or_default();
+                home_members.extend_from_slice(members);
+                home_members.sort_unstable();
+            }
+
+            let mut members = members.to_vec();
+            members.sort_unstable();
+            self.households.insert(household_id, members);
+            household_id += 1;
         }
 
         Ok(())
     }
-}
 
-const RED: &str = "\x1b[0;31m";
-const ORANGE: &str = "\x1b[1;31m";
-const YELLOW: &str = "\x1b[0;33m";
-const GREEN: &str = "\x1b[0;32m";
-const RESET: &str = "\x1b[0m";
-const BLUE: &str = "\x1b[0;34m";
+    /// Returns the members of each household, keyed by household id.
+    pub fn households(&self) -> &HashMap<usize, Vec<usize>> {
+        &self.households
+    }
+
+    /// Starts or lifts a lockdown. When a lockdown starts, each living agent is
+    /// sent home with probability `lockdown_compliance` until it is lifted.
+    pub fn set_lockdown(&mut self, active: bool) {
+        if active == self.lockdown_active {
+            return;
+        }
+        self.lockdown_active = active;
+
+        let compliance = self.lockdown_compliance.clamp(0.0, 1.0);
+        for agent_id in self.agents.get_agent_ids() {
+            let agent = self.agents.get_agent_mut(agent_id).unwrap();
+            agent.locked_down = active
+                && !agent.status.is_dead()
+                && self.decisions.decide_bool(
+                    self.curr_step,
+                    agent_id,
+                    DecisionKind::Lockdown,
+                    &mut self.rng,
+                    |rng| rng.gen_bool(compliance),
+                );
+        }
+    }
+
+    pub fn is_lockdown_active(&self) -> bool {
+        self.lockdown_active
+    }
+
+    /// Starts or lifts a lockdown if the prevalence crosses one of the
+    /// thresholds of the lockdown trigger.
+    fn check_lockdown_trigger(&mut self) {
+        let policy = match self.lockdown_trigger {
+            Some(policy) => policy,
+            None => return,
+        };
+
+        let stats = self.current_stats();
+        let living = stats.total() - stats.dead;
+        if living == 0 {
+            return;
+        }
+        let prevalence = stats.infectious as f64 / living as f64;
+
+        if !self.lockdown_active && prevalence >= policy.activate_prevalence {
+            self.set_lockdown(true);
+        } else if self.lockdown_active && prevalence <= policy.deactivate_prevalence {
+            self.set_lockdown(false);
+        }
+    }
+
+    /// Closes or reopens every structure of the given type, independently of
+    /// any lockdown. Agents whose task would take them to a closed workplace
+    /// or school head home instead, and closed structures don't mix their
+    /// members. Closing homes or hospitals is recorded, but doesn't change
+    /// where agents go.
+    pub fn set_structure_closure(&mut self, typ: StructureType, closed: bool) {
+        if closed {
+            self.closures |= typ.closure_bit();
+        } else {
+            self.closures &= !typ.closure_bit();
+        }
+    }
+
+    pub fn is_structure_closed(&self, typ: StructureType) -> bool {
+        self.closures & typ.closure_bit() != 0
+    }
+
+    /// Returns the mask of the closure bits of every closed structure type.
+    pub fn structure_closures(&self) -> u8 {
+        self.closures
+    }
+
+    /// Schedules closing every structure of the given type at the start time
+    /// and reopening them at the end time, see schedule_at.
+    pub fn schedule_structure_closure(&mut self, typ: StructureType, start: i64, end: i64) {
+        self.schedule_at(
+            start,
+            Box::new(move |world: &mut World<R>| world.set_structure_closure(typ, true)),
+        );
+        self.schedule_at(
+            end,
+            Box::new(move |world: &mut World<R>| world.set_structure_closure(typ, false)),
+        );
+    }
+
+    /// Schedules an action to run once, at the start of the step that covers
+    /// the given absolute time. Actions scheduled for a time that has already
+    /// passed run at the start of the next step.
+    pub fn schedule_at(&mut self, abs_time: i64, action: OnceAction<R>) {
+        self.push_action(abs_time, Action::Once(action));
+    }
+
+    /// Schedules an action to run every interval seconds, starting one interval
+    /// from now. If a step covers several intervals, the action runs once for
+    /// each of them. The interval is at least one second.
+    pub fn schedule_every(&mut self, interval: i64, action: RepeatedAction<R>) {
+        let interval = interval.max(1);
+        self.push_action(
+            self.time.abs_time() + interval,
+            Action::Every(interval, action),
+        );
+    }
+
+    fn push_action(&mut self, time: i64, action: Action<R>) {
+        self.scheduled.push(ScheduledAction {
+            time,
+            seq: self.next_action_seq,
+            action,
+        });
+        self.next_action_seq += 1;
+    }
+
+    /// Runs every action due before the end of the coming step, earliest
+    /// first. Each action is taken out of the world before it runs, so it can
+    /// freely mutate the world and schedule further actions, which also run
+    /// if they are due.
+    fn run_scheduled_actions(&mut self) {
+        let end = self.time.abs_time() + self.step_size;
+
+        loop {
+            let next = self
+                .scheduled
+                .iter()
+                .enumerate()
+                .filter(|(_, scheduled)| scheduled.time < end)
+                .min_by_key(|(_, scheduled)| (scheduled.time, scheduled.seq))
+                .map(|(i, _)| i);
+            let scheduled = match next {
+                Some(i) => self.scheduled.swap_remove(i),
+                None => break,
+            };
+
+            match scheduled.action {
+                Action::Once(action) => action(self),
+                Action::Every(interval, mut action) => {
+                    action(self);
+                    self.scheduled.push(ScheduledAction {
+                        time: scheduled.time + interval,
+                        action: Action::Every(interval, action),
+                        ..scheduled
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn step(&mut self) {
+        let now = Instant::now();
+        let infected_before = self.cumulative_infections;
+        self.events.clear();
+        self.phase_durations.clear();
+        let mut tally = StepTally::default();
+        for phase in self.pipeline.clone() {
+            let phase_start = Instant::now();
+            self.run_phase(phase, &mut tally);
+            *self.phase_durations.entry(phase).or_insert(0) += phase_start.elapsed().as_micros();
+        }
+
+        self.curr_step += 1;
+        self.record_positions();
+
+        self.time.advance(self.step_size);
+        self.last_step_size = self.step_size;
+        let step_duration = now.elapsed();
+        self.timings
+            .record(&self.phase_durations, step_duration.as_micros());
+        #[allow(deprecated)]
+        {
+            self.last_step_duration = step_duration.as_millis();
+        }
+
+        let mut stats = self.current_stats();
+        stats.new_infections = (self.cumulative_infections - infected_before) as usize;
+        stats.step_duration_ms = step_duration.as_millis();
+        stats.lockdown = self.lockdown_active;
+        stats.closures = self.closures;
+        stats.births = tally.births;
+        stats.contacts = tally.contacts;
+        stats.tests_administered = self.tests_administered;
+        stats.tests_returned = tally.tests_returned;
+        stats.positive_tests = tally.positive_tests;
+        self.history.push(stats);
+        self.last_stats = Some(stats);
+        self.strain_history.push(self.infectious_by_strain());
+        self.record_group_stats();
+        self.adapt_step_size(stats.new_infections);
+
+        // debug builds check the cheap invariants after every step, and the
+        // debug-checks feature checks all of them
+        #[cfg(any(debug_assertions, feature = "debug-checks"))]
+        {
+            let audited = if cfg!(feature = "debug-checks") {
+                self.audit()
+            } else {
+                self.audit_cheap()
+            };
+            if let Err(violations) = audited {
+                panic!(
+                    "audit failed after step {}: {}",
+                    self.curr_step,
+                    violations.join("; ")
+                );
+            }
+        }
+    }
+
+    /// Whether the last step crossed midnight, or no step has been taken yet,
+    /// which is when once-a-day events happen.
+    fn is_start_of_day(&self) -> bool {
+        self.time.day_time() < self.last_step_size.max(1)
+    }
+
+    /// Returns the current simulation time.
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Computes the number of agents in each compartment as of now, including
+    /// agents that have been removed after dying.
+    pub fn current_stats(&self) -> WorldStats {
+        WorldStats::from_agents(
+            self.curr_step,
+            self.time.abs_time,
+            self.agents
+                .iter()
+                .chain(self.deceased.iter().map(|(_, agent)| agent)),
+        )
+    }
+
+    /// Adds the newborns of one step, each a susceptible agent of age 0 placed
+    /// at and assigned to a random home. The expected number of births is the
+    /// birth rate scaled to the step and the living population, with the
+    /// fractional part becoming the chance of one more birth. Nobody is born
+    /// in a world without homes. Returns the number of births.
+    fn spawn_births(&mut self) -> usize {
+        if self.birth_rate <= 0.0 {
+            return 0;
+        }
+        let home_ids = match self.structure_ids.get(&StructureType::Home) {
+            Some(home_ids) if !home_ids.is_empty() => home_ids,
+            _ => return 0,
+        };
+
+        let living = self
+            .agents
+            .iter()
+            .filter(|agent| !agent.status.is_dead())
+            .count();
+        let expected =
+            self.birth_rate * living as f64 * self.step_size as f64 / SECONDS_PER_YEAR as f64;
+        let mut rng = self.decisions.decide_seed(
+            self.curr_step,
+            self.agents.next_agent_id(),
+            DecisionKind::Births,
+            &mut self.rng,
+        );
+        let births =
+            expected.floor() as usize + rng.gen_bool(expected.fract().clamp(0.0, 1.0)) as usize;
+
+        for _ in 0..births {
+            let index = rng.gen_range(0..home_ids.len());
+            let home = &mut self.structures[home_ids[index]];
+            let movement = MovementProfile::uniform(DEFAULT_SPEED_RANGE, &mut rng)
+                .expect("the default speeds are valid");
+            let mut agent = Agent::new_with_age(home.pos, movement, 0);
+            if let Some(gamma) = self.contact_dispersion.and_then(Gamma::with_unit_mean) {
+                agent.contact_multiplier = gamma.sample(&mut rng);
+            }
+            agent.home = home.pos;
+            agent.home_id = Some(home.id);
+            agent.home_region = self
+                .regions
+                .iter()
+                .position(|region| region.bounds.contains(home.pos));
+
+            let agent_id = self.agents.next_agent_id();
+            self.agents
+                .add_agent(agent)
+                .expect("homes are placed within the world");
+            home.occupancy += 1;
+            // ids only ever grow, so the members stay sorted
+            self.structure_members
+                .entry(home.id)
+                .or_default()
+                .push(agent_id);
+        }
+
+        births
+    }
+
+    /// Adds the agent to the world and returns its id. Its position is clamped
+    /// into the world. An agent that is already exposed or infectious counts
+    /// as an infection and becomes a root of the contact graph, with the
+    /// original strain unless it has one. Fails without adding the agent if
+    /// its position is NaN.
+    pub fn add_agent(&mut self, mut agent: Agent) -> Result<usize, Error> {
+        let agent_id = self.agents.next_agent_id();
+        // clamping would quietly move a NaN position into a corner
+        if agent.pos.x.is_nan() || agent.pos.y.is_nan() {
+            return Err(Error::AgentOutOfBounds {
+                id: agent_id,
+                pos: agent.pos,
+            });
+        }
+        agent.pos = Rect::new(Vec2D::new_zero(), self.size).clamp_point(agent.pos);
+
+        let infected = agent.status.is_infected();
+        if infected && agent.strain.is_none() {
+            agent.strain = Some(0);
+        }
+        let strain = agent.strain.unwrap_or(0);
+        self.count_status(agent.status, true);
+
+        self.agents
+            .add_agent(agent)
+            .expect("the agent was clamped into the world");
+
+        if infected {
+            let time = self.time.abs_time();
+            self.contacts
+                .add_node(agent_id, None, time, strain)
+                .expect("new agents are never in the graph");
+            self.cumulative_infections += 1;
+            self.events.push(WorldEvent::Exposed {
+                agent: agent_id,
+                by: None,
+                time,
+            });
+        }
+
+        Ok(agent_id)
+    }
+
+    /// Removes the agent from the world entirely, such as a traveler leaving,
+    /// and returns it. The agent leaves its household and structures and
+    /// frees its hospital bed, but its history in the contact graph is kept.
+    pub fn remove_agent(&mut self, agent_id: usize) -> Option<Agent> {
+        if self.agents.get_agent(agent_id)?.hospital_id.is_some() {
+            self.discharge_from_hospital(agent_id);
+        }
+        let agent = self.agents.remove_agent(agent_id)?;
+        self.count_status(agent.status, false);
+
+        for structure_id in [agent.home_id, agent.work_id, agent.school_id]
+            .into_iter()
+            .flatten()
+        {
+            if let Some(members) = self.structure_members.get_mut(&structure_id) {
+                members.retain(|id| *id != agent_id);
+            }
+            if let Some(structure) = self.structures.get_mut(structure_id) {
+                structure.occupancy -= 1;
+            }
+        }
+        if let Some(members) = agent
+            .household
+            .and_then(|household| self.households.get_mut(&household))
+        {
+            members.retain(|id| *id != agent_id);
+        }
+        self.pending_tests.retain(|test| test.agent_id != agent_id);
+
+        Some(agent)
+    }
+
+    /// Adds an exposed traveler with the original strain at the position and
+    /// returns its id. The traveler is an adult of working age, but has no
+    /// home or other structures, so it stays where it arrived. Fails if the
+    /// position is NaN.
+    pub fn import_case_at(&mut self, pos: Vec2D<f64>) -> Result<usize, Error> {
+        let movement = MovementProfile::uniform(DEFAULT_SPEED_RANGE, &mut self.rng)
+            .expect("the default speeds are valid");
+        let age = self
+            .rng
+            .gen_range(self.school_age_cutoff..=self.retirement_age.max(self.school_age_cutoff));
+        let mut agent = Agent::new_with_age(pos, movement, age);
+        if let Some(gamma) = self.contact_dispersion.and_then(Gamma::with_unit_mean) {
+            agent.contact_multiplier = gamma.sample(&mut self.rng);
+        }
+        let disease = self.disease;
+        agent.expose(self.time.abs_time(), &disease, &mut self.rng);
+        agent.strain = Some(0);
+        self.add_agent(agent)
+    }
+
+    /// Removes every dead agent from the quadtree and moves it into the
+    /// deceased archive. Since the quadtree never reuses ids, the ids in the
+    /// contact graph remain valid.
+    pub fn remove_dead_agents(&mut self) {
+        for agent_id in self.agents.get_agent_ids() {
+            let is_dead = self
+                .agents
+                .get_agent(agent_id)
+                .is_some_and(|agent| agent.status.is_dead());
+            if !is_dead {
+                continue;
+            }
+
+            if let Some(agent) = self.agents.remove_agent(agent_id) {
+                self.deceased.push((agent_id, agent));
+            }
+        }
+    }
+
+    /// Returns the agents that were removed after dying, with their ids.
+    pub fn deceased(&self) -> &[(usize, Agent)] {
+        &self.deceased
+    }
+
+    /// Returns the status changes made since the start of the last step that
+    /// were kept, in the order they happened. Changes made outside of a
+    /// step, such as seeding index cases, are kept until the next step
+    /// starts. See set_event_retention.
+    pub fn events(&self) -> &[WorldEvent] {
+        self.events.entries()
+    }
+
+    /// Takes the status changes made since the start of the last step, so
+    /// that each is only seen once.
+    pub fn drain_events(&mut self) -> Vec<WorldEvent> {
+        self.events.take()
+    }
+
+    /// Returns the stats recorded at the end of each step that were kept,
+    /// oldest first. See set_history_retention.
+    pub fn history(&self) -> &[WorldStats] {
+        self.history.entries()
+    }
+
+    /// Returns the stats recorded at the end of the last step, even if the
+    /// history doesn't keep them, or None before the first step.
+    pub fn last_stats(&self) -> Option<&WorldStats> {
+        self.last_stats.as_ref()
+    }
+
+    /// Writes the history that was kept as CSV, one row per step. See
+    /// `stats::write_csv` for the columns.
+    pub fn write_stats_csv<W: std::io::Write>(&self, w: W) -> std::io::Result<()> {
+        stats::write_csv(self.history(), w)
+    }
+
+    /// Rolls for the detection of every infectious agent according to the
+    /// isolation policy, and isolates detected agents that comply. Newly
+    /// symptomatic agents may also isolate on their own. Agents that are no
+    /// longer infectious stop isolating. Returns the ids of the agents
+    /// detected during this call.
+    fn detect_cases(&mut self) -> Vec<usize> {
+        let mut detected = Vec::new();
+        // statuses changed during the step start at its end
+        let step_end = self.time.abs_time() + self.step_size;
+
+        for agent_id in self.agents.get_agent_ids() {
+            let agent = self.agents.get_agent_mut(agent_id).unwrap();
+            let infectious_time = match agent.status {
+                Status::Infectious { since } | Status::Hospitalized { since } => step_end - since,
+                _ => {
+                    // quarantined agents are held until their quarantine is up
+                    // even if they aren't infectious
+                    let quarantined = agent
+                        .quarantine_end
+                        .is_some_and(|end| end > self.time.abs_time() && !agent.status.is_dead());
+                    if !quarantined {
+                        agent.isolated = false;
+                        agent.quarantine_end = None;
+                    }
+                    agent.detected = false;
+                    continue;
+                }
+            };
+
+            // symptomatic agents may isolate as soon as they show symptoms,
+            // without needing to be detected
+            if agent.status == (Status::Infectious { since: step_end })
+                && !agent.asymptomatic
+                && self.self_isolation_prob > 0.0
+                && self.decisions.decide_bool(
+                    self.curr_step,
+                    agent_id,
+                    DecisionKind::Isolation,
+                    &mut self.rng,
+                    |rng| rng.gen_bool(self.self_isolation_prob.clamp(0.0, 1.0)),
+                )
+            {
+                agent.isolated = true;
+            }
+
+            let policy = match self.isolation_policy {
+                Some(policy) => policy,
+                None => continue,
+            };
+
+            if agent.detected
+                || infectious_time < policy.delay
+                || !self.decisions.decide_bool(
+                    self.curr_step,
+                    agent_id,
+                    DecisionKind::Detection,
+                    &mut self.rng,
+                    |rng| rng.gen_bool(policy.detection_prob.clamp(0.0, 1.0)),
+                )
+            {
+                continue;
+            }
+
+            agent.detected = true;
+            if self.decisions.decide_bool(
+                self.curr_step,
+                agent_id,
+                DecisionKind::Isolation,
+                &mut self.rng,
+                |rng| rng.gen_bool(policy.compliance.clamp(0.0, 1.0)),
+            ) {
+                agent.isolated = true;
+            }
+            detected.push(agent_id);
+        }
+
+        detected
+    }
+
+    /// Hospitalizes newly symptomatic agents with their probability of
+    /// hospitalization, admitting them to the nearest hospital with a free
+    /// bed. Hospitalized agents that didn't get a bed stay home at greater
+    /// risk, and are admitted once a bed frees up. Agents that stopped being
+    /// hospitalized free their beds.
+    fn update_hospitalizations(&mut self) {
+        let time = self.time.abs_time();
+        // statuses changed during the step start at its end
+        let step_end = time + self.step_size;
+
+        for agent_id in self.agents.get_agent_ids() {
+            let agent = self.agents.get_agent(agent_id).unwrap();
+            match agent.status {
+                Status::Infectious { since }
+                    if since == step_end
+                        && !agent.asymptomatic
+                        && agent.hospitalization_prob > 0.0 =>
+                {
+                    let prob = agent.hospitalization_prob.clamp(0.0, 1.0);
+                    if !self.decisions.decide_bool(
+                        self.curr_step,
+                        agent_id,
+                        DecisionKind::Hospitalization,
+                        &mut self.rng,
+                        |rng| rng.gen_bool(prob),
+                    ) {
+                        continue;
+                    }
+                    self.agents.get_agent_mut(agent_id).unwrap().status =
+                        Status::Hospitalized { since };
+                    self.events.push(WorldEvent::Hospitalized {
+                        agent: agent_id,
+                        time,
+                    });
+                    self.admit_to_hospital(agent_id);
+                }
+                Status::Hospitalized { .. } if agent.hospital_id.is_none() => {
+                    self.admit_to_hospital(agent_id);
+                }
+                _ if !agent.status.is_hospitalized() && agent.hospital_id.is_some() => {
+                    self.discharge_from_hospital(agent_id);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Admits the agent to the nearest hospital with a free bed, or leaves it
+    /// at home at the overload mortality if there is none.
+    fn admit_to_hospital(&mut self, agent_id: usize) {
+        let pos = self.agents.get_agent(agent_id).unwrap().pos;
+        let hospital_id = self
+            .structures_of_type(StructureType::Hospital)
+            .filter(|hospital| hospital.has_room())
+            .min_by(|a, b| pos.dist(a.pos).total_cmp(&pos.dist(b.pos)))
+            .map(|hospital| hospital.id);
+
+        let agent = self.agents.get_agent_mut(agent_id).unwrap();
+
+        match hospital_id {
+            Some(hospital_id) => {
+                let hospital = &mut self.structures[hospital_id];
+                hospital.occupancy += 1;
+                agent.hospital = hospital.pos;
+                agent.hospital_id = Some(hospital.id);
+                agent.mortality_multiplier = self.hospital_mortality_multiplier;
+            }
+            None => agent.mortality_multiplier = self.overload_mortality_multiplier,
+        }
+    }
+
+    /// Frees the bed of the agent in its hospital.
+    fn discharge_from_hospital(&mut self, agent_id: usize) {
+        let agent = self.agents.get_agent_mut(agent_id).unwrap();
+        let hospital_id = agent.hospital_id.take();
+        agent.hospital = Vec2D::new_nan();
+
+        if let Some(hospital) = hospital_id.and_then(|id| self.structures.get_mut(id)) {
+            hospital.occupancy -= 1;
+        }
+    }
+
+    /// Returns the ids of the agents infected by the given agent, up to the
+    /// given number of transmission links away.
+    pub fn trace_contacts(&self, agent_id: usize, depth: usize) -> Vec<usize> {
+        self.contacts.trace(agent_id, depth, false)
+    }
+
+    /// Traces the contacts of each of the detected agents according to the
+    /// tracing policy and quarantines them. Agents that are already isolating
+    /// or are dead are left alone.
+    fn quarantine_contacts(&mut self, detected: &[usize]) {
+        let policy = match self.tracing_policy {
+            Some(policy) => policy,
+            None => return,
+        };
+
+        for agent_id in detected {
+            for contact_id in self
+                .contacts
+                .trace(*agent_id, policy.depth, policy.trace_parent)
+            {
+                let contact = match self.agents.get_agent_mut(contact_id) {
+                    Some(contact) if !contact.isolated && !contact.status.is_dead() => contact,
+                    _ => continue,
+                };
+
+                if self.decisions.decide_bool(
+                    self.curr_step,
+                    contact_id,
+                    DecisionKind::Quarantine,
+                    &mut self.rng,
+                    |rng| rng.gen_bool(policy.quarantine_prob.clamp(0.0, 1.0)),
+                ) {
+                    contact.isolated = true;
+                    contact.quarantine_end = Some(self.time.abs_time() + policy.quarantine_secs);
+                }
+            }
+        }
+    }
+
+    /// Sets the task of every agent based on the time of day and day of the
+    /// week. See task_at.
+    fn update_tasks(&mut self) {
+        for agent in self.agents.iter_mut() {
+            let task = Self::task_at(
+                agent,
+                self.time,
+                &self.schedule,
+                &self.dwell_times,
+                self.lockdown_active,
+                self.closures,
+            );
+            if task != agent.task {
+                agent.task = task;
+                agent.arrived_at = None;
+            }
+        }
+    }
+
+    /// Returns the task the agent should have at the given time. On weekdays,
+    /// agents with a workplace go to work during work hours and agents with a
+    /// school go to school during school hours. Otherwise, and whenever they
+    /// are isolating or locked down, agents head home, as they do instead of
+    /// going to a closed workplace or school. Agents that arrived at their
+    /// destination less than its dwell time ago keep their task, unless they
+    /// are sent home or it closed. Hospitalized agents stay in their hospital,
+    /// or at home if they don't have a bed, and travelers stay where they are
+    /// until their trip is over.
+    fn task_at(
+        agent: &Agent,
+        time: Time,
+        schedule: &Schedule,
+        dwell_times: &DwellTimes,
+        lockdown_active: bool,
+        closures: u8,
+    ) -> Task {
+        if agent.status.is_hospitalized() {
+            return if agent.hospital.is_nan() {
+                Task::Home
+            } else {
+                Task::Hospital
+            };
+        }
+        if agent.trip_end.is_some() {
+            return Task::Travel;
+        }
+        if agent.isolated || (lockdown_active && agent.locked_down) {
+            return Task::Home;
+        }
+
+        let is_open = |task: Task| {
+            let typ = match task {
+                Task::Work => StructureType::Work,
+                Task::School => StructureType::School,
+                _ => return true,
+            };
+            closures & typ.closure_bit() == 0
+        };
+
+        let dwelling = agent.arrived_at.is_some_and(|arrived_at| {
+            time.abs_time() - arrived_at < dwell_times.for_task(agent.task)
+        });
+        if dwelling && is_open(agent.task) {
+            return agent.task;
+        }
+
+        let is_weekday = time.is_weekday();
+        let day_time = time.day_time();
+        match agent.daytime_task() {
+            Task::Work if is_weekday && schedule.is_work_hours(day_time) && is_open(Task::Work) => {
+                Task::Work
+            }
+            Task::School
+                if is_weekday && schedule.is_school_hours(day_time) && is_open(Task::School) =>
+            {
+                Task::School
+            }
+            _ => Task::Home,
+        }
+    }
+
+    /// Returns how many seconds after the given time the task of the agent
+    /// next changes, and the task it changes to, looking no further than the
+    /// given number of seconds. Tasks only change at the boundaries of the
+    /// schedule, at midnight, or when a dwell time ends.
+    fn next_task_change(&self, agent: &Agent, time: Time, within: i64) -> Option<(i64, Task)> {
+        let mut offsets = Vec::new();
+        for boundary in [
+            0,
+            self.schedule.work_start,
+            self.schedule.work_end,
+            self.schedule.school_start,
+            self.schedule.school_end,
+        ] {
+            let mut offset = (boundary - time.day_time()).rem_euclid(86400);
+            if offset == 0 {
+                offset = 86400;
+            }
+            while offset <= within {
+                offsets.push(offset);
+                offset += 86400;
+            }
+        }
+        if let Some(arrived_at) = agent.arrived_at {
+            let offset = arrived_at + self.dwell_times.for_task(agent.task) - time.abs_time();
+            if offset > 0 && offset <= within {
+                offsets.push(offset);
+            }
+        }
+        offsets.sort_unstable();
+        offsets.dedup();
+
+        offsets.into_iter().find_map(|offset| {
+            let mut later = time;
+            later.advance(offset);
+            let task = Self::task_at(
+                agent,
+                later,
+                &self.schedule,
+                &self.dwell_times,
+                self.lockdown_active,
+                self.closures,
+            );
+            (task != agent.task).then_some((offset, task))
+        })
+    }
+
+    /// Moves every living agent for the length of the step. Agents travel
+    /// toward the destination of their task and stay there once they arrive,
+    /// but if the schedule sends them somewhere else before the step is over,
+    /// they set off again with the time they have left, so a long step can
+    /// cover several legs. With speed jitter, each agent travels slower by a
+    /// random fraction for the whole step.
+    fn move_agents(&mut self) {
+        let jitter = self.speed_jitter.clamp(0.0, 1.0);
+        let step_size = self.step_size;
+        let bounds = Rect::new(Vec2D::new_zero(), self.size);
+
+        for agent_id in self.agents.get_agent_ids() {
+            let mut agent = match self.agents.get_agent(agent_id) {
+                Some(agent) if !agent.status.is_dead() => agent.clone(),
+                _ => continue,
+            };
+
+            let speed = if jitter > 0.0 {
+                let fraction = self.decisions.decide(
+                    self.curr_step,
+                    agent_id,
+                    DecisionKind::Jitter,
+                    &mut self.rng,
+                    |rng| rng.gen::<f64>(),
+                );
+                agent.movement.speed_per_sec() * (1.0 - jitter * fraction)
+            } else {
+                agent.movement.speed_per_sec()
+            };
+            let mut pos = agent.pos;
+            // seconds into the step
+            let mut elapsed = 0.0;
+
+            // every pass either ends the step, moves time forward, or changes
+            // the task, and tasks can only change a handful of times a day, so
+            // the bound is only a safeguard
+            for _ in 0..16 * (1 + step_size / 86400) {
+                let mut dest = match agent.task {
+                    Task::Home => agent.home,
+                    Task::Work => agent.work,
+                    Task::None => agent.home,
+                    Task::School => agent.school,
+                    Task::Hospital => agent.hospital,
+                    Task::Travel => agent.travel_dest,
+                };
+
+                // agents aren't sent toward structures they were never assigned
+                if dest.is_nan() {
+                    agent.task = Task::Home;
+                    agent.arrived_at = None;
+                    dest = agent.home;
+                }
+                if dest.is_nan() {
+                    break;
+                }
+                let radius = self.settle_radii.for_task(agent.task);
+                if radius > 0.0 {
+                    // the spot is kept inside the world so that it can be
+                    // reached
+                    dest += SettleRadii::offset(agent_id, radius);
+                    dest = match self.boundary_mode {
+                        BoundaryMode::Clamp => bounds.clamp_point(dest),
+                        BoundaryMode::Wrap => dest.wrap(self.size),
+                    };
+                }
+
+                let dir = match self.boundary_mode {
+                    BoundaryMode::Clamp => dest - pos,
+                    BoundaryMode::Wrap => pos.toroidal_offset(dest, self.size),
+                };
+                let dist = dir.mag();
+                let remaining = step_size as f64 - elapsed;
+
+                // agents stay put once they arrive, until the schedule sends
+                // them somewhere else
+                if dist >= 1e-6 {
+                    // profiles only hold valid speeds, but one deserialized
+                    // from elsewhere mustn't teleport the agent
+                    if !speed.is_finite() || speed <= 0.0 {
+                        break;
+                    }
+                    let travel_time = dist / speed;
+                    if travel_time > remaining {
+                        pos += dir.normalize() * (speed * remaining);
+                        agent.commute_time += remaining;
+                        break;
+                    }
+
+                    pos = dest;
+                    elapsed += travel_time;
+                    agent.commute_time += travel_time;
+                    agent.arrived_at = Some(self.time.abs_time() + elapsed.ceil() as i64);
+                } else if agent.arrived_at.is_none() {
+                    agent.arrived_at = Some(self.time.abs_time() + elapsed.ceil() as i64);
+                }
+
+                let mut now = self.time;
+                now.advance(elapsed.ceil() as i64);
+                let task = Self::task_at(
+                    &agent,
+                    now,
+                    &self.schedule,
+                    &self.dwell_times,
+                    self.lockdown_active,
+                    self.closures,
+                );
+                if task != agent.task {
+                    agent.task = task;
+                    agent.arrived_at = None;
+                    continue;
+                }
+
+                let within = step_size - elapsed.ceil() as i64;
+                match self.next_task_change(&agent, now, within - 1) {
+                    Some((offset, task)) => {
+                        elapsed = (elapsed.ceil() as i64 + offset) as f64;
+                        agent.task = task;
+                        agent.arrived_at = None;
+                    }
+                    None => break,
+                }
+            }
+
+            // destinations outside the world are approached but never reached
+            let pos = match self.boundary_mode {
+                BoundaryMode::Clamp => bounds.clamp_point(pos),
+                BoundaryMode::Wrap => bounds.clamp_point(pos.wrap(self.size)),
+            };
+            // the quadtree updates the position itself when moving the agent
+            if let Some(slot) = self.agents.get_agent_mut(agent_id) {
+                *slot = agent;
+            }
+            self.agents
+                .move_agent(agent_id, pos)
+                .expect("living agents are in the tree and clamped into the world");
+        }
+    }
+
+    /// Moves the living agent straight to the position, such as to teleport
+    /// it, without changing its task or destination. Fails if there is no
+    /// living agent with the id or the position is outside of the world.
+    pub fn move_agent(&mut self, agent_id: usize, pos: Vec2D<f64>) -> Result<(), Error> {
+        match self.agents.get_agent(agent_id) {
+            Some(agent) if !agent.status.is_dead() => (),
+            _ => return Err(Error::UnknownAgent(agent_id)),
+        }
+        self.agents
+            .move_agent(agent_id, pos)
+            .map_err(|err| match err {
+                MoveError::UnknownItem(id) => Error::UnknownAgent(id),
+                MoveError::OutOfBounds(pos) => Error::AgentOutOfBounds { id: agent_id, pos },
+            })
+    }
+
+    /// Apply a random movement to each of the agents with a magnitude in the
+    /// range of [0, max_mag). World boundaries are handled by clipping
+    /// position, not by wrapping.
+    #[allow(dead_code)]
+    fn move_agents_random(&mut self, max_mag: f64) {
+        let distro = Uniform::from(-1.0..1.0);
+        for agent in self.agents.iter_mut() {
+            if agent.status.is_dead() {
+                continue;
+            }
+
+            // generate a movement vector with components in the range of [-1, 1)
+            let movement = Vec2D::new(distro.sample(&mut self.rng), distro.sample(&mut self.rng));
+            // scale the movement based on maximum magnitude and update position
+            agent.pos += movement.normalize() * max_mag;
+            // clamp position to world size
+            agent.pos.x = agent.pos.x.clamp(0.0, self.size.x);
+            agent.pos.y = agent.pos.y.clamp(0.0, self.size.y);
+        }
+    }
+
+    /// Places structures uniformly at random in the world. The map gives the
+    /// number of structures of each type and the capacity of each of them.
+    /// Fails without placing anything if the world doesn't have a positive
+    /// and finite size.
+    pub fn place_structures(
+        &mut self,
+        counts: HashMap<StructureType, (usize, i64)>,
+    ) -> Result<(), Error> {
+        let valid = |length: f64| length.is_finite() && length > 0.0;
+        if !valid(self.size.x) || !valid(self.size.y) {
+            return Err(Error::InvalidParameter("size"));
+        }
+
+        let x_distro = Uniform::from(0.0..self.size.x);
+        let y_distro = Uniform::from(0.0..self.size.y);
+
+        // sort the requested types so that placement doesn't depend on the
+        // iteration order of the map
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort();
+
+        for (structure, (count, capacity)) in counts.iter() {
+            for _ in 0..*count {
+                let pos = Vec2D::new(
+                    x_distro.sample(&mut self.rng),
+                    y_distro.sample(&mut self.rng),
+                );
+                self.add_structure(Structure::new(*structure, pos, *capacity));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds the structure at exactly its position and returns its id, which
+    /// replaces any id it had. Agents aren't assigned to it until structures
+    /// are next assigned.
+    pub fn add_structure(&mut self, mut structure: Structure) -> usize {
+        let id = self.structures.len();
+        structure.id = id;
+        self.structure_ids
+            .entry(structure.typ)
+            .or_default()
+            .push(id);
+        self.structures.push(structure);
+        id
+    }
+
+    /// Returns the structure with the given id.
+    pub fn get_structure(&self, id: usize) -> Option<&Structure> {
+        self.structures.get(id)
+    }
+
+    /// Returns every structure in order of id.
+    pub fn structures(&self) -> &[Structure] {
+        &self.structures
+    }
+
+    /// Returns the structures of the given type in the order they were added.
+    pub fn structures_of_type(&self, typ: StructureType) -> impl Iterator<Item = &Structure> + '_ {
+        self.structure_ids
+            .get(&typ)
+            .into_iter()
+            .flatten()
+            .map(|id| &self.structures[*id])
+    }
+
+    /// Assigns structures to every agent based on their age. Every agent gets
+    /// a home, agents younger than `school_age_cutoff` get a school, agents
+    /// younger than `retirement_age` get a workplace, and retired agents get
+    /// neither. Locations that aren't assigned are set to NaN.
+    ///
+    /// Types without any structures are skipped, leaving those locations of
+    /// every agent as they were. Missing workplaces or schools are allowed,
+    /// but without homes agents have nowhere to return to, so
+    /// NoStructuresOfType is returned as a warning once the other types have
+    /// been assigned.
+    pub fn assign_structures(&mut self) -> Result<(), Error> {
+        self.assign_structures_by(StructureChoice::Random)
+    }
+
+    /// Assigns structures to every agent like assign_structures, but gives
+    /// each agent the nearest structure of each type with room left rather
+    /// than a random one.
+    pub fn assign_structures_nearest(&mut self) -> Result<(), Error> {
+        self.assign_structures_by(StructureChoice::Nearest)
+    }
+
+    /// Assigns structures to every agent like assign_structures, but after
+    /// homes are assigned at random, gives each agent a workplace or school
+    /// with probability proportional to exp(-beta * distance) from its home,
+    /// among those with room left. A beta of 0 picks uniformly at random, and
+    /// larger betas make shorter commutes more likely, approaching the
+    /// nearest structure. Fails without assigning anything if beta is
+    /// negative or not finite.
+    pub fn assign_structures_gravity(&mut self, beta: f64) -> Result<(), Error> {
+        if !beta.is_finite() || beta < 0.0 {
+            return Err(Error::InvalidParameter("beta"));
+        }
+        self.assign_structures_by(StructureChoice::Gravity(beta))
+    }
+
+    /// Returns the index among the structures of the given type, and the
+    /// structure itself, of the structure nearest to the position.
+    pub fn nearest_structure(
+        &self,
+        pos: Vec2D<f64>,
+        typ: StructureType,
+    ) -> Option<(usize, &Structure)> {
+        self.structures_of_type(typ)
+            .enumerate()
+            .min_by(|(_, a), (_, b)| pos.dist(a.pos).total_cmp(&pos.dist(b.pos)))
+    }
+
+    fn assign_structures_by(&mut self, choice: StructureChoice) -> Result<(), Error> {
+        let school_age_cutoff = self.school_age_cutoff;
+        let retirement_age = self.retirement_age;
+
+        // homes are what commutes are measured from
+        let home_choice = match choice {
+            StructureChoice::Gravity(_) => StructureChoice::Random,
+            choice => choice,
+        };
+        self.assign_structure_type(
+            StructureType::Home,
+            home_choice,
+            |_| true,
+            |agent, structure| {
+                agent.home = structure.map_or(Vec2D::new_nan(), |structure| structure.pos);
+                agent.home_id = structure.map(|structure| structure.id);
+            },
+        );
+        self.assign_structure_type(
+            StructureType::Work,
+            choice,
+            |agent| agent.age >= school_age_cutoff && agent.age < retirement_age,
+            |agent, structure| {
+                agent.work = structure.map_or(Vec2D::new_nan(), |structure| structure.pos);
+                agent.work_id = structure.map(|structure| structure.id);
+            },
+        );
+        self.assign_structure_type(
+            StructureType::School,
+            choice,
+            |agent| agent.age < school_age_cutoff,
+            |agent, structure| {
+                agent.school = structure.map_or(Vec2D::new_nan(), |structure| structure.pos);
+                agent.school_id = structure.map(|structure| structure.id);
+            },
+        );
+
+        match self.structure_ids.get(&StructureType::Home) {
+            Some(home_ids) if !home_ids.is_empty() => Ok(()),
+            _ => Err(Error::NoStructuresOfType(StructureType::Home)),
+        }
+    }
+
+    /// Assigns every eligible agent a structure of the given type, only
+    /// choosing among structures with room left. If every structure is full,
+    /// the least occupied one is chosen instead. Agents that aren't eligible
+    /// are assigned nothing. Membership of each structure is recorded.
+    fn assign_structure_type(
+        &mut self,
+        typ: StructureType,
+        choice: StructureChoice,
+        eligible: impl Fn(&Agent) -> bool,
+        set: fn(&mut Agent, Option<&Structure>),
+    ) {
+        let ids = match self.structure_ids.get(&typ) {
+            Some(ids) if !ids.is_empty() => ids.clone(),
+            _ => return,
+        };
+
+        for id in ids.iter() {
+            self.structures[*id].occupancy = 0;
+            self.structure_members.insert(*id, Vec::new());
+        }
+
+        let nearest_index = match choice {
+            StructureChoice::Random | StructureChoice::Gravity(_) => None,
+            StructureChoice::Nearest => Some(NearestIndex::new(&self.structures, &ids)),
+        };
+
+        for agent_id in self.agents.get_agent_ids() {
+            let agent = self.agents.get_agent_mut(agent_id).unwrap();
+            if !eligible(agent) {
+                set(agent, None);
+                continue;
+            }
+
+            let id = match (choice, &nearest_index) {
+                (StructureChoice::Nearest, Some(nearest_index)) => nearest_index
+                    .nearest(&self.structures, agent.pos, Structure::has_room)
+                    .unwrap_or_else(|| Self::least_occupied_structure(&self.structures, &ids)),
+                (StructureChoice::Gravity(beta), _) => {
+                    let origin = if agent.home.is_nan() {
+                        agent.pos
+                    } else {
+                        agent.home
+                    };
+                    self.decisions.decide_index(
+                        self.curr_step,
+                        agent_id,
+                        DecisionKind::StructurePick,
+                        &mut self.rng,
+                        |rng| {
+                            Self::choose_structure_by_gravity(
+                                &self.structures,
+                                &ids,
+                                origin,
+                                beta,
+                                rng,
+                            )
+                        },
+                    )
+                }
+                _ => self.decisions.decide_index(
+                    self.curr_step,
+                    agent_id,
+                    DecisionKind::StructurePick,
+                    &mut self.rng,
+                    |rng| Self::choose_structure(&self.structures, &ids, rng),
+                ),
+            };
+            self.structures[id].occupancy += 1;
+            set(agent, Some(&self.structures[id]));
+            self.structure_members.entry(id).or_default().push(agent_id);
+        }
+    }
+
+    /// Returns the total time the agent has spent moving, in seconds.
+    pub fn commute_time(&self, agent_id: usize) -> Option<f64> {
+        self.agents
+            .get_agent(agent_id)
+            .or_else(|| {
+                self.deceased
+                    .iter()
+                    .find(|(id, _)| *id == agent_id)
+                    .map(|(_, agent)| agent)
+            })
+            .map(|agent| agent.commute_time)
+    }
+
+    /// Returns the ids of the agents assigned to the structure with the given
+    /// id, in ascending order.
+    pub fn structure_members(&self, structure_id: usize) -> &[usize] {
+        self.structure_members
+            .get(&structure_id)
+            .map_or(&[], |members| members.as_slice())
+    }
+
+    /// Picks the id of a structure uniformly among those with the given ids
+    /// that have room, falling back to the least occupied structure. The ids
+    /// must not be empty.
+    fn choose_structure(structures: &[Structure], ids: &[usize], rng: &mut R) -> usize {
+        let open = ids
+            .iter()
+            .copied()
+            .filter(|id| structures[*id].has_room())
+            .collect::<Vec<_>>();
+
+        if open.is_empty() {
+            Self::least_occupied_structure(structures, ids)
+        } else {
+            open[rng.gen_range(0..open.len())]
+        }
+    }
+
+    /// Picks the id of a structure among those with the given ids that have
+    /// room, with probability proportional to exp(-beta * distance) from the
+    /// origin, falling back to the least occupied structure. The ids must not
+    /// be empty.
+    fn choose_structure_by_gravity(
+        structures: &[Structure],
+        ids: &[usize],
+        origin: Vec2D<f64>,
+        beta: f64,
+        rng: &mut R,
+    ) -> usize {
+        let open = ids
+            .iter()
+            .copied()
+            .filter(|id| structures[*id].has_room())
+            .collect::<Vec<_>>();
+        if open.is_empty() {
+            return Self::least_occupied_structure(structures, ids);
+        }
+
+        let distances = open
+            .iter()
+            .map(|id| origin.dist(structures[*id].pos))
+            .collect::<Vec<_>>();
+        // measuring from the nearest keeps the weights from all underflowing
+        // to 0 for a large beta
+        let nearest = distances.iter().copied().fold(f64::INFINITY, f64::min);
+        let weights = WeightedIndex::new(
+            distances
+                .iter()
+                .map(|dist| (-beta * (dist - nearest)).exp()),
+        )
+        .expect("the nearest structure has a weight of 1");
+        open[weights.sample(rng)]
+    }
+
+    /// The ids must not be empty.
+    fn least_occupied_structure(structures: &[Structure], ids: &[usize]) -> usize {
+        ids.iter()
+            .copied()
+            .min_by_key(|id| structures[*id].occupancy)
+            .unwrap()
+    }
+
+    /// Returns the number of agents assigned to each structure, in the same
+    /// order as the structures were placed.
+    pub fn structure_occupancy(&self) -> HashMap<StructureType, Vec<i64>> {
+        self.structure_ids
+            .keys()
+            .map(|typ| {
+                (
+                    *typ,
+                    self.structures_of_type(*typ)
+                        .map(|structure| structure.occupancy)
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    // TODO(tslnc04): determine whether this function is worth keeping
+    #[allow(dead_code)]
+    fn new_structure_map() -> HashMap<StructureType, Vec<Structure>> {
+        HashMap::from([
+            (StructureType::Home, Vec::new()),
+            (StructureType::Work, Vec::new()),
+            (StructureType::School, Vec::new()),
+        ])
+    }
+}
+
+/// Debug output for World is simply a listing of the agents and their statuses
+impl<R> fmt::Debug for World<R>
+where
+    R: Rng,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "----- Time {:2}; Infected: active {}, cumulative {} -----",
+            self.curr_step,
+            self.currently_exposed + self.currently_infectious,
+            self.cumulative_infections
+        )?;
+        for agent in self.agents.iter() {
+            write!(f, "{}", agent)?
+        }
+
+        Ok(())
+    }
+}
+
+/// Display output for World is a visualization of the agents on a grid, drawn
+/// with the render options of the world.
+impl<R> fmt::Display for World<R>
+where
+    R: Rng,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_text(f, self.render_options)
+    }
+}
+
+/// Splits the square around a circle into the up to four rectangles that cover
+/// it in a world whose opposite edges are joined.
+fn wrapped_query_bounds(center: Vec2D<f64>, radius: f64, size: Vec2D<f64>) -> Vec<Rect<f64>> {
+    let ranges = |center: f64, size: f64| {
+        let (min, max) = (center - radius, center + radius);
+        if max - min >= size {
+            vec![(0.0, size)]
+        } else if min < 0.0 {
+            vec![(0.0, max), (min + size, size)]
+        } else if max > size {
+            vec![(min, size), (0.0, max - size)]
+        } else {
+            vec![(min, max)]
+        }
+    };
+
+    let xs = ranges(center.x, size.x);
+    let ys = ranges(center.y, size.y);
+    xs.iter()
+        .flat_map(|&(min_x, max_x)| {
+            ys.iter().map(move |&(min_y, max_y)| {
+                Rect::new(Vec2D::new(min_x, min_y), Vec2D::new(max_x, max_y))
+            })
+        })
+        .collect()
+}
+
+/// OnceAction is an action scheduled on a world with World::schedule_at.
+pub type OnceAction<R> = Box<dyn FnOnce(&mut World<R>)>;
+
+/// RepeatedAction is an action scheduled on a world with World::schedule_every.
+pub type RepeatedAction<R> = Box<dyn FnMut(&mut World<R>)>;
+
+/// ScheduledAction is an action waiting to be run by a world once its time has
+/// come. seq breaks ties between actions due at the same time.
+struct ScheduledAction<R: Rng> {
+    time: i64,
+    seq: u64,
+    action: Action<R>,
+}
+
+enum Action<R: Rng> {
+    Once(OnceAction<R>),
+    /// Runs every given number of seconds.
+    Every(i64, RepeatedAction<R>),
+}
+
+/// StructureChoice is how agents are given one of the structures of a type.
+#[derive(Clone, Copy)]
+enum StructureChoice {
+    /// A structure chosen uniformly at random
+    Random,
+    /// The nearest structure
+    Nearest,
+    /// A structure chosen with probability proportional to exp(-beta *
+    /// distance) from the home of the agent
+    Gravity(f64),
+}
+
+/// NearestIndex finds the nearest structure to a point by sweeping outward
+/// from the point through the structures sorted by x, stopping once the
+/// distance along x alone is further than the nearest structure found.
+struct NearestIndex {
+    order: Vec<usize>,
+}
+
+impl NearestIndex {
+    /// Indexes the structures with the given ids.
+    fn new(structures: &[Structure], ids: &[usize]) -> Self {
+        let mut order = ids.to_vec();
+        order.sort_by(|a, b| structures[*a].pos.x.total_cmp(&structures[*b].pos.x));
+        Self { order }
+    }
+
+    /// Returns the id of the nearest indexed structure that satisfies the
+    /// filter. The structures must be the ones the index was built from.
+    fn nearest(
+        &self,
+        structures: &[Structure],
+        pos: Vec2D<f64>,
+        filter: impl Fn(&Structure) -> bool,
+    ) -> Option<usize> {
+        let start = self.order.partition_point(|i| structures[*i].pos.x < pos.x);
+        let mut best: Option<(f64, usize)> = None;
+
+        let mut visit = |i: usize| -> bool {
+            let structure = &structures[i];
+            let dx = structure.pos.x - pos.x;
+            if best.is_some_and(|(best_dist, _)| dx * dx > best_dist) {
+                return false;
+            }
+            let dist = pos.dist(structure.pos).powi(2);
+            if filter(structure) && !best.is_some_and(|(best_dist, _)| dist >= best_dist) {
+                best = Some((dist, i));
+            }
+            true
+        };
+
+        let (below, above) = self.order.split_at(start);
+        let mut below = below.iter().rev();
+        let mut above = above.iter();
+        let (mut below_open, mut above_open) = (true, true);
+        while below_open || above_open {
+            if above_open {
+                above_open = above.next().is_some_and(|i| visit(*i));
+            }
+            if below_open {
+                below_open = below.next().is_some_and(|i| visit(*i));
+            }
+        }
+
+        best.map(|(_, i)| i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{AgePyramid, DeathCause, WALKING_UNITS_PER_HOUR};
+    use crate::builder::WorldBuilder;
+    use crate::disease::{DurationDistribution, IfrTable};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Returns a grid of agents spaced a unit apart, filling the world from
+    /// the bottom left.
+    fn grid_agents(count: usize, columns: usize) -> Vec<Agent> {
+        (0..count)
+            .map(|i| {
+                let pos = Vec2D::new((i % columns) as f64 + 0.5, (i / columns) as f64 + 0.5);
+                Agent::new(pos, MovementProfile::walking())
+            })
+            .collect()
+    }
+
+    /// Returns an agent that stays infectious for as long as any test runs.
+    fn infector(pos: Vec2D<f64>) -> Agent {
+        let mut agent = Agent::new(pos, MovementProfile::walking());
+        agent.status = Status::Infectious { since: 0 };
+        agent.infectious_period = i64::MAX / 2;
+        agent
+    }
+
+    #[test]
+    fn seeded_worlds_step_identically() {
+        let run = || {
+            let mut world = World::new_with_agents_and_rng(
+                Vec2D::new(10.0, 10.0),
+                grid_agents(50, 10),
+                StdRng::seed_from_u64(42),
+            )
+            .unwrap();
+            world.infect_random_agents(2);
+            for _ in 0..100 {
+                world.step();
+            }
+            format!("{:?}", world)
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn agents_outside_the_contact_radius_are_never_infected() {
+        // the corner agent is inside the box searched around the infector,
+        // but further away than the radius
+        let agents = vec![
+            infector(Vec2D::new(5.0, 5.0)),
+            Agent::new(Vec2D::new(5.9, 5.9), MovementProfile::walking()),
+            Agent::new(Vec2D::new(5.5, 5.0), MovementProfile::walking()),
+        ];
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(10.0, 10.0),
+            agents,
+            StdRng::seed_from_u64(3),
+        )
+        .unwrap();
+        world.contact_radius = 1.0;
+        world.infection_kernel = InfectionKernel::Step;
+        world.background_mortality = false;
+
+        for _ in 0..200 {
+            world.step();
+        }
+
+        assert!(world.agents.get_agent(1).unwrap().status.is_susceptible());
+        assert!(!world.agents.get_agent(2).unwrap().status.is_susceptible());
+    }
+
+    #[test]
+    fn pruned_dead_agents_leave_the_tree_but_stay_counted() {
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(10.0, 10.0),
+            grid_agents(100, 10),
+            StdRng::seed_from_u64(12),
+        )
+        .unwrap();
+        for agent_id in (0..100).step_by(2) {
+            world
+                .agents
+                .get_agent_mut(agent_id)
+                .unwrap()
+                .kill(DeathCause::Other);
+        }
+        // killing agents directly bypasses the counts
+        let stats = world.current_stats();
+        world.recount(&stats);
+
+        world.remove_dead_agents();
+
+        assert_eq!(world.agents.len(), 50);
+        assert_eq!(world.deceased().len(), 50);
+        assert!(world
+            .agents
+            .find_agents_in_bounds(Rect::new(Vec2D::new_zero(), world.size))
+            .iter()
+            .all(|agent_id| agent_id % 2 == 1));
+        assert_eq!(world.current_stats().dead, 50);
+
+        world.prune_dead = true;
+        world.step();
+        assert_eq!(world.agents.len() + world.deceased().len(), 100);
+        assert_eq!(
+            world.current_stats().dead,
+            world.deceased().len(),
+            "every dead agent is pruned by the step"
+        );
+    }
+
+    #[test]
+    fn assignment_respects_capacity_when_it_suffices() {
+        let world = WorldBuilder::new()
+            .size(Vec2D::new(100.0, 100.0))
+            .random_agents(300)
+            .structures_with_capacity(StructureType::Home, 80, 4)
+            .structures_with_capacity(StructureType::Work, 30, 12)
+            .structures_with_capacity(StructureType::School, 20, 20)
+            .seed(14)
+            .build()
+            .unwrap();
+
+        for typ in [
+            StructureType::Home,
+            StructureType::Work,
+            StructureType::School,
+        ] {
+            for structure in world.structures_of_type(typ) {
+                assert!(
+                    structure.occupancy <= structure.capacity,
+                    "{:?} holds {} of {}",
+                    typ,
+                    structure.occupancy,
+                    structure.capacity
+                );
+            }
+        }
+        let homes = world.structure_occupancy()[&StructureType::Home]
+            .iter()
+            .sum::<i64>();
+        assert_eq!(homes, 300);
+    }
+
+    /// Returns a world with a child, an adult and a retiree, and one home,
+    /// workplace and school, with structures assigned.
+    fn family_world(seed: u64) -> World<StdRng> {
+        let ages = [7, 30, 70];
+        let agents = ages
+            .iter()
+            .map(|age| {
+                Agent::new_with_age(
+                    Vec2D::new(1.0, 1.0),
+                    MovementProfile::walking(),
+                    age * SECONDS_PER_YEAR,
+                )
+            })
+            .collect();
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(100.0, 100.0),
+            agents,
+            StdRng::seed_from_u64(seed),
+        )
+        .unwrap();
+        world.add_structure(Structure::new_without_capacity(
+            StructureType::Home,
+            Vec2D::new(1.0, 1.0),
+        ));
+        world.add_structure(Structure::new_without_capacity(
+            StructureType::Work,
+            Vec2D::new(90.0, 10.0),
+        ));
+        world.add_structure(Structure::new_without_capacity(
+            StructureType::School,
+            Vec2D::new(10.0, 90.0),
+        ));
+        world.assign_structures().unwrap();
+        world
+    }
+
+    #[test]
+    fn agents_are_assigned_the_nearer_of_two_schools() {
+        let near = Vec2D::new(5.0, 5.0);
+        let far = Vec2D::new(95.0, 95.0);
+        let agents = (0..100)
+            .map(|i| {
+                Agent::new_with_age(
+                    Vec2D::new((i % 10) as f64 * 10.0 + 3.0, (i / 10) as f64 * 10.0 + 3.0),
+                    MovementProfile::walking(),
+                    7 * SECONDS_PER_YEAR,
+                )
+            })
+            .collect();
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(100.0, 100.0),
+            agents,
+            StdRng::seed_from_u64(43),
+        )
+        .unwrap();
+        world.add_structure(Structure::new_without_capacity(
+            StructureType::Home,
+            Vec2D::new(50.0, 50.0),
+        ));
+        world.add_structure(Structure::new_without_capacity(StructureType::School, near));
+        world.add_structure(Structure::new_without_capacity(StructureType::School, far));
+        world.assign_structures_nearest().unwrap();
+
+        for agent in world.agents.iter() {
+            // the diagonal between the two corners splits the world in half
+            let nearer = if agent.pos.x + agent.pos.y < 100.0 {
+                near
+            } else {
+                far
+            };
+            assert_eq!(agent.school, nearer, "agent at {:?}", agent.pos);
+            let (_, school) = world
+                .nearest_structure(agent.pos, StructureType::School)
+                .unwrap();
+            assert_eq!(school.pos, nearer);
+        }
+    }
+
+    #[test]
+    fn children_never_get_a_workplace() {
+        let mut world = family_world(15);
+        world.step_size = 3600;
+
+        let child = world.agents.get_agent(0).unwrap();
+        assert!(child.work.is_nan());
+        assert_eq!(child.work_id, None);
+        assert_eq!(child.school_id, Some(2));
+        let adult = world.agents.get_agent(1).unwrap();
+        assert_eq!(adult.work_id, Some(1));
+        assert!(adult.school.is_nan());
+        let retiree = world.agents.get_agent(2).unwrap();
+        assert!(retiree.work.is_nan() && retiree.school.is_nan());
+
+        for _ in 0..7 * 24 {
+            world.step();
+            let child = world.agents.get_agent(0).unwrap();
+            assert_ne!(child.task, Task::Work);
+            assert!(!child.pos.is_nan());
+            assert_eq!(world.agents.get_agent(2).unwrap().task, Task::Home);
+        }
+    }
+
+    #[test]
+    fn agents_follow_the_weekly_schedule() {
+        let mut world = family_world(16);
+        world.step_size = 3600;
+        // fast enough to cross the world within the hour
+        for agent in world.agents.iter_mut() {
+            agent.movement = MovementProfile::from_units_per_second(1.0).unwrap();
+        }
+        let run_until = |world: &mut World<StdRng>, day: i64, hour: i64| {
+            while world.time().abs_time() < day * 86400 + hour * 3600 {
+                world.step();
+            }
+            assert_eq!(world.time().day_of_week(), day % 7);
+            [0, 1].map(|agent_id| world.agents.get_agent(agent_id).unwrap().pos)
+        };
+        let at = |pos: Vec2D<f64>, structure: Vec2D<f64>| pos.dist(structure) < 1.0;
+        let home = Vec2D::new(1.0, 1.0);
+        let work = Vec2D::new(90.0, 10.0);
+        let school = Vec2D::new(10.0, 90.0);
+
+        // tuesday at noon
+        let [child, adult] = run_until(&mut world, 2, 12);
+        assert!(at(child, school), "child at {:?}", child);
+        assert!(at(adult, work), "adult at {:?}", adult);
+
+        // midnight at the start of wednesday
+        let [child, adult] = run_until(&mut world, 3, 0);
+        assert!(at(child, home) && at(adult, home));
+
+        // saturday at noon
+        let [child, adult] = run_until(&mut world, 6, 12);
+        assert!(at(child, home) && at(adult, home));
+    }
+
+    // the full audit rejects a workplace outside the world after every step
+    #[cfg(not(feature = "debug-checks"))]
+    #[test]
+    fn agents_sent_outside_the_world_stay_in_bounds() {
+        let mut world = family_world(39);
+        world.step_size = 3600;
+        for agent in world.agents.iter_mut() {
+            agent.movement = MovementProfile::from_units_per_second(1.0).unwrap();
+        }
+        world.agents.get_agent_mut(1).unwrap().work = Vec2D::new(500.0, -50.0);
+        let bounds = Rect::new(Vec2D::new_zero(), world.size);
+
+        for _ in 0..7 * 24 {
+            world.step();
+            for agent in world.agents.iter() {
+                assert!(bounds.contains(agent.pos), "agent at {:?}", agent.pos);
+            }
+        }
+    }
+
+    #[test]
+    fn agents_without_a_destination_head_home() {
+        let mut world = family_world(39);
+        world.step_size = 3600;
+        world.agents.get_agent_mut(1).unwrap().movement =
+            MovementProfile::from_units_per_second(1.0).unwrap();
+        // monday morning, on the way to work
+        while world.time().abs_time() < 86400 + 8 * 3600 {
+            world.step();
+        }
+        world.move_agent(1, Vec2D::new(50.0, 50.0)).unwrap();
+        world.agents.get_agent_mut(1).unwrap().work = Vec2D::new(f64::NAN, f64::NAN);
+
+        world.step();
+
+        let adult = world.agents.get_agent(1).unwrap();
+        assert!(
+            adult.pos.dist(Vec2D::new(1.0, 1.0)) < 1.0,
+            "adult at {:?}",
+            adult.pos
+        );
+    }
+
+    #[test]
+    fn advancing_by_several_days_rolls_over_each_one() {
+        let mut time = Time::new();
+        time.advance(10 * 86400 + 5 * 3600);
+
+        assert_eq!(time.day(), 10);
+        assert_eq!(time.day_of_week(), 3);
+        assert_eq!(time.hour_of_day(), 5);
+        assert!(time.is_weekday());
+
+        for _ in 0..3 {
+            time.advance(3 * 86400);
+        }
+        assert_eq!(time.day(), 19);
+        assert_eq!(time.day_of_week(), 5);
+        assert_eq!(time.abs_time(), 19 * 86400 + 5 * 3600);
+
+        time.advance(86400);
+        assert!(time.is_weekend());
+    }
+
+    /// Returns a world of 400 agents a unit apart that stay where they are,
+    /// each in contact with its four neighbors, with four index cases of a
+    /// disease that is infectious from six hours to four days after exposure.
+    fn outbreak_world(transmission_prob: f64, seed: u64) -> World<StdRng> {
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(20.0, 20.0),
+            grid_agents(400, 20),
+            StdRng::seed_from_u64(seed),
+        )
+        .unwrap();
+        world.disease = BasicDisease::new(
+            transmission_prob,
+            DurationDistribution::Fixed(6 * 3600),
+            DurationDistribution::Fixed(4 * 86400),
+            IfrTable::flat(0.0),
+        );
+        world.contact_radius = 1.1;
+        world.infection_kernel = InfectionKernel::Step;
+        world.background_mortality = false;
+        world.step_size = 3600;
+        world.infect_random_agents(4);
+        world
+    }
+
+    fn run_days(world: &mut World<StdRng>, days: i64) {
+        for _ in 0..days * 24 {
+            world.step();
+        }
+    }
+
+    /// Returns the fraction of the agents that were ever infected.
+    fn attack_rate(world: &World<StdRng>, agent_ids: &[usize]) -> f64 {
+        let infected = agent_ids
+            .iter()
+            .filter(|agent_id| {
+                !world
+                    .agents
+                    .get_agent(**agent_id)
+                    .unwrap()
+                    .status
+                    .is_susceptible()
+            })
+            .count();
+        infected as f64 / agent_ids.len() as f64
+    }
+
+    #[test]
+    fn a_perfect_vaccine_blocks_every_exposure() {
+        let mut world = outbreak_world(0.05, 20);
+        let index_cases = world.contacts.agent_ids().collect::<Vec<_>>();
+        world.vaccine_efficacy = 1.0;
+        let vaccinated = world
+            .vaccinate_fraction(0.5)
+            .into_iter()
+            .filter(|agent_id| !index_cases.contains(agent_id))
+            .collect::<Vec<_>>();
+
+        for _ in 0..30 * 24 {
+            world.step();
+            assert_eq!(attack_rate(&world, &vaccinated), 0.0);
+        }
+        assert!(world.contacts.infected_agent_count() > index_cases.len());
+    }
+
+    #[test]
+    fn a_useless_vaccine_changes_nothing() {
+        // the unvaccinated half of each world is the control
+        let (mut vaccinated_rate, mut control_rate) = (0.0, 0.0);
+        for seed in 20..23 {
+            let mut world = outbreak_world(0.009, seed);
+            world.vaccine_efficacy = 0.0;
+            let vaccinated = world.vaccinate_fraction(0.5);
+            let unvaccinated = (0..400)
+                .filter(|agent_id| !vaccinated.contains(agent_id))
+                .collect::<Vec<_>>();
+
+            run_days(&mut world, 30);
+
+            vaccinated_rate += attack_rate(&world, &vaccinated) / 3.0;
+            control_rate += attack_rate(&world, &unvaccinated) / 3.0;
+        }
+
+        assert!(control_rate > 0.2, "the outbreak died out");
+        assert!(
+            (vaccinated_rate - control_rate).abs() < 0.05,
+            "{} of the vaccinated and {} of the rest were infected",
+            vaccinated_rate,
+            control_rate
+        );
+    }
+
+    #[test]
+    fn isolating_detected_agents_shrinks_the_outbreak() {
+        let all = (0..400).collect::<Vec<_>>();
+        let (mut isolating_rate, mut baseline_rate) = (0.0, 0.0);
+        for seed in 21..24 {
+            let mut isolating = outbreak_world(0.012, seed);
+            isolating.isolation_policy = Some(IsolationPolicy::new(0.5, 0, 1.0));
+            let mut baseline = outbreak_world(0.012, seed);
+
+            run_days(&mut isolating, 30);
+            run_days(&mut baseline, 30);
+
+            assert!(isolating.history().iter().any(|stats| stats.isolated > 0));
+            assert_eq!(isolating.current_stats().isolated, 0);
+            isolating_rate += attack_rate(&isolating, &all) / 3.0;
+            baseline_rate += attack_rate(&baseline, &all) / 3.0;
+        }
+
+        assert!(
+            isolating_rate < baseline_rate / 2.0,
+            "{} infected with isolation and {} without",
+            isolating_rate,
+            baseline_rate
+        );
+    }
+
+    /// Returns a world of 300 agents that commute between 100 homes, 5
+    /// workplaces and 3 schools, with 5 index cases of a disease that is
+    /// infectious from six hours to four days after exposure.
+    fn commuting_world(transmission_prob: f64, seed: u64) -> World<StdRng> {
+        let mut world = WorldBuilder::new()
+            .size(Vec2D::new(100.0, 100.0))
+            .random_agents(300)
+            .agent_speed(0.5, 1.0)
+            .structures(StructureType::Home, 100)
+            .structures(StructureType::Work, 5)
+            .structures(StructureType::School, 3)
+            .step_size(3600)
+            .contact_radius(2.0)
+            .disease(BasicDisease::new(
+                transmission_prob,
+                DurationDistribution::Fixed(6 * 3600),
+                DurationDistribution::Fixed(4 * 86400),
+                IfrTable::flat(0.0),
+            ))
+            .index_cases(5)
+            .seed(seed)
+            .build()
+            .unwrap();
+        world.background_mortality = false;
+        world
+    }
+
+    #[test]
+    fn lockdowns_slow_the_spread() {
+        let mut world = commuting_world(0.005, 23);
+        let mut control = commuting_world(0.005, 23);
+        run_days(&mut world, 3);
+        run_days(&mut control, 3);
+        let activated_at = world.history().len();
+        assert_eq!(world.current_stats(), control.current_stats());
+
+        world.lockdown_compliance = 1.0;
+        world.set_lockdown(true);
+        run_days(&mut world, 14);
+        run_days(&mut control, 14);
+
+        let new_infections = |world: &World<StdRng>| -> usize {
+            world.history()[activated_at..]
+                .iter()
+                .map(|stats| stats.new_infections)
+                .sum()
+        };
+        assert!(world.history()[activated_at..]
+            .iter()
+            .all(|stats| stats.lockdown));
+        assert!(
+            new_infections(&world) * 2 < new_infections(&control),
+            "{} infections during the lockdown and {} without",
+            new_infections(&world),
+            new_infections(&control)
+        );
+    }
+
+    #[test]
+    fn immunity_wanes_and_agents_are_reinfected() {
+        let mut world = outbreak_world(0.05, 24);
+        world.disease.immunity_secs = Some(86400);
+
+        for _ in 0..30 {
+            world.infect_random_agents(1);
+            run_days(&mut world, 1);
+        }
+
+        let infections = world.contacts.infection_times().len();
+        assert!(
+            infections > world.contacts.infected_agent_count(),
+            "{} infections of {} agents",
+            infections,
+            world.contacts.infected_agent_count()
+        );
+    }
+
+    #[test]
+    fn asymptomatic_agents_never_self_isolate() {
+        let all = (0..400).collect::<Vec<_>>();
+        let run = |asymptomatic_prob: f64| {
+            let mut world = outbreak_world(0.012, 25);
+            world.disease.asymptomatic_prob = asymptomatic_prob;
+            world.self_isolation_prob = 0.9;
+            let mut ever_isolated = false;
+            for _ in 0..30 * 24 {
+                world.step();
+                ever_isolated |= world.current_stats().isolated > 0;
+            }
+            (attack_rate(&world, &all), ever_isolated)
+        };
+
+        let (asymptomatic_rate, asymptomatic_isolated) = run(1.0);
+        let (symptomatic_rate, symptomatic_isolated) = run(0.0);
+
+        assert!(!asymptomatic_isolated);
+        assert!(symptomatic_isolated);
+        assert!(
+            asymptomatic_rate > symptomatic_rate,
+            "{} infected when asymptomatic and {} when symptomatic",
+            asymptomatic_rate,
+            symptomatic_rate
+        );
+    }
+
+    #[test]
+    fn households_follow_the_size_distribution_and_share_homes() {
+        let mut world = WorldBuilder::new()
+            .size(Vec2D::new(100.0, 100.0))
+            .random_agents(5000)
+            .structures(StructureType::Home, 2000)
+            .seed(26)
+            .build()
+            .unwrap();
+        let weights = [0.2, 0.3, 0.3, 0.2];
+
+        world.assign_households(&weights).unwrap();
+
+        let mut counts = [0; 4];
+        for members in world.households().values() {
+            counts[members.len() - 1] += 1;
+            let first = world.agents.get_agent(members[0]).unwrap();
+            for agent_id in members {
+                let agent = world.agents.get_agent(*agent_id).unwrap();
+                assert_eq!(agent.home, first.home);
+                assert!(agent.home_id.is_some());
+                assert_eq!(agent.home_id, first.home_id);
+            }
+        }
+        let households = counts.iter().sum::<usize>() as f64;
+        for (count, weight) in counts.iter().zip(weights) {
+            assert!((*count as f64 / households - weight).abs() < 0.03);
+        }
+        let members = world.households().values().map(Vec::len).sum::<usize>();
+        assert_eq!(members, 5000);
+    }
+
+    #[test]
+    fn cohort_outbreaks_stay_within_the_workplace() {
+        let mut world = WorldBuilder::new()
+            .size(Vec2D::new(100.0, 100.0))
+            .random_agents(300)
+            .agent_speed(0.5, 1.0)
+            .structures(StructureType::Home, 100)
+            .structures(StructureType::Work, 5)
+            .step_size(3600)
+            .disease(BasicDisease::new(
+                0.0,
+                DurationDistribution::Fixed(6 * 3600),
+                DurationDistribution::Fixed(4 * 86400),
+                IfrTable::flat(0.0),
+            ))
+            .seed(27)
+            .build()
+            .unwrap();
+        world.background_mortality = false;
+        world.cohort_transmission_rate = 0.05;
+        let workplace = world
+            .structures_of_type(StructureType::Work)
+            .next()
+            .unwrap()
+            .id;
+        let members = world.structure_members(workplace).to_vec();
+        world.expose_agent(members[0], None, 0, None);
+
+        run_days(&mut world, 14);
+
+        let infected = world.contacts.agent_ids().collect::<Vec<_>>();
+        assert!(infected.len() > 1);
+        assert!(infected.iter().all(|agent_id| members.contains(agent_id)));
+    }
+
+    #[test]
+    fn index_cases_are_reproducible_under_a_seed() {
+        let choose = |seed: u64| {
+            let mut world = World::new_with_agents_and_rng(
+                Vec2D::new(10.0, 10.0),
+                grid_agents(100, 10),
+                StdRng::seed_from_u64(seed),
+            )
+            .unwrap();
+            world.infect_random_agents(10)
+        };
+
+        let chosen = choose(29);
+        assert_eq!(chosen, choose(29));
+        assert_ne!(chosen, choose(30));
+        assert_eq!(chosen.len(), 10);
+        assert!(chosen.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn asking_for_more_index_cases_than_agents_infects_everyone() {
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(10.0, 10.0),
+            grid_agents(5, 5),
+            StdRng::seed_from_u64(29),
+        )
+        .unwrap();
+
+        assert_eq!(world.infect_random_agents(8), vec![0, 1, 2, 3, 4]);
+        assert_eq!(world.contacts.roots().len(), 5);
+        assert_eq!(world.infect_random_agents(1), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn infections_by_index_cases_link_to_them() {
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(10.0, 10.0),
+            vec![Agent::new(Vec2D::new(5.0, 5.0), MovementProfile::walking())],
+            StdRng::seed_from_u64(33),
+        )
+        .unwrap();
+        world.disease = BasicDisease::new(
+            1.0,
+            DurationDistribution::Fixed(3600),
+            DurationDistribution::Fixed(86400),
+            IfrTable::flat(0.0),
+        );
+        world.step_size = 3600;
+        assert_eq!(world.infect_random_agents(1), vec![0]);
+        let contact = world
+            .add_agent(Agent::new(Vec2D::new(5.5, 5.0), MovementProfile::walking()))
+            .unwrap();
+
+        run_days(&mut world, 1);
+
+        assert_eq!(world.contacts.parent_of(contact), Some(0));
+        assert_eq!(world.contacts.children_of(0), Some(&[contact][..]));
+        assert_eq!(world.contacts.roots(), vec![0]);
+        assert_eq!(world.contacts.unlinked_count(), 0);
+        assert!(world.contacts.add_node(contact, Some(0), 0, 0).is_err());
+    }
+
+    #[test]
+    fn attribution_between_two_infectors_is_even() {
+        let seeds = 400;
+        let mut first = 0;
+        for seed in 0..seeds {
+            let agents = vec![
+                Agent::new(Vec2D::new(5.0, 5.0), MovementProfile::walking()),
+                infector(Vec2D::new(4.5, 5.0)),
+                infector(Vec2D::new(5.5, 5.0)),
+            ];
+            let mut world = World::new_with_agents_and_rng(
+                Vec2D::new(10.0, 10.0),
+                agents,
+                StdRng::seed_from_u64(seed),
+            )
+            .unwrap();
+            world.contact_radius = 1.0;
+            world.infection_kernel = InfectionKernel::Step;
+            for infector_id in [1, 2] {
+                world.contacts.add_node(infector_id, None, 0, 0).unwrap();
+            }
+
+            while world.agents.get_agent(0).unwrap().status.is_susceptible() {
+                world.step();
+            }
+
+            match world.contacts.parent_of(0) {
+                Some(1) => first += 1,
+                Some(2) => {}
+                parent => panic!("agent 0 was infected by {:?}", parent),
+            }
+        }
+
+        let share = first as f64 / seeds as f64;
+        assert!(
+            (0.4..0.6).contains(&share),
+            "{} attributed to the first",
+            share
+        );
+    }
+
+    #[test]
+    fn events_match_the_status_changes_of_each_step() {
+        use std::mem::discriminant;
+
+        let mut world = outbreak_world(0.05, 45);
+        world.disease.ifr = IfrTable::flat(0.3);
+        world.drain_events();
+        let statuses = |world: &World<StdRng>| {
+            world
+                .agents
+                .iter()
+                .map(|agent| discriminant(&agent.status))
+                .collect::<Vec<_>>()
+        };
+        let status_after = |event: &WorldEvent| match *event {
+            WorldEvent::Exposed { agent, .. } => (agent, Status::Exposed { since: 0 }),
+            WorldEvent::BecameInfectious { agent, .. } => (agent, Status::Infectious { since: 0 }),
+            WorldEvent::Hospitalized { agent, .. } => (agent, Status::Hospitalized { since: 0 }),
+            WorldEvent::Recovered { agent, .. } => (agent, Status::Recovered { since: 0 }),
+            WorldEvent::BecameSusceptible { agent, .. } => (agent, Status::Susceptible),
+            WorldEvent::Died { agent, .. } => (agent, Status::Dead),
+        };
+        let mut seen = [0; 3];
+
+        for _ in 0..10 * 24 {
+            let before = statuses(&world);
+            world.step();
+            let after = statuses(&world);
+
+            // the last event of each agent says what it ended the step as
+            let mut expected = before.clone();
+            for event in world.drain_events() {
+                let (agent, status) = status_after(&event);
+                expected[agent] = discriminant(&status);
+                match event {
+                    WorldEvent::Exposed { .. } => seen[0] += 1,
+                    WorldEvent::Recovered { .. } => seen[1] += 1,
+                    WorldEvent::Died { .. } => seen[2] += 1,
+                    _ => (),
+                }
+            }
+            assert_eq!(after, expected, "step {}", world.curr_step);
+        }
+        assert!(seen.iter().all(|count| *count > 0), "saw {:?}", seen);
+    }
+
+    #[test]
+    fn births_balancing_deaths_keep_the_population_stable() {
+        let mut world = WorldBuilder::new()
+            .size(Vec2D::new(100.0, 100.0))
+            .random_agents(500)
+            .age_distribution(AgePyramid::realistic())
+            .structures(StructureType::Home, 100)
+            .step_size(86400)
+            .seed(48)
+            .build()
+            .unwrap();
+        let deaths_per_year = world
+            .agents
+            .iter()
+            .map(|agent| agent.background_death_probability(SECONDS_PER_YEAR))
+            .sum::<f64>()
+            / world.agents.len() as f64;
+        world.birth_rate = deaths_per_year;
+
+        for _ in 0..10 * 365 {
+            world.step();
+        }
+
+        let births = world
+            .history()
+            .iter()
+            .map(|stats| stats.births)
+            .sum::<usize>();
+        let population = world.history().last().unwrap().population;
+        assert!(births > 0 && world.deaths > 0);
+        assert!(
+            (425..=575).contains(&population),
+            "{} with {} births and {} deaths",
+            population,
+            births,
+            world.deaths
+        );
+    }
+
+    #[test]
+    fn scheduled_actions_run_once_on_the_step_covering_their_time() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(10.0, 10.0),
+            grid_agents(10, 10),
+            StdRng::seed_from_u64(49),
+        )
+        .unwrap();
+        world.step_size = 30;
+        let runs = Rc::new(RefCell::new(Vec::new()));
+        let recorded = Rc::clone(&runs);
+        world.schedule_at(
+            100,
+            Box::new(move |world| recorded.borrow_mut().push(world.time().abs_time())),
+        );
+
+        for _ in 0..10 {
+            world.step();
+        }
+
+        // the step from 90 to 120 covers 100
+        assert_eq!(*runs.borrow(), vec![90]);
+    }
+
+    #[test]
+    fn agents_built_with_locations_complete_a_commute() {
+        let home = Vec2D::new(10.0, 10.0);
+        let work = Vec2D::new(80.0, 60.0);
+        let mut agent = Agent::new_with_locations(
+            home,
+            MovementProfile::from_units_per_second(1.0).unwrap(),
+            home,
+            work,
+            Vec2D::new_nan(),
+        );
+        agent.age = 30 * SECONDS_PER_YEAR;
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(100.0, 100.0),
+            vec![agent],
+            StdRng::seed_from_u64(54),
+        )
+        .unwrap();
+        world.step_size = 3600;
+
+        // monday, from midnight to midnight
+        let mut visited = Vec::new();
+        while world.time().abs_time() < 2 * 86400 {
+            world.step();
+            let pos = world.agents.get_agent(0).unwrap().pos;
+            let at = if pos.dist(home) < 1e-6 {
+                "home"
+            } else if pos.dist(work) < 1e-6 {
+                "work"
+            } else {
+                "away"
+            };
+            if world.time().abs_time() > 86400 && visited.last() != Some(&at) {
+                visited.push(at);
+            }
+        }
+
+        assert_eq!(visited, ["home", "work", "home"]);
+    }
+
+    #[test]
+    fn agents_dwell_at_work_after_a_late_arrival() {
+        let home = Vec2D::new(10.0, 10.0);
+        let work = Vec2D::new(10.0, 90.0);
+        // the commute takes six and a half hours, past the end of the work day
+        let speed = 80.0 / (6.5 * 3600.0);
+        let mut agent = Agent::new_with_locations(
+            home,
+            MovementProfile::from_units_per_second(speed).unwrap(),
+            home,
+            work,
+            Vec2D::new_nan(),
+        );
+        agent.age = 30 * SECONDS_PER_YEAR;
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(100.0, 100.0),
+            vec![agent],
+            StdRng::seed_from_u64(55),
+        )
+        .unwrap();
+        world.step_size = 3600;
+        world.dwell_times.work = 8 * 3600;
+        while world.time().abs_time() < 86400 {
+            world.step();
+        }
+
+        let at_work =
+            |world: &World<StdRng>| world.agents.get_agent(0).unwrap().pos.dist(work) < 1e-6;
+        while !at_work(&world) {
+            world.step();
+            assert!(world.time().abs_time() < 2 * 86400, "never arrived");
+        }
+        let commute_time = world.agents.get_agent(0).unwrap().commute_time;
+        assert!(
+            (commute_time - 6.5 * 3600.0).abs() < 1.0,
+            "{}",
+            commute_time
+        );
+
+        // arriving half way through a step, it is still there at the end of
+        // the eight steps from then and leaves during the ninth
+        for hour in 1..8 {
+            world.step();
+            assert!(at_work(&world), "left after {} hours", hour);
+        }
+        world.step();
+        assert!(!at_work(&world));
+    }
+
+    #[test]
+    fn a_day_long_step_covers_a_round_trip_to_work() {
+        let home = Vec2D::new(10.0, 10.0);
+        let work = Vec2D::new(70.0, 90.0);
+        let mut agent = Agent::new_with_locations(
+            home,
+            MovementProfile::from_units_per_second(0.1).unwrap(),
+            home,
+            work,
+            Vec2D::new_nan(),
+        );
+        agent.age = 30 * SECONDS_PER_YEAR;
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(100.0, 100.0),
+            vec![agent],
+            StdRng::seed_from_u64(56),
+        )
+        .unwrap();
+        world.step_size = 86400;
+        // sunday, which stays at home
+        world.step();
+        assert_eq!(world.agents.get_agent(0).unwrap().commute_time, 0.0);
+
+        world.step();
+
+        let agent = world.agents.get_agent(0).unwrap();
+        assert!(agent.pos.dist(home) < 1e-6, "agent at {:?}", agent.pos);
+        let round_trip = 2.0 * home.dist(work) / 0.1;
+        assert!((agent.commute_time - round_trip).abs() < 1.0);
+    }
+
+    #[test]
+    fn agents_with_no_susceptibility_are_never_infected() {
+        let mut world = outbreak_world(0.3, 57);
+        let immune = world
+            .agents
+            .iter()
+            .enumerate()
+            .filter(|(_, agent)| agent.status.is_susceptible())
+            .map(|(agent_id, _)| agent_id)
+            .filter(|agent_id| agent_id % 2 == 1)
+            .collect::<Vec<_>>();
+        for agent_id in immune.iter() {
+            world
+                .agents
+                .get_agent_mut(*agent_id)
+                .unwrap()
+                .relative_susceptibility = 0.0;
+        }
+
+        run_days(&mut world, 5);
+
+        assert!(world.cumulative_infections > 50);
+        assert_eq!(attack_rate(&world, &immune), 0.0);
+    }
+
+    #[test]
+    fn doubling_infectiousness_roughly_doubles_early_infections() {
+        // only the index cases are infectious before the first of the people
+        // they infect finish their six hour incubation
+        let early_infections = |infectiousness: f64| {
+            (0..40)
+                .map(|seed| {
+                    let mut world = outbreak_world(0.05, seed);
+                    for agent in world.agents.iter_mut() {
+                        agent.relative_infectiousness = infectiousness;
+                    }
+                    for _ in 0..12 {
+                        world.step();
+                    }
+                    world.cumulative_infections - 4
+                })
+                .sum::<i64>() as f64
+        };
+
+        let ratio = early_infections(2.0) / early_infections(1.0);
+
+        assert!((1.5..2.3).contains(&ratio), "ratio {}", ratio);
+    }
+
+    #[test]
+    fn the_fitter_of_two_strains_dominates() {
+        for seed in 0..3 {
+            let mut world = outbreak_world(0.02, seed);
+            let fitter = world.add_strain(BasicDisease {
+                transmission_prob: 0.2,
+                ..world.disease
+            });
+            world.infect_random_agents_with_strain(4, fitter);
+
+            run_days(&mut world, 8);
+
+            let mut infected = [0; 2];
+            for agent in world.agents.iter() {
+                if let Some(strain) = agent.strain {
+                    infected[strain] += 1;
+                }
+            }
+            assert!(infected[1] > 3 * infected[0], "{:?}", infected);
+        }
+    }
+
+    #[test]
+    fn wrapped_worlds_infect_across_the_edge() {
+        for (boundary_mode, infected) in [(BoundaryMode::Wrap, true), (BoundaryMode::Clamp, false)]
+        {
+            let susceptible = Agent::new(Vec2D::new(49.9, 25.0), MovementProfile::walking());
+            let mut world = World::new_with_agents_and_rng(
+                Vec2D::new(50.0, 50.0),
+                vec![infector(Vec2D::new(0.1, 25.0)), susceptible],
+                StdRng::seed_from_u64(61),
+            )
+            .unwrap();
+            world.disease.transmission_prob = 1.0;
+            world.contact_radius = 1.0;
+            world.infection_kernel = InfectionKernel::Step;
+            world.boundary_mode = boundary_mode;
+
+            world.step();
+
+            let status = world.agents.get_agent(1).unwrap().status;
+            assert_eq!(!status.is_susceptible(), infected, "{:?}", boundary_mode);
+        }
+    }
+
+    #[test]
+    fn universal_masking_shrinks_the_outbreak() {
+        let infections = |masked: bool| {
+            (0..5)
+                .map(|seed| {
+                    let mut world = outbreak_world(0.05, seed);
+                    if masked {
+                        world.set_mask_policy(1.0, 0, None);
+                    }
+                    run_days(&mut world, 6);
+                    if masked {
+                        assert_eq!(world.last_stats().unwrap().masked, 400);
+                    }
+                    world.cumulative_infections
+                })
+                .sum::<i64>() as f64
+        };
+
+        let unmasked = infections(false);
+        let masked = infections(true);
+
+        assert!(masked < 0.6 * unmasked, "{} vs {}", masked, unmasked);
+    }
+
+    #[test]
+    fn a_lack_of_beds_raises_mortality_among_severe_cases() {
+        let mortality = |hospital: Option<Structure>| {
+            let (mut severe, mut deaths) = (0, 0);
+            for seed in 0..5 {
+                let mut world = outbreak_world(0.05, seed);
+                world.disease.ifr = IfrTable::flat(0.05);
+                world.set_hospitalization_prob_by_age(|_| 0.5);
+                if let Some(hospital) = hospital.clone() {
+                    world.add_structure(hospital);
+                }
+
+                let mut events = Vec::new();
+                for _ in 0..10 * 24 {
+                    world.step();
+                    events.extend(world.drain_events());
+                }
+
+                let hospitalized = events
+                    .iter()
+                    .filter_map(|event| match event {
+                        WorldEvent::Hospitalized { agent, .. } => Some(*agent),
+                        _ => None,
+                    })
+                    .collect::<std::collections::HashSet<_>>();
+                severe += hospitalized.len();
+                deaths += events
+                    .iter()
+                    .filter(|event| {
+                        matches!(event, WorldEvent::Died { agent, .. } if hospitalized.contains(agent))
+                    })
+                    .count();
+            }
+            deaths as f64 / severe as f64
+        };
+
+        // a capacity of 0 is unlimited, so a world without beds has no hospital
+        let without_beds = mortality(None);
+        let unlimited = mortality(Some(Structure::new_without_capacity(
+            StructureType::Hospital,
+            Vec2D::new(10.0, 10.0),
+        )));
+
+        assert!(
+            without_beds > 1.3 * unlimited,
+            "{} vs {}",
+            without_beds,
+            unlimited
+        );
+    }
+
+    #[test]
+    fn perfect_immediate_testing_detects_every_symptomatic_case() {
+        let mut world = outbreak_world(0.2, 64);
+        world.disease.asymptomatic_prob = 0.3;
+        world.testing_policy = Some(TestingPolicy::new(1.0, 1.0, 1.0, 0));
+
+        world.run(crate::run::StopCondition::NoActiveInfections);
+
+        let symptomatic = world
+            .agents
+            .iter()
+            .filter(|agent| !agent.status.is_susceptible() && !agent.asymptomatic)
+            .count();
+        let positive = world
+            .history()
+            .iter()
+            .map(|stats| stats.positive_tests)
+            .sum::<usize>();
+        assert!(symptomatic > 10);
+        assert_eq!(positive, symptomatic);
+        assert_eq!(world.tests_administered(), symptomatic);
+    }
+
+    #[test]
+    fn adding_and_removing_agents_keeps_the_counters_consistent() {
+        let mut world = outbreak_world(0.1, 66);
+        run_days(&mut world, 2);
+        let recount = |world: &World<StdRng>| {
+            world.audit().unwrap();
+            (
+                world.currently_exposed,
+                world.currently_infectious,
+                world.cumulative_infections,
+            )
+        };
+        let (exposed, infectious, cumulative) = recount(&world);
+
+        let added = world.add_agent(infector(Vec2D::new(5.5, 5.5))).unwrap();
+        assert_eq!(recount(&world), (exposed, infectious + 1, cumulative + 1));
+        assert!(world.contacts.contains(added));
+
+        let imported = world.import_case_at(Vec2D::new(15.5, 5.5)).unwrap();
+        assert_eq!(
+            recount(&world),
+            (exposed + 1, infectious + 1, cumulative + 2)
+        );
+
+        world.remove_agent(added).unwrap();
+        world.remove_agent(imported).unwrap();
+        assert_eq!(recount(&world), (exposed, infectious, cumulative + 2));
+        // their histories stay in the contact graph
+        assert!(world.contacts.contains(added) && world.contacts.contains(imported));
+
+        let infected = world
+            .agents
+            .get_agent_ids()
+            .into_iter()
+            .filter(|agent_id| {
+                world
+                    .agents
+                    .get_agent(*agent_id)
+                    .unwrap()
+                    .status
+                    .is_infected()
+            })
+            .take(5)
+            .collect::<Vec<_>>();
+        for agent_id in infected {
+            world.remove_agent(agent_id).unwrap();
+            recount(&world);
+        }
+        world
+            .add_agent(Agent::new(Vec2D::new(1.0, 1.0), MovementProfile::walking()))
+            .unwrap();
+        recount(&world);
+
+        run_days(&mut world, 1);
+        recount(&world);
+    }
+
+    #[test]
+    fn outbreaks_only_reach_another_region_by_travel() {
+        let infected_in_b = |travel_prob: f64| {
+            // two towns of 200 agents ten units apart, further than contacts
+            // reach
+            let mut agents = vec![infector(Vec2D::new(5.0, 10.0))];
+            for x_offset in [0.0, 30.0] {
+                agents.extend(grid_agents(200, 10).into_iter().map(|mut agent| {
+                    agent.pos.x += x_offset;
+                    agent
+                }));
+            }
+            let mut world = World::new_with_agents_and_rng(
+                Vec2D::new(40.0, 20.0),
+                agents,
+                StdRng::seed_from_u64(67),
+            )
+            .unwrap();
+            world.disease = BasicDisease::new(
+                0.2,
+                DurationDistribution::Fixed(6 * 3600),
+                DurationDistribution::Fixed(4 * 86400),
+                IfrTable::flat(0.0),
+            );
+            world.contact_radius = 1.1;
+            world.infection_kernel = InfectionKernel::Step;
+            world.background_mortality = false;
+            world.step_size = 3600;
+            world.add_region("A", Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(20.0, 20.0)));
+            let b = world.add_region(
+                "B",
+                Rect::new(Vec2D::new(20.0, 0.0), Vec2D::new(40.0, 20.0)),
+            );
+            world
+                .travel_matrix
+                .insert(("A".to_string(), "B".to_string()), travel_prob);
+
+            run_days(&mut world, 10);
+
+            world
+                .agents
+                .iter()
+                .filter(|agent| agent.home_region == Some(b) && !agent.status.is_susceptible())
+                .count()
+        };
+
+        assert_eq!(infected_in_b(0.0), 0);
+        assert!(infected_in_b(0.2) > 0);
+    }
+
+    #[test]
+    fn secondary_cases_overdisperse_as_k_shrinks() {
+        use crate::run::StopCondition;
+
+        let variance_to_mean = |k: f64| {
+            let mut histogram = Vec::<usize>::new();
+            for seed in 0..5 {
+                let mut world = outbreak_world(0.001, seed);
+                world.contact_radius = 3.0;
+                world.contact_dispersion = Some(k);
+                world.draw_contact_multipliers();
+                world.run(StopCondition::Any(vec![
+                    StopCondition::NoActiveInfections,
+                    StopCondition::SimTime(20 * 86400),
+                ]));
+
+                let secondary = world.contacts.secondary_case_distribution();
+                if histogram.len() < secondary.len() {
+                    histogram.resize(secondary.len(), 0);
+                }
+                for (cases, count) in secondary.into_iter().enumerate() {
+                    histogram[cases] += count;
+                }
+            }
+
+            let n = histogram.iter().sum::<usize>() as f64;
+            let mean = histogram
+                .iter()
+                .enumerate()
+                .map(|(cases, count)| (cases * count) as f64)
+                .sum::<f64>()
+                / n;
+            let variance = histogram
+                .iter()
+                .enumerate()
+                .map(|(cases, count)| (cases as f64 - mean).powi(2) * *count as f64)
+                .sum::<f64>()
+                / n;
+            variance / mean
+        };
+
+        let overdispersed = variance_to_mean(0.1);
+        let homogeneous = variance_to_mean(100.0);
+
+        assert!(
+            overdispersed > 2.0 * homogeneous,
+            "{} vs {}",
+            overdispersed,
+            homogeneous
+        );
+    }
+
+    #[test]
+    fn seeded_worlds_record_identical_histories() {
+        let run = || {
+            let mut world = commuting_world(0.02, 31);
+            for _ in 0..200 {
+                world.step();
+            }
+            // the only stat that depends on anything but the seed
+            world
+                .history()
+                .iter()
+                .map(|stats| WorldStats {
+                    step_duration_ms: 0,
+                    ..*stats
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // twice over, so that an order that only sometimes differs is caught
+        for _ in 0..2 {
+            let history = run();
+            assert_eq!(history.len(), 200);
+            assert_eq!(history, run());
+        }
+    }
+
+    #[test]
+    fn large_seeded_worlds_step_identically() {
+        let run = || {
+            let mut world = WorldBuilder::new()
+                .size(Vec2D::new(200.0, 200.0))
+                .random_agents(3000)
+                .structures(StructureType::Home, 1000)
+                .structures(StructureType::Work, 50)
+                .structures(StructureType::School, 10)
+                .step_size(3600)
+                .contact_radius(2.0)
+                .index_cases(20)
+                .seed(32)
+                .build()
+                .unwrap();
+            for _ in 0..24 {
+                world.step();
+            }
+            format!("{:?}", world)
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn adjacent_agents_count_one_contact_pair_per_step() {
+        let agents = vec![
+            Agent::new(Vec2D::new(2.0, 2.0), MovementProfile::walking()),
+            Agent::new(Vec2D::new(2.5, 2.0), MovementProfile::walking()),
+            Agent::new(Vec2D::new(8.0, 8.0), MovementProfile::walking()),
+        ];
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(10.0, 10.0),
+            agents,
+            StdRng::seed_from_u64(33),
+        )
+        .unwrap();
+        world.contact_radius = 1.0;
+        world.background_mortality = false;
+        world.record_contacts = true;
+
+        for _ in 0..10 {
+            world.step();
+            assert_eq!(world.last_stats().unwrap().contacts, 1);
+        }
+
+        assert_eq!(world.agent_contact_counts(), vec![(0, 10), (1, 10), (2, 0)]);
+    }
+
+    #[test]
+    fn infectors_near_an_infection_build_up_exposure_hours() {
+        let agents = vec![
+            infector(Vec2D::new(4.5, 5.0)),
+            Agent::new(Vec2D::new(5.0, 5.0), MovementProfile::walking()),
+            infector(Vec2D::new(5.5, 5.0)),
+        ];
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(10.0, 10.0),
+            agents,
+            StdRng::seed_from_u64(34),
+        )
+        .unwrap();
+        world.disease = BasicDisease::new(
+            1.0,
+            DurationDistribution::Fixed(86400),
+            DurationDistribution::Fixed(86400),
+            IfrTable::flat(0.0),
+        );
+        world.contact_radius = 0.6;
+        world.infection_kernel = InfectionKernel::Step;
+        world.background_mortality = false;
+        world.step_size = 3600;
+        for infector in [0, 2] {
+            world.contacts.add_node(infector, None, 0, 0).unwrap();
+        }
+
+        for _ in 0..5 {
+            world.step();
+        }
+
+        // both infectors were in contact for every hour, but only one of
+        // them is the source
+        let mut infectors = world.contacts.potential_infectors(1);
+        assert_eq!(infectors.len(), 2);
+        assert!(infectors[0].2 && !infectors[1].2);
+        infectors.sort_by_key(|(agent_id, _, _)| *agent_id);
+        assert_eq!(infectors[0].0, 0);
+        assert_eq!(infectors[1].0, 2);
+        assert!(infectors.iter().all(|(_, weight, _)| *weight == 5.0));
+    }
+
+    #[test]
+    fn active_counts_return_to_zero_once_an_epidemic_dies_out() {
+        let mut world = outbreak_world(0.05, 35);
+        world.disease.ifr = IfrTable::flat(0.3);
+
+        let mut cumulative = world.cumulative_infections();
+        let mut peak_active = 0;
+        for _ in 0..60 * 24 {
+            world.step();
+            // the counters are only kept up to date, never recounted
+            let stats = world.current_stats();
+            assert_eq!(world.currently_exposed(), stats.exposed);
+            assert_eq!(world.currently_infectious(), stats.infectious);
+            assert_eq!(world.deaths(), stats.dead);
+            assert!(world.cumulative_infections() >= cumulative);
+            cumulative = world.cumulative_infections();
+            peak_active = peak_active.max(world.currently_exposed() + world.currently_infectious());
+            if world.currently_exposed() + world.currently_infectious() == 0 {
+                break;
+            }
+        }
+
+        let stats = world.current_stats();
+        assert!(peak_active > 10);
+        assert_eq!(world.currently_exposed(), 0);
+        assert_eq!(world.currently_infectious(), 0);
+        assert!(world.deaths() > 0);
+        assert_eq!(world.deaths(), stats.dead);
+        // every infection ended either in recovery or in death
+        assert_eq!(
+            world.cumulative_infections() as usize,
+            stats.recovered + stats.deaths_from_disease
+        );
+    }
+
+    #[test]
+    fn force_of_infection_converges_as_the_step_shrinks() {
+        // a day with a single infectious agent among agents that can't pass
+        // the infection on yet, so the attack rate only depends on how the
+        // chance of exposure adds up over the steps
+        let attack_rates = |model: TransmissionModel| {
+            [7200, 3600, 1800, 900].map(|step_size| {
+                let mut agents = grid_agents(400, 20);
+                agents[210] = infector(agents[210].pos);
+                let mut world = World::new_with_agents_and_rng(
+                    Vec2D::new(20.0, 20.0),
+                    agents,
+                    StdRng::seed_from_u64(36),
+                )
+                .unwrap();
+                world.disease = BasicDisease::new(
+                    0.02,
+                    DurationDistribution::Fixed(2 * 86400),
+                    DurationDistribution::Fixed(4 * 86400),
+                    IfrTable::flat(0.0),
+                );
+                world.contact_radius = 30.0;
+                world.infection_kernel = InfectionKernel::Step;
+                world.background_mortality = false;
+                world.transmission_model = model;
+                world.step_size = step_size;
+                for _ in 0..86400 / step_size {
+                    world.step();
+                }
+                world.cumulative_infections() as f64 / 399.0
+            })
+        };
+
+        // 1 - exp(-0.02 * 24) whatever the step size
+        let converged = attack_rates(TransmissionModel::ForceOfInfection { hourly_rate: 1.0 });
+        for attack_rate in converged {
+            assert!((attack_rate - 0.38).abs() < 0.07, "{:?}", converged);
+        }
+        // 1 - 0.98^steps, which grows as the steps get shorter
+        let naive = attack_rates(TransmissionModel::PerContactBernoulli);
+        assert!(
+            naive.windows(2).all(|pair| pair[1] > pair[0] + 0.1),
+            "{:?}",
+            naive
+        );
+    }
+
+    #[test]
+    fn structures_keep_their_ids_as_more_are_added() {
+        let mut world = World::new(Vec2D::new(20.0, 20.0));
+        world
+            .place_structures(HashMap::from([
+                (StructureType::Home, (5, 4)),
+                (StructureType::Work, (3, 10)),
+            ]))
+            .unwrap();
+        let placed = world.structures().to_vec();
+
+        let mut manual = Structure::new(StructureType::Hospital, Vec2D::new(3.25, 7.5), 20);
+        manual.id = 99;
+        let manual_id = world.add_structure(manual);
+
+        assert_eq!(manual_id, 8);
+        assert_eq!(world.structures()[..8], placed[..]);
+        for (id, structure) in world.structures().iter().enumerate() {
+            assert_eq!(structure.id, id);
+            assert_eq!(world.get_structure(id), Some(structure));
+        }
+        let hospital = world.get_structure(manual_id).unwrap();
+        assert_eq!(hospital.pos, Vec2D::new(3.25, 7.5));
+        assert_eq!(hospital.typ, StructureType::Hospital);
+        assert!(world.get_structure(9).is_none());
+
+        assert_eq!(world.structures_of_type(StructureType::Home).count(), 5);
+        assert!(world
+            .structures_of_type(StructureType::Work)
+            .all(|structure| structure.typ == StructureType::Work));
+        assert_eq!(world.structures_of_type(StructureType::School).count(), 0);
+    }
+
+    // the trajectory was recorded before agents stored the time they entered
+    // their status, when they counted up the time spent in it every step.
+    // rayon draws the rolls of the agents in a different order
+    #[cfg(not(feature = "rayon"))]
+    #[test]
+    fn absolute_status_times_keep_the_trajectory_of_counting_up() {
+        let mut world = WorldBuilder::new()
+            .size(Vec2D::new(30.0, 30.0))
+            .random_agents(400)
+            .step_size(3600)
+            .structures(StructureType::Home, 100)
+            .structures(StructureType::Work, 4)
+            .contact_radius(1.5)
+            .disease(BasicDisease::new(
+                0.05,
+                DurationDistribution::Fixed(86400),
+                DurationDistribution::Fixed(3 * 86400),
+                IfrTable::flat(0.0),
+            ))
+            .index_cases(5)
+            .seed(37)
+            .build()
+            .unwrap();
+        world.background_mortality = false;
+        run_days(&mut world, 12);
+
+        let daily = world
+            .history()
+            .chunks(24)
+            .map(|day| {
+                let stats = day.last().unwrap();
+                (
+                    stats.susceptible,
+                    stats.exposed,
+                    stats.infectious,
+                    stats.recovered,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            daily,
+            [
+                (395, 5, 0, 0),
+                (323, 72, 5, 0),
+                (185, 138, 77, 0),
+                (84, 104, 212, 0),
+                (41, 49, 305, 5),
+                (31, 11, 284, 74),
+                (27, 4, 170, 199),
+                (27, 0, 72, 301),
+                (27, 0, 21, 352),
+                (27, 0, 6, 367),
+                (27, 0, 0, 373),
+                (27, 0, 0, 373),
+            ]
+        );
+    }
+
+    #[test]
+    fn walking_agents_cover_a_walk_each_hour() {
+        let home = Vec2D::new(10.0, 10.0);
+        let work = Vec2D::new(90.0, 10.0);
+        let mut agent = Agent::new_with_locations(
+            home,
+            MovementProfile::walking(),
+            home,
+            work,
+            Vec2D::new_nan(),
+        );
+        agent.age = 30 * SECONDS_PER_YEAR;
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(100.0, 20.0),
+            vec![agent],
+            StdRng::seed_from_u64(55),
+        )
+        .unwrap();
+        world.step_size = 3600;
+
+        let mut displacements = Vec::new();
+        for _ in 0..48 {
+            let before = world.agents.get_agent(0).unwrap().pos;
+            world.step();
+            displacements.push(world.agents.get_agent(0).unwrap().pos.dist(before));
+        }
+
+        let walked = displacements
+            .iter()
+            .filter(|displacement| (**displacement - WALKING_UNITS_PER_HOUR).abs() < 1e-9)
+            .count();
+        assert!(walked > 5);
+        assert!(displacements
+            .iter()
+            .all(|displacement| *displacement <= WALKING_UNITS_PER_HOUR + 1e-9));
+    }
+
+    #[test]
+    fn agents_with_full_immunity_are_never_infected() {
+        let mut world = outbreak_world(0.3, 90);
+        let immune = world
+            .agents
+            .get_agent_ids()
+            .into_iter()
+            .filter(|agent_id| agent_id % 2 == 0)
+            .filter(|agent_id| {
+                world
+                    .agents
+                    .get_agent(*agent_id)
+                    .unwrap()
+                    .status
+                    .is_susceptible()
+            })
+            .collect::<Vec<_>>();
+        for agent_id in immune.iter() {
+            world.agents.get_agent_mut(*agent_id).unwrap().immunity = 1.0;
+        }
+
+        run_days(&mut world, 5);
+
+        assert!(world.cumulative_infections > 50);
+        assert_eq!(attack_rate(&world, &immune), 0.0);
+    }
+
+    #[test]
+    fn settled_agents_spread_over_a_disc_around_their_homes() {
+        let most_stacked = |world: &World<StdRng>| -> usize {
+            let mut counts = HashMap::<(u64, u64), usize>::new();
+            for agent in world.agents.iter() {
+                *counts
+                    .entry((agent.pos.x.to_bits(), agent.pos.y.to_bits()))
+                    .or_default() += 1;
+            }
+            counts.into_values().max().unwrap()
+        };
+
+        let mut stacked = commuting_world(0.0, 92);
+        run_days(&mut stacked, 2);
+        for _ in 0..3 {
+            stacked.step();
+        }
+        assert!(most_stacked(&stacked) > 1);
+
+        let mut world = commuting_world(0.0, 92);
+        world.settle_radii = SettleRadii {
+            home: 1.5,
+            work: 4.0,
+            school: 6.0,
+            hospital: 2.0,
+        };
+        run_days(&mut world, 2);
+        for _ in 0..3 {
+            world.step();
+        }
+        assert_eq!(most_stacked(&world), 1);
+        for agent in world.agents.iter() {
+            assert_eq!(agent.task, Task::Home);
+            assert!(
+                agent.pos.dist(agent.home) <= 1.5 + 1e-9,
+                "{:?} settled {} from home",
+                agent.pos,
+                agent.pos.dist(agent.home)
+            );
+        }
+    }
+
+    #[test]
+    fn rejected_operations_return_typed_errors() {
+        let agents = vec![
+            Agent::new(Vec2D::new(5.0, 5.0), MovementProfile::walking()),
+            Agent::new(Vec2D::new(20.0, 5.0), MovementProfile::walking()),
+        ];
+        let err = World::new_with_agents(Vec2D::new(10.0, 10.0), agents).unwrap_err();
+        assert_eq!(
+            err,
+            Error::AgentOutOfBounds {
+                id: 1,
+                pos: Vec2D::new(20.0, 5.0)
+            }
+        );
+        assert_eq!(err.to_string(), "agent 1 at (20, 5) is out of bounds");
+
+        let mut world = World::new_with_agents(
+            Vec2D::new(10.0, 10.0),
+            vec![Agent::new(Vec2D::new(5.0, 5.0), MovementProfile::walking())],
+        )
+        .unwrap();
+        let nan = Vec2D::new(f64::NAN, 5.0);
+        assert!(matches!(
+            world.add_agent(Agent::new(nan, MovementProfile::walking())),
+            Err(Error::AgentOutOfBounds { id: 1, pos }) if pos.x.is_nan()
+        ));
+        assert_eq!(world.agents.get_agent_ids(), vec![0]);
+
+        assert_eq!(
+            world.move_agent(0, Vec2D::new(5.0, -1.0)),
+            Err(Error::AgentOutOfBounds {
+                id: 0,
+                pos: Vec2D::new(5.0, -1.0)
+            })
+        );
+        assert_eq!(
+            world.move_agent(7, Vec2D::new(1.0, 1.0)),
+            Err(Error::UnknownAgent(7))
+        );
+        assert_eq!(Error::UnknownAgent(7).to_string(), "unknown agent 7");
+        world.agents.get_agent_mut(0).unwrap().status = Status::Dead;
+        assert_eq!(
+            world.move_agent(0, Vec2D::new(1.0, 1.0)),
+            Err(Error::UnknownAgent(0))
+        );
+
+        world
+            .place_structures(HashMap::from([(StructureType::Work, (2, 10))]))
+            .unwrap();
+        let err = world.assign_structures().unwrap_err();
+        assert_eq!(err, Error::NoStructuresOfType(StructureType::Home));
+        assert_eq!(err.to_string(), "there are no structures of type Home");
+        assert_eq!(
+            world.assign_structures_nearest(),
+            Err(Error::NoStructuresOfType(StructureType::Home))
+        );
+
+        let mut flat = World::new(Vec2D::new(10.0, 0.0));
+        let err = flat
+            .place_structures(HashMap::from([(StructureType::Home, (1, 4))]))
+            .unwrap_err();
+        assert_eq!(err, Error::InvalidParameter("size"));
+        assert_eq!(err.to_string(), "invalid size");
+        assert!(flat.structures().is_empty());
+    }
+
+    #[test]
+    fn closing_schools_mostly_protects_children() {
+        // transmission happens mostly within schools, so the adults are
+        // mostly infected at home by their children
+        let attack_rates = |seed: u64, close_schools: bool| -> (f64, f64) {
+            let mut world = WorldBuilder::new()
+                .size(Vec2D::new(100.0, 100.0))
+                .random_agents(400)
+                .agent_speed(0.5, 1.0)
+                .structures(StructureType::Home, 150)
+                .structures(StructureType::Work, 8)
+                .structures(StructureType::School, 2)
+                .step_size(3600)
+                .contact_radius(1.0)
+                .disease(BasicDisease::new(
+                    0.0003,
+                    DurationDistribution::Fixed(86400),
+                    DurationDistribution::Fixed(5 * 86400),
+                    IfrTable::flat(0.0),
+                ))
+                .seed(seed)
+                .build()
+                .unwrap();
+            world.background_mortality = false;
+            world.cohort_transmission_rate = 0.02;
+            world.set_structure_closure(StructureType::School, close_schools);
+
+            let (children, adults): (Vec<usize>, Vec<usize>) = world
+                .agents
+                .get_agent_ids()
+                .into_iter()
+                .partition(|agent_id| {
+                    world
+                        .agents
+                        .get_agent(*agent_id)
+                        .unwrap()
+                        .school_id
+                        .is_some()
+                });
+            for agent_id in children.iter().take(4) {
+                world.expose_agent(*agent_id, None, 0, None);
+            }
+            run_days(&mut world, 21);
+            (attack_rate(&world, &children), attack_rate(&world, &adults))
+        };
+
+        let (mut children_saved, mut adults_saved) = (0.0, 0.0);
+        for seed in 0..4 {
+            let (children_open, adults_open) = attack_rates(seed, false);
+            let (children_closed, adults_closed) = attack_rates(seed, true);
+            children_saved += children_open - children_closed;
+            adults_saved += adults_open - adults_closed;
+        }
+        assert!(
+            children_saved > 3.0 * adults_saved.max(0.0),
+            "closing schools cut the attack rates of children by {} and adults by {} in all",
+            children_saved,
+            adults_saved
+        );
+    }
+
+    #[test]
+    fn a_flat_ifr_kills_that_fraction_of_the_infected() {
+        for step_size in [3600, 4 * 3600, 5 * 3600] {
+            let mut world = World::new_with_agents_and_rng(
+                Vec2D::new(100.0, 40.0),
+                grid_agents(4000, 100),
+                StdRng::seed_from_u64(101),
+            )
+            .unwrap();
+            world.disease = BasicDisease::new(
+                0.0,
+                DurationDistribution::Fixed(3600),
+                DurationDistribution::Fixed(3 * 86400),
+                IfrTable::flat(0.1),
+            );
+            world.background_mortality = false;
+            world.step_size = step_size;
+            for agent_id in world.agents.get_agent_ids() {
+                world.expose_agent(agent_id, None, 0, None);
+            }
+
+            while world.currently_exposed() + world.currently_infectious() > 0 {
+                world.step();
+            }
+
+            // the standard error of the fraction is under 0.005
+            let stats = world.current_stats();
+            let fraction = stats.deaths_from_disease as f64 / 4000.0;
+            assert!(
+                (fraction - 0.1).abs() < 0.02,
+                "{} of the infected died at a step of {}",
+                fraction,
+                step_size
+            );
+            assert_eq!(stats.deaths_other, 0);
+            assert_eq!(stats.recovered + stats.deaths_from_disease, 4000);
+        }
+    }
+
+    #[test]
+    fn a_flat_ifr_holds_while_the_step_size_changes() {
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(100.0, 100.0),
+            grid_agents(10000, 100),
+            StdRng::seed_from_u64(101),
+        )
+        .unwrap();
+        world.disease = BasicDisease::new(
+            0.0,
+            DurationDistribution::Fixed(600),
+            DurationDistribution::Fixed(3 * 86400),
+            IfrTable::flat(0.1),
+        );
+        world.background_mortality = false;
+        world.step_size = 600;
+        // without any transmission the step doubles every step, from 10
+        // minutes to beyond the infectious period
+        world.enable_adaptive_stepping(600, 4 * 86400, 1.0);
+        for agent_id in world.agents.get_agent_ids() {
+            world.expose_agent(agent_id, None, 0, None);
+        }
+
+        let mut step_sizes = Vec::new();
+        while world.currently_exposed() + world.currently_infectious() > 0 {
+            step_sizes.push(world.step_size);
+            world.step();
+        }
+
+        step_sizes.dedup();
+        assert!(step_sizes.len() > 8, "{:?}", step_sizes);
+        // the standard error of the fraction is 0.003
+        let stats = world.current_stats();
+        let fraction = stats.deaths_from_disease as f64 / 10000.0;
+        assert!(
+            (fraction - 0.1).abs() < 0.01,
+            "{} of the infected died",
+            fraction
+        );
+        assert_eq!(stats.recovered + stats.deaths_from_disease, 10000);
+    }
+}
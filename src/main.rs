@@ -1,49 +1,226 @@
-use agent_sim::{agent::Agent, geometry::Vec2D, StructureType, World};
-use rand::Rng;
+use agent_sim::{
+    agent::AgePyramid, builder::WorldBuilder, geometry::Vec2D, retention::Retention,
+    run::StopCondition, scenarios, StructureType,
+};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal};
+use std::ops::ControlFlow;
+use std::process;
 // use std::fs;
 // use std::process::Command;
-use std::collections::HashMap;
-use std::{thread, time};
 
 const CLEAR: &str = "\x1b[H\x1b[2J";
 
+/// FRAMES_PER_SECOND is how often the world is drawn while rendering.
+const FRAMES_PER_SECOND: f64 = 10.0 / 3.0;
+
+const USAGE: &str = "usage: agent_sim [options]
+
+options:
+    --width N         width of the world (default 50)
+    --height N        height of the world (default 50)
+    --density P       probability of an agent on each grid point (default 0.6)
+    --steps N         number of steps to simulate (default 672)
+    --step-size SECS  seconds simulated per step (default 3600)
+    --seed N          seed for a reproducible run
+    --homes N         number of homes (default 4)
+    --works N         number of workplaces (default 2)
+    --schools N       number of schools (default 1)
+    --scenario NAME   start from a preset, one of small_town, dense_city, or
+                      classroom, instead of the world configured above
+    --no-render       run headless, without drawing the world
+    --stats-out FILE  write the stats of every step to FILE as CSV
+    --help            print this message";
+
+/// Args holds the options of a run, parsed from the command line.
+struct Args {
+    width: f64,
+    height: f64,
+    density: f64,
+    steps: usize,
+    step_size: i64,
+    seed: Option<u64>,
+    homes: usize,
+    works: usize,
+    schools: usize,
+    scenario: Option<String>,
+    render: bool,
+    stats_out: Option<String>,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            width: 50.0,
+            height: 50.0,
+            density: 0.6,
+            steps: 24 * 28,
+            // hourly steps so that agents follow their daily schedules
+            step_size: 3600,
+            seed: None,
+            homes: 4,
+            works: 2,
+            schools: 1,
+            scenario: None,
+            render: true,
+            stats_out: None,
+        }
+    }
+}
+
+impl Args {
+    /// Parses the arguments, accepting both `--flag value` and `--flag=value`.
+    /// Returns None if help was requested.
+    fn parse(mut raw: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut args = Self::default();
+
+        while let Some(arg) = raw.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+
+            match flag.as_str() {
+                "--help" | "-h" => return Ok(None),
+                "--no-render" => {
+                    args.render = false;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let value = match inline_value.or_else(|| raw.next()) {
+                Some(value) => value,
+                None => return Err(format!("missing value for {}", flag)),
+            };
+            match flag.as_str() {
+                "--width" => args.width = parse_value(&flag, &value)?,
+                "--height" => args.height = parse_value(&flag, &value)?,
+                "--density" => args.density = parse_value(&flag, &value)?,
+                "--steps" => args.steps = parse_value(&flag, &value)?,
+                "--step-size" => args.step_size = parse_value(&flag, &value)?,
+                "--seed" => args.seed = Some(parse_value(&flag, &value)?),
+                "--homes" => args.homes = parse_value(&flag, &value)?,
+                "--works" => args.works = parse_value(&flag, &value)?,
+                "--schools" => args.schools = parse_value(&flag, &value)?,
+                "--scenario" => {
+                    if !scenarios::SCENARIOS.contains(&value.as_str()) {
+                        return Err(format!(
+                            "unknown scenario {}, expected one of {}",
+                            value,
+                            scenarios::SCENARIOS.join(", ")
+                        ));
+                    }
+                    args.scenario = Some(value);
+                }
+                "--stats-out" => args.stats_out = Some(value),
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+
+        if !(0.0..=1.0).contains(&args.density) {
+            return Err(format!(
+                "density must be between 0 and 1, got {}",
+                args.density
+            ));
+        }
+
+        Ok(Some(args))
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    eprintln!("run with --help for usage");
+    process::exit(2);
+}
+
 fn main() {
-    let mut rng = rand::thread_rng();
-    let mut agents = Vec::<Agent>::new();
-
-    for i in 0..50 {
-        for j in 0..50 {
-            if rng.gen_bool(0.6) {
-                agents.push(Agent::new(
-                    Vec2D::new(i as f64, j as f64),
-                    (rng.gen::<f64>() + 0.5) * 3.0 / 86400.0,
-                ));
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => fail(&err),
+    };
+
+    let builder = match &args.scenario {
+        Some(name) => {
+            let seed = args.seed.unwrap_or_else(rand::random);
+            scenarios::by_name(name, seed).expect("the scenario was checked when parsing")
+        }
+        None => {
+            let mut builder = WorldBuilder::new()
+                .size(Vec2D::new(args.width, args.height))
+                .grid_agents(args.density)
+                .age_distribution(AgePyramid::realistic())
+                .step_size(args.step_size)
+                .structures(StructureType::Home, args.homes)
+                .structures(StructureType::Work, args.works)
+                .structures(StructureType::School, args.schools)
+                .index_cases(1);
+            if let Some(seed) = args.seed {
+                builder = builder.seed(seed);
+            }
+            builder
+        }
+    };
+    let mut world = match builder.build() {
+        Ok(world) => world,
+        Err(err) => fail(&err.to_string()),
+    };
+
+    // draw the world about once a simulated day, without escape codes when
+    // the output is redirected
+    let render_every = (86400 / world.step_size).max(1) as usize;
+    let terminal = io::stdout().is_terminal();
+    world.set_use_color(terminal);
+    let clear = if terminal { CLEAR } else { "" };
+
+    // stream the stats so that long runs don't hold them all in memory
+    if let Some(path) = &args.stats_out {
+        match File::create(path) {
+            Ok(file) => {
+                world.set_history_retention(Retention::Stream(Box::new(BufWriter::new(file))))
             }
+            Err(err) => fail(&format!("failed to write stats to {}: {}", path, err)),
         }
     }
 
-    let mut world = World::new_with_agents(Vec2D::new(50.0, 50.0), agents);
-    world.step_size = 86400;
-    world.infect_index_case();
-    world
-        .place_structures(HashMap::from([
-            (StructureType::Home, 4),
-            (StructureType::Work, 2),
-            (StructureType::School, 1),
-        ]))
-        .unwrap();
-    world.assign_structures();
-
-    println!("{}{}", CLEAR, world);
-    for step in 0..151 {
-        world.step();
-
-        if step % 10 == 0 {
-            println!("{}{}", CLEAR, world);
-            thread::sleep(time::Duration::from_millis(300));
+    let rendered_steps = if args.render {
+        let pace = (render_every as i64 * world.step_size) as f64 * FRAMES_PER_SECOND;
+        let rendered = world.run_realtime(pace, FRAMES_PER_SECOND, |world, frame| {
+            println!("{}{}", clear, world);
+            // leave the steps that don't fill a frame to the run below
+            if frame.steps as usize + render_every > args.steps {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        rendered.expect("the pace and frame rate are positive") as usize
+    } else {
+        0
+    };
+    world.run(StopCondition::Steps((args.steps - rendered_steps) as u64));
+
+    if let Some(path) = args.stats_out {
+        if let Err(err) = world.flush_streams() {
+            fail(&format!("failed to write stats to {}: {}", path, err));
         }
     }
 
+    // for stats in world.history() {
+    //     println!("{:?}", stats);
+    // }
     // println!("Average degree: {}", world.contacts.get_average_degree());
     // svg::save("quadtree.svg", &world.agents.render_as_svg()).unwrap();
 
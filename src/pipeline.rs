@@ -0,0 +1,510 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::World;
+
+/// Phase is one stage of a step. World::step runs the phases of its pipeline
+/// in order, after which time advances and the stats are recorded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Phase {
+    /// Interventions runs the scheduled actions and checks whether a
+    /// lockdown starts or ends.
+    Interventions,
+    /// Transmission counts contacts if they are recorded and exposes agents
+    /// to infectious agents nearby, in their cohort, and in their household.
+    Transmission,
+    /// Progression advances the disease of every agent and admits and
+    /// discharges hospital patients.
+    Progression,
+    /// Demography removes dead agents if they are pruned and adds births.
+    Demography,
+    /// Surveillance detects cases, runs tests, and quarantines contacts.
+    Surveillance,
+    /// Travel sends agents on trips between regions and brings them back.
+    Travel,
+    /// Movement picks the task of every agent and moves it towards it.
+    Movement,
+    /// Maintenance cleans up the quadtree after agents have moved.
+    Maintenance,
+}
+
+impl Phase {
+    /// The phases of a step in the order World::step runs them by default.
+    pub const DEFAULT_PIPELINE: [Phase; 8] = [
+        Phase::Interventions,
+        Phase::Transmission,
+        Phase::Progression,
+        Phase::Demography,
+        Phase::Surveillance,
+        Phase::Travel,
+        Phase::Movement,
+        Phase::Maintenance,
+    ];
+}
+
+/// AdaptiveConfig controls how the step size of a world changes with the
+/// activity of the epidemic. After each step the step size is scaled by the
+/// ratio of the target number of new infections to the number there were,
+/// by at most a factor of two either way, and kept within the bounds. Quiet
+/// stretches are then crossed in big steps while the peak is resolved in
+/// small ones.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AdaptiveConfig {
+    /// min_step and max_step bound the step size, in seconds.
+    pub min_step: i64,
+    pub max_step: i64,
+    /// target_events_per_step is the number of new infections per step that
+    /// the step size is adjusted towards.
+    pub target_events_per_step: f64,
+    /// reference_step is the step size, in seconds, that the per-step
+    /// probabilities of the world are meant for. They are converted to the
+    /// same rate per second over steps of any other size.
+    pub reference_step: i64,
+}
+
+/// StepTimings breaks down how long the last step took to compute, in
+/// microseconds, and tracks the total over the run.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StepTimings {
+    /// transmission_us is the time spent scanning for contacts and exposing
+    /// agents.
+    pub transmission_us: u128,
+    /// progression_us is the time spent advancing the disease of agents.
+    pub progression_us: u128,
+    /// movement_us is the time spent picking tasks and moving agents.
+    pub movement_us: u128,
+    /// maintenance_us is the time spent cleaning up the quadtree.
+    pub maintenance_us: u128,
+    /// other_us is the time spent in every other phase and in recording the
+    /// step, so that the phases add up to the total.
+    pub other_us: u128,
+    pub total_us: u128,
+    /// average_total_us is an exponential moving average of the total, with
+    /// each step weighted by AVERAGE_WEIGHT.
+    pub average_total_us: f64,
+    /// max_total_us is the longest total of any step so far.
+    pub max_total_us: u128,
+    /// steps is the number of steps timed so far.
+    pub steps: u64,
+}
+
+impl StepTimings {
+    /// The weight of the latest step in the moving average.
+    pub const AVERAGE_WEIGHT: f64 = 0.1;
+
+    /// Records the durations of the phases of a step that took total_us
+    /// microseconds in all.
+    pub(crate) fn record(&mut self, phase_durations: &HashMap<Phase, u128>, total_us: u128) {
+        let duration = |phase| phase_durations.get(&phase).copied().unwrap_or(0);
+        self.transmission_us = duration(Phase::Transmission);
+        self.progression_us = duration(Phase::Progression);
+        self.movement_us = duration(Phase::Movement);
+        self.maintenance_us = duration(Phase::Maintenance);
+        self.other_us = total_us.saturating_sub(
+            self.transmission_us + self.progression_us + self.movement_us + self.maintenance_us,
+        );
+        self.total_us = total_us;
+
+        self.average_total_us = if self.steps == 0 {
+            total_us as f64
+        } else {
+            Self::AVERAGE_WEIGHT * total_us as f64
+                + (1.0 - Self::AVERAGE_WEIGHT) * self.average_total_us
+        };
+        self.max_total_us = self.max_total_us.max(total_us);
+        self.steps += 1;
+    }
+}
+
+impl fmt::Display for StepTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.2} ms (avg {:.2}, max {:.2})",
+            self.total_us as f64 / 1000.0,
+            self.average_total_us / 1000.0,
+            self.max_total_us as f64 / 1000.0
+        )
+    }
+}
+
+/// StepTally holds the counts of the current step that are only known while
+/// its phases run, to be recorded in its stats.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub(crate) struct StepTally {
+    pub(crate) contacts: usize,
+    pub(crate) births: usize,
+    pub(crate) tests_returned: usize,
+    pub(crate) positive_tests: usize,
+}
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Returns the phases run by each step, in order.
+    pub fn pipeline(&self) -> &[Phase] {
+        &self.pipeline
+    }
+
+    /// Replaces the phases run by each step. Phases can be reordered, left
+    /// out, for example Movement for a population that stays in place, or
+    /// repeated.
+    pub fn set_pipeline(&mut self, pipeline: Vec<Phase>) {
+        self.pipeline = pipeline;
+    }
+
+    /// Starts adjusting the step size after every step to aim for the given
+    /// number of new infections per step, keeping it between min_step and
+    /// max_step seconds. The current step size is taken as the one the
+    /// per-step probabilities of the world are meant for.
+    pub fn enable_adaptive_stepping(
+        &mut self,
+        min_step: i64,
+        max_step: i64,
+        target_events_per_step: f64,
+    ) {
+        self.adaptive_stepping = Some(AdaptiveConfig {
+            min_step,
+            max_step,
+            target_events_per_step,
+            reference_step: self.step_size,
+        });
+    }
+
+    /// Converts a per-step probability meant for the reference step size of
+    /// adaptive stepping to the probability over the current step, assuming
+    /// a constant rate. Without adaptive stepping it is returned unchanged.
+    pub(crate) fn scale_to_step(&self, prob: f64) -> f64 {
+        match self.adaptive_stepping {
+            Some(config) if config.reference_step > 0 => {
+                let exponent = self.step_size as f64 / config.reference_step as f64;
+                1.0 - (1.0 - prob.clamp(0.0, 1.0)).powf(exponent)
+            }
+            _ => prob,
+        }
+    }
+
+    /// Scales the step size towards the target number of new infections per
+    /// step if adaptive stepping is enabled.
+    pub(crate) fn adapt_step_size(&mut self, new_infections: usize) {
+        let config = match self.adaptive_stepping {
+            Some(config) => config,
+            None => return,
+        };
+
+        let factor = if new_infections == 0 {
+            2.0
+        } else {
+            (config.target_events_per_step / new_infections as f64).clamp(0.5, 2.0)
+        };
+        let step_size = (self.step_size as f64 * factor).round() as i64;
+        self.step_size = step_size.min(config.max_step).max(config.min_step).max(1);
+    }
+
+    /// Returns how long the last step took, broken down by phase, along with
+    /// the average and longest step so far.
+    pub fn timings(&self) -> StepTimings {
+        self.timings
+    }
+
+    /// Returns how long each phase of the last step took in microseconds.
+    /// Phases that didn't run are missing, and phases that ran more than once
+    /// are summed.
+    pub fn phase_durations(&self) -> &HashMap<Phase, u128> {
+        &self.phase_durations
+    }
+
+    pub(crate) fn run_phase(&mut self, phase: Phase, tally: &mut StepTally) {
+        match phase {
+            Phase::Interventions => self.phase_interventions(),
+            Phase::Transmission => self.phase_transmission(tally),
+            Phase::Progression => self.phase_progression(),
+            Phase::Demography => self.phase_demography(tally),
+            Phase::Surveillance => self.phase_surveillance(tally),
+            Phase::Travel => self.update_travel(),
+            Phase::Movement => self.phase_movement(),
+            Phase::Maintenance => self.phase_maintenance(),
+        }
+    }
+
+    fn phase_interventions(&mut self) {
+        self.run_scheduled_actions();
+        self.check_lockdown_trigger();
+    }
+
+    fn phase_transmission(&mut self, tally: &mut StepTally) {
+        // every exposure is proposed against the state at the start of the
+        // phase before any of them are applied
+        let neighbors = if self.record_contacts {
+            let (contacts, neighbors) = self.count_contacts();
+            tally.contacts += contacts;
+            Some(neighbors)
+        } else {
+            None
+        };
+        let mut exposures = Vec::new();
+        self.propose_spatial_exposures(neighbors.as_deref(), &mut exposures);
+        self.propose_cohort_exposures(&mut exposures);
+        if self.is_start_of_day() {
+            self.propose_household_exposures(&mut exposures);
+        }
+        self.apply_exposures(exposures);
+    }
+
+    fn phase_progression(&mut self) {
+        self.step_agents();
+        self.update_hospitalizations();
+    }
+
+    fn phase_demography(&mut self, tally: &mut StepTally) {
+        if self.prune_dead {
+            self.remove_dead_agents();
+        }
+        tally.births += self.spawn_births();
+    }
+
+    fn phase_surveillance(&mut self, tally: &mut StepTally) {
+        let mut detected = self.detect_cases();
+        let test_results = self.run_tests();
+        detected.extend_from_slice(&test_results.positive);
+        self.quarantine_contacts(&detected);
+
+        tally.tests_returned += test_results.returned;
+        tally.positive_tests += test_results.positive.len();
+    }
+
+    fn phase_movement(&mut self) {
+        self.update_tasks();
+        self.move_agents();
+    }
+
+    fn phase_maintenance(&mut self) {
+        self.agents.clean_tree();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::WorldBuilder;
+    use crate::disease::{BasicDisease, DurationDistribution, IfrTable};
+    use crate::geometry::Vec2D;
+    use crate::StructureType;
+    use rand::rngs::StdRng;
+
+    /// Returns a world of 200 agents that commute between homes and
+    /// workplaces, with a few index cases.
+    fn commuting_world(seed: u64) -> World<StdRng> {
+        WorldBuilder::new()
+            .size(Vec2D::new(50.0, 50.0))
+            .random_agents(200)
+            .structures(StructureType::Home, 60)
+            .structures(StructureType::Work, 5)
+            .structures(StructureType::School, 2)
+            .step_size(3600)
+            .index_cases(5)
+            .seed(seed)
+            .build()
+            .unwrap()
+    }
+
+    fn positions(world: &World<StdRng>) -> Vec<Vec2D<f64>> {
+        world.agents.iter().map(|agent| agent.pos).collect()
+    }
+
+    #[test]
+    fn the_default_pipeline_is_the_whole_step() {
+        let mut world = commuting_world(1);
+        let mut explicit = commuting_world(1);
+        assert_eq!(world.pipeline(), Phase::DEFAULT_PIPELINE);
+        explicit.set_pipeline(Phase::DEFAULT_PIPELINE.to_vec());
+
+        for _ in 0..48 {
+            world.step();
+            explicit.step();
+        }
+
+        assert_eq!(format!("{:?}", world), format!("{:?}", explicit));
+    }
+
+    // the trajectory was recorded before World::step was split into phases.
+    // rayon draws the rolls of the agents in a different order
+    #[cfg(not(feature = "rayon"))]
+    #[test]
+    fn the_default_pipeline_keeps_the_trajectory_of_the_whole_step() {
+        let mut world = WorldBuilder::new()
+            .size(Vec2D::new(50.0, 50.0))
+            .random_agents(200)
+            .age_distribution(crate::agent::AgePyramid::uniform(115.0))
+            .structures(StructureType::Home, 60)
+            .structures(StructureType::Work, 5)
+            .structures(StructureType::School, 2)
+            .step_size(3600)
+            .contact_radius(2.0)
+            .disease(BasicDisease::new(
+                0.1,
+                DurationDistribution::Fixed(86400),
+                DurationDistribution::Fixed(3 * 86400),
+                IfrTable::flat(0.0),
+            ))
+            .index_cases(5)
+            .seed(76)
+            .build()
+            .unwrap();
+        for _ in 0..12 * 24 {
+            world.step();
+        }
+
+        let daily = world
+            .history()
+            .chunks(24)
+            .map(|day| {
+                let stats = day.last().unwrap();
+                (
+                    stats.susceptible,
+                    stats.exposed,
+                    stats.infectious,
+                    stats.recovered,
+                    stats.dead,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            daily,
+            [
+                (195, 5, 0, 0, 0),
+                (173, 22, 5, 0, 0),
+                (141, 32, 27, 0, 0),
+                (109, 34, 57, 0, 0),
+                (50, 62, 83, 5, 0),
+                (21, 29, 125, 25, 0),
+                (5, 15, 129, 50, 1),
+                (2, 3, 110, 84, 1),
+                (1, 1, 50, 147, 1),
+                (1, 0, 22, 176, 1),
+                (1, 0, 4, 194, 1),
+                (1, 0, 1, 197, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn agents_stay_in_place_without_the_movement_phase() {
+        let mut world = commuting_world(2);
+        let mut moving = commuting_world(2);
+        let start = positions(&world);
+        world.set_pipeline(
+            Phase::DEFAULT_PIPELINE
+                .into_iter()
+                .filter(|phase| *phase != Phase::Movement)
+                .collect(),
+        );
+
+        for _ in 0..10 {
+            world.step();
+            moving.step();
+        }
+
+        assert_eq!(positions(&world), start);
+        assert_ne!(positions(&moving), start);
+    }
+
+    #[test]
+    fn phase_timings_add_up_to_the_step() {
+        let mut world = commuting_world(3);
+        world.step();
+        let first = world.timings();
+        assert_eq!(first.steps, 1);
+        assert_eq!(first.average_total_us, first.total_us as f64);
+
+        for _ in 0..5 {
+            let previous = world.timings();
+            world.step();
+            let timings = world.timings();
+
+            assert_eq!(
+                timings.transmission_us
+                    + timings.progression_us
+                    + timings.movement_us
+                    + timings.maintenance_us
+                    + timings.other_us,
+                timings.total_us
+            );
+            assert!(world.phase_durations().values().sum::<u128>() <= timings.total_us);
+            #[allow(deprecated)]
+            let last_step_duration = world.last_step_duration;
+            assert_eq!(last_step_duration, timings.total_us / 1000);
+
+            assert_eq!(timings.steps, previous.steps + 1);
+            let expected_average = StepTimings::AVERAGE_WEIGHT * timings.total_us as f64
+                + (1.0 - StepTimings::AVERAGE_WEIGHT) * previous.average_total_us;
+            assert!((timings.average_total_us - expected_average).abs() < 1e-6);
+            assert_eq!(
+                timings.max_total_us,
+                previous.max_total_us.max(timings.total_us)
+            );
+        }
+    }
+
+    #[test]
+    fn quiet_worlds_grow_the_step_to_the_maximum() {
+        let mut world = WorldBuilder::new()
+            .size(Vec2D::new(50.0, 50.0))
+            .random_agents(100)
+            .structures(StructureType::Home, 30)
+            .step_size(3600)
+            .seed(4)
+            .build()
+            .unwrap();
+        world.enable_adaptive_stepping(600, 86400, 1.0);
+
+        let mut step_sizes = Vec::new();
+        for _ in 0..10 {
+            world.step();
+            step_sizes.push(world.step_size);
+        }
+
+        // doubling every step until it is capped
+        assert_eq!(
+            step_sizes,
+            [7200, 14400, 28800, 57600, 86400, 86400, 86400, 86400, 86400, 86400]
+        );
+    }
+
+    #[test]
+    fn bursts_of_infections_shrink_the_step_to_the_minimum() {
+        let mut world = WorldBuilder::new()
+            .size(Vec2D::new(20.0, 20.0))
+            .random_agents(400)
+            .structures(StructureType::Home, 100)
+            .step_size(3600)
+            .contact_radius(2.0)
+            .disease(BasicDisease::new(
+                0.5,
+                DurationDistribution::Fixed(600),
+                DurationDistribution::Fixed(4 * 86400),
+                IfrTable::flat(0.0),
+            ))
+            .index_cases(20)
+            .seed(5)
+            .build()
+            .unwrap();
+        world.enable_adaptive_stepping(60, 86400, 1.0);
+
+        let mut smallest = world.step_size;
+        for _ in 0..20 {
+            world.step();
+            if world.last_stats().unwrap().new_infections > 1 {
+                assert!(world.step_size < world.last_step_size);
+            }
+            smallest = smallest.min(world.step_size);
+        }
+
+        assert_eq!(smallest, 60);
+    }
+}
@@ -0,0 +1,11 @@
+//! The types most programs using the crate need, to be imported all at once
+//! with `use agent_sim::prelude::*`. Everything here is also reachable from
+//! the module it is defined in.
+
+pub use crate::agent::{Agent, ContactGraph, Status, Task};
+pub use crate::builder::{BuildError, WorldBuilder};
+pub use crate::geometry::{Rect, Vec2D};
+pub use crate::io::IoError;
+pub use crate::quadtree::{MoveError, Quadtree};
+pub use crate::stats::WorldStats;
+pub use crate::{Error, Structure, StructureType, World};
@@ -1,26 +1,145 @@
-use crate::{Agent, Rect, Vec2D};
-use std::collections::{HashMap, HashSet};
+use crate::agent::Agent;
+use crate::render;
+use crate::{Rect, Structure, Vec2D};
+use std::fmt;
+
+/// MoveError describes why an item could not be moved within the tree.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MoveError {
+    /// There is no item with the given id in the tree.
+    UnknownItem(usize),
+    /// The position is outside of the bounds of the tree, or is NaN.
+    OutOfBounds(Vec2D<f64>),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::UnknownItem(item_id) => write!(f, "unknown item {}", item_id),
+            MoveError::OutOfBounds(pos) => {
+                write!(f, "position ({}, {}) is out of bounds", pos.x, pos.y)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+/// QuadtreeConfig controls when leaves of the quadtree are split.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuadtreeConfig {
+    /// leaf_capacity is the number of items a leaf can hold before it is
+    /// split.
+    pub leaf_capacity: usize,
+    /// min_leaf_width is the width below which leaves are never split, no
+    /// matter how many items they hold.
+    pub min_leaf_width: f64,
+    /// max_depth is the depth below which leaves are never split, with the
+    /// root at a depth of 0.
+    pub max_depth: usize,
+}
+
+impl Default for QuadtreeConfig {
+    fn default() -> Self {
+        Self {
+            leaf_capacity: 4,
+            min_leaf_width: 2.0,
+            max_depth: 32,
+        }
+    }
+}
+
+/// QuadtreeStats describes the shape of a quadtree, for tuning its config.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuadtreeStats {
+    /// node_count is the number of live nodes, both roots and leaves.
+    pub node_count: usize,
+    pub leaf_count: usize,
+    /// max_depth is the depth of the deepest leaf, with the root of the tree
+    /// at a depth of 0.
+    pub max_depth: usize,
+    pub mean_leaf_occupancy: f64,
+    pub max_leaf_occupancy: usize,
+    pub empty_leaves: usize,
+    /// open_node_slots is the number of slots of removed nodes waiting to be
+    /// reused.
+    pub open_node_slots: usize,
+}
+
+/// HasPosition is implemented by anything that can be stored in a quadtree.
+pub trait HasPosition {
+    fn pos(&self) -> Vec2D<f64>;
+    fn set_pos(&mut self, pos: Vec2D<f64>);
+
+    /// Returns the color the item is drawn in when rendering the tree.
+    fn svg_fill(&self) -> &'static str {
+        "black"
+    }
+}
+
+impl HasPosition for Agent {
+    fn pos(&self) -> Vec2D<f64> {
+        self.pos
+    }
+
+    fn set_pos(&mut self, pos: Vec2D<f64>) {
+        self.pos = pos;
+    }
 
-pub struct Quadtree {
+    fn svg_fill(&self) -> &'static str {
+        render::svg_color(self)
+    }
+}
+
+impl HasPosition for Structure {
+    fn pos(&self) -> Vec2D<f64> {
+        self.pos
+    }
+
+    fn set_pos(&mut self, pos: Vec2D<f64>) {
+        self.pos = pos;
+    }
+}
+
+/// AgentTree is the quadtree used to store the agents of a world.
+pub type AgentTree = Quadtree<Agent>;
+
+/// Quadtree stores items by their position, giving each a unique id. Items are
+/// always iterated in ascending order of id, so that anything that depends on
+/// the order, such as drawing from a seeded RNG, is reproducible.
+pub struct Quadtree<T> {
     bounds: Rect<f64>,
-    leaf_capacity: usize,
-    next_agent_id: usize,
+    config: QuadtreeConfig,
+    next_item_id: usize,
     nodes: Vec<Node>,
-    agents: HashMap<usize, Agent>,
+    /// items is a slab indexed by item id, since ids are handed out
+    /// sequentially. Removed items leave their slot empty, and ids are never
+    /// reused.
+    items: Vec<Option<T>>,
+    /// item_count is the number of occupied slots in items.
+    item_count: usize,
     open_node_indices: Vec<usize>,
-    agent_to_node: HashMap<usize, usize>,
+    /// item_to_node is indexed by item id like items.
+    item_to_node: Vec<Option<usize>>,
 }
 
-impl Quadtree {
+impl<T: HasPosition> Quadtree<T> {
     pub fn new(bounds: Rect<f64>) -> Self {
+        Self::new_with_config(bounds, QuadtreeConfig::default())
+    }
+
+    pub fn new_with_config(bounds: Rect<f64>, config: QuadtreeConfig) -> Self {
         let mut new_quadtree = Self {
             bounds,
-            leaf_capacity: 4,
-            next_agent_id: 0,
+            config,
+            next_item_id: 0,
             nodes: Vec::new(),
-            agents: HashMap::new(),
+            items: Vec::new(),
+            item_count: 0,
             open_node_indices: Vec::new(),
-            agent_to_node: HashMap::new(),
+            item_to_node: Vec::new(),
         };
 
         new_quadtree.add_node(Node::new_leaf(None, bounds));
@@ -28,39 +147,180 @@ impl Quadtree {
         new_quadtree
     }
 
-    pub fn new_with_agents(bounds: Rect<f64>, agents: Vec<Agent>) -> Self {
-        let mut new_quadtree = Self::new(bounds);
+    pub fn new_with_items(bounds: Rect<f64>, items: Vec<T>) -> Self {
+        Self::new_with_items_and_config(bounds, items, QuadtreeConfig::default())
+    }
 
-        for agent in agents {
-            new_quadtree.add_agent(agent).unwrap();
+    pub fn new_with_items_and_config(
+        bounds: Rect<f64>,
+        items: Vec<T>,
+        config: QuadtreeConfig,
+    ) -> Self {
+        let mut new_quadtree = Self::new_with_config(bounds, config);
+        new_quadtree.rebuild(items);
+        new_quadtree
+    }
+
+    pub fn config(&self) -> QuadtreeConfig {
+        self.config
+    }
+
+    /// Replaces every item in the tree and builds the tree in a single pass,
+    /// rather than inserting items one at a time. Items are given ids in the
+    /// order they appear, starting from 0, which matches the ids they would
+    /// have been given by adding them one at a time to an empty tree. Items
+    /// outside of the bounds of the tree are discarded without taking an id.
+    pub fn rebuild(&mut self, items: Vec<T>) {
+        let items = items
+            .into_iter()
+            .filter(|item| self.bounds.contains(item.pos()))
+            .enumerate()
+            .collect();
+        self.rebuild_with_ids(items);
+    }
+
+    /// Replaces every item in the tree, keeping the ids given alongside each
+    /// item, and builds the tree in a single pass. Items outside of the
+    /// bounds of the tree and items with an id that was already used are
+    /// discarded. New items will be given ids above every id used here.
+    pub fn rebuild_with_ids(&mut self, items: Vec<(usize, T)>) {
+        self.nodes.clear();
+        self.open_node_indices.clear();
+        self.items.clear();
+        self.item_count = 0;
+        self.item_to_node.clear();
+        self.next_item_id = 0;
+
+        let mut item_ids = Vec::with_capacity(items.len());
+        for (item_id, item) in items {
+            if !self.bounds.contains(item.pos()) || self.get_item(item_id).is_some() {
+                continue;
+            }
+
+            self.insert_item(item_id, item);
+            item_ids.push(item_id);
+            self.next_item_id = self.next_item_id.max(item_id + 1);
         }
 
-        new_quadtree
+        self.build_node(None, self.bounds, 0, item_ids);
+    }
+
+    /// Returns the id that will be given to the next item added
+    pub fn next_item_id(&self) -> usize {
+        self.next_item_id
+    }
+
+    /// Ensures that no item added in the future is given an id below the
+    /// given one. Never lowers the next id, so ids are never reused.
+    pub fn reserve_item_ids(&mut self, next_item_id: usize) {
+        self.next_item_id = self.next_item_id.max(next_item_id);
+    }
+
+    /// Recursively builds the subtree for the given bounds and items, and
+    /// returns the id of its topmost node
+    fn build_node(
+        &mut self,
+        parent: Option<usize>,
+        bounds: Rect<f64>,
+        depth: usize,
+        item_ids: Vec<usize>,
+    ) -> usize {
+        if !self.should_split(&item_ids, bounds, depth) {
+            let id = self.add_node(Node::new_leaf(parent, bounds));
+            for item_id in item_ids.iter() {
+                self.set_node_for_item(*item_id, id);
+            }
+            self.nodes[id].children = item_ids;
+            return id;
+        }
+
+        let id = self.add_node(Node::new_root(parent, bounds, Vec::new()));
+
+        let mut partitions: [Vec<usize>; 4] = Default::default();
+        for item_id in item_ids {
+            let quadrant = bounds.get_quadrant(self.get_item(item_id).unwrap().pos());
+            partitions[quadrant].push(item_id);
+        }
+
+        let children = partitions
+            .into_iter()
+            .zip(bounds.quarter())
+            .map(|(partition, quarter)| self.build_node(Some(id), quarter, depth + 1, partition))
+            .collect();
+        self.nodes[id].children = children;
+
+        id
     }
 
-    /// Returns an iterator over the agents in an arbitrary order
-    pub fn iter(&self) -> impl Iterator<Item = &Agent> {
-        self.agents.values()
+    /// Returns an iterator over the items in ascending order of id
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.items.iter().flatten()
     }
 
     fn iter_nodes(&self) -> impl Iterator<Item = &Node> {
-        let open_node_indices = HashSet::<_>::from_iter(self.open_node_indices.iter());
-        (0..self.nodes.len())
-            .filter(move |i| !open_node_indices.contains(i))
-            .map(|i| &self.nodes[i])
+        self.nodes.iter().filter(|node| !node.is_open())
+    }
+
+    /// Returns the bounds of every leaf along with the ids of the items it
+    /// holds, for building visualizations or density estimates. Slots of
+    /// removed nodes are never included.
+    pub fn iter_leaves(&self) -> impl Iterator<Item = (Rect<f64>, &[usize])> {
+        self.iter_nodes()
+            .filter(|node| node.is_leaf())
+            .map(|node| (node.bounds, node.children.as_slice()))
+    }
+
+    /// Returns the number of node slots, including those of removed nodes
+    /// waiting to be reused.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns the number of nodes in the tree, both roots and leaves.
+    pub fn live_node_count(&self) -> usize {
+        self.nodes.len() - self.open_node_indices.len()
     }
 
-    /// Returns a mutable iterator over the agents in an arbitrary order
-    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Agent> {
-        self.agents.values_mut()
+    /// Returns a mutable iterator over the items in ascending order of id
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.items.iter_mut().flatten()
     }
 
+    /// Returns the number of items in the tree
     pub fn len(&self) -> usize {
-        self.agents.len()
+        self.item_count
     }
 
+    /// Returns whether the tree holds no items
+    pub fn is_empty(&self) -> bool {
+        self.item_count == 0
+    }
+
+    /// Puts the item in the slot for its id, which must be empty.
+    fn insert_item(&mut self, item_id: usize, item: T) {
+        if item_id >= self.items.len() {
+            self.items.resize_with(item_id + 1, || None);
+        }
+        debug_assert!(
+            self.items[item_id].is_none(),
+            "item {} already exists",
+            item_id
+        );
+        self.items[item_id] = Some(item);
+        self.item_count += 1;
+    }
+
+    fn set_node_for_item(&mut self, item_id: usize, node_id: usize) {
+        if item_id >= self.item_to_node.len() {
+            self.item_to_node.resize(item_id + 1, None);
+        }
+        self.item_to_node[item_id] = Some(node_id);
+    }
+
+    /// Returns the node with the given id, or None for open ids, so that stale
+    /// references to removed nodes never see their old contents.
     fn get(&self, id: usize) -> Option<&Node> {
-        self.nodes.get(id)
+        self.nodes.get(id).filter(|node| !node.is_open())
     }
 
     fn get_leaf(&self, id: usize) -> Option<&Node> {
@@ -72,12 +332,12 @@ impl Quadtree {
         }
     }
 
-    pub fn get_agent(&self, id: usize) -> Option<&Agent> {
-        self.agents.get(&id)
+    pub fn get_item(&self, id: usize) -> Option<&T> {
+        self.items.get(id)?.as_ref()
     }
 
     fn get_mut(&mut self, id: usize) -> Option<&mut Node> {
-        self.nodes.get_mut(id)
+        self.nodes.get_mut(id).filter(|node| !node.is_open())
     }
 
     fn get_leaf_mut(&mut self, id: usize) -> Option<&mut Node> {
@@ -89,19 +349,31 @@ impl Quadtree {
         }
     }
 
-    pub fn get_agent_mut(&mut self, id: usize) -> Option<&mut Agent> {
-        self.agents.get_mut(&id)
+    pub fn get_item_mut(&mut self, id: usize) -> Option<&mut T> {
+        self.items.get_mut(id)?.as_mut()
     }
 
-    /// Return all of the agent ids currently being used, in an arbitrary order
-    pub fn get_agent_ids(&self) -> Vec<usize> {
-        self.agents.keys().copied().collect()
+    /// Return all of the item ids currently being used, in ascending order
+    pub fn get_item_ids(&self) -> Vec<usize> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| item.is_some())
+            .map(|(id, _)| id)
+            .collect()
     }
 
-    /// Adds the node to the quadtree and returns the id of the node
+    /// Adds the node to the quadtree and returns the id of the node. Ids of
+    /// removed nodes are reused first.
     fn add_node(&mut self, node: Node) -> usize {
-        if self.open_node_indices.len() > 0 {
-            let id = self.open_node_indices.pop().unwrap();
+        if let Some(id) = self.open_node_indices.pop() {
+            debug_assert!(self.nodes[id].is_open(), "reused node {} is still live", id);
+            debug_assert!(
+                self.iter_nodes()
+                    .all(|other| other.is_leaf() || !other.children.contains(&id)),
+                "reused node {} is still referenced",
+                id
+            );
             self.nodes[id] = node;
             id
         } else {
@@ -110,12 +382,18 @@ impl Quadtree {
         }
     }
 
-    /// Removes the node from the quadtree. Due to how this functions
-    /// internally, the node is not actually removed and only overwritten when
-    /// the index is given to another node.
+    /// Removes the node from the quadtree. The node is marked as open so that
+    /// its id can be given to another node, and any trailing open nodes are
+    /// dropped entirely. The node must no longer be referenced by its parent.
     fn remove_node(&mut self, id: usize) {
+        self.nodes[id] = Node::new_open();
+
         if id == self.nodes.len() - 1 {
-            self.nodes.pop();
+            while self.nodes.last().is_some_and(|node| node.is_open()) {
+                self.nodes.pop();
+            }
+            let len = self.nodes.len();
+            self.open_node_indices.retain(|open_id| *open_id < len);
         } else {
             self.open_node_indices.push(id);
         }
@@ -123,7 +401,32 @@ impl Quadtree {
 
     /// Guaranteed to return a leaf node
     pub fn get_node_for_pos(&self, pos: Vec2D<f64>) -> Option<usize> {
-        let mut curr = 0;
+        self.descend_to_leaf(0, pos)
+    }
+
+    /// Guaranteed to return a leaf node. The hint is a node to start from. This
+    /// is intended to be used when one is moving an item, since the item is
+    /// likely moved to a nearby node in the tree.
+    fn get_node_for_pos_hinted(&self, pos: Vec2D<f64>, hint: usize) -> Option<usize> {
+        let mut curr = hint;
+
+        // walk up until a node contains the position, failing if even the
+        // root doesn't contain it
+        loop {
+            let curr_node = self.get(curr)?;
+            if curr_node.bounds.contains(pos) {
+                break;
+            }
+
+            curr = curr_node.parent?;
+        }
+
+        self.descend_to_leaf(curr, pos)
+    }
+
+    /// Walks down from the given node to the leaf containing the position
+    fn descend_to_leaf(&self, start: usize, pos: Vec2D<f64>) -> Option<usize> {
+        let mut curr = start;
 
         loop {
             let node = self.get(curr)?;
@@ -136,100 +439,203 @@ impl Quadtree {
 
                     curr = node.children[node.bounds.get_quadrant(pos)];
                 }
+                NodeType::Open => return None,
             }
         }
     }
 
-    /// Guaranteed to return a leaf node. The hint is a node to start from. This
-    /// is intended to be used when one is moving an agent, since the agent is
-    /// likely moved to a nearby node in the tree.
-    // fn get_node_for_pos_hinted(&self, pos: Vec2D<f64>, hint: usize) -> Option<usize> {
-    //     let mut curr = hint;
+    fn get_node_for_item(&self, item_id: usize) -> Option<usize> {
+        self.item_to_node.get(item_id).copied().flatten()
+    }
 
-    //     loop {
-    //         let curr_node = self.get(curr)?;
+    pub fn add_item(&mut self, item: T) -> Option<usize> {
+        let leaf_id = self.get_node_for_pos(item.pos())?;
+        let item_id = self.next_item_id;
 
-    //         if !curr_node.get_bounds().contains(pos) {
-    //             curr = curr_node.get_parent()?;
-    //             continue;
-    //         }
+        self.insert_item(item_id, item);
+        self.set_node_for_item(item_id, leaf_id);
+        self.get_leaf_mut(leaf_id)?.children.push(item_id);
 
-    //         match curr_node {
-    //             QuadtreeNode::Leaf(_) => return Some(curr),
-    //             QuadtreeNode::Root(root) => {
-    //                 curr = root.children[root.bounds.get_quadrant(pos)];
-    //             }
-    //         }
-    //     }
-    // }
+        self.next_item_id += 1;
+        self.check_capacity(leaf_id);
 
-    fn get_node_for_agent(&self, agent_id: usize) -> Option<usize> {
-        self.agent_to_node.get(&agent_id).copied()
+        Some(leaf_id)
     }
 
-    pub fn add_agent(&mut self, agent: Agent) -> Option<usize> {
-        let leaf_id = self.get_node_for_pos(agent.pos)?;
-        let agent_id = self.next_agent_id;
+    /// Adds the item with the given id rather than the next free one, and
+    /// returns the id of the leaf it was added to. Fails if the id is already
+    /// in use or the item is outside of the tree. Future items will be given
+    /// ids above this one.
+    pub fn add_item_with_id(&mut self, item_id: usize, item: T) -> Option<usize> {
+        if self.get_item(item_id).is_some() {
+            return None;
+        }
 
-        self.agents.insert(agent_id, agent);
-        self.agent_to_node.insert(agent_id, leaf_id);
-        self.get_leaf_mut(leaf_id)?.children.push(agent_id);
+        let leaf_id = self.get_node_for_pos(item.pos())?;
 
-        self.next_agent_id += 1;
+        self.insert_item(item_id, item);
+        self.set_node_for_item(item_id, leaf_id);
+        self.get_leaf_mut(leaf_id)?.children.push(item_id);
+
+        self.reserve_item_ids(item_id + 1);
         self.check_capacity(leaf_id);
 
         Some(leaf_id)
     }
 
-    pub fn remove_agent(&mut self, agent_id: usize) -> Option<Agent> {
-        let leaf_id = self.get_node_for_agent(agent_id)?;
+    /// Removes the item from the tree, joining the nodes above its leaf that
+    /// no longer hold enough items to stay split.
+    pub fn remove_item(&mut self, item_id: usize) -> Option<T> {
+        let leaf_id = self.get_node_for_item(item_id)?;
         let leaf = self.get_leaf_mut(leaf_id)?;
-        leaf.children.retain(|id| *id != agent_id);
+        leaf.children.retain(|id| *id != item_id);
+
+        self.item_to_node[item_id] = None;
+        let item = self.items[item_id].take();
+        if item.is_some() {
+            self.item_count -= 1;
+        }
+
+        let mut curr = self.get(leaf_id).and_then(|node| node.parent);
+        while let Some(parent_id) = curr {
+            if !self.try_join(parent_id) {
+                break;
+            }
+            curr = self.get(parent_id).and_then(|node| node.parent);
+        }
+
+        item
+    }
 
-        self.agent_to_node.remove(&agent_id);
-        self.agents.remove(&agent_id)
+    /// Whether a leaf with the given items, bounds, and depth should be split
+    /// into four. Leaves where every item is at the same position are never
+    /// split, since no amount of splitting would separate them.
+    fn should_split(&self, item_ids: &[usize], bounds: Rect<f64>, depth: usize) -> bool {
+        if item_ids.len() <= self.config.leaf_capacity
+            || bounds.get_width() <= self.config.min_leaf_width
+            || depth >= self.config.max_depth
+        {
+            return false;
+        }
+
+        let first_pos = self.get_item(item_ids[0]).map(|item| item.pos());
+        item_ids[1..]
+            .iter()
+            .any(|item_id| self.get_item(*item_id).map(|item| item.pos()) != first_pos)
     }
 
+    /// Walks the tree once to describe its shape.
+    pub fn stats(&self) -> QuadtreeStats {
+        let mut stats = QuadtreeStats {
+            node_count: 0,
+            leaf_count: 0,
+            max_depth: 0,
+            mean_leaf_occupancy: 0.0,
+            max_leaf_occupancy: 0,
+            empty_leaves: 0,
+            open_node_slots: self.open_node_indices.len(),
+        };
+
+        let mut stack = vec![(0, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            let node = match self.get(id) {
+                Some(node) => node,
+                None => continue,
+            };
+            stats.node_count += 1;
+
+            if node.is_leaf() {
+                stats.leaf_count += 1;
+                stats.max_depth = stats.max_depth.max(depth);
+                stats.max_leaf_occupancy = stats.max_leaf_occupancy.max(node.children.len());
+                if node.children.is_empty() {
+                    stats.empty_leaves += 1;
+                }
+            } else {
+                stack.extend(node.children.iter().map(|child| (*child, depth + 1)));
+            }
+        }
+        if stats.leaf_count > 0 {
+            stats.mean_leaf_occupancy = self.item_count as f64 / stats.leaf_count as f64;
+        }
+
+        stats
+    }
+
+    /// Returns the depth of the leaf holding the item, with the root of the
+    /// tree at a depth of 0.
+    pub fn depth_of(&self, item_id: usize) -> Option<usize> {
+        self.get_node_for_item(item_id).map(|id| self.depth(id))
+    }
+
+    /// Returns the number of ancestors of a node
+    fn depth(&self, id: usize) -> usize {
+        let mut depth = 0;
+        let mut curr = id;
+        while let Some(parent) = self.get(curr).and_then(|node| node.parent) {
+            depth += 1;
+            curr = parent;
+        }
+        depth
+    }
+
+    /// Splits the leaf if it holds too many items, and keeps splitting any of
+    /// the new leaves that still do, so that a crowded quadrant doesn't leave
+    /// an overfull leaf behind.
     fn check_capacity(&mut self, leaf_id: usize) {
         let leaf = self.get_leaf(leaf_id).unwrap();
-        if leaf.children.len() > self.leaf_capacity && leaf.bounds.get_width() > 2.0 {
-            self.split(leaf_id);
+        if !self.should_split(&leaf.children, leaf.bounds, self.depth(leaf_id)) {
+            return;
+        }
+
+        self.split(leaf_id);
+        for child in self.nodes[leaf_id].children.clone() {
+            self.check_capacity(child);
         }
     }
 
+    /// Joins every root node whose subtree holds few enough items to fit in a
+    /// single leaf. Roots are visited from the deepest up, so joins cascade all
+    /// the way up the tree in a single pass.
     pub fn clean_tree(&mut self) {
-        let mut leaf_parents = HashSet::new();
-        for leaf in self.iter_nodes().filter(|node| node.is_leaf()) {
-            if let Some(parent) = leaf.parent {
-                leaf_parents.insert(parent);
-            }
+        let mut roots = (0..self.nodes.len())
+            .filter(|id| self.nodes[*id].is_root())
+            .map(|id| (self.depth(id), id))
+            .collect::<Vec<_>>();
+        roots.sort_unstable_by(|a, b| b.cmp(a));
+
+        for (_, id) in roots {
+            self.try_join(id);
         }
+    }
 
-        for parent_id in leaf_parents.iter() {
-            if let Some(parent) = self.get(*parent_id) {
-                if !parent.is_leaf()
-                    && parent
-                        .children
-                        .iter()
-                        .all(|child| self.get(*child).unwrap().is_leaf())
-                    && parent
-                        .children
-                        .iter()
-                        .map(|child| self.get_leaf(*child).unwrap().children.len())
-                        .sum::<usize>()
-                        <= self.leaf_capacity
-                {
-                    self.join(*parent_id);
-                }
+    /// Joins the root node if all of its children are leaves that together
+    /// hold no more items than a single leaf can. Returns whether it joined.
+    fn try_join(&mut self, id: usize) -> bool {
+        let node = match self.get(id) {
+            Some(node) if node.is_root() => node,
+            _ => return false,
+        };
+
+        let mut item_count = 0;
+        for child in node.children.iter() {
+            match self.get_leaf(*child) {
+                Some(leaf) => item_count += leaf.children.len(),
+                None => return false,
             }
         }
+        if item_count > self.config.leaf_capacity {
+            return false;
+        }
+
+        self.join(id).is_some()
     }
 
     fn split(&mut self, id: usize) -> Option<()> {
         let node = self.get_leaf(id)?;
-        let node_parent = node.parent.clone();
+        let node_parent = node.parent;
         let node_bounds = node.bounds;
-        let node_agents = node.children.clone();
+        let node_items = node.children.clone();
 
         let mut new_leaves = node_bounds
             .quarter()
@@ -237,10 +643,10 @@ impl Quadtree {
             .map(|bound| Node::new_leaf(Some(id), bound))
             .collect::<Vec<_>>();
 
-        for agent_id in node_agents.into_iter() {
-            let agent = self.get_agent(agent_id)?;
-            let quadrant = node_bounds.get_quadrant(agent.pos);
-            new_leaves[quadrant].children.push(agent_id);
+        for item_id in node_items.into_iter() {
+            let item = self.get_item(item_id)?;
+            let quadrant = node_bounds.get_quadrant(item.pos());
+            new_leaves[quadrant].children.push(item_id);
         }
 
         let children = new_leaves
@@ -249,9 +655,9 @@ impl Quadtree {
             .collect::<Vec<_>>();
 
         for child_id in children.iter() {
-            let agents = self.get_leaf(*child_id)?.children.clone();
-            for agent_id in agents.iter() {
-                self.agent_to_node.insert(*agent_id, *child_id);
+            let items = self.get_leaf(*child_id)?.children.clone();
+            for item_id in items.iter() {
+                self.set_node_for_item(*item_id, *child_id);
             }
         }
 
@@ -263,30 +669,24 @@ impl Quadtree {
     /// Join a root node with leaves as children into a single leaf node
     fn join(&mut self, id: usize) -> Option<()> {
         let node = self.get(id)?;
+        let node_parent = node.parent;
         let node_bounds = node.bounds;
         let node_children = node.children.clone();
-        let node_agents = node_children
+        let node_items = node_children
             .iter()
-            .flat_map(|child| {
-                self.get_leaf(*child)
-                    .unwrap()
-                    .children
-                    .iter()
-                    .copied()
-                    .collect::<Vec<_>>()
-            })
+            .flat_map(|child| self.get_leaf(*child).unwrap().children.to_vec())
             .collect::<Vec<_>>();
 
-        for agent_id in node_agents.iter() {
-            self.agent_to_node.insert(*agent_id, id);
+        for item_id in node_items.iter() {
+            self.set_node_for_item(*item_id, id);
         }
 
         for leaf_id in node_children.iter() {
             self.remove_node(*leaf_id);
         }
 
-        let mut new_leaf = Node::new_leaf(Some(id), node_bounds);
-        new_leaf.children = node_agents;
+        let mut new_leaf = Node::new_leaf(node_parent, node_bounds);
+        new_leaf.children = node_items;
         self.nodes[id] = new_leaf;
 
         Some(())
@@ -297,9 +697,7 @@ impl Quadtree {
         let mut leaves = Vec::new();
         let mut to_visit = vec![0];
 
-        while to_visit.len() > 0 {
-            // unwrap since we know the vector isn't empty
-            let curr = to_visit.pop().unwrap();
+        while let Some(curr) = to_visit.pop() {
             let curr_node = self.get(curr).unwrap();
 
             if !curr_node.bounds.intersects(bounds) {
@@ -313,13 +711,14 @@ impl Quadtree {
                         to_visit.push(*child);
                     }
                 }
+                NodeType::Open => {}
             }
         }
 
-        return leaves;
+        leaves
     }
 
-    pub fn find_agents_in_bounds(&self, bounds: Rect<f64>) -> Vec<usize> {
+    pub fn find_items_in_bounds(&self, bounds: Rect<f64>) -> Vec<usize> {
         let leaves = self.find_leaves_in_bounds(bounds);
         leaves
             .iter()
@@ -327,58 +726,366 @@ impl Quadtree {
             .collect()
     }
 
-    pub fn move_agent(&mut self, agent_id: usize, new_pos: Vec2D<f64>) -> Option<()> {
-        let node_id = self.get_node_for_agent(agent_id)?;
-        let node_bounds = self.get_leaf(node_id)?.bounds;
+    /// Find every leaf node which has bounds that overlap with the given circle
+    pub fn find_leaves_in_radius(&self, center: Vec2D<f64>, radius: f64) -> Vec<usize> {
+        let mut leaves = Vec::new();
+        let mut to_visit = vec![0];
+
+        while let Some(curr) = to_visit.pop() {
+            let curr_node = self.get(curr).unwrap();
+
+            if !curr_node.bounds.intersects_circle(center, radius) {
+                continue;
+            }
+
+            match curr_node.typ {
+                NodeType::Leaf => leaves.push(curr),
+                NodeType::Root => to_visit.extend(curr_node.children.iter().copied()),
+                NodeType::Open => {}
+            }
+        }
+
+        leaves
+    }
+
+    /// Find every item within the given distance of the center, including
+    /// items exactly at that distance
+    pub fn find_items_in_radius(&self, center: Vec2D<f64>, radius: f64) -> Vec<usize> {
+        let leaves = self.find_leaves_in_radius(center, radius);
+        leaves
+            .iter()
+            .flat_map(|leaf| self.get_leaf(*leaf).unwrap().children.iter().copied())
+            .filter(|item_id| {
+                self.get_item(*item_id)
+                    .is_some_and(|item| item.pos().dist(center) <= radius)
+            })
+            .collect()
+    }
+
+    /// Returns a parallel iterator over the ids and items in the tree. The
+    /// positions of the items must not be changed through it, since the tree
+    /// is not updated.
+    #[cfg(feature = "rayon")]
+    pub fn par_items_mut(&mut self) -> impl rayon::iter::ParallelIterator<Item = (usize, &mut T)>
+    where
+        T: Send,
+    {
+        use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+        self.items
+            .par_iter_mut()
+            .enumerate()
+            .filter_map(|(id, item)| item.as_mut().map(|item| (id, item)))
+    }
+
+    /// Moves the item to the new position, moving it between leaves if
+    /// needed. The item is left where it was if the position is outside of the
+    /// bounds of the tree.
+    pub fn move_item(&mut self, item_id: usize, new_pos: Vec2D<f64>) -> Result<(), MoveError> {
+        let node_id = self
+            .get_node_for_item(item_id)
+            .ok_or(MoveError::UnknownItem(item_id))?;
+        if !self.bounds.contains(new_pos) {
+            return Err(MoveError::OutOfBounds(new_pos));
+        }
+        let node_bounds = self.get_leaf(node_id).expect("items are in leaves").bounds;
+
+        // the position must be updated before the new leaf is possibly split,
+        // since splitting sorts items into quadrants by their position
+        self.get_item_mut(item_id)
+            .ok_or(MoveError::UnknownItem(item_id))?
+            .set_pos(new_pos);
 
         if !node_bounds.contains(new_pos) {
-            let new_node_id = self.get_node_for_pos(new_pos)?;
-            let new_node = self.get_leaf_mut(new_node_id)?;
+            let new_node_id = self
+                .get_node_for_pos_hinted(new_pos, node_id)
+                .or_else(|| self.get_node_for_pos(new_pos))
+                .ok_or(MoveError::OutOfBounds(new_pos))?;
+            let new_node = self
+                .get_leaf_mut(new_node_id)
+                .expect("positions map to leaves");
 
-            new_node.children.push(agent_id);
-            self.agent_to_node.insert(agent_id, new_node_id);
+            new_node.children.push(item_id);
+            self.set_node_for_item(item_id, new_node_id);
 
-            let curr_node = self.get_leaf_mut(node_id)?;
-            curr_node.children.retain(|&id| id != agent_id);
+            let curr_node = self.get_leaf_mut(node_id).expect("items are in leaves");
+            curr_node.children.retain(|&id| id != item_id);
 
             self.check_capacity(new_node_id);
         }
 
-        self.get_agent_mut(agent_id)?.pos = new_pos;
-
-        Some(())
+        Ok(())
     }
 
+    /// Renders the cells of the tree and a circle for every item.
     pub fn render_as_svg(&self) -> svg::Document {
+        self.render_as_svg_with_options(true, false)
+    }
+
+    /// Renders the cells of the tree, along with a circle for every item in
+    /// the color given by HasPosition::svg_fill if show_items is set, and the
+    /// number of items in every leaf at its center if show_counts is set,
+    /// which helps with checking how balanced the tree is.
+    pub fn render_as_svg_with_options(&self, show_items: bool, show_counts: bool) -> svg::Document {
         let mut doc = svg::Document::new().set(
             "viewBox",
             (
                 self.bounds.bl.x,
                 self.bounds.bl.y,
-                self.bounds.tr.x,
-                self.bounds.tr.y,
+                self.bounds.get_width(),
+                self.bounds.get_height(),
             ),
         );
 
-        for node in self.iter_nodes() {
-            let rect = svg::node::element::Rectangle::new()
-                .set("x", node.bounds.bl.x)
-                .set("y", node.bounds.bl.y)
-                .set("width", node.bounds.get_width())
-                .set("height", node.bounds.get_height())
-                .set("fill", "none")
-                .set("stroke", "black");
-
+        for rect in self.svg_cells() {
             doc = doc.add(rect);
         }
 
+        if show_items {
+            for item in self.iter() {
+                doc = doc.add(
+                    svg::node::element::Circle::new()
+                        .set("cx", item.pos().x)
+                        .set("cy", item.pos().y)
+                        .set("r", render::AGENT_RADIUS)
+                        .set("fill", item.svg_fill()),
+                );
+            }
+        }
+
+        if show_counts {
+            for leaf in self.iter_nodes().filter(|node| node.is_leaf()) {
+                let size = leaf.bounds.get_width().min(leaf.bounds.get_height());
+                let center = leaf.bounds.bl
+                    + Vec2D::new(leaf.bounds.get_width(), leaf.bounds.get_height()) / 2.0;
+                doc = doc.add(render::text(
+                    center.x,
+                    center.y + size * 0.15,
+                    size * 0.4,
+                    &leaf.children.len().to_string(),
+                ));
+            }
+        }
+
         doc
     }
+
+    /// Verifies the internal consistency of the tree, returning a description
+    /// of the first problem found. Checks that every item is in exactly one
+    /// leaf, that item_to_node agrees with it, that every item is within the
+    /// bounds of its leaf, that every node is reachable from the root exactly
+    /// once through valid, non-open indices, and that the children of every
+    /// root node are the quarters of its bounds so the leaves tile the tree.
+    /// It is public rather than only built for tests because World::audit
+    /// checks the tree with it.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        use std::collections::{HashMap, HashSet};
+
+        let open = HashSet::<usize>::from_iter(self.open_node_indices.iter().copied());
+        if open.len() != self.open_node_indices.len() {
+            return Err("open node indices contain duplicates".to_string());
+        }
+        if let Some(index) = open.iter().find(|index| **index >= self.nodes.len()) {
+            return Err(format!("open node index {} is out of range", index));
+        }
+        if let Some(index) = open.iter().find(|index| !self.nodes[**index].is_open()) {
+            return Err(format!("open node index {} refers to a live node", index));
+        }
+        if let Some(index) = (0..self.nodes.len())
+            .find(|index| self.nodes[*index].is_open() && !open.contains(index))
+        {
+            return Err(format!("node {} is open but not in the open list", index));
+        }
+        if self.nodes.last().is_some_and(|node| node.is_open()) {
+            return Err("the last node is open".to_string());
+        }
+        if open.contains(&0) {
+            return Err("the root node is open".to_string());
+        }
+        if self.nodes[0].bounds != self.bounds {
+            return Err("the root node doesn't match the bounds of the tree".to_string());
+        }
+
+        let mut visited = HashSet::new();
+        let mut item_leaves = HashMap::<usize, usize>::new();
+        let mut stack = vec![0];
+        while let Some(id) = stack.pop() {
+            if !visited.insert(id) {
+                return Err(format!("node {} is reachable more than once", id));
+            }
+            let node = &self.nodes[id];
+
+            match node.typ {
+                NodeType::Root => {
+                    let quarters = node.bounds.quarter();
+                    if node.children.len() != quarters.len() {
+                        return Err(format!(
+                            "root node {} has {} children",
+                            id,
+                            node.children.len()
+                        ));
+                    }
+                    for (child, quarter) in node.children.iter().zip(quarters) {
+                        if *child >= self.nodes.len() || open.contains(child) {
+                            return Err(format!(
+                                "root node {} references invalid node {}",
+                                id, child
+                            ));
+                        }
+                        if self.nodes[*child].parent != Some(id) {
+                            return Err(format!(
+                                "node {} doesn't have its parent {} as parent",
+                                child, id
+                            ));
+                        }
+                        if self.nodes[*child].bounds != quarter {
+                            return Err(format!(
+                                "node {} isn't a quarter of its parent {}",
+                                child, id
+                            ));
+                        }
+                        stack.push(*child);
+                    }
+                }
+                NodeType::Leaf => {
+                    for item_id in node.children.iter() {
+                        if let Some(other) = item_leaves.insert(*item_id, id) {
+                            return Err(format!(
+                                "item {} is in both leaves {} and {}",
+                                item_id, other, id
+                            ));
+                        }
+                        let item = self
+                            .get_item(*item_id)
+                            .ok_or(format!("leaf {} references unknown item {}", id, item_id))?;
+                        if !node.bounds.contains(item.pos()) {
+                            return Err(format!(
+                                "item {} is outside the bounds of its leaf {}",
+                                item_id, id
+                            ));
+                        }
+                    }
+                }
+                NodeType::Open => return Err(format!("open node {} is reachable", id)),
+            }
+        }
+
+        if let Some(id) =
+            (0..self.nodes.len()).find(|id| !open.contains(id) && !visited.contains(id))
+        {
+            return Err(format!("node {} isn't reachable from the root", id));
+        }
+
+        for item_id in self.get_item_ids() {
+            let leaf = item_leaves
+                .get(&item_id)
+                .ok_or(format!("item {} isn't in any leaf", item_id))?;
+            if self.get_node_for_item(item_id) != Some(*leaf) {
+                return Err(format!(
+                    "item_to_node has {:?} for item {}, which is in leaf {}",
+                    self.get_node_for_item(item_id),
+                    item_id,
+                    leaf
+                ));
+            }
+        }
+        if self.item_to_node.iter().flatten().count() != self.item_count {
+            return Err("item_to_node has entries for unknown items".to_string());
+        }
+        if self.items.iter().flatten().count() != self.item_count {
+            return Err(format!("the item count {} is wrong", self.item_count));
+        }
+
+        Ok(())
+    }
+
+    /// Returns an outlined rectangle for the bounds of every node in the tree.
+    pub(crate) fn svg_cells(&self) -> Vec<svg::node::element::Rectangle> {
+        self.iter_nodes()
+            .map(|node| {
+                svg::node::element::Rectangle::new()
+                    .set("x", node.bounds.bl.x)
+                    .set("y", node.bounds.bl.y)
+                    .set("width", node.bounds.get_width())
+                    .set("height", node.bounds.get_height())
+                    .set("fill", "none")
+                    .set("stroke", "black")
+            })
+            .collect()
+    }
+}
+
+/// The agent specific names of the generic methods, kept so that code written
+/// before the tree was generic doesn't break.
+impl AgentTree {
+    pub fn new_with_agents(bounds: Rect<f64>, agents: Vec<Agent>) -> Self {
+        Self::new_with_items(bounds, agents)
+    }
+
+    pub fn new_with_agents_and_config(
+        bounds: Rect<f64>,
+        agents: Vec<Agent>,
+        config: QuadtreeConfig,
+    ) -> Self {
+        Self::new_with_items_and_config(bounds, agents, config)
+    }
+
+    pub fn next_agent_id(&self) -> usize {
+        self.next_item_id()
+    }
+
+    pub fn reserve_agent_ids(&mut self, next_agent_id: usize) {
+        self.reserve_item_ids(next_agent_id)
+    }
+
+    pub fn get_agent(&self, id: usize) -> Option<&Agent> {
+        self.get_item(id)
+    }
+
+    pub fn get_agent_mut(&mut self, id: usize) -> Option<&mut Agent> {
+        self.get_item_mut(id)
+    }
+
+    pub fn get_agent_ids(&self) -> Vec<usize> {
+        self.get_item_ids()
+    }
+
+    pub fn add_agent(&mut self, agent: Agent) -> Option<usize> {
+        self.add_item(agent)
+    }
+
+    pub fn add_agent_with_id(&mut self, agent_id: usize, agent: Agent) -> Option<usize> {
+        self.add_item_with_id(agent_id, agent)
+    }
+
+    pub fn remove_agent(&mut self, agent_id: usize) -> Option<Agent> {
+        self.remove_item(agent_id)
+    }
+
+    pub fn find_agents_in_bounds(&self, bounds: Rect<f64>) -> Vec<usize> {
+        self.find_items_in_bounds(bounds)
+    }
+
+    pub fn find_agents_in_radius(&self, center: Vec2D<f64>, radius: f64) -> Vec<usize> {
+        self.find_items_in_radius(center, radius)
+    }
+
+    pub fn move_agent(&mut self, agent_id: usize, new_pos: Vec2D<f64>) -> Result<(), MoveError> {
+        self.move_item(agent_id, new_pos)
+    }
+
+    #[cfg(feature = "rayon")]
+    pub fn par_agents_mut(
+        &mut self,
+    ) -> impl rayon::iter::ParallelIterator<Item = (usize, &mut Agent)> {
+        self.par_items_mut()
+    }
 }
 
 enum NodeType {
     Root,
     Leaf,
+    /// Open nodes have been removed and their ids are free to be reused.
+    Open,
 }
 struct Node {
     typ: NodeType,
@@ -406,10 +1113,502 @@ impl Node {
         }
     }
 
+    fn new_open() -> Self {
+        Self {
+            typ: NodeType::Open,
+            parent: None,
+            bounds: Rect::new(Vec2D::new_nan(), Vec2D::new_nan()),
+            children: Vec::new(),
+        }
+    }
+
     fn is_leaf(&self) -> bool {
-        match self.typ {
-            NodeType::Leaf => true,
-            NodeType::Root => false,
+        matches!(self.typ, NodeType::Leaf)
+    }
+
+    fn is_root(&self) -> bool {
+        matches!(self.typ, NodeType::Root)
+    }
+
+    fn is_open(&self) -> bool {
+        matches!(self.typ, NodeType::Open)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::MovementProfile;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn agent_at(x: f64, y: f64) -> Agent {
+        Agent::new(Vec2D::new(x, y), MovementProfile::walking())
+    }
+
+    fn random_agents(count: usize, size: f64, seed: u64) -> Vec<Agent> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..count)
+            .map(|_| agent_at(rng.gen_range(0.0..size), rng.gen_range(0.0..size)))
+            .collect()
+    }
+
+    fn sorted(mut ids: Vec<usize>) -> Vec<usize> {
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn radius_queries_include_the_boundary() {
+        let tree = AgentTree::new_with_agents(
+            Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(10.0, 10.0)),
+            vec![
+                agent_at(6.0, 5.0),
+                agent_at(5.0, 4.0),
+                agent_at(5.0, 5.0),
+                agent_at(6.0, 6.0),
+            ],
+        );
+
+        assert_eq!(
+            sorted(tree.find_agents_in_radius(Vec2D::new(5.0, 5.0), 1.0)),
+            vec![0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn radius_queries_skip_agents_outside_the_circle_in_intersecting_leaves() {
+        let config = QuadtreeConfig {
+            leaf_capacity: 1,
+            min_leaf_width: 0.1,
+            ..QuadtreeConfig::default()
+        };
+        let tree = AgentTree::new_with_agents_and_config(
+            Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(10.0, 10.0)),
+            vec![
+                agent_at(5.5, 5.0),
+                agent_at(5.8, 5.8),
+                agent_at(1.0, 1.0),
+                agent_at(9.0, 9.0),
+            ],
+            config,
+        );
+        let center = Vec2D::new(5.0, 5.0);
+
+        let corner_leaf = tree.get_node_for_pos(Vec2D::new(5.8, 5.8)).unwrap();
+        assert!(tree
+            .find_leaves_in_radius(center, 1.0)
+            .contains(&corner_leaf));
+        assert_eq!(tree.find_agents_in_radius(center, 1.0), vec![0]);
+        assert!(tree
+            .find_agents_in_bounds(Rect::new_centered(center, Vec2D::new(2.0, 2.0)))
+            .contains(&1));
+    }
+
+    #[test]
+    fn bulk_and_incremental_builds_agree() {
+        let bounds = Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(1000.0, 1000.0));
+        let agents = random_agents(100_000, 1000.0, 10);
+        let config = QuadtreeConfig {
+            min_leaf_width: 0.0,
+            ..QuadtreeConfig::default()
+        };
+
+        let bulk = AgentTree::new_with_agents_and_config(bounds, agents.clone(), config);
+        let mut incremental = AgentTree::new_with_config(bounds, config);
+        for agent in agents {
+            incremental.add_agent(agent);
+        }
+
+        for tree in [&bulk, &incremental] {
+            tree.check_invariants().unwrap();
+            assert_eq!(tree.len(), 100_000);
+            assert!(tree.stats().max_leaf_occupancy <= tree.config().leaf_capacity);
+        }
+        assert_eq!(bulk.get_agent_ids(), incremental.get_agent_ids());
+        assert_eq!(bulk.stats().leaf_count, incremental.stats().leaf_count);
+        for id in bulk.get_agent_ids() {
+            assert_eq!(
+                bulk.get_agent(id).unwrap().pos,
+                incremental.get_agent(id).unwrap().pos
+            );
+        }
+    }
+
+    #[test]
+    fn bulk_built_trees_keep_handing_out_ids() {
+        let bounds = Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(100.0, 100.0));
+        let mut tree = AgentTree::new_with_agents(bounds, random_agents(1000, 100.0, 11));
+
+        assert!(tree.remove_agent(500).is_some());
+        tree.add_agent(agent_at(50.0, 50.0)).unwrap();
+
+        assert!(tree.get_agent(500).is_none());
+        assert!(tree.get_agent(1000).is_some());
+        assert_eq!(tree.len(), 1000);
+        tree.check_invariants().unwrap();
+    }
+
+    fn split_tree() -> AgentTree {
+        let config = QuadtreeConfig {
+            leaf_capacity: 2,
+            min_leaf_width: 0.1,
+            ..QuadtreeConfig::default()
+        };
+        AgentTree::new_with_agents_and_config(
+            Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(10.0, 10.0)),
+            vec![
+                agent_at(1.0, 1.0),
+                agent_at(4.0, 1.0),
+                agent_at(1.0, 4.0),
+                agent_at(9.0, 9.0),
+            ],
+            config,
+        )
+    }
+
+    #[test]
+    fn moves_within_a_leaf_keep_the_leaf() {
+        let mut tree = split_tree();
+        let leaf = tree.get_node_for_pos(Vec2D::new(1.0, 1.0)).unwrap();
+
+        tree.move_agent(0, Vec2D::new(1.5, 1.5)).unwrap();
+
+        assert_eq!(tree.get_node_for_item(0), Some(leaf));
+        assert_eq!(tree.get_agent(0).unwrap().pos, Vec2D::new(1.5, 1.5));
+        tree.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn moves_to_a_sibling_leaf_follow_the_hint() {
+        let mut tree = split_tree();
+        let from = tree.get_node_for_pos(Vec2D::new(1.0, 1.0)).unwrap();
+        let to = tree.get_node_for_pos(Vec2D::new(3.0, 1.0)).unwrap();
+        assert_ne!(from, to);
+        assert_eq!(tree.nodes[from].parent, tree.nodes[to].parent);
+
+        assert_eq!(
+            tree.get_node_for_pos_hinted(Vec2D::new(3.0, 1.0), from),
+            Some(to)
+        );
+        tree.move_agent(0, Vec2D::new(3.0, 1.0)).unwrap();
+
+        assert_eq!(tree.get_node_for_item(0), Some(to));
+        tree.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn moves_across_the_world_walk_up_to_the_root() {
+        let mut tree = split_tree();
+        let from = tree.get_node_for_pos(Vec2D::new(1.0, 1.0)).unwrap();
+
+        assert_eq!(
+            tree.get_node_for_pos_hinted(Vec2D::new(8.0, 8.0), from),
+            tree.get_node_for_pos(Vec2D::new(8.0, 8.0))
+        );
+        assert_eq!(
+            tree.get_node_for_pos_hinted(Vec2D::new(11.0, 8.0), from),
+            None
+        );
+
+        tree.move_agent(0, Vec2D::new(8.0, 8.0)).unwrap();
+        assert_eq!(
+            tree.move_agent(1, Vec2D::new(11.0, 8.0)),
+            Err(MoveError::OutOfBounds(Vec2D::new(11.0, 8.0)))
+        );
+
+        assert_eq!(
+            tree.find_agents_in_radius(Vec2D::new(8.0, 8.0), 0.5),
+            vec![0]
+        );
+        assert_eq!(tree.get_agent(1).unwrap().pos, Vec2D::new(4.0, 1.0));
+        tree.check_invariants().unwrap();
+    }
+
+    fn tree_with_capacity(leaf_capacity: usize, agents: Vec<Agent>) -> AgentTree {
+        let config = QuadtreeConfig {
+            leaf_capacity,
+            min_leaf_width: 0.0,
+            ..QuadtreeConfig::default()
+        };
+        let bounds = Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(100.0, 100.0));
+        let mut tree = AgentTree::new_with_config(bounds, config);
+        for agent in agents {
+            tree.add_agent(agent);
+        }
+        tree
+    }
+
+    #[test]
+    fn a_capacity_of_one_leaves_one_item_per_leaf() {
+        let tree = tree_with_capacity(1, random_agents(500, 100.0, 13));
+
+        tree.check_invariants().unwrap();
+        assert_eq!(tree.stats().max_leaf_occupancy, 1);
+    }
+
+    #[test]
+    fn a_capacity_of_a_thousand_never_splits() {
+        let tree = tree_with_capacity(1000, random_agents(1000, 100.0, 13));
+
+        tree.check_invariants().unwrap();
+        assert_eq!(tree.stats().leaf_count, 1);
+        assert_eq!(tree.live_node_count(), 1);
+    }
+
+    #[test]
+    fn items_at_the_same_position_stop_splitting() {
+        let agents = (0..100).map(|_| agent_at(12.5, 12.5)).collect::<Vec<_>>();
+        let mut tree = tree_with_capacity(1, agents.clone());
+
+        tree.check_invariants().unwrap();
+        assert_eq!(tree.stats().max_leaf_occupancy, 100);
+        assert_eq!(tree.depth_of(0), Some(0));
+
+        let bulk = AgentTree::new_with_agents_and_config(
+            Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(100.0, 100.0)),
+            agents,
+            tree.config(),
+        );
+        assert_eq!(bulk.stats().leaf_count, 1);
+
+        tree.add_agent(agent_at(80.0, 80.0)).unwrap();
+        tree.check_invariants().unwrap();
+        assert_eq!(tree.stats().max_leaf_occupancy, 100);
+    }
+
+    #[test]
+    fn random_operations_keep_the_invariants() {
+        let size = 100.0;
+        let bounds = Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(size, size));
+        let config = QuadtreeConfig {
+            leaf_capacity: 4,
+            min_leaf_width: 0.01,
+            ..QuadtreeConfig::default()
+        };
+
+        for seed in 0..4 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut tree = AgentTree::new_with_config(bounds, config);
+            let mut ids = Vec::new();
+            // half of the positions fall in a small corner to force deep splits
+            let random_pos = |rng: &mut StdRng| {
+                let extent = if rng.gen_bool(0.5) { 1.0 } else { size };
+                Vec2D::new(rng.gen_range(0.0..extent), rng.gen_range(0.0..extent))
+            };
+
+            for _ in 0..100 {
+                for _ in 0..50 {
+                    match rng.gen_range(0..10) {
+                        0..=3 => {
+                            let pos = random_pos(&mut rng);
+                            ids.push(tree.next_agent_id());
+                            tree.add_agent(agent_at(pos.x, pos.y)).unwrap();
+                        }
+                        4..=6 if !ids.is_empty() => {
+                            let id = ids[rng.gen_range(0..ids.len())];
+                            let pos = random_pos(&mut rng);
+                            tree.move_agent(id, pos).unwrap();
+                        }
+                        7..=8 if !ids.is_empty() => {
+                            let id = ids.swap_remove(rng.gen_range(0..ids.len()));
+                            assert!(tree.remove_agent(id).is_some());
+                        }
+                        _ => tree.clean_tree(),
+                    }
+                }
+
+                tree.check_invariants().unwrap();
+                assert_eq!(tree.len(), ids.len());
+                assert_eq!(tree.get_agent_ids(), sorted(ids.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn cleaning_a_thinned_cluster_joins_back_up_the_tree() {
+        let mut rng = StdRng::seed_from_u64(41);
+        let cluster = (0..100)
+            .map(|_| {
+                agent_at(
+                    50.0 + rng.gen_range(0.0..0.5),
+                    50.0 + rng.gen_range(0.0..0.5),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut tree = tree_with_capacity(4, cluster);
+        assert!(tree.live_node_count() > 20);
+
+        for id in 2..100 {
+            tree.remove_agent(id).unwrap();
+        }
+        tree.clean_tree();
+
+        tree.check_invariants().unwrap();
+        assert_eq!(tree.live_node_count(), 1);
+        for id in [0, 1] {
+            let pos = tree.get_agent(id).unwrap().pos;
+            assert!(tree.find_agents_in_radius(pos, 0.01).contains(&id));
+        }
+        let everything = Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(100.0, 100.0));
+        assert_eq!(sorted(tree.find_agents_in_bounds(everything)), vec![0, 1]);
+    }
+
+    #[test]
+    fn repeated_split_and_join_cycles_free_every_node_slot() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut tree = tree_with_capacity(2, vec![agent_at(90.0, 90.0)]);
+
+        for _ in 0..200 {
+            // a cluster somewhere different each cycle, added then removed
+            let corner = Vec2D::new(rng.gen_range(0.0..80.0), rng.gen_range(0.0..80.0));
+            let first = tree.next_agent_id();
+            for _ in 0..30 {
+                let offset = Vec2D::new(rng.gen_range(0.0..5.0), rng.gen_range(0.0..5.0));
+                tree.add_agent(agent_at(corner.x + offset.x, corner.y + offset.y));
+            }
+            tree.check_invariants().unwrap();
+
+            for id in first..first + 30 {
+                tree.remove_agent(id).unwrap();
+                tree.check_invariants().unwrap();
+            }
+            tree.clean_tree();
+            tree.check_invariants().unwrap();
+            assert_eq!(tree.get_agent_ids(), vec![0]);
+            // every slot but the root's was freed, so none are left behind
+            assert_eq!(tree.node_count(), 1);
         }
     }
+
+    #[test]
+    fn stats_describe_a_hand_built_tree() {
+        // one agent in each quadrant, then a second in the top right, which
+        // splits it again
+        let mut tree = tree_with_capacity(
+            1,
+            vec![
+                agent_at(10.0, 10.0),
+                agent_at(60.0, 10.0),
+                agent_at(10.0, 60.0),
+                agent_at(60.0, 60.0),
+                agent_at(80.0, 80.0),
+            ],
+        );
+
+        assert_eq!(
+            tree.stats(),
+            QuadtreeStats {
+                node_count: 9,
+                leaf_count: 7,
+                max_depth: 2,
+                mean_leaf_occupancy: 5.0 / 7.0,
+                max_leaf_occupancy: 1,
+                empty_leaves: 2,
+                open_node_slots: 0,
+            }
+        );
+        assert_eq!(tree.depth_of(0), Some(1));
+        assert_eq!(tree.depth_of(3), Some(2));
+        assert_eq!(tree.depth_of(4), Some(2));
+        assert_eq!(tree.depth_of(5), None);
+
+        tree.remove_agent(4);
+        let stats = tree.stats();
+        assert_eq!(
+            (stats.node_count, stats.leaf_count, stats.max_depth),
+            (5, 4, 1)
+        );
+        assert_eq!(stats.open_node_slots, tree.node_count() - 5);
+        assert_eq!(tree.depth_of(3), Some(1));
+    }
+
+    #[test]
+    fn removed_slots_drop_out_of_every_view_of_a_large_population() {
+        let agents = random_agents(5000, 100.0, 9);
+        let mut tree = AgentTree::new_with_agents(
+            Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(100.0, 100.0)),
+            agents,
+        );
+
+        for agent_id in (0..5000).step_by(3) {
+            assert!(tree.remove_agent(agent_id).is_some());
+            assert!(tree.remove_agent(agent_id).is_none());
+        }
+
+        let live = (0..5000).filter(|id| id % 3 != 0).collect::<Vec<_>>();
+        assert_eq!(tree.len(), live.len());
+        assert_eq!(tree.get_agent_ids(), live);
+        assert!(tree.get_agent(3).is_none());
+        assert!(tree.get_agent(4).is_some());
+        // iteration follows the order of the ids
+        assert!(tree
+            .iter()
+            .zip(&live)
+            .all(|(agent, agent_id)| agent.pos() == tree.get_agent(*agent_id).unwrap().pos()));
+        assert_eq!(tree.iter().count(), live.len());
+        tree.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn svgs_are_framed_by_the_bounds_and_draw_every_agent() {
+        let bounds = Rect::new(Vec2D::new(10.0, 10.0), Vec2D::new(20.0, 30.0));
+        let agents = random_agents(30, 10.0, 10)
+            .into_iter()
+            .map(|mut agent| {
+                agent.pos = agent.pos + Vec2D::new(10.0, 10.0 + agent.pos.y);
+                agent
+            })
+            .collect();
+        let tree = AgentTree::new_with_agents(bounds, agents);
+
+        let svg = tree.render_as_svg().to_string();
+        assert!(svg.contains(r#"viewBox="10 10 10 20""#));
+        assert_eq!(svg.matches("<circle").count(), tree.len());
+        assert_eq!(svg.matches("<text").count(), 0);
+
+        let leaf_count = tree.iter_leaves().count();
+        let counts = tree.render_as_svg_with_options(false, true).to_string();
+        assert_eq!(counts.matches("<circle").count(), 0);
+        assert_eq!(counts.matches("<text").count(), leaf_count);
+    }
+
+    #[test]
+    fn leaves_never_include_recycled_node_slots() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut tree = tree_with_capacity(2, random_agents(60, 100.0, 11));
+        let mut saw_open_slots = false;
+
+        for _ in 0..300 {
+            // gather a few agents into a cluster, splitting the leaves there
+            // and joining the ones they left behind
+            let corner = Vec2D::new(rng.gen_range(0.0..95.0), rng.gen_range(0.0..95.0));
+            for _ in 0..10 {
+                let agent_id = rng.gen_range(0..60);
+                let offset = Vec2D::new(rng.gen_range(0.0..5.0), rng.gen_range(0.0..5.0));
+                tree.move_item(agent_id, corner + offset).unwrap();
+            }
+            tree.clean_tree();
+            saw_open_slots |= tree.live_node_count() < tree.node_count();
+
+            let leaves = tree.iter_leaves().collect::<Vec<_>>();
+            assert_eq!(
+                leaves.len(),
+                tree.iter_nodes().filter(|node| node.is_leaf()).count()
+            );
+            // the leaves tile the tree, which a stale slot would overlap
+            let area = leaves
+                .iter()
+                .map(|(bounds, _)| bounds.get_width() * bounds.get_height())
+                .sum::<f64>();
+            assert!((area - 100.0 * 100.0).abs() < 1e-6);
+            let agent_ids = leaves
+                .iter()
+                .flat_map(|(_, agent_ids)| agent_ids.iter().copied())
+                .collect::<Vec<_>>();
+            assert_eq!(sorted(agent_ids), (0..60).collect::<Vec<_>>());
+        }
+
+        assert!(saw_open_slots);
+    }
 }
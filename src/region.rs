@@ -0,0 +1,196 @@
+use rand::Rng;
+
+use crate::agent::{Agent, Task};
+use crate::geometry::{Rect, Vec2D};
+use crate::replay::DecisionKind;
+use crate::stats::WorldStats;
+use crate::World;
+
+/// Region is a named part of a world, such as a town, that agents live in and
+/// travel between.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Region {
+    pub name: String,
+    pub bounds: Rect<f64>,
+}
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Adds a region and returns its id, which is its index among the regions
+    /// in the order they were added. Agents whose home, or position if they
+    /// have no home, is within the region and that don't have a home region
+    /// yet are given this one.
+    pub fn add_region(&mut self, name: impl Into<String>, bounds: Rect<f64>) -> usize {
+        let region_id = self.regions.len();
+        self.regions.push(Region {
+            name: name.into(),
+            bounds,
+        });
+
+        for agent in self.agents.iter_mut() {
+            if agent.home_region.is_none() && bounds.contains(Self::home_or_pos(agent)) {
+                agent.home_region = Some(region_id);
+            }
+        }
+
+        region_id
+    }
+
+    /// Returns the regions, indexed by id.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Returns the id of the first region containing the position.
+    pub fn region_at(&self, pos: Vec2D<f64>) -> Option<usize> {
+        self.regions
+            .iter()
+            .position(|region| region.bounds.contains(pos))
+    }
+
+    /// Counts the agents within the bounds like current_stats, including
+    /// agents that were removed after dying where they died.
+    pub fn current_stats_in(&self, bounds: Rect<f64>) -> WorldStats {
+        let agent_ids = self.agents.find_agents_in_bounds(bounds);
+        WorldStats::from_agents(
+            self.curr_step,
+            self.time.abs_time(),
+            agent_ids
+                .iter()
+                .filter_map(|id| self.agents.get_agent(*id))
+                .chain(
+                    self.deceased
+                        .iter()
+                        .map(|(_, agent)| agent)
+                        .filter(|agent| bounds.contains(agent.pos)),
+                ),
+        )
+    }
+
+    fn home_or_pos(agent: &Agent) -> Vec2D<f64> {
+        if agent.home.is_nan() {
+            agent.pos
+        } else {
+            agent.home
+        }
+    }
+
+    /// Brings travelers whose trips are over back home. Then, once a day,
+    /// every agent at home in its region may leave on a trip to another
+    /// region with the probability given by the travel matrix, and is moved
+    /// to a random position there for trip_duration seconds. Isolating,
+    /// hospitalized, and dead agents don't travel.
+    pub(crate) fn update_travel(&mut self) {
+        if self.regions.is_empty() {
+            return;
+        }
+        let now = self.time.abs_time();
+
+        for agent_id in self.agents.get_agent_ids() {
+            let agent = self.agents.get_agent_mut(agent_id).unwrap();
+            match agent.trip_end {
+                Some(end) if end <= now => (),
+                _ => continue,
+            }
+            agent.trip_end = None;
+            agent.travel_dest = Vec2D::new_nan();
+            agent.task = Task::Home;
+            agent.arrived_at = None;
+            if agent.status.is_dead() {
+                continue;
+            }
+
+            // travelers without a home return somewhere in their region
+            let home = agent.home;
+            let pos = match agent.home_region.and_then(|id| self.regions.get(id)) {
+                Some(region) if home.is_nan() => {
+                    let mut rng = self.decisions.decide_seed(
+                        self.curr_step,
+                        agent_id,
+                        DecisionKind::Relocation,
+                        &mut self.rng,
+                    );
+                    Self::random_point_in(region.bounds, &mut rng)
+                }
+                _ => home,
+            };
+            if !pos.is_nan() {
+                self.relocate(agent_id, pos);
+            }
+        }
+
+        if !self.is_start_of_day() || self.travel_matrix.is_empty() {
+            return;
+        }
+
+        for agent_id in self.agents.get_agent_ids() {
+            let agent = self.agents.get_agent(agent_id).unwrap();
+            let home_region = match agent.home_region {
+                Some(home_region) if home_region < self.regions.len() => home_region,
+                _ => continue,
+            };
+            if agent.trip_end.is_some()
+                || agent.isolated
+                || agent.status.is_dead()
+                || agent.status.is_hospitalized()
+            {
+                continue;
+            }
+
+            for dest_region in 0..self.regions.len() {
+                if dest_region == home_region {
+                    continue;
+                }
+                let key = (
+                    self.regions[home_region].name.clone(),
+                    self.regions[dest_region].name.clone(),
+                );
+                let prob = self.travel_matrix.get(&key).copied().unwrap_or(0.0);
+                if prob <= 0.0
+                    || !self.decisions.decide_bool(
+                        self.curr_step,
+                        agent_id,
+                        DecisionKind::Travel,
+                        &mut self.rng,
+                        |rng| rng.gen_bool(prob.min(1.0)),
+                    )
+                {
+                    continue;
+                }
+
+                let mut rng = self.decisions.decide_seed(
+                    self.curr_step,
+                    agent_id,
+                    DecisionKind::Relocation,
+                    &mut self.rng,
+                );
+                let dest = Self::random_point_in(self.regions[dest_region].bounds, &mut rng);
+                let agent = self.agents.get_agent_mut(agent_id).unwrap();
+                agent.trip_end = Some(now + self.trip_duration.max(0));
+                agent.travel_dest = dest;
+                agent.task = Task::Travel;
+                agent.arrived_at = None;
+                self.relocate(agent_id, dest);
+                break;
+            }
+        }
+    }
+
+    fn random_point_in<G: Rng>(bounds: Rect<f64>, rng: &mut G) -> Vec2D<f64> {
+        Vec2D::new(
+            rng.gen_range(bounds.bl.x..=bounds.tr.x),
+            rng.gen_range(bounds.bl.y..=bounds.tr.y),
+        )
+    }
+
+    /// Moves the agent straight to the position, clamped into the world.
+    fn relocate(&mut self, agent_id: usize, pos: Vec2D<f64>) {
+        let pos = Rect::new(Vec2D::new_zero(), self.size).clamp_point(pos);
+        self.agents
+            .move_agent(agent_id, pos)
+            .expect("the agent is in the tree and clamped into the world");
+    }
+}
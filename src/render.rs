@@ -0,0 +1,534 @@
+use rand::Rng;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use svg::node::element::{Circle, Element, Path as SvgPath, Rectangle};
+use svg::node::Text;
+use svg::{Document, Node};
+
+use crate::agent::{Agent, Status};
+use crate::{RenderOptions, StructureType, World};
+
+/// AGENT_RADIUS is the radius of the circle drawn for each agent, in world
+/// units.
+pub(crate) const AGENT_RADIUS: f64 = 0.4;
+/// STROKE_WIDTH is the width of every outline, in world units.
+const STROKE_WIDTH: f64 = 0.05;
+
+const RED: &str = "\x1b[0;31m";
+const ORANGE: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[0;33m";
+const GREEN: &str = "\x1b[0;32m";
+const RESET: &str = "\x1b[0m";
+const BLUE: &str = "\x1b[0;34m";
+const CYAN: &str = "\x1b[0;36m";
+const DIM: &str = "\x1b[2m";
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Renders the world as an SVG document, with a square for every structure,
+    /// a circle for every living agent colored like the terminal display, a
+    /// cross for every dead agent, and a header with the step and counts. The
+    /// view box only depends on the size of the world, so frames of the same
+    /// world line up when animated.
+    pub fn render_svg(&self) -> Document {
+        self.render_svg_layers(false)
+    }
+
+    /// Renders the world like render_svg, with the cells of the quadtree drawn
+    /// behind everything else.
+    pub fn render_svg_with_quadtree(&self) -> Document {
+        self.render_svg_layers(true)
+    }
+
+    /// Renders the world like render_svg and saves it to the given path.
+    pub fn render_svg_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        svg::save(path, &self.render_svg())
+    }
+
+    /// Returns the render options Display draws the world with.
+    pub fn render_options(&self) -> RenderOptions {
+        self.render_options
+    }
+
+    /// Replaces the render options Display draws the world with.
+    pub fn set_render_options(&mut self, options: RenderOptions) {
+        self.render_options = options;
+    }
+
+    /// Turns coloring the output of Display on or off, such as when it isn't
+    /// going to a terminal.
+    pub fn set_use_color(&mut self, use_color: bool) {
+        self.render_options.use_color = use_color;
+    }
+
+    /// Draws the world as text with the given options, like Display does with
+    /// the options of the world.
+    pub fn render_text(&self, options: RenderOptions) -> String {
+        let mut text = String::new();
+        self.write_text(&mut text, options)
+            .expect("writing to a string never fails");
+        text
+    }
+
+    /// Writes a header with the step and counts followed by a grid with the
+    /// structures and agents, and the legend if it is shown.
+    pub(crate) fn write_text<W: fmt::Write>(
+        &self,
+        w: &mut W,
+        options: RenderOptions,
+    ) -> fmt::Result {
+        writeln!(
+            w,
+            "----- Time {:2} {}; Infected: active {}, cumulative {} of {}; Dead {}; Step {} -----",
+            self.curr_step,
+            self.step_size,
+            self.currently_exposed + self.currently_infectious,
+            self.cumulative_infections,
+            self.agents.len() + self.deceased.len(),
+            self.deaths,
+            self.timings,
+        )?;
+
+        // bucket everything into the grid in one pass, with x as columns and y
+        // as rows. structures take precedence over agents, and only the
+        // lowest id agent in a grid square is shown
+        let width = self.size.x.ceil() as i64;
+        let height = self.size.y.ceil() as i64;
+        let cell_index = |x: i64, y: i64| {
+            if (0..width).contains(&x) && (0..height).contains(&y) {
+                Some((y * width + x) as usize)
+            } else {
+                None
+            }
+        };
+        let mut cells = vec![Cell::Empty; (width * height).max(0) as usize];
+
+        for agent_id in self.agents.get_agent_ids().into_iter().rev() {
+            let agent = self.agents.get_agent(agent_id).unwrap();
+            if let Some(index) = cell_index(agent.pos.x.round() as i64, agent.pos.y.round() as i64)
+            {
+                cells[index] = Cell::Agent(agent);
+            }
+        }
+
+        let mut structure_types = self.structure_ids.keys().copied().collect::<Vec<_>>();
+        structure_types.sort_unstable();
+        for structure_type in structure_types {
+            for structure in self.structures_of_type(structure_type) {
+                if let Some(index) = cell_index(
+                    structure.pos.x.floor() as i64,
+                    structure.pos.y.floor() as i64,
+                ) {
+                    if !matches!(cells[index], Cell::Structure(_)) {
+                        cells[index] = Cell::Structure(structure_type);
+                    }
+                }
+            }
+        }
+
+        for row in cells.chunks(width.max(1) as usize) {
+            for cell in row {
+                match cell {
+                    Cell::Empty => write!(w, "{:1$}", "", options.cell_width)?,
+                    Cell::Structure(structure_type) => write_structure_cell(
+                        w,
+                        *structure_type,
+                        self.is_structure_closed(*structure_type),
+                        options,
+                    )?,
+                    Cell::Agent(agent) => {
+                        write_agent_cell(w, agent, Some(self.time.abs_time()), options)?
+                    }
+                }
+            }
+            writeln!(w)?;
+        }
+
+        if options.show_legend {
+            write_legend(w, options)?;
+        }
+
+        Ok(())
+    }
+
+    fn render_svg_layers(&self, include_quadtree: bool) -> Document {
+        // the header sits above the world so that it never covers any agents
+        let header_height = (self.size.y * 0.05).max(1.0);
+        let mut doc = Document::new().set(
+            "viewBox",
+            (
+                0.0,
+                -header_height,
+                self.size.x,
+                self.size.y + header_height,
+            ),
+        );
+
+        if include_quadtree {
+            for rect in self.agents.svg_cells() {
+                doc = doc.add(rect.set("stroke-width", STROKE_WIDTH));
+            }
+        }
+
+        let mut structure_types = self.structure_ids.keys().copied().collect::<Vec<_>>();
+        structure_types.sort_unstable();
+        for structure_type in structure_types {
+            for structure in self.structures_of_type(structure_type) {
+                let x = structure.pos.x.floor();
+                let y = structure.pos.y.floor();
+                doc = doc.add(
+                    Rectangle::new()
+                        .set("x", x)
+                        .set("y", y)
+                        .set("width", 1.0)
+                        .set("height", 1.0)
+                        .set(
+                            "fill",
+                            if self.is_structure_closed(structure_type) {
+                                "white"
+                            } else {
+                                "lightgray"
+                            },
+                        )
+                        .set("stroke", "black")
+                        .set("stroke-width", STROKE_WIDTH),
+                );
+                doc = doc.add(text(x + 0.5, y + 0.75, 0.8, &structure_type.to_string()));
+            }
+        }
+
+        for agent_id in self.agents.get_agent_ids() {
+            let agent = self.agents.get_agent(agent_id).unwrap();
+            let (x, y) = (agent.pos.x, agent.pos.y);
+            if agent.status.is_dead() {
+                let d = AGENT_RADIUS;
+                let cross = format!(
+                    "M {} {} L {} {} M {} {} L {} {}",
+                    x - d,
+                    y - d,
+                    x + d,
+                    y + d,
+                    x - d,
+                    y + d,
+                    x + d,
+                    y - d
+                );
+                doc = doc.add(
+                    SvgPath::new()
+                        .set("d", cross)
+                        .set("stroke", svg_color(agent))
+                        .set("stroke-width", STROKE_WIDTH * 2.0),
+                );
+            } else {
+                doc = doc.add(
+                    Circle::new()
+                        .set("cx", x)
+                        .set("cy", y)
+                        .set("r", AGENT_RADIUS)
+                        .set("fill", svg_color(agent)),
+                );
+            }
+        }
+
+        let header = format!(
+            "Step {}; Infected: active {}, cumulative {} of {}; Dead {}",
+            self.curr_step,
+            self.currently_exposed + self.currently_infectious,
+            self.cumulative_infections,
+            self.agents.len() + self.deceased.len(),
+            self.deaths
+        );
+        doc.add(text(
+            self.size.x / 2.0,
+            -header_height * 0.3,
+            header_height * 0.6,
+            &header,
+        ))
+    }
+}
+
+/// Cell is what is drawn in a single grid square when displaying a world.
+#[derive(Clone, Copy)]
+enum Cell<'a> {
+    Empty,
+    Structure(StructureType),
+    Agent(&'a Agent),
+}
+
+/// Returns the terminal color of the status of the agent.
+fn color(agent: &Agent) -> &'static str {
+    match agent.status {
+        Status::Susceptible if agent.vaccinated => CYAN,
+        Status::Susceptible => GREEN,
+        Status::Exposed { .. } => ORANGE,
+        Status::Infectious { .. } | Status::Hospitalized { .. } => RED,
+        Status::Recovered { .. } => YELLOW,
+        Status::Dead => BLUE,
+    }
+}
+
+/// Writes the three columns of the agent centered in the cell width. If the
+/// current time is known, the letters of the exposed and infectious statuses
+/// are followed by the days the agent has been in them, see
+/// Agent::render_cell.
+pub(crate) fn write_agent_cell<W: fmt::Write>(
+    w: &mut W,
+    agent: &Agent,
+    now: Option<i64>,
+    options: RenderOptions,
+) -> fmt::Result {
+    let columns = match now {
+        Some(now) => agent.render_cell(now),
+        None => [' ', agent.status_letter(), ' '],
+    };
+    let cell = format!(
+        "{:^1$}",
+        columns.iter().collect::<String>(),
+        options.cell_width
+    );
+    let color = color(agent);
+
+    if options.use_color {
+        write!(w, "{}{}{}", color, cell, RESET)
+    } else {
+        write!(w, "{}", cell)
+    }
+}
+
+/// Writes the letter of the structure type centered in the cell, in brackets
+/// without color since some of the letters are also statuses. Closed
+/// structures are dimmed, or in parentheses without color.
+fn write_structure_cell<W: fmt::Write>(
+    w: &mut W,
+    structure_type: StructureType,
+    closed: bool,
+    options: RenderOptions,
+) -> fmt::Result {
+    match (options.use_color, closed) {
+        (true, false) => write!(w, "{:^1$}", structure_type.to_string(), options.cell_width),
+        (true, true) => write!(
+            w,
+            "{}{:^3$}{}",
+            DIM,
+            structure_type.to_string(),
+            RESET,
+            options.cell_width
+        ),
+        (false, false) => write!(
+            w,
+            "{:^1$}",
+            format!("[{}]", structure_type),
+            options.cell_width
+        ),
+        (false, true) => write!(
+            w,
+            "{:^1$}",
+            format!("({})", structure_type),
+            options.cell_width
+        ),
+    }
+}
+
+/// Writes a line explaining each status glyph and a line for the structures.
+fn write_legend<W: fmt::Write>(w: &mut W, options: RenderOptions) -> fmt::Result {
+    let statuses = [
+        ("S", GREEN, "susceptible"),
+        ("V", CYAN, "vaccinated"),
+        ("E", ORANGE, "exposed"),
+        ("I", RED, "infectious"),
+        ("A", RED, "asymptomatic"),
+        ("H", RED, "hospitalized"),
+        ("R", YELLOW, "recovered"),
+        ("D", BLUE, "dead"),
+    ];
+    for (i, (letter, color, name)) in statuses.into_iter().enumerate() {
+        if i > 0 {
+            write!(w, ", ")?;
+        }
+        if options.use_color {
+            write!(w, "{}{}{} {}", color, letter, RESET, name)?;
+        } else {
+            write!(w, "{} {}", letter, name)?;
+        }
+    }
+    writeln!(w, "; E, I, A, and H are followed by days in the status")?;
+
+    let structures = [
+        (StructureType::Home, "home"),
+        (StructureType::Work, "work"),
+        (StructureType::School, "school"),
+        (StructureType::Hospital, "hospital"),
+    ];
+    for (i, (structure_type, name)) in structures.into_iter().enumerate() {
+        if i > 0 {
+            write!(w, ", ")?;
+        }
+        if options.use_color {
+            write!(w, "{} {}", structure_type, name)?;
+        } else {
+            write!(w, "[{}] {}", structure_type, name)?;
+        }
+    }
+    if options.use_color {
+        writeln!(w, "; dimmed when closed")
+    } else {
+        writeln!(w, "; in parentheses when closed")
+    }
+}
+
+/// Returns the SVG color matching the terminal color of the agent.
+pub(crate) fn svg_color(agent: &Agent) -> &'static str {
+    match agent.status {
+        Status::Susceptible if agent.vaccinated => "cyan",
+        Status::Susceptible => "green",
+        Status::Exposed { .. } => "orange",
+        Status::Infectious { .. } => "red",
+        Status::Hospitalized { .. } => "darkred",
+        Status::Recovered { .. } => "gold",
+        Status::Dead => "blue",
+    }
+}
+
+/// Creates a text element centered on the given point.
+pub(crate) fn text(x: f64, y: f64, font_size: f64, content: &str) -> Element {
+    let mut element = Element::new("text");
+    element.assign("x", x);
+    element.assign("y", y);
+    element.assign("font-size", font_size);
+    element.assign("font-family", "sans-serif");
+    element.assign("text-anchor", "middle");
+    element.append(Text::new(content));
+    element
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::MovementProfile;
+    use crate::geometry::Vec2D;
+    use crate::Structure;
+
+    fn plain() -> RenderOptions {
+        RenderOptions {
+            use_color: false,
+            ..RenderOptions::default()
+        }
+    }
+
+    #[test]
+    fn agents_render_with_x_as_columns_and_y_as_rows() {
+        let agent = Agent::new(Vec2D::new(2.0, 0.0), MovementProfile::walking());
+        let world = World::new_with_agents(Vec2D::new(3.0, 3.0), vec![agent]).unwrap();
+
+        let text = world.render_text(plain());
+        let rows = text.lines().skip(1).collect::<Vec<_>>();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], "       S ");
+        assert_eq!(rows[1].trim(), "");
+        assert_eq!(rows[2].trim(), "");
+    }
+
+    #[test]
+    fn svg_frames_draw_one_circle_per_living_agent() {
+        let mut agents = (0..5)
+            .map(|i| Agent::new(Vec2D::new(i as f64 + 0.5, 1.5), MovementProfile::walking()))
+            .collect::<Vec<_>>();
+        agents[3].status = Status::Dead;
+        let world = World::new_with_agents(Vec2D::new(5.0, 3.0), agents).unwrap();
+
+        for document in [world.render_svg(), world.render_svg_with_quadtree()] {
+            let svg = document.to_string();
+            assert_eq!(svg.matches("<circle").count(), 4);
+            assert_eq!(svg.matches("<path").count(), 1);
+        }
+    }
+
+    #[test]
+    fn rendering_without_color_writes_no_escape_codes() {
+        let statuses = [
+            Status::Susceptible,
+            Status::Exposed { since: 0 },
+            Status::Infectious { since: 0 },
+            Status::Hospitalized { since: 0 },
+            Status::Recovered { since: 0 },
+            Status::Dead,
+        ];
+        let agents = statuses
+            .iter()
+            .enumerate()
+            .map(|(i, status)| {
+                let mut agent = Agent::new(Vec2D::new(i as f64, 1.0), MovementProfile::walking());
+                agent.status = *status;
+                agent
+            })
+            .collect();
+        let mut world = World::new_with_agents(Vec2D::new(7.0, 4.0), agents).unwrap();
+        world.add_structure(Structure::new(StructureType::Home, Vec2D::new(1.5, 3.5), 4));
+        world.add_structure(Structure::new(StructureType::Work, Vec2D::new(4.5, 3.5), 4));
+
+        let colored = world.render_text(RenderOptions {
+            show_legend: true,
+            ..RenderOptions::default()
+        });
+        assert!(colored.contains('\x1b'));
+
+        let text = world.render_text(RenderOptions {
+            show_legend: true,
+            ..plain()
+        });
+        assert!(!text.contains('\x1b'));
+        for glyph in ["S", "E", "I", "H", "R", "D"] {
+            assert!(text.contains(glyph));
+        }
+
+        world.set_use_color(false);
+        assert!(!world.to_string().contains('\x1b'));
+    }
+
+    #[test]
+    fn manually_placed_structures_render_in_their_cell() {
+        let mut world = World::new(Vec2D::new(4.0, 3.0));
+        world.add_structure(Structure::new(StructureType::Work, Vec2D::new(2.7, 1.2), 4));
+
+        let text = world.render_text(plain());
+        let rows = text.lines().skip(1).collect::<Vec<_>>();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].trim(), "");
+        assert_eq!(rows[1], "      [W]   ");
+        assert_eq!(rows[2].trim(), "");
+    }
+
+    #[test]
+    fn long_and_future_statuses_keep_the_grid_aligned() {
+        let statuses = [
+            Status::Exposed { since: -3 * 86400 },
+            Status::Infectious { since: -42 * 86400 },
+            Status::Hospitalized {
+                since: -150 * 86400,
+            },
+            Status::Infectious { since: 86400 },
+        ];
+        let mut agents = statuses
+            .iter()
+            .enumerate()
+            .map(|(i, status)| {
+                let mut agent = Agent::new(Vec2D::new(i as f64, 0.0), MovementProfile::walking());
+                agent.status = *status;
+                agent
+            })
+            .collect::<Vec<_>>();
+        agents.push(Agent::new(Vec2D::new(2.0, 1.0), MovementProfile::walking()));
+        let world = World::new_with_agents(Vec2D::new(4.0, 2.0), agents).unwrap();
+
+        let text = world.render_text(plain());
+        let rows = text.lines().skip(1).collect::<Vec<_>>();
+
+        assert_eq!(rows[0], "E3 I42H+ I? ");
+        assert_eq!(rows[1], "       S    ");
+    }
+}
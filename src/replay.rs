@@ -0,0 +1,651 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::agent::DeathCause;
+use crate::io::{parse_csv, IoError};
+use crate::World;
+
+/// DecisionKind is the kind of stochastic decision made by the world.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DecisionKind {
+    /// The random fraction of its speed an agent loses to speed jitter for a
+    /// step.
+    Jitter,
+    /// Whether an agent is exposed by a single transmission roll.
+    Infection,
+    /// Which of the agents that exposed an agent during a step it is
+    /// infected by.
+    InfectorPick,
+    /// Whether the infection of an agent is a new variant of the strain of
+    /// its infector.
+    Mutation,
+    /// Whether an agent dies during a step, and of what.
+    Death,
+    /// Whether a newly symptomatic agent is hospitalized.
+    Hospitalization,
+    /// Whether an infectious agent is detected.
+    Detection,
+    /// Whether an agent isolates, either on showing symptoms or on being
+    /// detected.
+    Isolation,
+    /// Whether a traced contact of a detected agent is quarantined.
+    Quarantine,
+    /// The structure an agent, or the first member of a household, is
+    /// assigned to.
+    StructurePick,
+    /// The incubation period drawn for an agent when it is exposed.
+    IncubationPeriod,
+    /// The infectious period drawn for an agent when it is exposed.
+    InfectiousPeriod,
+    /// Whether an agent shows no symptoms once it becomes infectious.
+    Asymptomatic,
+    /// The seed that the parameters of a new variant are drawn with.
+    Variant,
+    /// Whether an agent complies with a lockdown that starts.
+    Lockdown,
+    /// The seed that the births of a step are drawn with, logged for the id
+    /// the first newborn gets.
+    Births,
+    /// Whether an agent seeks a test.
+    TestSeeking,
+    /// Whether the test of an agent comes back positive.
+    TestResult,
+    /// Whether an agent leaves on a trip to a region.
+    Travel,
+    /// The seed that the position an agent is moved to when it travels, or
+    /// returns from a trip without a home, is drawn with.
+    Relocation,
+}
+
+impl DecisionKind {
+    fn name(&self) -> &'static str {
+        match self {
+            DecisionKind::Jitter => "jitter",
+            DecisionKind::Infection => "infection",
+            DecisionKind::InfectorPick => "infector",
+            DecisionKind::Mutation => "mutation",
+            DecisionKind::Death => "death",
+            DecisionKind::Hospitalization => "hospitalization",
+            DecisionKind::Detection => "detection",
+            DecisionKind::Isolation => "isolation",
+            DecisionKind::Quarantine => "quarantine",
+            DecisionKind::StructurePick => "structure",
+            DecisionKind::IncubationPeriod => "incubation",
+            DecisionKind::InfectiousPeriod => "infectious",
+            DecisionKind::Asymptomatic => "asymptomatic",
+            DecisionKind::Variant => "variant",
+            DecisionKind::Lockdown => "lockdown",
+            DecisionKind::Births => "births",
+            DecisionKind::TestSeeking => "seeking",
+            DecisionKind::TestResult => "result",
+            DecisionKind::Travel => "travel",
+            DecisionKind::Relocation => "relocation",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "jitter" => Some(DecisionKind::Jitter),
+            "infection" => Some(DecisionKind::Infection),
+            "infector" => Some(DecisionKind::InfectorPick),
+            "mutation" => Some(DecisionKind::Mutation),
+            "death" => Some(DecisionKind::Death),
+            "hospitalization" => Some(DecisionKind::Hospitalization),
+            "detection" => Some(DecisionKind::Detection),
+            "isolation" => Some(DecisionKind::Isolation),
+            "quarantine" => Some(DecisionKind::Quarantine),
+            "structure" => Some(DecisionKind::StructurePick),
+            "incubation" => Some(DecisionKind::IncubationPeriod),
+            "infectious" => Some(DecisionKind::InfectiousPeriod),
+            "asymptomatic" => Some(DecisionKind::Asymptomatic),
+            "variant" => Some(DecisionKind::Variant),
+            "lockdown" => Some(DecisionKind::Lockdown),
+            "births" => Some(DecisionKind::Births),
+            "seeking" => Some(DecisionKind::TestSeeking),
+            "result" => Some(DecisionKind::TestResult),
+            "travel" => Some(DecisionKind::Travel),
+            "relocation" => Some(DecisionKind::Relocation),
+            _ => None,
+        }
+    }
+}
+
+/// Decision is a single stochastic decision made for an agent during a step.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Decision {
+    pub step: i64,
+    pub agent: usize,
+    pub kind: DecisionKind,
+    /// value is the outcome of the decision. Yes or no decisions are 1 or 0,
+    /// deaths are 0 for surviving, 1 for other causes, and 2 for the disease,
+    /// infector picks are the id of the infector, structure picks are the id
+    /// of the structure, periods are in seconds, and seeds are below 2^53 so
+    /// that they are exact.
+    pub value: f64,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of agent {} at step {}",
+            self.kind.name(),
+            self.agent,
+            self.step
+        )
+    }
+}
+
+/// DecisionLog is the sequence of decisions made while recording, in the
+/// order they were made.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecisionLog {
+    pub decisions: Vec<Decision>,
+}
+
+impl DecisionLog {
+    /// Writes the log as CSV with a header row followed by one row per
+    /// decision, in the format read by read_csv.
+    pub fn write_csv<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "step,agent,kind,value")?;
+        for decision in self.decisions.iter() {
+            writeln!(
+                w,
+                "{},{},{},{}",
+                decision.step,
+                decision.agent,
+                decision.kind.name(),
+                decision.value
+            )?;
+        }
+
+        w.flush()
+    }
+
+    /// Reads a log written by write_csv.
+    pub fn read_csv<R: Read>(r: R) -> Result<Self, IoError> {
+        let decisions = parse_csv(r, &["step", "agent", "kind", "value"], |row| {
+            let kind = match row.field("kind").and_then(DecisionKind::from_name) {
+                Some(kind) => kind,
+                None => return Err(row.invalid("kind")),
+            };
+            Ok(Decision {
+                step: row.require("step")?,
+                agent: row.require("agent")?,
+                kind,
+                value: row.require("value")?,
+            })
+        })?;
+
+        Ok(Self { decisions })
+    }
+}
+
+/// Divergence is the point at which a replayed run stopped making the same
+/// decisions as the recorded one.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Divergence {
+    /// index is the position in the log of the first decision that didn't
+    /// line up.
+    pub index: usize,
+    /// expected is the decision recorded at index, or None if the replay made
+    /// more decisions than were recorded.
+    pub expected: Option<Decision>,
+    /// found is the decision made by the replay instead, or None if the
+    /// replay ended before making every recorded decision.
+    pub found: Option<Decision>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "replay diverged at decision {}: ", self.index)?;
+        match (self.expected, self.found) {
+            (Some(expected), Some(found)) => {
+                write!(f, "expected {} but found {}", expected, found)
+            }
+            (Some(expected), None) => write!(f, "expected {} but the replay ended", expected),
+            (None, Some(found)) => write!(f, "found {} past the end of the log", found),
+            (None, None) => write!(f, "no decisions"),
+        }
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// DecisionMode is whether the world records its decisions or replays them.
+#[derive(Debug, Clone, Default)]
+pub(crate) enum DecisionMode {
+    #[default]
+    Off,
+    Record(Vec<Decision>),
+    Replay {
+        decisions: Vec<Decision>,
+        next: usize,
+    },
+    Diverged(Divergence),
+}
+
+impl DecisionMode {
+    /// Makes a decision for the agent by calling sample. While recording, the
+    /// outcome is added to the log, and while replaying, the recorded outcome
+    /// is used instead without calling sample, so the RNG is only drawn from
+    /// for decisions that aren't replayed.
+    pub(crate) fn decide<G: Rng>(
+        &mut self,
+        step: i64,
+        agent: usize,
+        kind: DecisionKind,
+        rng: &mut G,
+        sample: impl FnOnce(&mut G) -> f64,
+    ) -> f64 {
+        let (index, expected) = match self {
+            DecisionMode::Off | DecisionMode::Diverged(_) => return sample(rng),
+            DecisionMode::Record(decisions) => {
+                let value = sample(rng);
+                decisions.push(Decision {
+                    step,
+                    agent,
+                    kind,
+                    value,
+                });
+                return value;
+            }
+            DecisionMode::Replay { decisions, next } => match decisions.get(*next) {
+                Some(expected)
+                    if expected.step == step
+                        && expected.agent == agent
+                        && expected.kind == kind =>
+                {
+                    *next += 1;
+                    return expected.value;
+                }
+                expected => (*next, expected.copied()),
+            },
+        };
+
+        // the rest of the run samples its own decisions
+        let value = sample(rng);
+        *self = DecisionMode::Diverged(Divergence {
+            index,
+            expected,
+            found: Some(Decision {
+                step,
+                agent,
+                kind,
+                value,
+            }),
+        });
+        value
+    }
+
+    pub(crate) fn decide_bool<G: Rng>(
+        &mut self,
+        step: i64,
+        agent: usize,
+        kind: DecisionKind,
+        rng: &mut G,
+        sample: impl FnOnce(&mut G) -> bool,
+    ) -> bool {
+        self.decide(step, agent, kind, rng, |rng| sample(rng) as u8 as f64) != 0.0
+    }
+
+    pub(crate) fn decide_index<G: Rng>(
+        &mut self,
+        step: i64,
+        agent: usize,
+        kind: DecisionKind,
+        rng: &mut G,
+        sample: impl FnOnce(&mut G) -> usize,
+    ) -> usize {
+        self.decide(step, agent, kind, rng, |rng| sample(rng) as f64) as usize
+    }
+
+    /// Decides on a seed and returns an RNG seeded with it, for draws that
+    /// are made together, such as the parameters of a new variant.
+    pub(crate) fn decide_seed<G: Rng>(
+        &mut self,
+        step: i64,
+        agent: usize,
+        kind: DecisionKind,
+        rng: &mut G,
+    ) -> StdRng {
+        let seed = self.decide(step, agent, kind, rng, |rng| {
+            rng.gen_range(0..1u64 << 53) as f64
+        });
+        StdRng::seed_from_u64(seed as u64)
+    }
+}
+
+/// Returns the value a death decision is logged as.
+pub(crate) fn encode_death(cause: Option<DeathCause>) -> usize {
+    match cause {
+        None => 0,
+        Some(DeathCause::Other) => 1,
+        Some(DeathCause::Disease) => 2,
+    }
+}
+
+/// Returns the cause of death a logged death decision stands for.
+pub(crate) fn decode_death(value: usize) -> Option<DeathCause> {
+    match value {
+        0 => None,
+        1 => Some(DeathCause::Other),
+        _ => Some(DeathCause::Disease),
+    }
+}
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Starts recording every decision of the kinds in DecisionKind,
+    /// discarding anything recorded or being replayed. These cover everything
+    /// the phases of a step draw from the RNG, so a replay doesn't depend on
+    /// how the replaying world is seeded.
+    ///
+    /// Draws made by other methods, and so by scheduled actions such as the
+    /// mask policy, aren't recorded: choosing index cases or agents to
+    /// vaccinate, assigning households, and importing cases. Since replayed
+    /// decisions don't draw from the RNG, these come out differently in a
+    /// replay unless they are made before it starts. Exposures and structure
+    /// picks they make are still recorded.
+    pub fn enable_decision_log(&mut self) {
+        self.decisions = DecisionMode::Record(Vec::new());
+    }
+
+    /// Stops recording and returns the decisions made since
+    /// enable_decision_log, or None if the world wasn't recording.
+    pub fn take_decision_log(&mut self) -> Option<DecisionLog> {
+        match std::mem::take(&mut self.decisions) {
+            DecisionMode::Record(decisions) => Some(DecisionLog { decisions }),
+            mode => {
+                self.decisions = mode;
+                None
+            }
+        }
+    }
+
+    /// Replays the log in the following steps, which should start from the
+    /// same state the recording did, such as an identically built world.
+    /// Every decision must be made for the same agent, of the same kind, and
+    /// at the same step as the next one in the log, and is given the recorded
+    /// outcome. At the first decision that doesn't line up, the world stops
+    /// consuming the log and goes back to making its own decisions, and the
+    /// divergence is kept for try_step, replay_divergence, and finish_replay.
+    pub fn replay_from(&mut self, log: DecisionLog) {
+        self.decisions = DecisionMode::Replay {
+            decisions: log.decisions,
+            next: 0,
+        };
+    }
+
+    /// Steps the world like step, then fails with where the replay diverged
+    /// from the log if it has, during this step or an earlier one.
+    pub fn try_step(&mut self) -> Result<(), Divergence> {
+        self.step();
+        match self.replay_divergence() {
+            Some(divergence) => Err(*divergence),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns where the replay diverged from the log, if it has.
+    pub fn replay_divergence(&self) -> Option<&Divergence> {
+        match &self.decisions {
+            DecisionMode::Diverged(divergence) => Some(divergence),
+            _ => None,
+        }
+    }
+
+    /// Stops replaying, returning where the replay diverged from the log,
+    /// including when recorded decisions were never made. Returns Ok if the
+    /// world wasn't replaying.
+    pub fn finish_replay(&mut self) -> Result<(), Divergence> {
+        match std::mem::take(&mut self.decisions) {
+            DecisionMode::Diverged(divergence) => Err(divergence),
+            DecisionMode::Replay { decisions, next } if next < decisions.len() => Err(Divergence {
+                index: next,
+                expected: Some(decisions[next]),
+                found: None,
+            }),
+            DecisionMode::Record(decisions) => {
+                self.decisions = DecisionMode::Record(decisions);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::WorldBuilder;
+    use crate::disease::{BasicDisease, DurationDistribution, IfrTable, TransmissionModel};
+    use crate::geometry::Rect;
+    use crate::intervention::{IsolationPolicy, LockdownPolicy, TestingPolicy, TracingPolicy};
+    use crate::{StructureType, Vec2D};
+
+    /// Builds a world that makes every kind of decision a step can make
+    /// within its first couple of days.
+    fn busy_world(model: TransmissionModel) -> World<StdRng> {
+        let mut world = WorldBuilder::new()
+            .size(Vec2D::new(60.0, 60.0))
+            .random_agents(300)
+            .agent_speed(0.5, 1.0)
+            .structures(StructureType::Home, 100)
+            .structures(StructureType::Work, 5)
+            .structures(StructureType::School, 3)
+            .step_size(3600)
+            .contact_radius(5.0)
+            .transmission_model(model)
+            .disease(BasicDisease::new(
+                0.5,
+                DurationDistribution::Uniform(2 * 3600, 4 * 3600),
+                DurationDistribution::Uniform(3 * 86400, 5 * 86400),
+                IfrTable::flat(0.05),
+            ))
+            .index_cases(10)
+            .seed(91)
+            .build()
+            .unwrap();
+        for agent in world.agents.iter_mut() {
+            agent.hospitalization_prob = 0.2;
+        }
+        world.disease.asymptomatic_prob = 0.3;
+        world.speed_jitter = 0.2;
+        world.mutation_prob = 0.1;
+        world.self_isolation_prob = 0.3;
+        world.birth_rate = 2.0;
+        world.isolation_policy = Some(IsolationPolicy::new(0.2, 3600, 0.8));
+        world.tracing_policy = Some(TracingPolicy::new(1, true, 0.5, 86400));
+        let mut testing = TestingPolicy::new(0.3, 0.9, 0.95, 3600);
+        testing.background_prob = 0.01;
+        world.testing_policy = Some(testing);
+        world.lockdown_compliance = 0.3;
+        world.lockdown_trigger = Some(LockdownPolicy::new(0.06, 0.0));
+        world.add_region(
+            "west",
+            Rect::new(Vec2D::new(0.0, 0.0), Vec2D::new(30.0, 60.0)),
+        );
+        world.add_region(
+            "east",
+            Rect::new(Vec2D::new(30.0, 0.0), Vec2D::new(60.0, 60.0)),
+        );
+        for (from, to) in [("west", "east"), ("east", "west")] {
+            world
+                .travel_matrix
+                .insert((from.to_string(), to.to_string()), 0.1);
+        }
+        world.trip_duration = 86400;
+        world
+    }
+
+    /// Records the decisions of the given number of steps.
+    fn record(world: &mut World<StdRng>, steps: usize) -> DecisionLog {
+        world.enable_decision_log();
+        for _ in 0..steps {
+            world.step();
+        }
+        world.take_decision_log().unwrap()
+    }
+
+    /// Returns the agents, variants, pending tests, and history of the world
+    /// as text, leaving out how long its steps took.
+    fn state(world: &World<StdRng>) -> String {
+        let mut snapshot = world.snapshot();
+        for stats in snapshot.history.iter_mut() {
+            stats.step_duration_ms = 0;
+        }
+        format!(
+            "{:?}",
+            (
+                snapshot.agents,
+                snapshot.deceased,
+                snapshot.variants,
+                snapshot.pending_tests,
+                snapshot.history,
+                snapshot.strain_history,
+            )
+        )
+    }
+
+    #[test]
+    fn replayed_runs_end_in_the_recorded_state() {
+        for model in [
+            TransmissionModel::PerContactBernoulli,
+            TransmissionModel::ForceOfInfection { hourly_rate: 0.5 },
+        ] {
+            let mut recorded = busy_world(model);
+            let log = record(&mut recorded, 50);
+
+            for kind in [
+                DecisionKind::Jitter,
+                DecisionKind::Infection,
+                DecisionKind::InfectorPick,
+                DecisionKind::Mutation,
+                DecisionKind::Death,
+                DecisionKind::Hospitalization,
+                DecisionKind::Detection,
+                DecisionKind::Isolation,
+                DecisionKind::Quarantine,
+                DecisionKind::IncubationPeriod,
+                DecisionKind::InfectiousPeriod,
+                DecisionKind::Asymptomatic,
+                DecisionKind::Variant,
+                DecisionKind::Lockdown,
+                DecisionKind::Births,
+                DecisionKind::TestSeeking,
+                DecisionKind::TestResult,
+                DecisionKind::Travel,
+                DecisionKind::Relocation,
+            ] {
+                assert!(
+                    log.decisions.iter().any(|decision| decision.kind == kind),
+                    "{:?} wasn't logged under {:?}",
+                    kind,
+                    model
+                );
+            }
+
+            let mut csv = Vec::new();
+            log.write_csv(&mut csv).unwrap();
+            let log = DecisionLog::read_csv(csv.as_slice()).unwrap();
+
+            // a replay draws nothing a step keeps from the RNG, so seeding it
+            // differently changes nothing
+            let mut replayed = busy_world(model);
+            *replayed.rng = StdRng::seed_from_u64(1);
+            replayed.replay_from(log);
+            for _ in 0..50 {
+                replayed.try_step().unwrap();
+            }
+            assert_eq!(replayed.finish_replay(), Ok(()));
+            assert_eq!(state(&replayed), state(&recorded));
+        }
+    }
+
+    #[test]
+    fn replays_follow_the_log_until_it_stops_lining_up() {
+        let mut recorded = busy_world(TransmissionModel::PerContactBernoulli);
+        let mut log = record(&mut recorded, 50);
+
+        // an agent that escaped every roll of the last step is infected
+        // instead, and its infection makes decisions the log doesn't have
+        let index = log
+            .decisions
+            .iter()
+            .rposition(|decision| {
+                decision.step == 49
+                    && decision.kind == DecisionKind::Infection
+                    && recorded
+                        .agents
+                        .get_agent(decision.agent)
+                        .is_some_and(|agent| agent.status.is_susceptible())
+            })
+            .unwrap();
+        log.decisions[index].value = 1.0;
+        let target = log.decisions[index].agent;
+
+        let mut replayed = busy_world(TransmissionModel::PerContactBernoulli);
+        replayed.replay_from(log);
+        for _ in 0..49 {
+            replayed.try_step().unwrap();
+        }
+        let divergence = replayed.try_step().unwrap_err();
+
+        assert!(divergence.index > index);
+        let found = divergence.found.unwrap();
+        assert_eq!((found.step, found.agent), (49, target));
+        assert_ne!(
+            divergence.expected.map(|expected| expected.agent),
+            Some(target)
+        );
+        assert!(replayed
+            .agents
+            .get_agent(target)
+            .unwrap()
+            .status
+            .is_exposed());
+        assert_eq!(replayed.try_step(), Err(divergence));
+        assert_eq!(replayed.finish_replay(), Err(divergence));
+        assert_eq!(replayed.try_step(), Ok(()));
+    }
+
+    #[test]
+    fn replayed_decisions_dont_draw_from_the_rng() {
+        let recorded = Decision {
+            step: 0,
+            agent: 3,
+            kind: DecisionKind::Death,
+            value: 2.0,
+        };
+        let mut mode = DecisionMode::Replay {
+            decisions: vec![recorded],
+            next: 0,
+        };
+        let mut rng = StdRng::seed_from_u64(91);
+
+        let value = mode.decide(0, 3, DecisionKind::Death, &mut rng, |_| {
+            panic!("sampled a replayed decision")
+        });
+        assert_eq!(value, 2.0);
+
+        // past the end of the log the world samples its own decisions again
+        let value = mode.decide(0, 4, DecisionKind::Death, &mut rng, |_| 0.0);
+        assert_eq!(value, 0.0);
+        assert!(matches!(
+            mode,
+            DecisionMode::Diverged(Divergence {
+                index: 1,
+                expected: None,
+                found: Some(Decision { agent: 4, .. }),
+            })
+        ));
+    }
+}
@@ -0,0 +1,274 @@
+use rand::Rng;
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::World;
+
+/// Retention is how much of a record that grows as the world steps is kept in
+/// memory. Long runs at a fine step size can keep a bounded amount, or stream
+/// everything out as it is recorded.
+#[derive(Default)]
+pub enum Retention {
+    /// Keeps every entry.
+    #[default]
+    All,
+    /// Keeps only the given number of the most recent entries.
+    LastN(usize),
+    /// Keeps every given number of entries, counting from the first ever
+    /// recorded. Entries kept before switching to it stay as they are.
+    EveryKth(usize),
+    /// Writes every entry as a row of CSV as soon as it is recorded, after a
+    /// header row, and keeps nothing.
+    Stream(Box<dyn Write>),
+}
+
+impl fmt::Debug for Retention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Retention::All => write!(f, "All"),
+            Retention::LastN(n) => write!(f, "LastN({})", n),
+            Retention::EveryKth(k) => write!(f, "EveryKth({})", k),
+            Retention::Stream(_) => write!(f, "Stream(..)"),
+        }
+    }
+}
+
+/// CsvRow is an entry of a log that can be written as a row of CSV.
+pub(crate) trait CsvRow {
+    /// HEADER names the columns of the row.
+    const HEADER: &'static str;
+
+    fn write_row(&self, w: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Log is a record of entries kept according to a retention policy.
+pub(crate) struct Log<T> {
+    retention: Retention,
+    entries: Vec<T>,
+    /// recorded is the number of entries ever pushed, kept or not.
+    recorded: usize,
+    /// error is the first error writing to the stream, after which nothing
+    /// more is written to it.
+    error: Option<io::Error>,
+}
+
+impl<T: CsvRow> Log<T> {
+    pub(crate) fn new() -> Self {
+        Self::from_entries(Vec::new())
+    }
+
+    /// Creates a log that keeps every entry, starting with the given ones.
+    pub(crate) fn from_entries(entries: Vec<T>) -> Self {
+        Self {
+            retention: Retention::All,
+            recorded: entries.len(),
+            entries,
+            error: None,
+        }
+    }
+
+    /// Replaces the retention. LastN drops all but the last n entries kept so
+    /// far, and a new stream is given the header and then every entry kept so
+    /// far. EveryKth only thins the entries pushed from then on, since which
+    /// entries were recorded at a multiple of k isn't known once any have been
+    /// dropped.
+    pub(crate) fn set_retention(&mut self, retention: Retention) {
+        self.retention = retention;
+        self.error = None;
+
+        match &mut self.retention {
+            Retention::All | Retention::EveryKth(_) => (),
+            Retention::LastN(n) => {
+                let excess = self.entries.len().saturating_sub(*n);
+                self.entries.drain(..excess);
+            }
+            Retention::Stream(w) => {
+                let mut written = writeln!(w, "{}", T::HEADER);
+                for entry in self.entries.drain(..) {
+                    written = written.and_then(|()| entry.write_row(w.as_mut()));
+                }
+                self.error = written.err();
+            }
+        }
+    }
+
+    pub(crate) fn push(&mut self, entry: T) {
+        let index = self.recorded;
+        self.recorded += 1;
+
+        match &mut self.retention {
+            Retention::All => self.entries.push(entry),
+            Retention::LastN(0) => (),
+            Retention::LastN(n) => {
+                // drained in batches so that pushing stays cheap, while
+                // entries only ever shows the last n
+                if self.entries.len() >= 2 * *n {
+                    let excess = self.entries.len() - *n + 1;
+                    self.entries.drain(..excess);
+                }
+                self.entries.push(entry);
+            }
+            Retention::EveryKth(k) => {
+                if index.is_multiple_of((*k).max(1)) {
+                    self.entries.push(entry);
+                }
+            }
+            Retention::Stream(w) => {
+                if self.error.is_none() {
+                    self.error = entry.write_row(w.as_mut()).err();
+                }
+            }
+        }
+    }
+
+    /// Returns the entries kept, oldest first.
+    pub(crate) fn entries(&self) -> &[T] {
+        let start = match self.retention {
+            Retention::LastN(n) => self.entries.len().saturating_sub(n),
+            _ => 0,
+        };
+        &self.entries[start..]
+    }
+
+    /// Returns the number of entries ever pushed, including those that
+    /// weren't kept.
+    pub(crate) fn recorded(&self) -> usize {
+        self.recorded
+    }
+
+    /// Discards the entries kept, without changing the count recorded.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Takes the entries kept, oldest first.
+    pub(crate) fn take(&mut self) -> Vec<T> {
+        let mut entries = std::mem::take(&mut self.entries);
+        if let Retention::LastN(n) = self.retention {
+            let excess = entries.len().saturating_sub(n);
+            entries.drain(..excess);
+        }
+        entries
+    }
+
+    /// Returns the first error writing to the stream since the last call,
+    /// or flushes the stream if there was none.
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        match &mut self.retention {
+            Retention::Stream(w) => w.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl CsvRow for Vec<usize> {
+    const HEADER: &'static str = "infectious_by_strain";
+
+    /// Writes the counts separated by semicolons, so that they stay in a
+    /// single column.
+    fn write_row(&self, w: &mut dyn Write) -> io::Result<()> {
+        let counts = self
+            .iter()
+            .map(|count| count.to_string())
+            .collect::<Vec<_>>();
+        writeln!(w, "{}", counts.join(";"))
+    }
+}
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Sets how much of the stats history is kept, see history. The strain
+    /// and group histories are kept the same way, except that they aren't
+    /// streamed and so aren't kept at all while the stats are. Run summaries
+    /// stay correct whatever is kept.
+    pub fn set_history_retention(&mut self, retention: Retention) {
+        let alongside = || match &retention {
+            Retention::All => Retention::All,
+            Retention::LastN(n) => Retention::LastN(*n),
+            Retention::EveryKth(k) => Retention::EveryKth(*k),
+            Retention::Stream(_) => Retention::Stream(Box::new(io::sink())),
+        };
+        self.strain_history.set_retention(alongside());
+        self.group_history.set_retention(alongside());
+        self.history.set_retention(retention);
+    }
+
+    /// Sets how much of the event log is kept, see events. The log is still
+    /// cleared at the start of every step, but a stream receives every event.
+    pub fn set_event_retention(&mut self, retention: Retention) {
+        self.events.set_retention(retention);
+    }
+
+    /// Sets how many of the recorded positions are kept, see trajectories.
+    pub fn set_trajectory_retention(&mut self, retention: Retention) {
+        self.trajectories.set_retention(retention);
+    }
+
+    /// Returns the first error writing to any of the streams since the last
+    /// call, after which that stream was no longer written to, or otherwise
+    /// flushes every stream. Call it at the end of a run, since streams are
+    /// only flushed when they are dropped otherwise.
+    pub fn flush_streams(&mut self) -> io::Result<()> {
+        self.history.flush()?;
+        self.events.flush()?;
+        self.trajectories.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::WorldBuilder;
+    use crate::disease::{BasicDisease, DurationDistribution, IfrTable};
+    use crate::run::StopCondition;
+    use crate::{StructureType, Vec2D};
+    use rand::rngs::StdRng;
+
+    /// Returns a world with an epidemic that peaks within a few days.
+    fn outbreak(seed: u64) -> World<StdRng> {
+        WorldBuilder::new()
+            .size(Vec2D::new(50.0, 50.0))
+            .random_agents(300)
+            .structures(StructureType::Home, 100)
+            .structures(StructureType::Work, 5)
+            .step_size(3600)
+            .contact_radius(2.0)
+            .disease(BasicDisease::new(
+                0.05,
+                DurationDistribution::Fixed(12 * 3600),
+                DurationDistribution::Fixed(3 * 86400),
+                IfrTable::flat(0.0),
+            ))
+            .index_cases(5)
+            .seed(seed)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn bounded_histories_still_see_the_peak() {
+        let mut bounded = outbreak(97);
+        bounded.set_history_retention(Retention::LastN(10));
+        let mut full = outbreak(97);
+
+        let summary = bounded.run(StopCondition::Steps(300));
+        let full_summary = full.run(StopCondition::Steps(300));
+
+        assert_eq!(bounded.history().len(), 10);
+        assert_eq!(full.history().len(), 300);
+        let full_peak = full.history().iter().map(|stats| stats.infectious).max();
+        assert_eq!(Some(full_summary.peak_infectious), full_peak);
+        assert!(full_summary.peak_infectious > 5);
+        // the peak is long gone from the bounded history by the end
+        assert!(bounded
+            .history()
+            .iter()
+            .all(|stats| stats.infectious < full_summary.peak_infectious));
+        assert_eq!(summary.peak_infectious, full_summary.peak_infectious);
+    }
+}
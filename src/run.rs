@@ -0,0 +1,323 @@
+use rand::Rng;
+use std::ops::ControlFlow;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::stats::WorldStats;
+use crate::{Error, World};
+
+/// StopCondition decides when World::run stops stepping the world. Conditions
+/// are checked before every step, so a condition that is already met runs no
+/// steps at all.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopCondition {
+    /// Stop after the given number of steps of this run.
+    Steps(u64),
+    /// Stop once the absolute simulation time reaches the given time, in
+    /// seconds.
+    SimTime(i64),
+    /// Stop once there are no exposed or infectious agents left.
+    NoActiveInfections,
+    /// Stop once the run has taken the given wall-clock time.
+    WallClock(Duration),
+    /// Stop once any of the conditions is met.
+    Any(Vec<StopCondition>),
+    /// Stop once all of the conditions are met.
+    All(Vec<StopCondition>),
+}
+
+impl StopCondition {
+    fn is_met(&self, steps: u64, started: Instant, stats: &WorldStats) -> bool {
+        match self {
+            StopCondition::Steps(max_steps) => steps >= *max_steps,
+            StopCondition::SimTime(time) => stats.abs_time >= *time,
+            StopCondition::NoActiveInfections => stats.exposed + stats.infectious == 0,
+            StopCondition::WallClock(duration) => started.elapsed() >= *duration,
+            StopCondition::Any(conditions) => conditions
+                .iter()
+                .any(|condition| condition.is_met(steps, started, stats)),
+            StopCondition::All(conditions) => conditions
+                .iter()
+                .all(|condition| condition.is_met(steps, started, stats)),
+        }
+    }
+}
+
+/// RunSummary describes a finished run of a world. Times are absolute
+/// simulation times in seconds, and totals count from the start of the
+/// simulation, not just the run.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RunSummary {
+    /// steps is the number of steps executed by the run.
+    pub steps: u64,
+    /// final_time is the time the run stopped at.
+    pub final_time: i64,
+    /// peak_infectious is the largest number of infectious agents seen during
+    /// the run, first reached at peak_time.
+    pub peak_infectious: usize,
+    pub peak_time: i64,
+    /// peak_hospital_occupancy is the largest number of agents in hospital
+    /// beds at once during the run.
+    pub peak_hospital_occupancy: usize,
+    /// total_infections counts every exposure, including reinfections.
+    pub total_infections: i64,
+    pub total_deaths: usize,
+    /// total_agents is the number of agents at the end of the run, living or
+    /// dead.
+    pub total_agents: usize,
+    /// wall_time is the wall-clock time the run took.
+    pub wall_time: Duration,
+}
+
+/// FrameInfo describes a frame of World::run_realtime as it is handed to the
+/// frame callback.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FrameInfo {
+    /// frame is the index of the frame, starting from 0 before any steps.
+    pub frame: u64,
+    /// steps is the number of steps the run has taken so far.
+    pub steps: u64,
+    /// lag is how late the frame is compared to its schedule, because the
+    /// steps or the callback of the previous frame took longer than a frame.
+    pub lag: Duration,
+}
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Steps the world until the condition is met and summarizes the run.
+    /// Make sure the condition is eventually met, for example by combining it
+    /// with a limit on the steps, since otherwise the run never ends.
+    pub fn run(&mut self, condition: StopCondition) -> RunSummary {
+        let started = Instant::now();
+        let mut steps = 0;
+        let mut stats = self.current_stats();
+        let mut peak = (stats.infectious, stats.abs_time);
+        let mut peak_hospital_occupancy = stats.hospital_occupancy;
+
+        while !condition.is_met(steps, started, &stats) {
+            self.step();
+            steps += 1;
+            // the stats were just counted at the end of the step
+            stats = *self.last_stats().expect("a step was just taken");
+            if stats.infectious > peak.0 {
+                peak = (stats.infectious, stats.abs_time);
+            }
+            peak_hospital_occupancy = peak_hospital_occupancy.max(stats.hospital_occupancy);
+        }
+
+        RunSummary {
+            steps,
+            final_time: stats.abs_time,
+            peak_infectious: peak.0,
+            peak_time: peak.1,
+            peak_hospital_occupancy,
+            total_infections: self.cumulative_infections,
+            total_deaths: stats.dead,
+            total_agents: stats.total(),
+            wall_time: started.elapsed(),
+        }
+    }
+
+    /// Steps the world in real time, advancing sim_seconds_per_wall_second
+    /// seconds of simulation time for every second of wall-clock time, and
+    /// calls on_frame frames_per_second times a second until it breaks.
+    /// Returns the number of steps taken.
+    ///
+    /// The first frame comes before any steps, and every frame after takes
+    /// the steps owed for its share of the pace, carrying over any fraction
+    /// of a step, so the number of steps per frame doesn't depend on timing.
+    /// Frames that run late skip their sleep and report the lag, and once a
+    /// frame falls more than a frame behind, such as while the callback
+    /// waits for the user to resume, the schedule restarts from there rather
+    /// than rushing to catch up. Fails without calling on_frame if the pace
+    /// or frame rate isn't positive and finite.
+    pub fn run_realtime(
+        &mut self,
+        sim_seconds_per_wall_second: f64,
+        frames_per_second: f64,
+        mut on_frame: impl FnMut(&World<R>, &FrameInfo) -> ControlFlow<()>,
+    ) -> Result<u64, Error> {
+        if !sim_seconds_per_wall_second.is_finite() || sim_seconds_per_wall_second <= 0.0 {
+            return Err(Error::InvalidParameter("sim_seconds_per_wall_second"));
+        }
+        if !frames_per_second.is_finite() || frames_per_second <= 0.0 {
+            return Err(Error::InvalidParameter("frames_per_second"));
+        }
+
+        let frame_duration = Duration::from_secs_f64(1.0 / frames_per_second);
+        let secs_per_frame = sim_seconds_per_wall_second / frames_per_second;
+        let mut owed_secs = 0.0;
+        let mut steps = 0;
+        let mut deadline = Instant::now();
+        let mut lag = Duration::ZERO;
+
+        for frame in 0.. {
+            let info = FrameInfo { frame, steps, lag };
+            if on_frame(self, &info).is_break() {
+                break;
+            }
+
+            owed_secs += secs_per_frame;
+            // adaptive stepping may change the step size after every step
+            while owed_secs >= self.step_size.max(1) as f64 {
+                owed_secs -= self.step_size.max(1) as f64;
+                self.step();
+                steps += 1;
+            }
+
+            deadline += frame_duration;
+            let now = Instant::now();
+            lag = now.saturating_duration_since(deadline);
+            if lag.is_zero() {
+                thread::sleep(deadline - now);
+            } else if lag > frame_duration {
+                deadline = now;
+            }
+        }
+
+        Ok(steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{Agent, MovementProfile};
+    use crate::disease::{BasicDisease, DurationDistribution, IfrTable};
+    use crate::geometry::Vec2D;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Returns a world of 100 agents with 4 index cases of a disease that
+    /// never spreads, which is exposed for 6 hours and infectious for a day.
+    fn quiet_world(seed: u64) -> World<StdRng> {
+        let agents = (0..100)
+            .map(|i| {
+                let pos = Vec2D::new((i % 10) as f64 + 0.5, (i / 10) as f64 + 0.5);
+                Agent::new(pos, MovementProfile::walking())
+            })
+            .collect();
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(10.0, 10.0),
+            agents,
+            StdRng::seed_from_u64(seed),
+        )
+        .unwrap();
+        world.disease = BasicDisease::new(
+            0.0,
+            DurationDistribution::Fixed(6 * 3600),
+            DurationDistribution::Fixed(86400),
+            IfrTable::flat(0.0),
+        );
+        world.background_mortality = false;
+        world.step_size = 3600;
+        world.infect_random_agents(4);
+        world
+    }
+
+    #[test]
+    fn runs_without_transmission_stop_once_the_index_cases_recover() {
+        let mut world = quiet_world(50);
+
+        let summary = world.run(StopCondition::NoActiveInfections);
+
+        assert_eq!(summary.total_infections, 4);
+        assert_eq!(summary.peak_infectious, 4);
+        // exposed for 6 hours, then infectious for 24
+        assert!((30..48).contains(&summary.steps), "{} steps", summary.steps);
+        assert_eq!(summary.final_time, summary.steps as i64 * 3600);
+        let stats = world.last_stats().unwrap();
+        assert_eq!(stats.exposed + stats.infectious, 0);
+        assert_eq!(world.run(StopCondition::NoActiveInfections).steps, 0);
+    }
+
+    #[test]
+    fn step_conditions_run_exactly_that_many_steps() {
+        let mut world = quiet_world(50);
+
+        let summary = world.run(StopCondition::Steps(10));
+
+        assert_eq!(summary.steps, 10);
+        assert_eq!(summary.final_time, 10 * 3600);
+        assert_eq!(world.history().len(), 10);
+    }
+
+    #[test]
+    fn realtime_runs_call_back_every_frame_until_broken() {
+        let mut world = quiet_world(100);
+        // a step and a half per frame at a millisecond a frame
+        let mut frames = Vec::new();
+        let started = Instant::now();
+        let steps = world
+            .run_realtime(5_400_000.0, 1000.0, |world, info| {
+                assert_eq!(world.history().len() as u64, info.steps);
+                frames.push(*info);
+                if info.frame == 3 {
+                    // the user paused for a while
+                    thread::sleep(Duration::from_millis(20));
+                }
+                if info.frame == 10 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap();
+
+        assert_eq!(steps, 15);
+        assert_eq!(world.history().len(), 15);
+        assert_eq!(
+            frames.iter().map(|info| info.frame).collect::<Vec<_>>(),
+            (0..=10).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            frames.iter().map(|info| info.steps).collect::<Vec<_>>(),
+            vec![0, 1, 3, 4, 6, 7, 9, 10, 12, 13, 15]
+        );
+        assert!(frames[4].lag >= Duration::from_millis(15));
+        // the schedule restarts after the pause, and the six frames from
+        // there to the last one are still held to their pace
+        assert!(started.elapsed() >= Duration::from_millis(26));
+
+        let mut called = false;
+        for (pace, rate, name) in [
+            (0.0, 30.0, "sim_seconds_per_wall_second"),
+            (f64::INFINITY, 30.0, "sim_seconds_per_wall_second"),
+            (3600.0, -1.0, "frames_per_second"),
+            (3600.0, f64::NAN, "frames_per_second"),
+        ] {
+            let result = world.run_realtime(pace, rate, |_, _| {
+                called = true;
+                ControlFlow::Break(())
+            });
+            assert_eq!(result, Err(Error::InvalidParameter(name)));
+        }
+        assert!(!called);
+    }
+
+    #[test]
+    fn realtime_runs_follow_adaptive_step_sizes() {
+        let mut world = quiet_world(101);
+        // the step doubles after every quiet step until it is 4 hours
+        world.enable_adaptive_stepping(3600, 4 * 3600, 1.0);
+        let mut times = Vec::new();
+
+        let steps = world
+            .run_realtime(4.0 * 3600.0 * 1000.0, 1000.0, |world, info| {
+                times.push(world.time().abs_time());
+                if info.frame == 4 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap();
+
+        // steps of 1 and 2 hours fill the first frame, then one step a frame
+        assert_eq!(steps, 5);
+        assert_eq!(times, [0, 3 * 3600, 7 * 3600, 11 * 3600, 15 * 3600]);
+    }
+}
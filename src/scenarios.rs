@@ -0,0 +1,175 @@
+//! Preset worlds with sensible parameters, each returned as a builder that can
+//! be tweaked further before it is built. Positions are in units of a
+//! kilometer for the towns and a meter for the classroom, which the speeds of
+//! the agents are set to match.
+
+use crate::agent::AgePyramid;
+use crate::builder::WorldBuilder;
+use crate::disease::{
+    BasicDisease, DurationDistribution, IfrTable, InfectionKernel, TransmissionModel,
+};
+use crate::geometry::Vec2D;
+use crate::{Schedule, StructureType};
+
+/// SCENARIOS are the names of the presets accepted by by_name.
+pub const SCENARIOS: [&str; 3] = ["small_town", "dense_city", "classroom"];
+
+/// Returns the preset with the given name, one of SCENARIOS, or None if there
+/// is no such preset.
+pub fn by_name(name: &str, seed: u64) -> Option<WorldBuilder> {
+    match name {
+        "small_town" => Some(small_town(seed)),
+        "dense_city" => Some(dense_city(seed)),
+        "classroom" => Some(classroom(seed)),
+        _ => None,
+    }
+}
+
+/// A respiratory disease with an incubation period of about 5 days and an
+/// infectious period of about a week, a third of cases without symptoms, a
+/// fatality ratio that rises steeply with age, and immunity that wanes after
+/// about half a year.
+fn respiratory_disease(transmission_prob: f64) -> BasicDisease {
+    BasicDisease {
+        immunity_secs: Some(180 * 86400),
+        asymptomatic_prob: 0.3,
+        ..BasicDisease::new(
+            transmission_prob,
+            DurationDistribution::LogNormal {
+                mean: 5.0 * 86400.0,
+                std_dev: 2.0 * 86400.0,
+            },
+            DurationDistribution::LogNormal {
+                mean: 7.0 * 86400.0,
+                std_dev: 2.0 * 86400.0,
+            },
+            IfrTable::realistic(),
+        )
+    }
+}
+
+/// A town of 2,000 agents across 20 km by 20 km, with 24 homes, 8 workplaces,
+/// 2 schools, and a hospital, stepped hourly. Agents travel at 5 to 15 km/h
+/// and follow the default schedule, and transmission follows the force of
+/// infection so that each case infects a few others over its infectious
+/// period. Five agents are index cases.
+pub fn small_town(seed: u64) -> WorldBuilder {
+    WorldBuilder::new()
+        .size(Vec2D::new(20.0, 20.0))
+        .random_agents(2000)
+        .age_distribution(AgePyramid::realistic())
+        .step_size(3600)
+        .schedule(Schedule::default())
+        .structures(StructureType::Home, 24)
+        .structures(StructureType::Work, 8)
+        .structures(StructureType::School, 2)
+        .structures(StructureType::Hospital, 1)
+        .contact_radius(0.05)
+        .infection_kernel(InfectionKernel::Exponential(0.02))
+        .transmission_model(TransmissionModel::ForceOfInfection { hourly_rate: 1.0 })
+        .disease(respiratory_disease(0.0002))
+        .index_cases(5)
+        .seed(seed)
+}
+
+/// A city of 10,000 agents packed into 10 km by 10 km, with 200 homes, 50
+/// workplaces, 10 schools, and 3 hospitals, stepped hourly. Structures hold a
+/// limited number of agents so that the city spreads across them, and
+/// contacts are overdispersed so that a few agents cause most infections. Ten
+/// agents are index cases.
+pub fn dense_city(seed: u64) -> WorldBuilder {
+    WorldBuilder::new()
+        .size(Vec2D::new(10.0, 10.0))
+        .random_agents(10_000)
+        .age_distribution(AgePyramid::realistic())
+        .step_size(3600)
+        .schedule(Schedule::default())
+        .structures_with_capacity(StructureType::Home, 200, 60)
+        .structures_with_capacity(StructureType::Work, 50, 250)
+        .structures_with_capacity(StructureType::School, 10, 400)
+        .structures(StructureType::Hospital, 3)
+        .contact_radius(0.1)
+        .infection_kernel(InfectionKernel::Exponential(0.03))
+        .transmission_model(TransmissionModel::ForceOfInfection { hourly_rate: 1.0 })
+        .contact_dispersion(0.4)
+        .disease(respiratory_disease(0.0003))
+        .index_cases(10)
+        .seed(seed)
+}
+
+/// A classroom of 30 children aged 6 to 12 in a room of 10 m by 8 m, stepped
+/// every minute. There is a single school and no homes, so the children stay
+/// where they are outside of school hours and gather at the front of the room
+/// during them, walking at about a meter per second. Transmission falls off
+/// over a couple of meters. One child is the index case.
+pub fn classroom(seed: u64) -> WorldBuilder {
+    let ages = AgePyramid::new(vec![(6.0, 12.0, 1.0)]).expect("the band is valid");
+
+    WorldBuilder::new()
+        .size(Vec2D::new(10.0, 8.0))
+        .random_agents(30)
+        .age_distribution(ages)
+        .agent_speed(0.8, 1.2)
+        .step_size(60)
+        .schedule(Schedule::default())
+        .structures(StructureType::School, 1)
+        .contact_radius(2.0)
+        .infection_kernel(InfectionKernel::Exponential(1.0))
+        .transmission_model(TransmissionModel::ForceOfInfection { hourly_rate: 1.0 })
+        .disease(respiratory_disease(0.005))
+        .index_cases(1)
+        .seed(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the scenario for the given time and checks that infections spread
+    /// beyond the index cases and that no agent went missing. The index cases
+    /// skip their incubation so that a short run is enough.
+    fn run_sanely(name: &str, secs: i64, index_cases: i64) {
+        let mut world = by_name(name, 1).unwrap().build().unwrap();
+        let population = world.agents.len();
+        for agent in world.agents.iter_mut() {
+            agent.incubation_period = 0;
+        }
+
+        while world.time().abs_time() < secs {
+            world.step();
+        }
+
+        assert!(
+            world.cumulative_infections() > index_cases,
+            "{} had {} infections",
+            name,
+            world.cumulative_infections()
+        );
+        assert_eq!(world.agents.len() + world.deceased.len(), population);
+        assert_eq!(world.audit(), Ok(()));
+    }
+
+    #[test]
+    fn small_towns_spread_the_disease() {
+        run_sanely("small_town", 3 * 86400, 5);
+    }
+
+    #[test]
+    fn dense_cities_spread_the_disease() {
+        run_sanely("dense_city", 2 * 86400, 10);
+    }
+
+    #[test]
+    fn classrooms_spread_the_disease() {
+        // through monday, since nobody is at school on sunday
+        run_sanely("classroom", 2 * 86400, 1);
+    }
+
+    #[test]
+    fn unknown_scenarios_have_no_preset() {
+        assert!(by_name("village", 1).is_none());
+        for name in SCENARIOS {
+            assert!(by_name(name, 1).is_some());
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use crate::agent::{Agent, ContactGraph, GroupId};
+use crate::disease::{BasicDisease, InfectionKernel, TransmissionModel};
+use crate::geometry::Vec2D;
+use crate::groups::GroupStats;
+use crate::intervention::{IsolationPolicy, LockdownPolicy, TestingPolicy, TracingPolicy};
+use crate::pipeline::{AdaptiveConfig, Phase};
+use crate::quadtree::QuadtreeConfig;
+use crate::region::Region;
+use crate::stats::WorldStats;
+use crate::testing::PendingTest;
+use crate::{BoundaryMode, DwellTimes, Schedule, SettleRadii, Structure, Time};
+use std::collections::HashMap;
+
+/// WorldSnapshot holds everything needed to recreate a World, other than its
+/// RNG. The quadtree itself isn't stored since it can be rebuilt from the
+/// agents and the quadtree config.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WorldSnapshot {
+    pub size: Vec2D<f64>,
+    /// agents holds every living agent alongside its id, in ascending order of
+    /// id.
+    pub agents: Vec<(usize, Agent)>,
+    /// deceased holds agents that were pruned from the world after dying.
+    pub deceased: Vec<(usize, Agent)>,
+    /// next_agent_id is the id the next agent added to the world will get,
+    /// which keeps ids of pruned agents from being reused.
+    pub next_agent_id: usize,
+    /// structures holds every structure, in order of id.
+    pub structures: Vec<Structure>,
+    pub contacts: ContactGraph,
+    pub time: Time,
+    pub curr_step: i64,
+    pub step_size: i64,
+    pub adaptive_stepping: Option<AdaptiveConfig>,
+    pub last_step_size: i64,
+    pub cumulative_infections: i64,
+    pub history: Vec<WorldStats>,
+    pub quadtree_config: QuadtreeConfig,
+    pub contact_radius: f64,
+    pub setting_radius: f64,
+    pub infection_kernel: InfectionKernel,
+    pub transmission_model: TransmissionModel,
+    pub disease: BasicDisease,
+    pub variants: Vec<BasicDisease>,
+    pub mutation_prob: f64,
+    pub strain_history: Vec<Vec<usize>>,
+    pub stratified_groups: Vec<GroupId>,
+    pub group_history: Vec<GroupStats>,
+    pub schedule: Schedule,
+    pub dwell_times: DwellTimes,
+    pub settle_radii: SettleRadii,
+    pub speed_jitter: f64,
+    pub boundary_mode: BoundaryMode,
+    pub school_age_cutoff: i64,
+    pub retirement_age: i64,
+    pub prune_dead: bool,
+    pub contact_dispersion: Option<f64>,
+    pub record_contacts: bool,
+    pub vaccine_efficacy: f64,
+    pub mask_source_control: f64,
+    pub mask_wearer_protection: f64,
+    pub isolation_policy: Option<IsolationPolicy>,
+    pub tracing_policy: Option<TracingPolicy>,
+    pub testing_policy: Option<TestingPolicy>,
+    pub pending_tests: Vec<PendingTest>,
+    pub tests_administered: usize,
+    pub lockdown_active: bool,
+    pub lockdown_compliance: f64,
+    pub lockdown_trigger: Option<LockdownPolicy>,
+    pub closures: u8,
+    pub self_isolation_prob: f64,
+    pub hospital_mortality_multiplier: f64,
+    pub overload_mortality_multiplier: f64,
+    pub background_mortality: bool,
+    pub households: HashMap<usize, Vec<usize>>,
+    pub household_transmission_prob: f64,
+    pub structure_members: HashMap<usize, Vec<usize>>,
+    pub cohort_transmission_rate: f64,
+    pub regions: Vec<Region>,
+    /// travel_matrix holds the daily probability of travel from the first
+    /// region to the second by name, sorted by the names.
+    pub travel_matrix: Vec<(String, String, f64)>,
+    pub trip_duration: i64,
+    pub birth_rate: f64,
+    pub pipeline: Vec<Phase>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::WorldBuilder;
+    use crate::disease::{DurationDistribution, IfrTable};
+    use crate::{StructureType, World};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Returns a world two days into an outbreak of a disease that spreads
+    /// within hours.
+    fn mid_epidemic_world(seed: u64) -> World<StdRng> {
+        let disease = BasicDisease::new(
+            0.5,
+            DurationDistribution::Fixed(6 * 3600),
+            DurationDistribution::Fixed(5 * 86400),
+            IfrTable::flat(0.0),
+        );
+        let mut world = WorldBuilder::new()
+            .size(Vec2D::new(50.0, 50.0))
+            .random_agents(200)
+            .structures(StructureType::Home, 50)
+            .structures(StructureType::Work, 10)
+            .structures(StructureType::School, 5)
+            .step_size(3600)
+            .contact_radius(2.0)
+            .disease(disease)
+            .index_cases(5)
+            .seed(seed)
+            .build()
+            .unwrap();
+        for _ in 0..48 {
+            world.step();
+        }
+        world
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshots_survive_a_json_round_trip() {
+        let snapshot = mid_epidemic_world(18).snapshot();
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let restored = serde_json::from_str::<WorldSnapshot>(&json).unwrap();
+
+        // compared as JSON values since maps don't serialize in a fixed order
+        let value = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(serde_json::to_value(&restored).unwrap(), value);
+        let world = World::from_snapshot(restored, StdRng::seed_from_u64(18));
+        assert_eq!(serde_json::to_value(world.snapshot()).unwrap(), value);
+
+        let infected = snapshot
+            .agents
+            .iter()
+            .filter(|(_, agent)| !agent.status.is_susceptible())
+            .collect::<Vec<_>>();
+        assert!(infected.len() > 5);
+        for (agent_id, agent) in infected {
+            assert!(world.contacts.contains(*agent_id));
+            let restored = world.agents.get_agent(*agent_id).unwrap();
+            assert_eq!(restored.status, agent.status);
+            assert_eq!(restored.pos, agent.pos);
+        }
+    }
+
+    #[test]
+    fn restored_worlds_keep_contact_parents_resolving() {
+        let world = mid_epidemic_world(19);
+        let mut restored = World::from_snapshot(world.snapshot(), StdRng::seed_from_u64(19));
+        let infected_before = restored.contacts.infected_agent_count();
+
+        assert_eq!(
+            restored.agents.get_agent_ids(),
+            world.agents.get_agent_ids()
+        );
+        assert_eq!(
+            restored.agents.next_agent_id(),
+            world.agents.next_agent_id()
+        );
+        for _ in 0..48 {
+            restored.step();
+        }
+
+        assert!(restored.contacts.infected_agent_count() > infected_before);
+        for agent_id in world.contacts.agent_ids() {
+            assert_eq!(
+                restored.contacts.parent_of(agent_id),
+                world.contacts.parent_of(agent_id)
+            );
+        }
+        for agent_id in restored.contacts.agent_ids() {
+            let parent = match restored.contacts.parent_of(agent_id) {
+                Some(parent) => parent,
+                None => continue,
+            };
+            let parent = restored
+                .agents
+                .get_agent(parent)
+                .expect("dead agents aren't pruned by default");
+            assert!(!parent.status.is_susceptible());
+            assert!(restored.agents.get_agent(agent_id).is_some());
+        }
+    }
+}
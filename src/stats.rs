@@ -0,0 +1,252 @@
+use crate::agent::{Agent, DeathCause, Status};
+use crate::retention::CsvRow;
+use std::io::{self, Write};
+
+/// WorldStats is a summary of the state of the world at a single point in
+/// time, counting how many agents are in each compartment of the SEIRD model.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WorldStats {
+    /// step is the simulation step the stats were recorded at.
+    pub step: i64,
+    /// abs_time is the number of seconds since the simulation began.
+    pub abs_time: i64,
+    pub susceptible: usize,
+    pub exposed: usize,
+    pub infectious: usize,
+    /// symptomatic and asymptomatic split the infectious agents by whether
+    /// they show symptoms.
+    pub symptomatic: usize,
+    pub asymptomatic: usize,
+    /// hospitalized is the number of infectious agents that are severe cases,
+    /// of which hospital_occupancy are in a hospital bed.
+    pub hospitalized: usize,
+    pub hospital_occupancy: usize,
+    pub recovered: usize,
+    pub dead: usize,
+    /// deaths_from_disease and deaths_other split the dead agents by whether
+    /// they died of the disease or of other causes.
+    pub deaths_from_disease: usize,
+    pub deaths_other: usize,
+    /// population is the number of living agents.
+    pub population: usize,
+    /// vaccinated is the number of agents that have been vaccinated,
+    /// regardless of their status.
+    pub vaccinated: usize,
+    /// isolated is the number of agents currently isolating.
+    pub isolated: usize,
+    /// masked is the number of living agents that wear masks at all.
+    pub masked: usize,
+    /// mean_immunity is the mean immunity of the living agents, or 0 if
+    /// there are none.
+    pub mean_immunity: f64,
+    /// new_infections is the number of agents exposed during the step.
+    pub new_infections: usize,
+    /// births is the number of agents born during the step.
+    pub births: usize,
+    /// contacts is the number of pairs of living agents within the contact
+    /// radius of each other at the start of the step. It is only counted
+    /// while the world records contacts, and is 0 otherwise.
+    pub contacts: usize,
+    /// tests_administered is the number of tests since the simulation began.
+    pub tests_administered: usize,
+    /// tests_returned is the number of test results that came back during
+    /// the step, of which positive_tests were positive. Positive tests are
+    /// the observed cases, as opposed to the true new infections.
+    pub tests_returned: usize,
+    pub positive_tests: usize,
+    /// step_duration_ms is the wall-clock time it took to compute the step.
+    pub step_duration_ms: u128,
+    /// lockdown is whether a lockdown was active at the end of the step.
+    pub lockdown: bool,
+    /// closures is the mask of the closure bits of the structure types that
+    /// were closed at the end of the step, see StructureType::closure_bit.
+    pub closures: u8,
+}
+
+impl WorldStats {
+    /// Counts the agents in each compartment in a single pass.
+    pub fn from_agents<'a>(
+        step: i64,
+        abs_time: i64,
+        agents: impl Iterator<Item = &'a Agent>,
+    ) -> Self {
+        let mut stats = Self {
+            step,
+            abs_time,
+            ..Default::default()
+        };
+
+        let mut total_immunity = 0.0;
+        for agent in agents {
+            if !agent.status.is_dead() {
+                total_immunity += agent.immunity;
+            }
+            if agent.vaccinated {
+                stats.vaccinated += 1;
+            }
+            if agent.isolated {
+                stats.isolated += 1;
+            }
+            if agent.mask_compliance > 0.0 && !agent.status.is_dead() {
+                stats.masked += 1;
+            }
+
+            match agent.status {
+                Status::Susceptible => stats.susceptible += 1,
+                Status::Exposed { .. } => stats.exposed += 1,
+                Status::Infectious { .. } => {
+                    stats.infectious += 1;
+                    if agent.asymptomatic {
+                        stats.asymptomatic += 1;
+                    } else {
+                        stats.symptomatic += 1;
+                    }
+                }
+                Status::Hospitalized { .. } => {
+                    stats.infectious += 1;
+                    stats.symptomatic += 1;
+                    stats.hospitalized += 1;
+                    if agent.hospital_id.is_some() {
+                        stats.hospital_occupancy += 1;
+                    }
+                }
+                Status::Recovered { .. } => stats.recovered += 1,
+                Status::Dead => {
+                    stats.dead += 1;
+                    match agent.death_cause {
+                        Some(DeathCause::Disease) => stats.deaths_from_disease += 1,
+                        _ => stats.deaths_other += 1,
+                    }
+                }
+            }
+        }
+        stats.population = stats.total() - stats.dead;
+        if stats.population > 0 {
+            stats.mean_immunity = total_immunity / stats.population as f64;
+        }
+
+        stats
+    }
+
+    /// The fraction of the test results that came back during the step that
+    /// were positive, or None if none came back.
+    pub fn test_positivity(&self) -> Option<f64> {
+        if self.tests_returned == 0 {
+            return None;
+        }
+        Some(self.positive_tests as f64 / self.tests_returned as f64)
+    }
+
+    /// Total number of agents counted, living or dead.
+    pub fn total(&self) -> usize {
+        self.susceptible + self.exposed + self.infectious + self.recovered + self.dead
+    }
+}
+
+/// Writes the stats as CSV with a header row followed by one row per entry.
+/// An empty slice produces only the header.
+pub fn write_csv<W: Write>(stats: &[WorldStats], mut w: W) -> io::Result<()> {
+    writeln!(w, "{}", WorldStats::HEADER)?;
+    for row in stats {
+        row.write_row(&mut w)?;
+    }
+
+    w.flush()
+}
+
+impl CsvRow for WorldStats {
+    const HEADER: &'static str = "step,abs_time,susceptible,exposed,infectious,symptomatic,asymptomatic,hospitalized,hospital_occupancy,recovered,dead,deaths_from_disease,deaths_other,population,vaccinated,isolated,masked,mean_immunity,new_infections,births,contacts,tests_administered,tests_returned,positive_tests,step_duration_ms,lockdown,closures";
+
+    fn write_row(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            w,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.step,
+            self.abs_time,
+            self.susceptible,
+            self.exposed,
+            self.infectious,
+            self.symptomatic,
+            self.asymptomatic,
+            self.hospitalized,
+            self.hospital_occupancy,
+            self.recovered,
+            self.dead,
+            self.deaths_from_disease,
+            self.deaths_other,
+            self.population,
+            self.vaccinated,
+            self.isolated,
+            self.masked,
+            self.mean_immunity,
+            self.new_infections,
+            self.births,
+            self.contacts,
+            self.tests_administered,
+            self.tests_returned,
+            self.positive_tests,
+            self.step_duration_ms,
+            self.lockdown as u8,
+            self.closures,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::WorldBuilder;
+    use crate::geometry::Vec2D;
+
+    #[test]
+    fn empty_history_writes_only_the_header() {
+        let mut csv = Vec::new();
+        write_csv(&[], &mut csv).unwrap();
+
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            format!("{}\n", WorldStats::HEADER)
+        );
+    }
+
+    #[test]
+    fn csv_has_a_row_per_step() {
+        let mut world = WorldBuilder::new()
+            .size(Vec2D::new(10.0, 10.0))
+            .random_agents(100)
+            .step_size(3600)
+            .contact_radius(2.0)
+            .index_cases(3)
+            .seed(7)
+            .build()
+            .unwrap();
+        for _ in 0..10 {
+            world.step();
+        }
+
+        let mut csv = Vec::new();
+        world.write_stats_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        let header = lines.next().unwrap().split(',').collect::<Vec<_>>();
+        let rows = lines
+            .map(|line| line.split(',').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 10);
+        assert!(rows.iter().all(|row| row.len() == header.len()));
+
+        // immunity never wanes, so agents that stop being susceptible never
+        // are again and the count of those ever infected only grows
+        let susceptible = header
+            .iter()
+            .position(|column| *column == "susceptible")
+            .unwrap();
+        let cumulative = rows
+            .iter()
+            .map(|row| 100 - row[susceptible].parse::<usize>().unwrap())
+            .collect::<Vec<_>>();
+        assert!(cumulative[0] >= 3);
+        assert!(cumulative.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}
@@ -0,0 +1,141 @@
+use rand::Rng;
+
+use crate::agent::Status;
+use crate::replay::DecisionKind;
+use crate::World;
+
+/// PendingTest is a test that has been administered but whose result hasn't
+/// come back yet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PendingTest {
+    pub agent_id: usize,
+    /// result_time is the absolute time at which the result comes back.
+    pub result_time: i64,
+    pub positive: bool,
+}
+
+/// TestResults are the results that came back during a step.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TestResults {
+    pub(crate) returned: usize,
+    /// positive holds the ids of the agents that tested positive.
+    pub(crate) positive: Vec<usize>,
+}
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Returns the tests whose results haven't come back yet, in the order
+    /// they were administered.
+    pub fn pending_tests(&self) -> &[PendingTest] {
+        &self.pending_tests
+    }
+
+    /// Returns the number of tests administered since the simulation began.
+    pub fn tests_administered(&self) -> usize {
+        self.tests_administered
+    }
+
+    /// Tests the agents that seek a test this step according to the testing
+    /// policy, then handles every result that comes back before the step is
+    /// over, including those of the tests just administered if there is no
+    /// delay. Agents that test positive are detected and may isolate.
+    pub(crate) fn run_tests(&mut self) -> TestResults {
+        let policy = match self.testing_policy {
+            Some(policy) => policy,
+            None => return TestResults::default(),
+        };
+        let now = self.time.abs_time();
+
+        for agent_id in self.agents.get_agent_ids() {
+            let agent = self.agents.get_agent(agent_id).unwrap();
+            if agent.status.is_dead()
+                || agent.detected
+                || self
+                    .pending_tests
+                    .iter()
+                    .any(|test| test.agent_id == agent_id)
+            {
+                continue;
+            }
+
+            let symptomatic = agent.status.is_infectious() && !agent.asymptomatic;
+            let seek_prob = if symptomatic {
+                policy.seek_prob
+            } else {
+                policy.background_prob
+            };
+            let seek_prob = self.scale_to_step(seek_prob).clamp(0.0, 1.0);
+            if seek_prob <= 0.0
+                || !self.decisions.decide_bool(
+                    self.curr_step,
+                    agent_id,
+                    DecisionKind::TestSeeking,
+                    &mut self.rng,
+                    |rng| rng.gen_bool(seek_prob),
+                )
+            {
+                continue;
+            }
+
+            let infected =
+                matches!(agent.status, Status::Exposed { .. }) || agent.status.is_infectious();
+            let positive = self.decisions.decide_bool(
+                self.curr_step,
+                agent_id,
+                DecisionKind::TestResult,
+                &mut self.rng,
+                |rng| {
+                    if infected {
+                        rng.gen_bool(policy.sensitivity.clamp(0.0, 1.0))
+                    } else {
+                        !rng.gen_bool(policy.specificity.clamp(0.0, 1.0))
+                    }
+                },
+            );
+            self.pending_tests.push(PendingTest {
+                agent_id,
+                result_time: now + policy.delay.max(0),
+                positive,
+            });
+            self.tests_administered += 1;
+        }
+
+        // results that come back during the step are handled now, in the
+        // order the tests were administered
+        let end = now + self.step_size;
+        let (returned, pending) = std::mem::take(&mut self.pending_tests)
+            .into_iter()
+            .partition::<Vec<_>, _>(|test| test.result_time < end);
+        self.pending_tests = pending;
+
+        let mut results = TestResults {
+            returned: returned.len(),
+            positive: Vec::new(),
+        };
+        for test in returned.into_iter().filter(|test| test.positive) {
+            let agent = match self.agents.get_agent_mut(test.agent_id) {
+                Some(agent) if !agent.status.is_dead() => agent,
+                _ => continue,
+            };
+
+            agent.detected = true;
+            if self.decisions.decide_bool(
+                self.curr_step,
+                test.agent_id,
+                DecisionKind::Isolation,
+                &mut self.rng,
+                |rng| rng.gen_bool(policy.compliance.clamp(0.0, 1.0)),
+            ) {
+                agent.isolated = true;
+                let end = now + policy.isolation_secs;
+                agent.quarantine_end = Some(agent.quarantine_end.map_or(end, |prev| prev.max(end)));
+            }
+            results.positive.push(test.agent_id);
+        }
+
+        results
+    }
+}
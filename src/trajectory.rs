@@ -0,0 +1,174 @@
+use rand::Rng;
+use std::io::{self, Write};
+
+use crate::retention::CsvRow;
+use crate::World;
+
+/// TrajectoryPoint is the position and status of a single agent at the end of
+/// a step.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrajectoryPoint {
+    pub step: i64,
+    pub agent_id: usize,
+    pub x: f64,
+    pub y: f64,
+    /// status is the letter of the compartment of the agent, see
+    /// Status::as_char.
+    pub status: char,
+}
+
+impl CsvRow for TrajectoryPoint {
+    const HEADER: &'static str = "step,agent_id,x,y,status";
+
+    fn write_row(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            w,
+            "{},{},{},{},{}",
+            self.step, self.agent_id, self.x, self.y, self.status
+        )
+    }
+}
+
+/// PositionRecording is which agents have their positions recorded, and how
+/// often.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PositionRecording {
+    /// sample_every is the number of steps between samples.
+    sample_every: usize,
+    /// agent_ids are the only agents recorded, in ascending order. All agents
+    /// are recorded if None.
+    agent_ids: Option<Vec<usize>>,
+}
+
+impl<R> World<R>
+where
+    R: Rng,
+{
+    /// Starts recording the positions of agents at the end of every
+    /// sample_every-th step, replacing any previous settings. Only the agents
+    /// with the given ids are recorded if a filter is given, which along with
+    /// the interval keeps the memory used in check for large worlds.
+    pub fn enable_position_recording(
+        &mut self,
+        sample_every_n_steps: usize,
+        agent_filter: Option<Vec<usize>>,
+    ) {
+        let agent_ids = agent_filter.map(|mut agent_ids| {
+            agent_ids.sort_unstable();
+            agent_ids.dedup();
+            agent_ids
+        });
+        self.position_recording = Some(PositionRecording {
+            sample_every: sample_every_n_steps.max(1),
+            agent_ids,
+        });
+    }
+
+    /// Stops recording positions. The trajectories recorded so far are kept.
+    pub fn disable_position_recording(&mut self) {
+        self.position_recording = None;
+    }
+
+    /// Returns the recorded positions that were kept, oldest first and in
+    /// ascending order of agent id within a step. See
+    /// set_trajectory_retention.
+    pub fn trajectories(&self) -> &[TrajectoryPoint] {
+        self.trajectories.entries()
+    }
+
+    /// Discards the recorded positions, for example between phases of a run.
+    pub fn clear_trajectories(&mut self) {
+        self.trajectories.clear();
+    }
+
+    /// Writes the recorded positions that were kept as CSV with a header row
+    /// followed by one row per position.
+    pub fn write_trajectories_csv<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{}", TrajectoryPoint::HEADER)?;
+        for point in self.trajectories() {
+            point.write_row(&mut w)?;
+        }
+
+        w.flush()
+    }
+
+    /// Records the positions of the agents if recording is enabled and the
+    /// current step is sampled. Agents that were removed are skipped.
+    pub(crate) fn record_positions(&mut self) {
+        let recording = match &self.position_recording {
+            Some(recording) if self.curr_step % recording.sample_every as i64 == 0 => recording,
+            _ => return,
+        };
+
+        let agent_ids = match &recording.agent_ids {
+            Some(agent_ids) => agent_ids.clone(),
+            None => self.agents.get_agent_ids(),
+        };
+        for agent_id in agent_ids {
+            if let Some(agent) = self.agents.get_agent(agent_id) {
+                self.trajectories.push(TrajectoryPoint {
+                    step: self.curr_step,
+                    agent_id,
+                    x: agent.pos.x,
+                    y: agent.pos.y,
+                    status: agent.status.as_char(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{Agent, MovementProfile, SECONDS_PER_YEAR};
+    use crate::geometry::Vec2D;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn recorded_positions_head_toward_work() {
+        let home = Vec2D::new(1.0, 1.0);
+        let work = Vec2D::new(90.0, 10.0);
+        let mut agent = Agent::new_with_locations(
+            home,
+            MovementProfile::from_units_per_second(0.01).unwrap(),
+            home,
+            work,
+            Vec2D::new_nan(),
+        );
+        agent.age = 30 * SECONDS_PER_YEAR;
+        let bystander = Agent::new(Vec2D::new(50.0, 50.0), MovementProfile::walking());
+        let mut world = World::new_with_agents_and_rng(
+            Vec2D::new(100.0, 100.0),
+            vec![agent, bystander],
+            StdRng::seed_from_u64(52),
+        )
+        .unwrap();
+        // monday morning, as work starts
+        world.step_size = 3600;
+        while world.time().abs_time() < 86400 + 8 * 3600 {
+            world.step();
+        }
+
+        world.step_size = 300;
+        world.enable_position_recording(1, Some(vec![0]));
+        for _ in 0..20 {
+            world.step();
+        }
+
+        let points = world.trajectories();
+        assert_eq!(points.len(), 20);
+        assert!(points.iter().all(|point| point.agent_id == 0));
+        let distances = points
+            .iter()
+            .map(|point| Vec2D::new(point.x, point.y).dist(work))
+            .collect::<Vec<_>>();
+        assert!(
+            distances.windows(2).all(|pair| pair[1] < pair[0]),
+            "{:?}",
+            distances
+        );
+    }
+}